@@ -0,0 +1,101 @@
+/*
+Benchmarks of the renderer's core kernels: box and sphere intersection, traversal of each acceleration structure over the example scenes,
+Perlin noise and a full 64x64 render, measured with criterion. Run with cargo bench, optionally followed by part of a benchmark's name
+to run only those that match; criterion compares each run against the last and keeps its reports in target/criterion.
+*/
+
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rust_tracer::accelerator::AcceleratorKind;
+use rust_tracer::bvh::AABB;
+use rust_tracer::camera::{Camera, StandardCamera};
+use rust_tracer::examples::example;
+use rust_tracer::hitting::{Hittable, HitRecord};
+use rust_tracer::integrator::{Integrator, Clamping};
+use rust_tracer::materials::Material;
+use rust_tracer::ray::Ray;
+use rust_tracer::render::{Renderer, RenderSettings};
+use rust_tracer::sampler::RandomSampler;
+use rust_tracer::scene::Scene;
+use rust_tracer::scene_file::SceneFile;
+use rust_tracer::textures::{Perlin, Textures};
+use rust_tracer::vec3::{Vec3, Point3, set_seed, Float};
+
+///Loads an example scene, with the camera it is rendered from.
+fn example_scene(name : &str, accelerator : AcceleratorKind) -> (SceneFile, Scene, StandardCamera) {
+    let file = example(name).expect("Failed to load example scene");
+    let world = file.scene(None, accelerator).expect("Failed to build example scene");
+    let settings = &file.settings;
+    let camera = StandardCamera::new(
+        settings.lookfrom.unwrap_or(Point3::new(0.0, 0.0, 0.0)),
+        settings.lookat.unwrap_or(Point3::new(0.0, 0.0, -1.0)),
+        settings.vup.unwrap_or(Vec3::new(0.0, 1.0, 0.0)),
+        settings.vfov.unwrap_or(20.0),
+        settings.aspect_ratio.unwrap_or(1.0),
+        0.0,
+        1.0,
+    );
+    (file, world, camera)
+}
+
+///Rays through a 64x64 grid of points spread evenly over the camera's image.
+fn camera_rays(camera : &StandardCamera) -> Vec<Ray> {
+    let mut sampler = RandomSampler;
+    (0..64 * 64).map(|k| camera.get_ray(((k % 64) as Float + 0.5) / 64.0, ((k / 64) as Float + 0.5) / 64.0, &mut sampler)).collect()
+}
+
+///Box and sphere intersection, with a ray that hits both.
+fn intersection(c : &mut Criterion) {
+    let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.3, 0.2, -1.0));
+    let aabb = AABB::new(Point3::new(-1.0, -1.0, -3.0), Point3::new(1.0, 1.0, -2.0));
+    c.bench_function("aabb_hit", |b| b.iter(|| black_box(aabb).hit(black_box(r), 0.001, Float::INFINITY)));
+
+    let sphere = Hittable::Sphere(Material::Lambertian(0), Point3::new(0.0, 0.0, -2.5), 0.5);
+    let textures = Textures::new();
+    c.bench_function("sphere_hit", |b| b.iter(|| {
+        let mut rec = HitRecord::new();
+        black_box(black_box(&sphere).hit(black_box(r), 0.001, Float::INFINITY, &mut rec, &textures));
+        rec
+    }));
+}
+
+///Closest hits of a grid of camera rays through each acceleration structure, over a scene of a few large objects and one of many small ones.
+fn traversal(c : &mut Criterion) {
+    set_seed(1);
+    for scene in ["cornell-box", "random-spheres"] {
+        for (kind, accelerator) in [("bvh", AcceleratorKind::Bvh), ("bvh4", AcceleratorKind::Bvh4), ("kdtree", AcceleratorKind::KdTree), ("flat", AcceleratorKind::Flat)] {
+            let (_file, world, camera) = example_scene(scene, accelerator);
+            let rays = camera_rays(&camera);
+            c.bench_function(&format!("traverse_{}_{}", scene, kind), |b| b.iter(|| {
+                let mut rec = HitRecord::new();
+                for r in &rays {
+                    black_box(world.hit(*r, 0.001, Float::INFINITY, &mut rec));
+                }
+            }));
+        }
+    }
+}
+
+///Turbulence, as the marble texture looks it up.
+fn noise(c : &mut Criterion) {
+    let perlin = Perlin::new();
+    c.bench_function("perlin_turbulence", |b| b.iter(|| black_box(&perlin).turb(black_box(Point3::new(1.3, -0.7, 2.1)), 7)));
+}
+
+///A whole small render, on every core.
+fn render(c : &mut Criterion) {
+    set_seed(1);
+    let (_file, mut world, camera) = example_scene("cornell-box", AcceleratorKind::Bvh);
+    let cams : Vec<Box<dyn Camera>> = vec![Box::new(camera)];
+    let renderer = Renderer::new(RenderSettings::new(64, 64, 4, Integrator::PathTracer(8, Clamping::None)));
+    c.bench_function("render_cornell_box_64x64_4spp", |b| b.iter(|| renderer.render(&mut world, &cams)));
+}
+
+criterion_group!(kernels, intersection, traversal, noise);
+criterion_group! {
+    name = renders;
+    //Renders take tens of milliseconds each, so fewer of them are timed
+    config = Criterion::default().sample_size(10);
+    targets = render
+}
+criterion_main!(kernels, renders);
@@ -0,0 +1,32 @@
+//Generates the C header, rust_tracer.h, from the functions in lib.rs into OUT_DIR, and warns when the copy in include/ is out of date
+
+use std::env;
+use std::fs::read;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=include/rust_tracer.h");
+    let (Some(directory), Some(out)) = (env::var_os("CARGO_MANIFEST_DIR"), env::var_os("OUT_DIR")) else {
+        println!("cargo:warning=CARGO_MANIFEST_DIR or OUT_DIR isn't set, so the C header wasn't generated");
+        return;
+    };
+    let (directory, header) = (PathBuf::from(directory), PathBuf::from(out).join("rust_tracer.h"));
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&directory)
+        .with_language(cbindgen::Language::C)
+        .with_include_guard("RUST_TRACER_H")
+        .with_header("/* Generated from ffi/src/lib.rs by cbindgen; don't edit by hand. */")
+        .with_cpp_compat(true)
+        .generate();
+    match bindings {
+        Ok(bindings) => {
+            bindings.write_to_file(&header);
+            let checked_in = directory.join("include").join("rust_tracer.h");
+            if read(&checked_in).ok() != read(&header).ok() {
+                println!("cargo:warning={} is out of date; copy {} over it", checked_in.display(), header.display());
+            }
+        },
+        Err(error) => println!("cargo:warning=Couldn't generate the C header: {}", error),
+    }
+}
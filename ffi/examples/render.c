@@ -0,0 +1,54 @@
+/*
+Renders a glass sphere and a mirror tetrahedron on a diffuse floor with the C interface, writing a PPM image to the path given
+(or image.ppm). Build the library with cargo build --release -p rust-tracer-ffi, and then, from the repository's root,
+
+    cc ffi/examples/render.c -Iffi/include -Ltarget/release -lrust_tracer_ffi -o render
+*/
+
+#include <stdio.h>
+#include "rust_tracer.h"
+
+#define WIDTH 320
+#define HEIGHT 240
+
+int main(int argc, char **argv) {
+    static uint8_t pixels[4 * WIDTH * HEIGHT];
+    RtScene *scene = rt_scene_new();
+    int floor = rt_material_lambertian(scene, 0.5f, 0.5f, 0.5f);
+    int glass = rt_material_dielectric(scene, 1.5f);
+    int mirror = rt_material_metal(scene, 0.9f, 0.8f, 0.7f, 0.0f);
+    int lamp = rt_material_light(scene, 8.0f, 8.0f, 8.0f);
+
+    const float ground[3] = {0.0f, -1000.0f, 0.0f}, ball[3] = {-1.2f, 1.0f, 0.0f}, light[3] = {0.0f, 6.0f, 2.0f};
+    rt_scene_add_sphere(scene, floor, ground, 1000.0f);
+    rt_scene_add_sphere(scene, glass, ball, 1.0f);
+    rt_scene_add_sphere(scene, lamp, light, 1.0f);
+
+    const float corners[] = {1.0f, 0.0f, -1.0f, 2.5f, 0.0f, 1.0f, 0.5f, 0.0f, 1.0f, 1.3f, 1.8f, 0.3f};
+    const uint32_t triangles[] = {0, 2, 1, 0, 1, 3, 1, 2, 3, 2, 0, 3};
+    if (rt_scene_add_mesh(scene, mirror, corners, 4, triangles, 12) != 0) {
+        fprintf(stderr, "Failed to add the mesh: %s\n", rt_last_error());
+        return 1;
+    }
+
+    const float lookfrom[3] = {0.0f, 2.0f, 8.0f}, lookat[3] = {0.0f, 0.8f, 0.0f};
+    rt_scene_set_camera(scene, lookfrom, lookat, 35.0f, 0.0f, 8.0f);
+    rt_scene_set_background(scene, 0.1f, 0.12f, 0.2f);
+    if (rt_render(scene, WIDTH, HEIGHT, 64, 20, pixels) != 0) {
+        fprintf(stderr, "Failed to render: %s\n", rt_last_error());
+        return 1;
+    }
+    rt_scene_free(scene);
+
+    FILE *file = fopen(argc > 1 ? argv[1] : "image.ppm", "wb");
+    if (!file) {
+        perror("Failed to save the image");
+        return 1;
+    }
+    fprintf(file, "P6\n%d %d\n255\n", WIDTH, HEIGHT);
+    for (int i = 0; i < WIDTH * HEIGHT; i++) {
+        fwrite(&pixels[4 * i], 1, 3, file);
+    }
+    fclose(file);
+    return 0;
+}
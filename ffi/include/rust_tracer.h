@@ -0,0 +1,155 @@
+/* Generated from ffi/src/lib.rs by cbindgen; don't edit by hand. */
+
+#ifndef RUST_TRACER_H
+#define RUST_TRACER_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ *Scene being built up from C, holding its objects (with whether each is sampled as a light), its camera, the materials objects can use
+ *
+ * along with their textures, and the color of the background. Separate scenes can be used on separate threads at once, but one scene can't.
+ */
+typedef struct RtScene RtScene;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ *Returns the message of the last call on this thread that failed, which stays valid until the next call that fails, or an empty string.
+ */
+const char *rt_last_error(void);
+
+/**
+ *Creates an empty scene, with a black background and the camera settings of the command line's defaults, to be freed with rt_scene_free().
+ */
+struct RtScene *rt_scene_new(void);
+
+/**
+ *Frees a scene created with rt_scene_new(), along with its materials and textures. Null is ignored.
+ *
+ * # Safety
+ * The scene must have come from rt_scene_new() and not have been freed already.
+ */
+void rt_scene_free(struct RtScene *scene);
+
+/**
+ *Adds a diffuse material of the given color, returning its index for objects to use, or -1 if the scene is null.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new().
+ */
+int32_t rt_material_lambertian(struct RtScene *scene,
+                               float r,
+                               float g,
+                               float b);
+
+/**
+ *Adds a metal of the given color and fuzziness (0 for a mirror), returning its index, or -1 if the scene is null.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new().
+ */
+int32_t rt_material_metal(struct RtScene *scene,
+                          float r,
+                          float g,
+                          float b,
+                          float fuzz);
+
+/**
+ *Adds a clear dielectric, such as glass or water, with the given index of refraction, returning its index, or -1 if the scene is null.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new().
+ */
+int32_t rt_material_dielectric(struct RtScene *scene,
+                               float index_of_refraction);
+
+/**
+ *Adds a light giving off the given radiance, returning its index, or -1 if the scene is null. Objects made of it are sampled as lights.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new().
+ */
+int32_t rt_material_light(struct RtScene *scene,
+                          float r,
+                          float g,
+                          float b);
+
+/**
+ *Adds a sphere made of a material added to the scene, returning 0, or -1 if the scene is null or the material doesn't exist.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new(), and center must point to 3 floats.
+ */
+int32_t rt_scene_add_sphere(struct RtScene *scene,
+                            int32_t material,
+                            const float *center,
+                            float radius);
+
+/**
+ *Adds a triangle mesh made of a material added to the scene, returning 0, or -1 if the scene is null, the material doesn't exist
+ *
+ * or the triangles use vertices that don't exist. Positions holds the x, y and z of vertex_count vertices, and indices holds
+ * index_count indices of vertices, three to a triangle, whose corners wind counterclockwise around the side it faces.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new(), positions must point to 3 * vertex_count floats and indices to index_count indices.
+ */
+int32_t rt_scene_add_mesh(struct RtScene *scene,
+                          int32_t material,
+                          const float *positions,
+                          uintptr_t vertex_count,
+                          const uint32_t *indices,
+                          uintptr_t index_count);
+
+/**
+ *Places the camera at lookfrom, looking at lookat with +y up, with the given vertical field of view in degrees, returning 0,
+ *
+ * or -1 if the scene is null. An aperture above 0 blurs whatever isn't focus_distance away.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new(), and lookfrom and lookat must point to 3 floats.
+ */
+int32_t rt_scene_set_camera(struct RtScene *scene,
+                            const float *lookfrom,
+                            const float *lookat,
+                            float vfov,
+                            float aperture,
+                            float focus_distance);
+
+/**
+ *Lights the scene with a background of the given radiance in every direction, returning 0, or -1 if the scene is null.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new().
+ */
+int32_t rt_scene_set_background(struct RtScene *scene,
+                                float r,
+                                float g,
+                                float b);
+
+/**
+ *Path traces the scene on every core, width by height pixels with samples_per_pixel samples and paths up to max_depth bounces long,
+ *
+ * writing 8 bit sRGB RGBA values into pixels, top row first, and returning 0, or -1 if the scene is null or can't be built.
+ *
+ * # Safety
+ * The scene must be null or a live scene from rt_scene_new(), and pixels must point to 4 * width * height bytes.
+ */
+int32_t rt_render(const struct RtScene *scene,
+                  uint32_t width,
+                  uint32_t height,
+                  uint32_t samples_per_pixel,
+                  uint32_t max_depth,
+                  uint8_t *pixels);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RUST_TRACER_H */
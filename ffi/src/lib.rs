@@ -0,0 +1,293 @@
+/*
+Module to store the C interface of the tracer, for embedding it in C and C++ programs such as game editors. Scenes are built up from materials,
+spheres and triangle meshes through an opaque RtScene handle, and rendered into a buffer the caller owns. The header, include/rust_tracer.h,
+is generated from this file by build.rs with cbindgen (into the build directory, with a warning when the copy in include/ is out of date),
+and linking against the static or shared library built from this crate is all that's needed.
+
+Functions that can fail return a negative number (or null), with rt_last_error() describing why, including when the tracer panics, which is caught
+rather than unwound into C. Each scene holds its own objects, materials and textures, so separate scenes can be built and rendered on separate threads
+at once; a single scene must not be used from several threads at once. The tracer's few process-wide settings (the random seed, the self-intersection
+epsilon and the asset cache directory) are shared by every scene, and this interface leaves them at their defaults.
+*/
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use rust_tracer::vec3::{Vec3, Point3, Color, Float};
+use rust_tracer::materials::Material;
+use rust_tracer::textures::{Texture, Textures};
+use rust_tracer::hitting::Hittable;
+use rust_tracer::camera::{Camera, StandardCamera};
+use rust_tracer::environment::Environment;
+use rust_tracer::accelerator::AcceleratorKind;
+use rust_tracer::integrator::{Integrator, Clamping};
+use rust_tracer::scene_file::{SceneFile, FileSettings};
+use rust_tracer::render::{Renderer, RenderSettings, SaveSettings, get_color};
+
+thread_local! {
+    ///Why the last call on this thread that failed did, for rt_last_error().
+    static LAST_ERROR : RefCell<CString> = RefCell::new(CString::default());
+}
+
+///Scene being built up from C, holding its objects (with whether each is sampled as a light), its camera, the materials objects can use
+///
+/// along with their textures, and the color of the background. Separate scenes can be used on separate threads at once, but one scene can't.
+pub struct RtScene {
+    file : SceneFile,
+    materials : Vec<Material>,
+    background : Option<Color>,
+}
+
+///Records why a call failed, returning the value it fails with.
+fn fail<T>(message : &str, value : T) -> T {
+    LAST_ERROR.with(|error| *error.borrow_mut() = CString::new(message.replace('\0', "")).unwrap_or_default());
+    value
+}
+
+///Runs the body of a C function, turning a panic in it into a failure with the given value, since unwinding into C is undefined behavior.
+fn guard<T>(value : T, body : impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().copied().or_else(|| payload.downcast_ref::<String>().map(String::as_str));
+            fail(&format!("Panicked: {}", message.unwrap_or("unknown reason")), value)
+        },
+    }
+}
+
+///Reads a point from 3 floats, or returns None if p is null.
+unsafe fn point(p : *const f32) -> Option<Point3> {
+    (!p.is_null()).then(|| Point3::new(*p as Float, *p.add(1) as Float, *p.add(2) as Float))
+}
+
+///Adds a material to a scene, returning its index.
+unsafe fn add_material(scene : *mut RtScene, material : Material) -> i32 {
+    match scene.as_mut() {
+        Some(scene) => {
+            scene.materials.push(material);
+            scene.materials.len() as i32 - 1
+        },
+        None => fail("The scene is null", -1),
+    }
+}
+
+///Adds a material made of a texture, adding the texture to the scene along with it, and returns the material's index.
+unsafe fn add_textured(scene : *mut RtScene, texture : Texture, material : fn(usize) -> Material) -> i32 {
+    match scene.as_mut() {
+        Some(scene) => {
+            let texture = scene.file.textures.add(texture);
+            add_material(scene, material(texture))
+        },
+        None => fail("The scene is null", -1),
+    }
+}
+
+///Returns the message of the last call on this thread that failed, which stays valid until the next call that fails, or an empty string.
+#[no_mangle]
+pub extern "C" fn rt_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|error| error.borrow().as_ptr())
+    })
+}
+
+///Creates an empty scene, with a black background and the camera settings of the command line's defaults, to be freed with rt_scene_free().
+#[no_mangle]
+pub extern "C" fn rt_scene_new() -> *mut RtScene {
+    guard(ptr::null_mut(), || {
+        let file = SceneFile {settings : FileSettings::default(), textures : Textures::new(), objects : vec![], lights : vec![]};
+        Box::into_raw(Box::new(RtScene {file, materials : vec![], background : None}))
+    })
+}
+
+///Frees a scene created with rt_scene_new(), along with its materials and textures. Null is ignored.
+///
+/// # Safety
+/// The scene must have come from rt_scene_new() and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_free(scene : *mut RtScene) {
+    guard((), || {
+        if !scene.is_null() {
+            drop(Box::from_raw(scene));
+        }
+    })
+}
+
+///Adds a diffuse material of the given color, returning its index for objects to use, or -1 if the scene is null.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new().
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_lambertian(scene : *mut RtScene, r : f32, g : f32, b : f32) -> i32 {
+    guard(-1, || {
+        add_textured(scene, Texture::Solid(Color::new(r as Float, g as Float, b as Float)), Material::Lambertian)
+    })
+}
+
+///Adds a metal of the given color and fuzziness (0 for a mirror), returning its index, or -1 if the scene is null.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new().
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_metal(scene : *mut RtScene, r : f32, g : f32, b : f32, fuzz : f32) -> i32 {
+    guard(-1, || {
+        add_material(scene, Material::Metal(Color::new(r as Float, g as Float, b as Float), fuzz as Float))
+    })
+}
+
+///Adds a clear dielectric, such as glass or water, with the given index of refraction, returning its index, or -1 if the scene is null.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new().
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_dielectric(scene : *mut RtScene, index_of_refraction : f32) -> i32 {
+    guard(-1, || {
+        add_material(scene, Material::Dielectric(Color::new(1.0, 1.0, 1.0), index_of_refraction as Float, 0.0, 0))
+    })
+}
+
+///Adds a light giving off the given radiance, returning its index, or -1 if the scene is null. Objects made of it are sampled as lights.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new().
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_light(scene : *mut RtScene, r : f32, g : f32, b : f32) -> i32 {
+    guard(-1, || {
+        add_textured(scene, Texture::Solid(Color::new(r as Float, g as Float, b as Float)), Material::Light)
+    })
+}
+
+///Adds a sphere made of a material added to the scene, returning 0, or -1 if the scene is null or the material doesn't exist.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new(), and center must point to 3 floats.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_sphere(scene : *mut RtScene, material : i32, center : *const f32, radius : f32) -> i32 {
+    guard(-1, || {
+        let (Some(scene), Some(center)) = (scene.as_mut(), point(center)) else {
+            return fail("The scene or center is null", -1);
+        };
+        let Some(material) = scene.materials.get(material as usize).copied() else {
+            return fail(&format!("Material {} doesn't exist", material), -1);
+        };
+        scene.file.objects.push((Hittable::Sphere(material, center, radius as Float), matches!(material, Material::Light(_))));
+        0
+    })
+}
+
+///Adds a triangle mesh made of a material added to the scene, returning 0, or -1 if the scene is null, the material doesn't exist
+///
+/// or the triangles use vertices that don't exist. Positions holds the x, y and z of vertex_count vertices, and indices holds
+/// index_count indices of vertices, three to a triangle, whose corners wind counterclockwise around the side it faces.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new(), positions must point to 3 * vertex_count floats and indices to index_count indices.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_mesh(scene : *mut RtScene, material : i32, positions : *const f32, vertex_count : usize,
+    indices : *const u32, index_count : usize) -> i32 {
+    guard(-1, || {
+        let Some(scene) = scene.as_mut() else {
+            return fail("The scene is null", -1);
+        };
+        let Some(material) = scene.materials.get(material as usize).copied() else {
+            return fail(&format!("Material {} doesn't exist", material), -1);
+        };
+        if positions.is_null() || indices.is_null() {
+            return fail("The positions or indices are null", -1);
+        }
+        let positions = slice::from_raw_parts(positions, 3 * vertex_count);
+        let indices = slice::from_raw_parts(indices, index_count);
+        if !index_count.is_multiple_of(3) || indices.iter().any(|index| *index as usize >= vertex_count) {
+            return fail("The mesh has triangles with corners that don't exist", -1);
+        }
+        let vertex = |index : u32| Point3::new(positions[3 * index as usize] as Float, positions[3 * index as usize + 1] as Float, positions[3 * index as usize + 2] as Float);
+        let emitter = matches!(material, Material::Light(_));
+        for triangle in indices.chunks_exact(3) {
+            let corners = [vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2])];
+            scene.file.objects.push((Hittable::Triangle(material, corners, [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]), emitter));
+        }
+        0
+    })
+}
+
+///Places the camera at lookfrom, looking at lookat with +y up, with the given vertical field of view in degrees, returning 0,
+///
+/// or -1 if the scene is null. An aperture above 0 blurs whatever isn't focus_distance away.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new(), and lookfrom and lookat must point to 3 floats.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_camera(scene : *mut RtScene, lookfrom : *const f32, lookat : *const f32, vfov : f32,
+    aperture : f32, focus_distance : f32) -> i32 {
+    guard(-1, || {
+        let (Some(scene), Some(lookfrom), Some(lookat)) = (scene.as_mut(), point(lookfrom), point(lookat)) else {
+            return fail("The scene, lookfrom or lookat is null", -1);
+        };
+        let settings = &mut scene.file.settings;
+        settings.lookfrom = Some(lookfrom);
+        settings.lookat = Some(lookat);
+        settings.vfov = Some(vfov as Float);
+        settings.aperture = Some(aperture as Float);
+        settings.focus_distance = Some(focus_distance as Float);
+        settings.autofocus = Some(false);
+        0
+    })
+}
+
+///Lights the scene with a background of the given radiance in every direction, returning 0, or -1 if the scene is null.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new().
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_background(scene : *mut RtScene, r : f32, g : f32, b : f32) -> i32 {
+    guard(-1, || {
+        match scene.as_mut() {
+            Some(scene) => {
+                scene.background = Some(Color::new(r as Float, g as Float, b as Float));
+                0
+            },
+            None => fail("The scene is null", -1),
+        }
+    })
+}
+
+///Path traces the scene on every core, width by height pixels with samples_per_pixel samples and paths up to max_depth bounces long,
+///
+/// writing 8 bit sRGB RGBA values into pixels, top row first, and returning 0, or -1 if the scene is null or can't be built.
+///
+/// # Safety
+/// The scene must be null or a live scene from rt_scene_new(), and pixels must point to 4 * width * height bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render(scene : *const RtScene, width : u32, height : u32, samples_per_pixel : u32, max_depth : u32, pixels : *mut u8) -> i32 {
+    guard(-1, || {
+        let Some(scene) = scene.as_ref() else {
+            return fail("The scene is null", -1);
+        };
+        if pixels.is_null() || width == 0 || height == 0 {
+            return fail("The pixel buffer is null or empty", -1);
+        }
+        let environment = scene.background.map(|c| Environment::new(vec![c.x, c.y, c.z], 1, 1, 1.0));
+        let mut world = match scene.file.scene(environment, AcceleratorKind::Bvh) {
+            Ok(world) => world,
+            Err(error) => return fail(&error.to_string(), -1),
+        };
+
+        let file = &scene.file.settings;
+        let aspect_ratio = width as Float / height as Float;
+        let camera = StandardCamera::new(file.lookfrom.unwrap_or(Point3::new(278.0, 278.0, -800.0)), file.lookat.unwrap_or(Point3::new(278.0, 278.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0), file.vfov.unwrap_or(40.0), aspect_ratio, file.aperture.unwrap_or(0.0), file.focus_distance.unwrap_or(20.0));
+        let cameras : Vec<Box<dyn Camera>> = vec![Box::new(camera)];
+        let integrator = Integrator::PathTracer(max_depth.min(i32::MAX as u32) as i32, Clamping::None);
+        let renderer = Renderer::new(RenderSettings::new(width, height, samples_per_pixel.clamp(1, i32::MAX as u32) as i32, integrator));
+        let image = renderer.render(&mut world, &cameras);
+
+        let save = SaveSettings::default();
+        let pixels = slice::from_raw_parts_mut(pixels, 4 * width as usize * height as usize);
+        for (rgba, (pixel, alpha)) in pixels.chunks_exact_mut(4).zip(image.pixels()) {
+            let (r, g, b) = if alpha > 0.0 {get_color(pixel / alpha, save.exposure, save.tone_map, save.transform)} else {(0, 0, 0)};
+            rgba.copy_from_slice(&[r, g, b, 255]);
+        }
+        0
+    })
+}
@@ -0,0 +1,206 @@
+/*
+Module to store acceleration structures, which find the objects a ray hits without testing every object in the scene.
+*/
+
+use crate::hitting::{Hittable, HitRecord};
+use crate::bvh::AABB;
+use crate::ray::Ray;
+use crate::tree::{Tree, MAX_PACKET};
+use crate::bvh4::Bvh4;
+use crate::kdtree::KdTree;
+use crate::flat::FlatScene;
+use crate::sbvh;
+use crate::error::{Error, Result};
+use crate::textures::{Texture, Textures};
+use std::cell::Cell;
+use web_time::Instant;
+use tracing::{info, info_span};
+#[cfg(feature = "gpu")]
+use tracing::warn;
+#[cfg(feature = "gpu")]
+use crate::gpu::GpuTree;
+use crate::vec3::Float;
+
+thread_local! {
+    ///Number of rays this thread has traced through any acceleration structure.
+    static RAYS : Cell<u64> = const { Cell::new(0) };
+}
+
+///Returns how many rays the calling thread has traced so far, camera, bounce and shadow rays alike.
+pub fn rays_traced() -> u64 {
+    RAYS.with(|rays| rays.get())
+}
+
+///Determines which acceleration structure holds the scene's objects. Variants include
+///
+/// Bvh: a Bounding Volume Hierarchy, which splits the list of objects in half at every level. Quick to build, and fast for most scenes.
+///
+/// SpatialBvh: a Bounding Volume Hierarchy split by the surface area heuristic, which may also split objects through space
+/// so that long, thin and large ones don't inflate the boxes of the nodes around them. Slower to build, but faster to trace in scenes with such objects.
+///
+/// Bvh4: the same hierarchy collapsed to four children per node, whose boxes are tested together with SIMD instructions. Usually the fastest.
+///
+/// KdTree: a kd-tree, which splits space with the planes the surface area heuristic finds cheapest. Slower to build,
+/// but can be faster for scenes with many large, overlapping objects such as rectangles and media.
+///
+/// Flat: the same hierarchy as Bvh, flattened with its spheres and triangles into structures of arrays. Finds the same hits as Bvh,
+/// faster in scenes of tens of thousands of spheres or triangles, whose nodes and objects no longer fit in cache.
+///
+/// Gpu: the same hierarchy as Bvh, also uploaded to the GPU (with the gpu feature, see gpu.rs), which finds the first hits of packets of camera rays
+/// and traces the shadow rays towards lights. Falls back to Bvh, with a warning, on machines without a GPU or for scenes with media or custom shapes.
+#[derive(Debug, Clone, Copy)]
+pub enum AcceleratorKind {
+    Bvh,
+    SpatialBvh,
+    Bvh4,
+    KdTree,
+    Flat,
+    #[cfg(feature = "gpu")]
+    Gpu,
+}
+
+impl AcceleratorKind {
+
+    ///Builds an acceleration structure of this kind over the given objects. Fails with Error::Scene if an object uses a texture
+    ///
+    /// that isn't in the scene's textures, or a region of a texture added after it, which would otherwise only be found once a ray hit it.
+    pub fn build(&self, objects : &mut [Hittable], textures : &Textures) -> Result<Accelerator> {
+        let _span = info_span!("build_accelerator", kind = ?self, objects = objects.len()).entered();
+        for (index, object) in objects.iter().enumerate() {
+            for texture_id in object.texture_ids(textures) {
+                match textures.get(texture_id) {
+                    None => return Err(Error::Scene(format!("object {} uses texture {}, but only {} textures have been added", index, texture_id, textures.len()))),
+                    Some(Texture::Region(inner, ..)) if *inner >= texture_id => {
+                        return Err(Error::Scene(format!("object {} uses texture {}, a region of texture {}, which wasn't added before it", index, texture_id, inner)));
+                    },
+                    Some(_) => {},
+                }
+            }
+        }
+        let start = Instant::now();
+        let accelerator = match self {
+            AcceleratorKind::Bvh => Accelerator::Bvh(Tree::build(objects)?),
+            AcceleratorKind::SpatialBvh => Accelerator::Bvh(sbvh::build(objects)?),
+            AcceleratorKind::Bvh4 => Accelerator::Bvh4(Bvh4::build(objects)?),
+            AcceleratorKind::KdTree => Accelerator::KdTree(KdTree::build(objects)?),
+            AcceleratorKind::Flat => Accelerator::Flat(Box::new(FlatScene::build(objects)?)),
+            #[cfg(feature = "gpu")]
+            AcceleratorKind::Gpu => match GpuTree::new(Tree::build(objects)?) {
+                Ok(tree) => {
+                    info!(adapter = tree.adapter(), "uploaded the scene to the GPU");
+                    Accelerator::Gpu(Box::new(tree))
+                },
+                Err((tree, error)) => {
+                    warn!("{}; tracing on the CPU instead", error);
+                    Accelerator::Bvh(tree)
+                },
+            },
+        };
+        info!(elapsed = ?start.elapsed(), "built acceleration structure");
+        Ok(accelerator)
+    }
+}
+
+///Acceleration structure holding the objects of a scene, built with AcceleratorKind::build().
+#[derive(Debug, Clone)]
+pub enum Accelerator {
+    Bvh(Tree),
+    Bvh4(Bvh4),
+    KdTree(KdTree),
+    Flat(Box<FlatScene>),
+    #[cfg(feature = "gpu")]
+    Gpu(Box<GpuTree>),
+}
+
+impl Accelerator {
+
+    ///Determines if a ray hits any object, filling in rec with the closest hit. Media look their density up in the given textures.
+    pub fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, textures : &Textures) -> bool {
+        RAYS.with(|rays| rays.set(rays.get() + 1));
+        match self {
+            Accelerator::Bvh(tree) => tree.hit(r, t_min, t_max, rec, tree.root, textures),
+            Accelerator::Bvh4(tree) => tree.hit(r, t_min, t_max, rec, textures),
+            Accelerator::KdTree(tree) => tree.hit(r, t_min, t_max, rec, textures),
+            Accelerator::Flat(scene) => scene.hit(r, t_min, t_max, rec, textures),
+            #[cfg(feature = "gpu")]
+            Accelerator::Gpu(gpu) => gpu.tree.hit(r, t_min, t_max, rec, gpu.tree.root, textures),
+        }
+    }
+
+    ///Determines which of a packet of at most tree::MAX_PACKET rays hit any object, filling in recs with each ray's closest hit,
+    ///
+    /// and returns a bitmask of the rays that hit. Only the Bounding Volume Hierarchy traces packets together; the rest trace each ray on its own.
+    pub fn hit_packet(&self, rays : &[Ray], t_min : Float, t_max : Float, recs : &mut [HitRecord], textures : &Textures) -> u32 {
+        match self {
+            Accelerator::Bvh(tree) => {
+                RAYS.with(|count| count.set(count.get() + rays.len().min(MAX_PACKET) as u64));
+                tree.hit_packet(rays, t_min, t_max, recs, textures)
+            },
+            #[cfg(feature = "gpu")]
+            Accelerator::Gpu(gpu) => {
+                RAYS.with(|count| count.set(count.get() + rays.len().min(MAX_PACKET) as u64));
+                gpu.hit_packet(rays, t_min, t_max, recs, textures)
+            },
+            _ => {
+                let mut hits = 0;
+                for (k, (r, rec)) in rays.iter().zip(recs.iter_mut()).take(MAX_PACKET).enumerate() {
+                    if self.hit(*r, t_min, t_max, rec, textures) {
+                        hits |= 1 << k;
+                    }
+                }
+                hits
+            },
+        }
+    }
+
+    ///Determines which of a packet of at most tree::MAX_PACKET shadow rays are blocked by any object before their own t_max,
+    ///
+    /// returning a bitmask of them. Only the Bounding Volume Hierarchy traces packets together; the rest trace each ray on its own.
+    pub fn occluded_packet(&self, rays : &[Ray], t_min : Float, t_max : &[Float], textures : &Textures) -> u32 {
+        match self {
+            Accelerator::Bvh(tree) => {
+                RAYS.with(|count| count.set(count.get() + rays.len().min(MAX_PACKET) as u64));
+                tree.occluded_packet(rays, t_min, t_max, textures)
+            },
+            #[cfg(feature = "gpu")]
+            Accelerator::Gpu(gpu) => {
+                RAYS.with(|count| count.set(count.get() + rays.len().min(MAX_PACKET) as u64));
+                gpu.occluded_packet(rays, t_min, t_max)
+            },
+            _ => {
+                let mut blocked = 0;
+                let mut rec = HitRecord::new();
+                for (k, (r, t_max)) in rays.iter().zip(t_max).take(MAX_PACKET).enumerate() {
+                    if self.hit(*r, t_min, *t_max, &mut rec, textures) {
+                        blocked |= 1 << k;
+                    }
+                }
+                blocked
+            },
+        }
+    }
+
+    ///Returns the object at the given index, as recorded in the HitRecords of hits on it.
+    pub fn object(&self, index : usize) -> Option<&Hittable> {
+        match self {
+            Accelerator::Bvh(tree) => tree.items.get(index).and_then(|node| node.data.as_ref()),
+            Accelerator::Bvh4(tree) => tree.objects.get(index),
+            Accelerator::KdTree(tree) => tree.objects.get(index),
+            Accelerator::Flat(scene) => scene.objects.get(index),
+            #[cfg(feature = "gpu")]
+            Accelerator::Gpu(gpu) => gpu.tree.items.get(index).and_then(|node| node.data.as_ref()),
+        }
+    }
+
+    ///Returns the bounding box surrounding every object, or None if there are none.
+    pub fn bounding_box(&self) -> Option<AABB> {
+        match self {
+            Accelerator::Bvh(tree) => tree.bounding_box(),
+            Accelerator::Bvh4(tree) => tree.bounding_box(),
+            Accelerator::KdTree(tree) => tree.bounding_box(),
+            Accelerator::Flat(scene) => scene.bounding_box(),
+            #[cfg(feature = "gpu")]
+            Accelerator::Gpu(gpu) => gpu.tree.bounding_box(),
+        }
+    }
+}
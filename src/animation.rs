@@ -0,0 +1,95 @@
+/*
+Module to store camera paths, which move the camera smoothly between keyframes over the frames of an animation, and helpers to move objects over time.
+*/
+
+use crate::vec3::{Vec3, Point3, dot, cross, Float};
+
+///Where the camera is, what it looks at, and its vertical field of view (in degrees) at a given frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub frame : Float,
+    pub lookfrom : Point3,
+    pub lookat : Point3,
+    pub vfov : Float,
+}
+
+impl Keyframe {
+
+    ///Creates a keyframe placing the camera at lookfrom, looking at lookat with the given field of view, at the given frame.
+    pub fn new(frame : Float, lookfrom : Point3, lookat : Point3, vfov : Float) -> Keyframe {
+        Keyframe {frame, lookfrom, lookat, vfov}
+    }
+}
+
+///Camera path through a list of keyframes. Between keyframes, the camera follows a Catmull-Rom spline, which passes through
+///
+/// every keyframe without sudden changes of direction or speed. Before the first keyframe and after the last, it holds still.
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+    pub keyframes : Vec<Keyframe>,
+}
+
+impl CameraPath {
+
+    ///Creates a path through the given keyframes, in any order.
+    pub fn new(mut keyframes : Vec<Keyframe>) -> CameraPath {
+        keyframes.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        CameraPath {keyframes}
+    }
+
+    ///Returns the camera's lookfrom, lookat and vertical field of view at the given frame.
+    pub fn at(&self, frame : Float) -> (Point3, Point3, Float) {
+        let keys = &self.keyframes;
+        let n = keys.len();
+        if n == 0 {
+            return (Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), 40.0);
+        }
+        if frame <= keys[0].frame {
+            return (keys[0].lookfrom, keys[0].lookat, keys[0].vfov);
+        }
+        if frame >= keys[n - 1].frame {
+            return (keys[n - 1].lookfrom, keys[n - 1].lookat, keys[n - 1].vfov);
+        }
+
+        let i = keys.iter().rposition(|key| key.frame <= frame).unwrap_or(0).min(n - 2);
+        let (k0, k1, k2, k3) = (keys[i.saturating_sub(1)], keys[i], keys[i + 1], keys[(i + 2).min(n - 1)]);
+        let t = (frame - k1.frame) / (k2.frame - k1.frame);
+        let spline = |p0 : Vec3, p1 : Vec3, p2 : Vec3, p3 : Vec3| catmull_rom(p0, p1, p2, p3, [k0.frame, k1.frame, k2.frame, k3.frame], t);
+
+        let vfov = |key : Keyframe| Vec3::new(key.vfov, 0.0, 0.0);
+        (
+            spline(k0.lookfrom, k1.lookfrom, k2.lookfrom, k3.lookfrom),
+            spline(k0.lookat, k1.lookat, k2.lookat, k3.lookat),
+            spline(vfov(k0), vfov(k1), vfov(k2), vfov(k3)).x,
+        )
+    }
+}
+
+///Evaluates the Catmull-Rom spline between p1 and p2 at t (from 0 to 1), given the frames of all four points.
+///
+/// Tangents are scaled by the frames between keyframes, so the speed stays smooth even when keyframes are unevenly spaced.
+fn catmull_rom(p0 : Vec3, p1 : Vec3, p2 : Vec3, p3 : Vec3, frames : [Float ; 4], t : Float) -> Vec3 {
+    let span = frames[2] - frames[1];
+    let tangent = |before : Vec3, after : Vec3, from : Float, to : Float| {
+        if to > from {(after - before) * (span / (to - from))} else {Vec3::new(0.0, 0.0, 0.0)}
+    };
+    let m1 = tangent(p0, p2, frames[0], frames[2]);
+    let m2 = tangent(p1, p3, frames[1], frames[3]);
+
+    //Cubic Hermite basis
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p1 * (2.0 * t3 - 3.0 * t2 + 1.0) + m1 * (t3 - 2.0 * t2 + t) + p2 * (-2.0 * t3 + 3.0 * t2) + m2 * (t3 - t2)
+}
+
+///Returns where a point ends up after orbiting center by angle radians, counterclockwise about axis (seen from where it points),
+///
+/// for placing objects that spin or circle around something at a given frame, as on a turntable. An angle of 0 leaves the point where it is.
+pub fn orbit(point : Point3, center : Point3, axis : Vec3, angle : Float) -> Point3 {
+    let k = axis.unit_vector();
+    let p = point - center;
+    let (sin, cos) = angle.sin_cos();
+
+    //Rodrigues' rotation formula
+    center + p * cos + cross(k, p) * sin + k * (dot(k, p) * (1.0 - cos))
+}
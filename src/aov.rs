@@ -0,0 +1,391 @@
+/*
+Module to store the auxiliary outputs (AOVs, or render passes) saved alongside the rendered image, for compositing and for denoisers.
+*/
+
+use std::ops::AddAssign;
+use crate::vec3::{Color, Vec3, Float};
+use crate::output::DeepSample;
+use crate::color::luminance;
+
+///Number of different IDs each pixel keeps the coverage of. Few pixels show more objects than this.
+const MAX_IDS : usize = 8;
+
+///Number of IDs saved for each pixel in the ID passes, from the one covering most of the pixel down.
+pub const ID_RANKS : usize = 3;
+
+///Mean luminance below which pixels' relative error is measured against this instead, as the error of black pixels would be endless.
+const MIN_LUMINANCE : Float = 0.01;
+
+///Depths of the same object within this fraction of each other are merged into one deep sample.
+const DEEP_MERGE : Float = 0.01;
+
+///Auxiliary image that can be saved alongside the rendered image. Variants include
+///
+/// Albedo: the color of the first surface each ray hits, without any lighting.
+///
+/// Normal: the outward shading normal of that surface in world space, from -1 to 1.
+///
+/// Depth: the distance from the camera to that surface, averaged over the samples that hit something, or 0 where none did.
+///
+/// Direct: light that reached that surface straight from a light source, the environment or an emissive object, and was reflected towards the camera.
+///
+/// Indirect: light reflected towards the camera after bouncing off other surfaces first.
+///
+/// Emission: light emitted by that surface, or by the environment where rays escape.
+///
+/// ObjectId: the IDs of the objects seen through each pixel (see Hittable::id()), with the fraction of the pixel each covers, most first.
+///
+/// MaterialId: the same for the materials of the objects seen (see Material::id()).
+///
+/// Variance: the variance of the pixel's mean luminance (how far it is likely to be from the noiseless value, squared), and its relative error,
+/// the standard deviation of the mean over the mean, which falls with the square root of the samples taken.
+///
+/// Emission, Direct and Indirect add up to the rendered image, and are only filled in by the path tracing integrators.
+/// In the ID passes, as in Cryptomatte, each ID's bits are stored as a float (see id_to_float()), and its coverage gives a matte
+/// that selects the object or material with antialiased edges. Pixels with fewer IDs than ID_RANKS have an ID and coverage of 0 in the rest.
+#[derive(Debug, Clone, Copy)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    Direct,
+    Indirect,
+    Emission,
+    ObjectId,
+    MaterialId,
+    Variance,
+}
+
+impl Aov {
+
+    ///Returns the name of the pass, used for its layer or file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Emission => "emission",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Variance => "variance",
+        }
+    }
+
+    ///Returns the names of the pass's channels.
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id0", "coverage0", "id1", "coverage1", "id2", "coverage2"],
+            Aov::Variance => &["variance", "relative_error"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    ///Returns whether the pass needs 32 bit floats: depths beyond a few hundred units lose too much precision as half floats, and IDs lose their bits.
+    pub fn full_precision(&self) -> bool {
+        matches!(self, Aov::Depth | Aov::ObjectId | Aov::MaterialId)
+    }
+}
+
+///Determines where passes are saved. Variants include
+///
+/// Layers: as extra layers of the rendered image, when it is saved as a .exr. Other formats get separate files instead.
+///
+/// Files: as separate .exr files next to the rendered image, named after it and the pass (imageTest.albedo.exr and so on).
+#[derive(Debug, Clone, Copy)]
+pub enum AovLayout {
+    Layers,
+    Files,
+}
+
+///Settings for the passes to save alongside the rendered image.
+#[derive(Debug, Clone)]
+pub struct AovSettings {
+    pub passes : Vec<Aov>,
+    pub layout : AovLayout,
+}
+
+impl AovSettings {
+
+    ///Creates settings saving the given passes with the given layout.
+    pub fn new(passes : Vec<Aov>, layout : AovLayout) -> AovSettings {
+        AovSettings {passes, layout}
+    }
+}
+
+///Values of every pass for one camera ray, or their sum over many. Coverage is 1 for a ray that hits something and 0 otherwise,
+///
+/// and depth is only added for rays that hit, so that the pixel's depth is depth / coverage. Deep holds the surfaces seen, for a deep image.
+/// Luminance and luminance_squared sum the luminance of every sample's color and its square, for the spread of the pixel's samples,
+/// and are added for every render, not just those collecting passes.
+#[derive(Debug, Clone, Copy)]
+pub struct AovSample {
+    pub albedo : Color,
+    pub normal : Vec3,
+    pub depth : Float,
+    pub coverage : Float,
+    pub direct : Color,
+    pub indirect : Color,
+    pub emission : Color,
+    pub objects : Coverage,
+    pub materials : Coverage,
+    pub deep : Deep,
+    pub luminance : Float,
+    pub luminance_squared : Float,
+}
+
+impl AovSample {
+
+    ///Creates a sample with every pass 0, as for a ray that hits nothing.
+    pub fn new() -> AovSample {
+        AovSample {
+            albedo : Color::new(0.0, 0.0, 0.0),
+            normal : Vec3::new(0.0, 0.0, 0.0),
+            depth : 0.0,
+            coverage : 0.0,
+            direct : Color::new(0.0, 0.0, 0.0),
+            indirect : Color::new(0.0, 0.0, 0.0),
+            emission : Color::new(0.0, 0.0, 0.0),
+            objects : Coverage::new(),
+            materials : Coverage::new(),
+            deep : Deep::new(),
+            luminance : 0.0,
+            luminance_squared : 0.0,
+        }
+    }
+
+    ///Adds a sample's color to the sums of luminance.
+    pub fn add_luminance(&mut self, color : Color) {
+        let l = luminance(color);
+        self.luminance += l;
+        self.luminance_squared += l * l;
+    }
+
+    ///Returns the variance of the mean luminance of a pixel, given how many samples it took, from the spread of its samples. A single sample gives 0.
+    pub fn variance(&self, samples : Float) -> Float {
+        if samples < 2.0 {
+            return 0.0;
+        }
+        let spread = (self.luminance_squared - self.luminance * self.luminance / samples) / (samples - 1.0);
+        spread.max(0.0) / samples
+    }
+
+    ///Returns the standard deviation of a pixel's mean luminance relative to that luminance, kept from blowing up in pixels near black.
+    pub fn relative_error(&self, samples : Float) -> Float {
+        self.variance(samples).sqrt() / (self.luminance / samples).max(MIN_LUMINANCE)
+    }
+
+    ///Fills in values with every channel of a pass for a pixel, given the sum of its samples and how many there were.
+    ///
+    /// The lighting passes are scaled by the exposure, as the rendered image is.
+    pub fn pass(&self, aov : Aov, samples : Float, exposure : Float, values : &mut [Float]) {
+        let color = match aov {
+            Aov::Albedo => self.albedo / samples,
+            Aov::Normal => self.normal / samples,
+            Aov::Depth => {
+                values[0] = if self.coverage > 0.0 {self.depth / self.coverage} else {0.0};
+                return;
+            },
+            Aov::Direct => self.direct * (exposure / samples),
+            Aov::Indirect => self.indirect * (exposure / samples),
+            Aov::Emission => self.emission * (exposure / samples),
+            Aov::ObjectId | Aov::MaterialId => {
+                let coverage = if matches!(aov, Aov::ObjectId) {&self.objects} else {&self.materials};
+                for (rank, (id, weight)) in coverage.ranked().into_iter().enumerate() {
+                    values[2 * rank] = if weight > 0.0 {id_to_float(id)} else {0.0};
+                    values[2 * rank + 1] = weight / samples;
+                }
+                return;
+            },
+            Aov::Variance => {
+                values[0] = self.variance(samples) * exposure * exposure;
+                values[1] = self.relative_error(samples);
+                return;
+            },
+        };
+        values.copy_from_slice(&[color.x, color.y, color.z]);
+    }
+}
+
+impl Default for AovSample {
+    fn default() -> AovSample {
+        AovSample::new()
+    }
+}
+
+impl AddAssign for AovSample {
+    fn add_assign(&mut self, other : AovSample) {
+        self.albedo += other.albedo;
+        self.normal += other.normal;
+        self.depth += other.depth;
+        self.coverage += other.coverage;
+        self.direct += other.direct;
+        self.indirect += other.indirect;
+        self.emission += other.emission;
+        self.objects += other.objects;
+        self.materials += other.materials;
+        self.deep += other.deep;
+        self.luminance += other.luminance;
+        self.luminance_squared += other.luminance_squared;
+    }
+}
+
+///How much of a pixel each of the IDs seen through it covers, as the number of samples that saw it.
+#[derive(Debug, Clone, Copy)]
+pub struct Coverage {
+    ids : [u32 ; MAX_IDS],
+    weights : [Float ; MAX_IDS],
+    count : usize,
+}
+
+impl Coverage {
+
+    ///Creates an empty coverage, as for a ray that hits nothing.
+    pub fn new() -> Coverage {
+        Coverage {ids : [0 ; MAX_IDS], weights : [0.0 ; MAX_IDS], count : 0}
+    }
+
+    ///Creates the coverage of a single sample that saw the given ID.
+    pub fn single(id : u32) -> Coverage {
+        let mut coverage = Coverage::new();
+        coverage.add(id, 1.0);
+        coverage
+    }
+
+    ///Adds weight to an ID's coverage. Once MAX_IDS different IDs have been seen, a new ID replaces the one with the least coverage, if it has more.
+    pub fn add(&mut self, id : u32, weight : Float) {
+        if let Some(i) = self.ids[..self.count].iter().position(|other| *other == id) {
+            self.weights[i] += weight;
+        } else if self.count < MAX_IDS {
+            self.ids[self.count] = id;
+            self.weights[self.count] = weight;
+            self.count += 1;
+        } else {
+            let smallest = (0..MAX_IDS).min_by(|a, b| self.weights[*a].total_cmp(&self.weights[*b])).unwrap();
+            if weight > self.weights[smallest] {
+                self.ids[smallest] = id;
+                self.weights[smallest] = weight;
+            }
+        }
+    }
+
+    ///Returns the ID_RANKS IDs with the most coverage, most first, with their coverage. Missing ranks are 0.
+    pub fn ranked(&self) -> [(u32, Float) ; ID_RANKS] {
+        let mut order : Vec<usize> = (0..self.count).collect();
+        order.sort_by(|a, b| self.weights[*b].total_cmp(&self.weights[*a]));
+        let mut ranked = [(0, 0.0) ; ID_RANKS];
+        for (rank, i) in order.into_iter().take(ID_RANKS).enumerate() {
+            ranked[rank] = (self.ids[i], self.weights[i]);
+        }
+        ranked
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new()
+    }
+}
+
+impl AddAssign for Coverage {
+    fn add_assign(&mut self, other : Coverage) {
+        for i in 0..other.count {
+            self.add(other.ids[i], other.weights[i]);
+        }
+    }
+}
+
+///Surfaces seen through a pixel, for a deep image: the ID of each one's object, the nearest and farthest depths it was seen at,
+///
+/// and the sum of the radiance and opacity of the samples that saw it. Samples of the same object at about the same depth are merged,
+/// so a pixel keeps one sample for each surface seen rather than one for each ray. Once MAX_IDS samples are kept,
+/// further ones are merged into the nearest in depth. Rays that hit nothing add no samples.
+#[derive(Debug, Clone, Copy)]
+pub struct Deep {
+    ids : [u32 ; MAX_IDS],
+    fronts : [Float ; MAX_IDS],
+    backs : [Float ; MAX_IDS],
+    colors : [Color ; MAX_IDS],
+    alphas : [Float ; MAX_IDS],
+    count : usize,
+}
+
+impl Deep {
+
+    ///Creates an empty list of samples, as for a ray that hits nothing.
+    pub fn new() -> Deep {
+        Deep {ids : [0 ; MAX_IDS], fronts : [0.0 ; MAX_IDS], backs : [0.0 ; MAX_IDS], colors : [Color::new(0.0, 0.0, 0.0) ; MAX_IDS], alphas : [0.0 ; MAX_IDS], count : 0}
+    }
+
+    ///Creates the sample of a single ray that hit the given object at the given depth, with the color and opacity seen along it.
+    pub fn single(id : u32, depth : Float, color : Color, alpha : Float) -> Deep {
+        let mut deep = Deep::new();
+        deep.add(id, depth, depth, color, alpha);
+        deep
+    }
+
+    ///Adds a sample of an object spanning the depths from front to back, merging it into a sample of the same object it overlaps, if any.
+    pub fn add(&mut self, id : u32, front : Float, back : Float, color : Color, alpha : Float) {
+        let overlaps = |i : usize| self.ids[i] == id && front <= self.backs[i] * (1.0 + DEEP_MERGE) && back >= self.fronts[i] * (1.0 - DEEP_MERGE);
+        let i = match (0..self.count).find(|i| overlaps(*i)) {
+            Some(i) => i,
+            None if self.count < MAX_IDS => {
+                self.ids[self.count] = id;
+                self.fronts[self.count] = front;
+                self.backs[self.count] = back;
+                self.count += 1;
+                self.count - 1
+            },
+            None => (0..MAX_IDS).min_by(|a, b| (self.fronts[*a] - front).abs().total_cmp(&(self.fronts[*b] - front).abs())).unwrap(),
+        };
+        self.fronts[i] = self.fronts[i].min(front);
+        self.backs[i] = self.backs[i].max(back);
+        self.colors[i] += color;
+        self.alphas[i] += alpha;
+    }
+
+    ///Returns the pixel's deep samples, nearest first, given how many rays were traced through it. Colors are scaled by the exposure,
+    ///
+    /// as the rendered image is, and like opacities, are the fraction of the pixel's light each sample gives, so they stay premultiplied.
+    pub fn samples(&self, samples : Float, exposure : Float) -> Vec<DeepSample> {
+        let mut deep : Vec<DeepSample> = (0..self.count).map(|i| DeepSample {
+            front : self.fronts[i],
+            back : self.backs[i],
+            color : self.colors[i] * (exposure / samples),
+            alpha : self.alphas[i] / samples,
+        }).collect();
+        deep.sort_by(|a, b| a.front.total_cmp(&b.front));
+        deep
+    }
+}
+
+impl Default for Deep {
+    fn default() -> Deep {
+        Deep::new()
+    }
+}
+
+impl AddAssign for Deep {
+    fn add_assign(&mut self, other : Deep) {
+        for i in 0..other.count {
+            self.add(other.ids[i], other.fronts[i], other.backs[i], other.colors[i], other.alphas[i]);
+        }
+    }
+}
+
+///Hashes a description of an object or material into an ID (32 bit FNV-1a), which is the same on every run.
+pub fn stable_id(description : &str) -> u32 {
+    description.bytes().fold(0x811c9dc5, |hash : u32, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
+
+///Stores an ID's bits as a float, as Cryptomatte does. IDs whose bits would make an infinite, NaN or denormal float
+///
+/// have a bit of their exponent flipped, so that the float survives being read and written by compositing software.
+pub fn id_to_float(id : u32) -> Float {
+    let exponent = (id >> 23) & 0xff;
+    let id = if exponent == 0 || exponent == 0xff {id ^ (1 << 23)} else {id};
+    f32::from_bits(id) as Float
+}
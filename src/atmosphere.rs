@@ -0,0 +1,213 @@
+/*
+Module to store the physically based atmosphere, which works out the color of the sky, sunsets and the haze of distant objects (aerial perspective)
+by following light from the sun as it scatters off the air (Rayleigh scattering, which makes the sky blue) and off aerosols such as dust and water droplets
+(Mie scattering, which makes the glow around the sun). Only light scattering once is followed, after Nishita et al.,
+which is close to the full answer except in twilight.
+
+The air thins out exponentially with height above a spherical planet. Lengths are in meters, and scene units are turned into meters with Atmosphere::scale.
+*/
+
+use crate::vec3::consts::PI;
+use crate::vec3::{Vec3, Color, dot, Float, wide};
+use crate::ray::Ray;
+use crate::environment::Environment;
+
+///Steps taken along a view ray, and along the path from each of them towards the sun, to add up the light scattered along it.
+const VIEW_STEPS : usize = 16;
+const SUN_STEPS : usize = 8;
+
+///Planet's atmosphere, lit by the sun from sun_direction (which is towards the sun, with y up). Scattering coefficients are per meter at the ground,
+///
+/// where rayleigh_scattering is how strongly air scatters red, green and blue light, and mie_scattering how strongly aerosols do (absorbing a tenth as much again),
+/// thinning out with height by a factor of e every rayleigh_height and mie_height meters. Mie_g is the Henyey-Greenstein anisotropy of the aerosols, from 0 (scattering
+/// evenly) to nearly 1 (scattering forwards, making a bright halo around the sun). The scene's origin is altitude meters above the ground, a scene unit is scale meters,
+/// and the ground below the horizon has the color ground_albedo. Sun_intensity is the irradiance of sunlight above the atmosphere, so that an environment intensity of 1
+/// gives a reasonable daylight exposure with its default of 20.
+#[derive(Debug, Clone, Copy)]
+pub struct Atmosphere {
+    pub planet_radius : Float,
+    pub height : Float,
+    pub rayleigh_height : Float,
+    pub mie_height : Float,
+    pub rayleigh_scattering : Color,
+    pub mie_scattering : Float,
+    pub mie_g : Float,
+    pub sun_direction : Vec3,
+    pub sun_intensity : Float,
+    pub altitude : Float,
+    pub scale : Float,
+    pub ground_albedo : Color,
+}
+
+impl Atmosphere {
+
+    ///Creates the Earth's atmosphere on a clear day, lit by a sun in the given direction.
+    pub fn earth(sun_direction : Vec3) -> Atmosphere {
+        Atmosphere {
+            planet_radius : 6_360_000.0,
+            height : 60_000.0,
+            rayleigh_height : 7994.0,
+            mie_height : 1200.0,
+            rayleigh_scattering : Color::new(5.8e-6, 13.5e-6, 33.1e-6),
+            mie_scattering : 21e-6,
+            mie_g : 0.76,
+            sun_direction : sun_direction.unit_vector(),
+            sun_intensity : 20.0,
+            altitude : 1.0,
+            scale : 1.0,
+            ground_albedo : Color::new(0.3, 0.3, 0.3),
+        }
+    }
+
+    ///Returns the light reaching the scene's origin from a direction: the light the sky scatters towards it, along with the lit ground
+    ///
+    /// (seen through the air in between) below the horizon. The sun's disc is left out.
+    pub fn radiance(&self, direction : Vec3) -> Color {
+        let origin = self.planet_point(Vec3::new(0.0, 0.0, 0.0));
+        let direction = direction.unit_vector();
+        let top = self.planet_radius + self.height;
+        let Some((_, exit)) = sphere_distances(origin, direction, top) else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+        match sphere_distances(origin, direction, self.planet_radius).filter(|(ground, _)| *ground > 0.0) {
+            Some((ground, _)) => {
+                let (transmittance, inscattered) = self.scatter(origin, direction, ground);
+                let p = origin + direction * ground;
+                let cos = dot(p.unit_vector(), self.sun_direction).max(0.0);
+                let sunlight = self.sun_transmittance(p).unwrap_or(Color::new(0.0, 0.0, 0.0)) * (self.sun_intensity * cos / PI);
+                transmittance * self.ground_albedo * sunlight + inscattered
+            },
+            None => self.scatter(origin, direction, exit.max(0.0)).1,
+        }
+    }
+
+    ///Returns the irradiance of the sun's disc as seen from the scene's origin, dimmed and reddened by the air in between.
+    pub fn sun_irradiance(&self) -> Color {
+        self.sun_transmittance(self.planet_point(Vec3::new(0.0, 0.0, 0.0))).unwrap_or(Color::new(0.0, 0.0, 0.0)) * self.sun_intensity
+    }
+
+    ///Returns how much of the light from a point t along a ray reaches the ray's origin through the air, and the light the air
+    ///
+    /// in between scatters towards the origin, so that distant objects fade into the color of the sky.
+    pub fn aerial_perspective(&self, r : Ray, t : Float) -> (Color, Color) {
+        let origin = self.planet_point(r.origin_point);
+        let distance = t * r.direction.length() * self.scale;
+        self.scatter(origin, r.direction.unit_vector(), distance)
+    }
+
+    ///Bakes the sky, the sun's disc and the ground into an equirectangular environment map, so that they are importance sampled as a light.
+    pub fn bake(&self, width : u32, height : u32, intensity : Float) -> Environment {
+        let mut pixels = vec![0.0 ; 3 * (width * height) as usize];
+        for j in 0..height {
+            for i in 0..width {
+                let phi = 2.0 * PI * (i as Float + 0.5) / width as Float - PI;
+                let theta = PI * (j as Float + 0.5) / height as Float;
+                let c = self.radiance(Vec3::new(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin()));
+                let index = 3 * (j * width + i) as usize;
+                pixels[index..index + 3].copy_from_slice(&[c.x, c.y, c.z]);
+            }
+        }
+
+        //The sun is much smaller than a pixel, so its light is spread over the pixel containing it
+        let d = self.sun_direction;
+        let i = ((((-d.z).atan2(d.x) + PI) / (2.0 * PI) * width as Float) as u32).min(width - 1);
+        let j = ((d.y.clamp(-1.0, 1.0).acos() / PI * height as Float) as u32).min(height - 1);
+        let pixel_solid_angle = (2.0 * PI / width as Float) * (PI / height as Float) * (PI * (j as Float + 0.5) / height as Float).sin();
+        let sun = self.sun_irradiance() * (1.0 / pixel_solid_angle);
+        let index = 3 * (j * width + i) as usize;
+        for (value, channel) in pixels[index..index + 3].iter_mut().zip([sun.x, sun.y, sun.z]) {
+            *value += channel;
+        }
+
+        Environment::new(pixels, width, height, intensity)
+    }
+
+    ///Returns where a point of the scene is, in meters from the center of the planet.
+    fn planet_point(&self, p : Vec3) -> Vec3 {
+        Vec3::new(p.x * self.scale, self.planet_radius + self.altitude + p.y * self.scale, p.z * self.scale)
+    }
+
+    ///Returns the density of air and of aerosols at a point, relative to their density at the ground.
+    fn densities(&self, p : Vec3) -> (Float, Float) {
+        let h = altitude(p, self.planet_radius).max(0.0);
+        ((-h / self.rayleigh_height).exp(), (-h / self.mie_height).exp())
+    }
+
+    ///Returns how much light passes through the given amounts of air and aerosols (as densities times distance).
+    fn transmittance(&self, rayleigh : Float, mie : Float) -> Color {
+        let tau = self.rayleigh_scattering * rayleigh + Color::new(1.0, 1.0, 1.0) * (1.1 * self.mie_scattering * mie);
+        Color::new((-tau.x).exp(), (-tau.y).exp(), (-tau.z).exp())
+    }
+
+    ///Returns how much sunlight reaches a point through the atmosphere, or None if the planet is in the way.
+    fn sun_transmittance(&self, p : Vec3) -> Option<Color> {
+        if sphere_distances(p, self.sun_direction, self.planet_radius).is_some_and(|(near, _)| near > 0.0) {
+            return None;
+        }
+        let (_, exit) = sphere_distances(p, self.sun_direction, self.planet_radius + self.height)?;
+        let step = exit.max(0.0) / SUN_STEPS as Float;
+        let (mut rayleigh, mut mie) = (0.0, 0.0);
+        for i in 0..SUN_STEPS {
+            let (r, m) = self.densities(p + self.sun_direction * (step * (i as Float + 0.5)));
+            rayleigh += r * step;
+            mie += m * step;
+        }
+        Some(self.transmittance(rayleigh, mie))
+    }
+
+    ///Follows a ray (from a point given in planet coordinates) for distance meters, returning the fraction of light that passes through,
+    ///
+    /// and the sunlight scattered towards the start of the ray along the way.
+    fn scatter(&self, origin : Vec3, direction : Vec3, distance : Float) -> (Color, Color) {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if distance <= 0.0 {
+            return (Color::new(1.0, 1.0, 1.0), black);
+        }
+        let step = distance / VIEW_STEPS as Float;
+        let (mut rayleigh, mut mie) = (0.0, 0.0);
+        let (mut rayleigh_sum, mut mie_sum) = (black, black);
+        for i in 0..VIEW_STEPS {
+            let p = origin + direction * (step * (i as Float + 0.5));
+            let (r, m) = self.densities(p);
+            rayleigh += r * step;
+            mie += m * step;
+            if let Some(sunlight) = self.sun_transmittance(p) {
+                let seen = sunlight * self.transmittance(rayleigh, mie);
+                rayleigh_sum += seen * (r * step);
+                mie_sum += seen * (m * step);
+            }
+        }
+
+        //Phase functions of air, and of aerosols after Cornette and Shanks
+        let cos = dot(direction, self.sun_direction);
+        let g = self.mie_g;
+        let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + cos * cos);
+        let mie_phase = 3.0 / (8.0 * PI) * (1.0 - g * g) * (1.0 + cos * cos) / ((2.0 + g * g) * (1.0 + g * g - 2.0 * g * cos).max(1e-6).powf(1.5));
+        let inscattered = (rayleigh_sum * self.rayleigh_scattering * rayleigh_phase + mie_sum * (self.mie_scattering * mie_phase)) * self.sun_intensity;
+        (self.transmittance(rayleigh, mie), inscattered)
+    }
+}
+
+//Points are thousands of kilometers from the planet's center, where f32 can't tell apart heights a meter or so apart,
+//so heights and distances to spheres are worked out in f64
+
+///Returns how far a point is above a sphere around the planet's center.
+fn altitude(p : Vec3, radius : Float) -> Float {
+    let (x, y, z, radius) = (wide(p.x), wide(p.y), wide(p.z), wide(radius));
+    let length = (x * x + y * y + z * z).sqrt();
+    (length - radius) as Float
+}
+
+///Returns the distances along a ray (from origin, in a unit direction) to where it enters and leaves a sphere around the planet's center, if it meets it.
+fn sphere_distances(origin : Vec3, direction : Vec3, radius : Float) -> Option<(Float, Float)> {
+    let (o, d) = ([wide(origin.x), wide(origin.y), wide(origin.z)], [wide(direction.x), wide(direction.y), wide(direction.z)]);
+    let b = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+    let length = (o[0] * o[0] + o[1] * o[1] + o[2] * o[2]).sqrt();
+    let c = (length - wide(radius)) * (length + wide(radius));
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some(((-b - root) as Float, (-b + root) as Float))
+}
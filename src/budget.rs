@@ -0,0 +1,74 @@
+/*
+Module to store sample budgets, which give some regions of the image more samples per pixel than others.
+*/
+
+use image::{open, ImageResult};
+use crate::vec3::Float;
+
+///Rectangle of the image, from (x0, y0) to (x1, y1) non-inclusive in pixels from the top left corner, rendered with its own number of samples per pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub x0 : u32,
+    pub y0 : u32,
+    pub x1 : u32,
+    pub y1 : u32,
+    pub samples : i32,
+}
+
+impl Region {
+
+    ///Creates a region giving pixels from (x0, y0) to (x1, y1) the given samples per pixel.
+    pub fn new(x0 : u32, y0 : u32, x1 : u32, y1 : u32, samples : i32) -> Region {
+        Region {x0, y0, x1, y1, samples}
+    }
+
+    fn contains(&self, x : u32, y : u32) -> bool {
+        x >= self.x0 && x < self.x1 && y >= self.y0 && y < self.y1
+    }
+}
+
+///Decides how many samples each pixel gets. Variants include
+///
+/// Uniform: every pixel gets the same number of samples.
+///
+/// Map: a grayscale importance map (its values, width and height) stretched over the image, along with the samples per pixel for black and for white.
+/// Shades in between get a proportional number of samples.
+///
+/// Regions: a list of rectangles with their own samples per pixel. Where rectangles overlap the last one listed wins,
+/// and pixels outside all of them get the usual number of samples.
+#[derive(Debug, Clone)]
+pub enum SampleBudget {
+    Uniform,
+    Map(Vec<Float>, u32, u32, i32, i32),
+    Regions(Vec<Region>),
+}
+
+impl SampleBudget {
+
+    ///Loads an importance map from disk, where black pixels get minimum samples per pixel and white pixels get maximum.
+    pub fn load(path : &str, minimum : i32, maximum : i32) -> ImageResult<SampleBudget> {
+        let img = open(path)?.to_luma32f();
+        let (width, height) = img.dimensions();
+        Ok(SampleBudget::Map(img.into_raw().into_iter().map(|x| x as Float).collect(), width, height, minimum, maximum))
+    }
+
+    ///Returns the number of samples for pixel (i, j) of an image_width by image_height image, counting j from the bottom as the render loop does.
+    ///
+    /// samples is the usual number of samples per pixel.
+    pub fn samples(&self, i : u32, j : u32, image_width : u32, image_height : u32, samples : i32) -> i32 {
+        let y = image_height - j - 1;
+        match self {
+            SampleBudget::Uniform => samples,
+            SampleBudget::Map(values, width, height, minimum, maximum) => {
+                let mx = ((i as u64 * *width as u64 / image_width as u64) as u32).min(width - 1);
+                let my = ((y as u64 * *height as u64 / image_height as u64) as u32).min(height - 1);
+                let value = values[(my * width + mx) as usize].clamp(0.0, 1.0);
+                (*minimum as Float + (maximum - minimum) as Float * value).round() as i32
+            },
+            SampleBudget::Regions(regions) => match regions.iter().rev().find(|region| region.contains(i, y)) {
+                Some(region) => region.samples,
+                None => samples,
+            },
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use crate::ray_class::Ray;
-use crate::vec_class::Point3;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Float};
 
 ///Axis-aligned bounding box represented by two corners. For use in a Bounding Volume Hierarchy
 /// 
@@ -19,22 +19,57 @@ impl AABB {
         }
     }
 
-    pub fn hit(&self, r : Ray, t_min : f32, t_max : f32) -> bool {
+    pub fn hit(&self, r : Ray, t_min : Float, t_max : Float) -> bool {
+        self.interval(r, t_min, t_max).is_some()
+    }
+
+    ///Returns the range of t between t_min and t_max over which the ray is inside the box, if any.
+    pub fn interval(&self, r : Ray, t_min : Float, t_max : Float) -> Option<(Float, Float)> {
         let mut t_mi = t_min;
         let mut t_ma = t_max;
         for i in 0..3 {
             let mut t0 = (self.minimum[i] - r.origin_point[i]) / r.direction[i];
             let mut t1 = (self.maximum[i] - r.origin_point[i]) / r.direction[i];
-            if r.direction[i] < 0.0 {
+            //Checking the sign bit, since rays parallel to an axis (like an orthographic camera's) can have a direction of -0
+            if r.direction[i].is_sign_negative() {
                 (t0, t1) = (t1, t0);
             }
             t_mi = t_mi.max(t0);
             t_ma = t_ma.min(t1);
             if t_ma <= t_mi {
-                return false;
+                return None;
             }
         }
-        return true;
+        Some((t_mi, t_ma))
+    }
+
+    ///Returns whether any coordinate of the box is NaN.
+    pub fn has_nan(&self) -> bool {
+        (0..3).any(|i| self.minimum[i].is_nan() || self.maximum[i].is_nan())
+    }
+
+    ///Returns whether other lies entirely within this box.
+    pub fn contains(&self, other : AABB) -> bool {
+        (0..3).all(|i| self.minimum[i] <= other.minimum[i] && other.maximum[i] <= self.maximum[i])
+    }
+
+    ///Returns the total area of the box's six sides.
+    pub fn surface_area(&self) -> Float {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    ///Returns the box with its corners in order and a little thickness along any flat side, since rays can never enter a box with no volume.
+    pub fn padded(&self) -> AABB {
+        let mut minimum = self.minimum;
+        let mut maximum = self.maximum;
+        for i in 0..3 {
+            let (low, high) = (minimum[i].min(maximum[i]), minimum[i].max(maximum[i]));
+            let pad = if high - low < 0.0001 {0.0001} else {0.0};
+            minimum[i] = low - pad;
+            maximum[i] = high + pad;
+        }
+        AABB::new(minimum, maximum)
     }
 
 }
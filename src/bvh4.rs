@@ -0,0 +1,222 @@
+/*
+Module to store the 4-wide Bounding Volume Hierarchy, whose nodes test the boxes of all four children at once with SIMD instructions.
+*/
+
+use crate::hitting::{Hittable, HitRecord};
+use crate::ray::Ray;
+use crate::textures::Textures;
+use crate::bvh::{AABB, surrounding_box};
+use crate::tree::Tree;
+use crate::vec3::{Point3, Float};
+use crate::stats::count_traversal;
+use std::io::Result;
+
+///Maximum number of children waiting on the stack while hit() walks the hierarchy. Every level adds at most three.
+const STACK_SIZE : usize = 256;
+
+///Child of a node in a 4-wide Bounding Volume Hierarchy. Variants include
+///
+/// Empty: an unused slot, in nodes with fewer than four children.
+///
+/// Node: another node, by its index.
+///
+/// Object: an object, by its index.
+#[derive(Debug, Clone, Copy)]
+pub enum WideChild {
+    Empty,
+    Node(usize),
+    Object(usize),
+}
+
+///Node of a 4-wide Bounding Volume Hierarchy. The corners of the children's boxes are stored
+///
+/// one axis at a time (minimum[axis][child]), so that each axis of all four boxes loads into a single register.
+#[derive(Debug, Clone)]
+pub struct WideNode {
+    pub minimum : [[Float ; 4] ; 3],
+    pub maximum : [[Float ; 4] ; 3],
+    pub children : [WideChild ; 4],
+}
+
+///Represents a Bounding Volume Hierarchy with four children per node, made by collapsing a binary one.
+///
+/// Traversal visits a quarter as many nodes at half the depth, and tests each node's four boxes together.
+#[derive(Debug, Clone)]
+pub struct Bvh4 {
+    pub nodes : Vec<WideNode>,
+    pub objects : Vec<Hittable>,
+    pub root : WideChild,
+}
+
+impl Bvh4 {
+
+    ///Builds a 4-wide Bounding Volume Hierarchy from a list of Hittable objects. Fails as Tree::build() does.
+    pub fn build(lst : &mut [Hittable]) -> Result<Bvh4> {
+        let tree = Tree::build(lst)?;
+        let mut wide = Bvh4 {nodes : vec![], objects : vec![], root : WideChild::Empty};
+        if !tree.items.is_empty() {
+            wide.root = wide.collapse(&tree, tree.root);
+        }
+        Ok(wide)
+    }
+
+    ///Recursive helper function that turns the binary node at index into a wide node, by repeatedly replacing
+    ///
+    /// the interior child with the largest surface area by its own two children until there are four.
+    fn collapse(&mut self, tree : &Tree, index : usize) -> WideChild {
+        let node = &tree.items[index];
+        if let Some(data) = &node.data {
+            self.objects.push(data.clone());
+            return WideChild::Object(self.objects.len() - 1);
+        }
+
+        let mut children : Vec<usize> = [node.left, node.right].into_iter().flatten().collect();
+        while children.len() < 4 {
+            let largest = children.iter().enumerate()
+                .filter(|(_, child)| tree.items[**child].data.is_none())
+                .max_by(|(_, a), (_, b)| tree.items[**a].aabb.surface_area().total_cmp(&tree.items[**b].aabb.surface_area()));
+            match largest {
+                Some((i, _)) => {
+                    let opened = &tree.items[children.swap_remove(i)];
+                    children.extend([opened.left, opened.right].into_iter().flatten());
+                },
+                None => break,
+            }
+        }
+
+        let wide_index = self.nodes.len();
+        self.nodes.push(WideNode {
+            minimum : [[Float::INFINITY ; 4] ; 3],
+            maximum : [[Float::NEG_INFINITY ; 4] ; 3],
+            children : [WideChild::Empty ; 4],
+        });
+        for (slot, child) in children.into_iter().enumerate() {
+            let aabb = tree.items[child].aabb;
+            let collapsed = self.collapse(tree, child);
+            let wide = &mut self.nodes[wide_index];
+            for axis in 0..3 {
+                wide.minimum[axis][slot] = aabb.minimum[axis];
+                wide.maximum[axis][slot] = aabb.maximum[axis];
+            }
+            wide.children[slot] = collapsed;
+        }
+        WideChild::Node(wide_index)
+    }
+
+    ///Returns the bounding box surrounding every object in the hierarchy, or None if it is empty.
+    pub fn bounding_box(&self) -> Option<AABB> {
+        match self.root {
+            WideChild::Empty => None,
+            WideChild::Object(i) => Some(self.objects[i].bounding_box().padded()),
+            WideChild::Node(n) => {
+                let node = &self.nodes[n];
+                let corner = |corners : &[[Float ; 4] ; 3], slot : usize| Point3::new(corners[0][slot], corners[1][slot], corners[2][slot]);
+                node.children.iter().enumerate()
+                    .filter(|(_, child)| !matches!(child, WideChild::Empty))
+                    .map(|(slot, _)| AABB::new(corner(&node.minimum, slot), corner(&node.maximum, slot)))
+                    .reduce(surrounding_box)
+            },
+        }
+    }
+
+    ///Determines if a ray hits any object in the hierarchy, filling in rec with the closest hit.
+    ///
+    /// Children whose boxes the ray enters are visited nearest first, and skipped once something closer has been hit.
+    pub fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, textures : &Textures) -> bool {
+        let origin = [r.origin_point.x, r.origin_point.y, r.origin_point.z];
+        let inverse = [1.0 / r.direction.x, 1.0 / r.direction.y, 1.0 / r.direction.z];
+        let negative = [r.direction.x.is_sign_negative(), r.direction.y.is_sign_negative(), r.direction.z.is_sign_negative()];
+
+        let mut stack = [(0.0, WideChild::Empty) ; STACK_SIZE];
+        stack[0] = (t_min, self.root);
+        let mut top = 1;
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        let (mut nodes, mut primitives) = (0, 0);
+
+        while top > 0 {
+            top -= 1;
+            let (t_enter, child) = stack[top];
+            if t_enter > closest {
+                continue;
+            }
+
+            match child {
+                WideChild::Empty => (),
+                WideChild::Object(i) => {
+                    primitives += 1;
+                    if self.objects[i].hit(r, t_min, closest, rec, textures) {
+                        hit_anything = true;
+                        closest = rec.t;
+                        rec.object = i;
+                    }
+                },
+                WideChild::Node(n) => {
+                    let node = &self.nodes[n];
+                    nodes += 1;
+                    let (mask, near) = intersect4(node, origin, inverse, negative, t_min, closest);
+
+                    let mut hits = [(0.0, WideChild::Empty) ; 4];
+                    let mut count = 0;
+                    for (slot, child) in node.children.iter().enumerate() {
+                        if mask & (1 << slot) != 0 && !matches!(child, WideChild::Empty) {
+                            hits[count] = (near[slot], *child);
+                            count += 1;
+                        }
+                    }
+                    //Pushed farthest first, so the nearest is visited next
+                    hits[..count].sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+                    stack[top..top + count].copy_from_slice(&hits[..count]);
+                    top += count;
+                },
+            }
+        }
+        count_traversal(nodes, primitives);
+        hit_anything
+    }
+}
+
+///Tests a ray against the four boxes of a node, returning a bitmask of the boxes it hits between t_min and t_max, and where it enters each.
+///
+/// NaNs, from rays running exactly along a side of a box, are ignored as AABB::hit() ignores them.
+#[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+fn intersect4(node : &WideNode, origin : [Float ; 3], inverse : [Float ; 3], negative : [bool ; 3], t_min : Float, t_max : Float) -> (i32, [Float ; 4]) {
+    use std::arch::x86_64::*;
+
+    //SSE is part of every x86_64 processor, and the loads and stores are of whole [f32 ; 4] arrays
+    unsafe {
+        let mut near = _mm_set1_ps(t_min);
+        let mut far = _mm_set1_ps(t_max);
+        for axis in 0..3 {
+            let (low, high) = if negative[axis] {(&node.maximum[axis], &node.minimum[axis])} else {(&node.minimum[axis], &node.maximum[axis])};
+            let o = _mm_set1_ps(origin[axis]);
+            let inv = _mm_set1_ps(inverse[axis]);
+            let t0 = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(low.as_ptr()), o), inv);
+            let t1 = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(high.as_ptr()), o), inv);
+            //These return their second argument when the first is NaN
+            near = _mm_max_ps(t0, near);
+            far = _mm_min_ps(t1, far);
+        }
+        let mut entry = [0.0 ; 4];
+        _mm_storeu_ps(entry.as_mut_ptr(), near);
+        (_mm_movemask_ps(_mm_cmpgt_ps(far, near)), entry)
+    }
+}
+
+///Tests a ray against the four boxes of a node, returning a bitmask of the boxes it hits between t_min and t_max, and where it enters each.
+///
+/// Written lane by lane so the compiler can vectorize it on processors without the x86_64 version.
+#[cfg(not(all(target_arch = "x86_64", not(feature = "f64"))))]
+fn intersect4(node : &WideNode, origin : [Float ; 3], inverse : [Float ; 3], negative : [bool ; 3], t_min : Float, t_max : Float) -> (i32, [Float ; 4]) {
+    let mut near = [t_min ; 4];
+    let mut far = [t_max ; 4];
+    for axis in 0..3 {
+        let (low, high) = if negative[axis] {(&node.maximum[axis], &node.minimum[axis])} else {(&node.minimum[axis], &node.maximum[axis])};
+        for (slot, (near, far)) in near.iter_mut().zip(far.iter_mut()).enumerate() {
+            *near = ((low[slot] - origin[axis]) * inverse[axis]).max(*near);
+            *far = ((high[slot] - origin[axis]) * inverse[axis]).min(*far);
+        }
+    }
+    let mask = (0..4).fold(0, |mask, slot| if far[slot] > near[slot] {mask | (1 << slot)} else {mask});
+    (mask, near)
+}
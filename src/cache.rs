@@ -0,0 +1,148 @@
+/*
+Module to store the asset cache, which keeps decoded images and triangulated meshes on disk in a binary form that loads without decoding,
+named by a hash of the files they came from, so that rendering the same scene again skips decoding its assets. The cache is off until
+set_directory() is given a directory (--cache on the command line), and stale entries are never used, since changing an asset changes its hash.
+Images are kept along with their mipmaps, so loading them skips making those too, and meshes already split into triangles, whether they came from
+.obj and .ply files (see mesh.rs) or from glTF scenes.
+Entries are written to a temporary file first, so a render stopped while caching never leaves a broken entry behind, and entries that fail
+to load (as when written by a different version) are converted again and overwritten.
+*/
+
+use std::fs::{read, write, rename, create_dir_all};
+use std::io::{Error, ErrorKind, Result};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use image::load_from_memory;
+use tracing::warn;
+use crate::textures::{TextureHandle, Mipmap};
+use crate::vec3::Float;
+
+///Written at the start of cached images, with the version of their layout.
+const IMAGE_MAGIC : &[u8 ; 8] = b"RTIMAGE2";
+
+///Written at the start of cached meshes, with the version of their layout.
+const MESH_MAGIC : &[u8 ; 8] = b"RTMESH01";
+
+///Directory entries are kept in, if caching is on.
+static DIRECTORY : Mutex<Option<PathBuf>> = Mutex::new(None);
+
+///Corner of a cached triangle: its position and texture coordinates, before the triangle is placed in the scene.
+pub type Corner = [Float ; 5];
+
+///Turns the cache on, keeping entries in directory (which is created when the first is written), or off with None.
+///
+/// The directory is process-wide: it applies to every scene loaded afterwards, on every thread.
+pub fn set_directory(directory : Option<PathBuf>) {
+    *DIRECTORY.lock().unwrap() = directory;
+}
+
+///Returns whether caching is on, so that the hashes naming entries are only worked out when they will be used.
+pub fn enabled() -> bool {
+    DIRECTORY.lock().unwrap().is_some()
+}
+
+///Returns a 64 bit FNV-1a hash of some bytes, which names the cache entries converted from them.
+pub fn content_hash(bytes : &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash : u64, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+///Decodes an image file's bytes into 8 bit RGB pixels, making their mipmaps, or loads both from the cache if it has been decoded before.
+pub fn decode_image(bytes : &[u8]) -> Result<TextureHandle> {
+    let entry = entry(content_hash(bytes), "image");
+    if let Some(image) = entry.as_ref().and_then(|path| read(path).ok()).and_then(|cached| parse_image(&cached)) {
+        return Ok(image);
+    }
+
+    let image : TextureHandle = load_from_memory(bytes).map_err(|error| Error::new(ErrorKind::InvalidData, error.to_string()))?.into_rgb8().into();
+    if let Some(path) = entry {
+        //Mipmaps follow the image, smallest last, and their sizes follow from its size
+        let mut cached = Vec::with_capacity(16 + image.pixels.len() * 4 / 3 + 3);
+        cached.extend_from_slice(IMAGE_MAGIC);
+        cached.extend_from_slice(&image.width.to_le_bytes());
+        cached.extend_from_slice(&image.height.to_le_bytes());
+        cached.extend_from_slice(&image.pixels);
+        for mipmap in image.mipmaps.iter() {
+            cached.extend_from_slice(&mipmap.pixels);
+        }
+        store(path, &cached);
+    }
+    Ok(image)
+}
+
+///Loads an image file as decode_image() does.
+pub fn load_image(path : impl Into<PathBuf>) -> Result<TextureHandle> {
+    let path = path.into();
+    decode_image(&read(&path)?).map_err(|error| Error::new(error.kind(), format!("Failed to load {}: {}", path.display(), error)))
+}
+
+///Returns the triangles of a mesh, from the cache if it holds an entry under key (a hash of everything the mesh is converted from),
+///
+/// or else from convert, caching what it returns.
+pub fn triangles(key : u64, convert : impl FnOnce() -> Result<Vec<[Corner ; 3]>>) -> Result<Vec<[Corner ; 3]>> {
+    let entry = entry(key, "mesh");
+    if let Some(triangles) = entry.as_ref().and_then(|path| read(path).ok()).and_then(|cached| parse_mesh(&cached)) {
+        return Ok(triangles);
+    }
+
+    let triangles = convert()?;
+    if let Some(path) = entry {
+        let mut cached = Vec::with_capacity(16 + triangles.len() * 15 * size_of::<Float>());
+        cached.extend_from_slice(MESH_MAGIC);
+        cached.extend_from_slice(&(triangles.len() as u64).to_le_bytes());
+        for value in triangles.iter().flatten().flatten() {
+            cached.extend_from_slice(&value.to_le_bytes());
+        }
+        store(path, &cached);
+    }
+    Ok(triangles)
+}
+
+///Returns where the entry with the given hash and kind is kept, or None if caching is off.
+fn entry(hash : u64, kind : &str) -> Option<PathBuf> {
+    DIRECTORY.lock().unwrap().as_ref().map(|directory| directory.join(format!("{:016x}.{}", hash, kind)))
+}
+
+///Writes an entry, giving up quietly if it can't be written, since the asset has been converted anyway.
+fn store(path : PathBuf, bytes : &[u8]) {
+    let partial = path.with_extension("partial");
+    let stored = path.parent().map_or(Ok(()), create_dir_all).and_then(|_| write(&partial, bytes)).and_then(|_| rename(&partial, &path));
+    if let Err(error) = stored {
+        warn!("Failed to cache {}: {}", path.display(), error);
+    }
+}
+
+fn parse_image(cached : &[u8]) -> Option<TextureHandle> {
+    let rest = cached.strip_prefix(IMAGE_MAGIC)?;
+    let width = u32::from_le_bytes(rest.get(0..4)?.try_into().ok()?);
+    let height = u32::from_le_bytes(rest.get(4..8)?.try_into().ok()?);
+    let (pixels, mut rest) = rest[8..].split_at_checked(3 * width as usize * height as usize)?;
+    let mut mipmaps = vec![];
+    let (mut above_width, mut above_height) = (width, height);
+    while above_width > 1 || above_height > 1 {
+        (above_width, above_height) = (above_width.div_ceil(2), above_height.div_ceil(2));
+        let (mipmap, after) = rest.split_at_checked(3 * above_width as usize * above_height as usize)?;
+        mipmaps.push(Mipmap {pixels : mipmap.to_vec(), width : above_width, height : above_height});
+        rest = after;
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    TextureHandle::try_with_mipmaps(pixels, width, height, mipmaps).ok()
+}
+
+fn parse_mesh(cached : &[u8]) -> Option<Vec<[Corner ; 3]>> {
+    let rest = cached.strip_prefix(MESH_MAGIC)?;
+    let count = u64::from_le_bytes(rest.get(0..8)?.try_into().ok()?);
+    let values = &rest[8..];
+    //Entries written with the other precision (see the f64 feature) have the wrong length, and are converted again
+    if values.len() as u64 != count.checked_mul(15 * size_of::<Float>() as u64)? {
+        return None;
+    }
+    let values = values.chunks_exact(size_of::<Float>()).map(|bytes| Float::from_le_bytes(bytes.try_into().unwrap())).collect::<Vec<_>>();
+    Some(values.chunks_exact(15).map(|triangle| [0, 1, 2].map(|corner| {
+        let mut values = [0.0 ; 5];
+        values.copy_from_slice(&triangle[5 * corner..5 * corner + 5]);
+        values
+    })).collect())
+}
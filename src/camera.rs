@@ -1,16 +1,181 @@
-use crate::ray_class::Ray;
-use crate::vec_class::{Vec3, Point3, cross, random_in_unit_disk};
-use core::f32::consts::PI;
+use crate::ray::{Ray, t_min};
+use crate::vec3::{Vec3, Point3, cross, dot, point_in_unit_disk, Float};
+use crate::sampler::Sampler;
+use crate::hitting::HitRecord;
+use crate::scene::Scene;
+use crate::vec3::consts::PI;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
-fn degrees_to_radians(degrees : f32) -> f32 {
+fn degrees_to_radians(degrees : Float) -> Float {
     degrees * PI / 180.0
 }
 
-///Camera struct to view scenes. Allows the scene to be viewed from many different perspectives 
+///Turns points on the image into rays. Implement this to render with a custom projection.
+/// 
+/// Cameras must draw their random numbers from the sampler they are given, rather than from random_float().
+pub trait Camera : Send + Sync {
+
+    ///Returns a ray through the point (u, v) of the image, where (0, 0) is the bottom left corner and (1, 1) the top right.
+    fn get_ray(&self, u : Float, v : Float, sampler : &mut dyn Sampler) -> Ray;
+
+    ///Picks a random moment while the shutter is open.
+    fn sample_time(&self, _sampler : &mut dyn Sampler) -> Float {
+        0.0
+    }
+
+    ///Returns the angle (in radians) a pixel of an image height pixels tall covers, which its rays widen by as they travel
+    ///
+    /// (see Ray::spread), or 0 if they don't widen, which keeps image textures at their sharpest.
+    fn spread(&self, _height : u32) -> Float {
+        0.0
+    }
+
+    ///Returns this camera moved to an eye offset distance to the right (or left, if negative) for stereo rendering,
+    /// 
+    /// or None if it doesn't support stereo.
+    fn eye(&self, _offset : Float) -> Option<Box<dyn Camera>> {
+        None
+    }
+}
+
+///How the standard camera maps the image onto rays. Variants include
+/// 
+/// Perspective: rays spread out from the lens, so distant objects look smaller.
+/// 
+/// Orthographic: rays travel in parallel, so objects look the same size at any distance. Suited to technical and isometric views.
+/// 
+/// Equirectangular: rays leave in every direction, giving a full 360 by 180 degree panorama (use an aspect ratio of 2).
+/// Moving right across the image turns right, as in panoramas from 360 degree cameras, so it can be used as a VR background or HDRI.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Equirectangular,
+}
+
+///How the two views of a stereo pair are laid out in the image. Variants include
+/// 
+/// SideBySide: the left eye's view on the left half, and the right eye's on the right half.
+/// 
+/// TopBottom: the left eye's view on the top half, and the right eye's on the bottom half.
+#[derive(Debug, Clone, Copy)]
+pub enum StereoLayout {
+    SideBySide,
+    TopBottom,
+}
+
+///Settings for rendering a stereo pair, with the eyes interocular apart (in world units) and laid out as given.
+/// 
+/// Perspective eyes share the camera's plane of focus, so objects there appear at the depth of the screen.
+/// Panorama eyes are omni-directional: each direction is seen from an eye circling the camera's position, so the stereo holds up all the way around.
+#[derive(Debug, Clone, Copy)]
+pub struct Stereo {
+    pub interocular : Float,
+    pub layout : StereoLayout,
+}
+
+impl Stereo {
+
+    ///Size of the image holding both views, when each view is width by height.
+    pub fn image_size(&self, width : u32, height : u32) -> (u32, u32) {
+        match self.layout {
+            StereoLayout::SideBySide => (2 * width, height),
+            StereoLayout::TopBottom => (width, 2 * height),
+        }
+    }
+
+    ///Returns the left and right eye versions of cam, or None if it can't be moved to each eye.
+    pub fn eyes(&self, cam : &dyn Camera) -> Option<(Box<dyn Camera>, Box<dyn Camera>)> {
+        Some((cam.eye(-self.interocular / 2.0)?, cam.eye(self.interocular / 2.0)?))
+    }
+
+    ///Finds which view pixel (i, j) of the combined image belongs to (true for the right eye), and where it lies in that view.
+    /// 
+    /// j counts from the bottom, as in the render loop.
+    pub fn locate(&self, i : u32, j : u32, width : u32, height : u32) -> (bool, u32, u32) {
+        match self.layout {
+            StereoLayout::SideBySide => (i >= width, i % width, j),
+            StereoLayout::TopBottom => (j < height, i, j % height),
+        }
+    }
+}
+
+///Physical exposure of a camera, from its ISO sensitivity, shutter speed (in seconds) and f-number.
+/// 
+/// Radiance is treated as luminance in candelas per square meter, so emissive materials can be given real world brightnesses:
+/// for example ISO 100, 1/125 seconds and f/16 suits a sunlit scene, and ISO 1600, 1/30 seconds and f/2 a dim room.
+/// The shutter speed only scales brightness; motion blur is controlled by StandardCamera::with_shutter() instead.
+#[derive(Debug, Clone, Copy)]
+pub struct Exposure {
+    pub iso : Float,
+    pub shutter_speed : Float,
+    pub f_number : Float,
+}
+
+impl Exposure {
+
+    ///Creates exposure settings from an ISO sensitivity, a shutter speed in seconds and an f-number.
+    pub fn new(iso : Float, shutter_speed : Float, f_number : Float) -> Exposure {
+        Exposure {iso, shutter_speed, f_number}
+    }
+
+    ///Exposure value of these settings, relative to ISO 100.
+    pub fn ev100(&self) -> Float {
+        (self.f_number * self.f_number / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    ///Factor converting radiance into pixel values, where 1 is the brightest a pixel can be. Follows the saturation based
+    /// 
+    /// sensitivity model, in which the sensor saturates at a luminance of 1.2 times 2 to the power of the exposure value.
+    pub fn scale(&self) -> Float {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+}
+
+///Brown-Conrady lens distortion, with the same coefficients as camera calibration tools such as OpenCV: k1 and k2 are radial
+/// 
+/// (negative for barrel distortion, positive for pincushion), and p1 and p2 tangential (from a lens that isn't quite parallel to the sensor).
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LensDistortion {
+    pub k1 : Float,
+    pub k2 : Float,
+    pub p1 : Float,
+    pub p2 : Float,
+}
+
+impl LensDistortion {
+
+    ///Moves the undistorted point (x, y), in units of the focal length from the center of the image, to where the lens images it.
+    pub fn distort(&self, x : Float, y : Float) -> (Float, Float) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    ///Finds the undistorted point that the lens images at (x, y), by fixed point iteration.
+    pub fn undistort(&self, x : Float, y : Float) -> (Float, Float) {
+        let (mut ux, mut uy) = (x, y);
+        for _ in 0..20 {
+            let (dx, dy) = self.distort(ux, uy);
+            ux += x - dx;
+            uy += y - dy;
+        }
+        (ux, uy)
+    }
+}
+
+///The built-in camera, which views the scene through one of several projections, from many different perspectives 
 /// 
 /// and settings (such as focal point, defocus blur, and smaller viewport).
 #[derive(Debug, Clone, Copy)]
-pub struct Camera {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StandardCamera {
     pub origin : Point3,
     pub lower_left_corner : Point3,
     pub horizontal : Vec3,
@@ -18,11 +183,19 @@ pub struct Camera {
     pub u : Vec3,
     pub v : Vec3,
     pub w : Vec3,
-    pub lens_radius : f32,
+    pub lens_radius : Float,
+    pub projection : Projection,
+    pub time0 : Float,
+    pub time1 : Float,
+    ///Normal of the plane in focus, which is w unless the lens is tilted.
+    pub focus_normal : Vec3,
+    ///Distance of the eye this camera renders for from the middle of a stereo pair (negative for the left eye), or 0 without stereo.
+    pub eye_offset : Float,
+    pub distortion : Option<LensDistortion>,
 }
 
-impl Camera {
-    pub fn new(lookfrom : Point3, lookat : Point3, vup : Vec3, vfov : f32, aspect_ratio : f32, aperture : f32, focus_dist : f32) -> Camera {
+impl StandardCamera {
+    pub fn new(lookfrom : Point3, lookat : Point3, vup : Vec3, vfov : Float, aspect_ratio : Float, aperture : Float, focus_dist : Float) -> StandardCamera {
         let theta = degrees_to_radians(vfov);
         let h = (theta/2.0).tan();
 
@@ -36,7 +209,7 @@ impl Camera {
         let hor = u * viewport_width * focus_dist;
         let ver = v * viewport_height * focus_dist;
         let llc = lookfrom - hor/2.0 - ver/2.0 - w*focus_dist;
-        Camera {
+        StandardCamera {
             origin : lookfrom,
             lower_left_corner : llc,
             horizontal : hor, 
@@ -45,12 +218,190 @@ impl Camera {
             v,
             w,
             lens_radius : aperture / 2.0,
+            projection : Projection::Perspective,
+            time0 : 0.0,
+            time1 : 0.0,
+            focus_normal : w,
+            eye_offset : 0.0,
+            distortion : None,
+        }
+    }
+
+    ///Creates an orthographic camera looking from lookfrom towards lookat, whose view is ortho_width wide in world units.
+    pub fn orthographic(lookfrom : Point3, lookat : Point3, vup : Vec3, ortho_width : Float, aspect_ratio : Float) -> StandardCamera {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = cross(vup, w).unit_vector();
+        let v = cross(w, u);
+
+        let hor = u * ortho_width;
+        let ver = v * (ortho_width / aspect_ratio);
+        StandardCamera {
+            origin : lookfrom,
+            lower_left_corner : lookfrom - hor/2.0 - ver/2.0,
+            horizontal : hor,
+            vertical : ver,
+            u,
+            v,
+            w,
+            lens_radius : 0.0,
+            projection : Projection::Orthographic,
+            time0 : 0.0,
+            time1 : 0.0,
+            focus_normal : w,
+            eye_offset : 0.0,
+            distortion : None,
         }
     }
 
-    pub fn get_ray(&self, u : f32, v : f32) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
-        let offset = self.u * rd.x + self.v * rd.y;
-        Ray::new(self.origin + offset, self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin - offset)
+    ///Creates a camera at lookfrom that sees in every direction, with lookat in the middle of the image.
+    pub fn panorama(lookfrom : Point3, lookat : Point3, vup : Vec3) -> StandardCamera {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = cross(vup, w).unit_vector();
+        let v = cross(w, u);
+        StandardCamera {
+            origin : lookfrom,
+            lower_left_corner : lookfrom,
+            horizontal : u,
+            vertical : v,
+            u,
+            v,
+            w,
+            lens_radius : 0.0,
+            projection : Projection::Equirectangular,
+            time0 : 0.0,
+            time1 : 0.0,
+            focus_normal : w,
+            eye_offset : 0.0,
+            distortion : None,
+        }
+    }
+
+    ///Returns this camera with its shutter open from time0 to time1, so that objects moving in the meantime are motion blurred.
+    pub fn with_shutter(self, time0 : Float, time1 : Float) -> StandardCamera {
+        StandardCamera {time0, time1, ..self}
+    }
+
+    ///Returns this camera with its image shifted parallel to the sensor by the given fractions of the image's width and height
+    /// 
+    /// (positive values shift right and up). Unlike turning the camera, shifting keeps vertical lines vertical, so tall buildings
+    /// can be framed without their sides converging. Only affects perspective cameras.
+    pub fn with_shift(self, shift_x : Float, shift_y : Float) -> StandardCamera {
+        StandardCamera {lower_left_corner : self.lower_left_corner + self.horizontal * shift_x + self.vertical * shift_y, ..self}
+    }
+
+    ///Returns this camera with its lens tilted, so that the plane in focus is no longer parallel to the sensor.
+    /// 
+    /// tilt_x (in degrees) swings the plane of focus about the image's horizontal axis, with positive angles pushing the focus further away
+    /// towards the top of the image (as along a floor), and tilt_y about its vertical axis, with positive angles pushing it further away
+    /// towards the right. Tilting the focus across a scene the other way gives the miniature effect. Only visible with an aperture above 0.
+    pub fn with_tilt(self, tilt_x : Float, tilt_y : Float) -> StandardCamera {
+        let normal = self.w + self.v * degrees_to_radians(tilt_x).tan() + self.u * degrees_to_radians(tilt_y).tan();
+        StandardCamera {focus_normal : normal.unit_vector(), ..self}
     }
-}
\ No newline at end of file
+
+    ///Returns this camera focused on whatever the ray from its center towards target hits first (or unchanged, if it hits nothing).
+    /// 
+    /// Focusing on lookat saves measuring the focus distance by hand. Only affects perspective cameras.
+    pub fn autofocus(self, scene : &Scene, target : Point3) -> StandardCamera {
+        self.focus_along(scene, target - self.origin)
+    }
+
+    ///Returns this camera focused on whatever is seen at the point (u, v) of the image (or unchanged, if nothing is there),
+    /// 
+    /// where (0, 0) is the bottom left corner and (1, 1) the top right. Only affects perspective cameras.
+    pub fn autofocus_at(self, scene : &Scene, u : Float, v : Float) -> StandardCamera {
+        self.focus_along(scene, self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin)
+    }
+
+    ///Moves the plane of focus to where a probe ray from the center of the lens in the given direction first hits the scene.
+    fn focus_along(self, scene : &Scene, direction : Vec3) -> StandardCamera {
+        if !matches!(self.projection, Projection::Perspective) {
+            return self;
+        }
+        let mut rec = HitRecord::new();
+        let probe = Ray::new(self.origin, direction.unit_vector()).with_time(self.time0);
+        if !scene.hit(probe, t_min(), Float::INFINITY, &mut rec) {
+            return self;
+        }
+
+        //The viewport lies on the plane of focus, so it scales with the distance to it
+        let depth = rec.t * dot(probe.direction, -self.w);
+        let current = dot(self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0 - self.origin, -self.w);
+        if depth <= 0.0 || current <= 0.0 {
+            return self;
+        }
+        let scale = depth / current;
+        StandardCamera {
+            lower_left_corner : self.origin + (self.lower_left_corner - self.origin) * scale,
+            horizontal : self.horizontal * scale,
+            vertical : self.vertical * scale,
+            ..self
+        }
+    }
+
+    ///Returns this camera with its image distorted by the given lens distortion coefficients, so renders can match footage from a real camera.
+    /// 
+    /// Only affects perspective cameras.
+    pub fn with_distortion(self, k1 : Float, k2 : Float, p1 : Float, p2 : Float) -> StandardCamera {
+        StandardCamera {distortion : Some(LensDistortion {k1, k2, p1, p2}), ..self}
+    }
+}
+
+impl Camera for StandardCamera {
+    fn sample_time(&self, sampler : &mut dyn Sampler) -> Float {
+        if self.time1 <= self.time0 {
+            return self.time0;
+        }
+        self.time0 + (self.time1 - self.time0) * sampler.get_1d()
+    }
+
+    fn spread(&self, height : u32) -> Float {
+        match self.projection {
+            //The viewport is as far in front of the lens as it is in focus
+            Projection::Perspective => {
+                let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0 - self.origin;
+                self.vertical.length() / (dot(center, self.w).abs() * height as Float)
+            },
+            Projection::Orthographic => 0.0,
+            Projection::Equirectangular => PI / height as Float,
+        }
+    }
+
+    fn get_ray(&self, u : Float, v : Float, sampler : &mut dyn Sampler) -> Ray {
+        let ray = match self.projection {
+            Projection::Perspective => {
+                let (r1, r2) = sampler.get_2d();
+                let rd = point_in_unit_disk(r1, r2) * self.lens_radius;
+                let offset = self.u * (rd.x + self.eye_offset) + self.v * rd.y;
+
+                //The point in focus is where the ray through the center of the lens meets the plane of focus, which passes through the viewport's center
+                let mut through = self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin;
+                if let Some(distortion) = self.distortion {
+                    //Each pixel shows what the lens bends onto it, so the ray goes where the undistorted point is
+                    let depth = dot(through, -self.w);
+                    let (x, y) = distortion.undistort(dot(through, self.u) / depth, dot(through, self.v) / depth);
+                    through = (-self.w + self.u * x + self.v * y) * depth;
+                }
+                let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0 - self.origin;
+                let focus = self.origin + through * (dot(center, self.focus_normal) / dot(through, self.focus_normal));
+                Ray::new(self.origin + offset, focus - self.origin - offset)
+            },
+            Projection::Orthographic => Ray::new(self.lower_left_corner + self.horizontal * u + self.vertical * v + self.u * self.eye_offset, -self.w),
+            Projection::Equirectangular => {
+                //v runs from the bottom of the image up, while the angle from straight up runs from the top down
+                let phi = 2.0 * PI * u - PI;
+                let theta = PI * (1.0 - v);
+                let direction = -self.w * (theta.sin() * phi.cos()) + self.v * theta.cos() + self.u * (theta.sin() * phi.sin());
+
+                //Each eye sits beside the horizontal heading of the direction it looks in
+                let right = self.u * phi.cos() + self.w * phi.sin();
+                Ray::new(self.origin + right * self.eye_offset, direction)
+            },
+        };
+        ray.with_time(self.sample_time(sampler))
+    }
+
+    fn eye(&self, offset : Float) -> Option<Box<dyn Camera>> {
+        Some(Box::new(StandardCamera {eye_offset : offset, ..*self}))
+    }
+}
@@ -0,0 +1,281 @@
+/*
+Module to store the command line interface, parsed with clap, which overrides the render settings in main() without recompiling, as in
+
+RustTracer render scenes/solar_system.json --width 1920 --spp 256 --depth 32 -o out.exr --threads 8
+
+or renders a generated scene instead (see generator.rs), as in
+
+RustTracer generate --count 20000 --mix 0.6,0.2,0.1,0.1 --bounds -50,0,-50,50,20,50 --radius 0.1,0.5 --seed 3
+
+or compares a render with a reference render (see compare.rs), as in
+
+RustTracer compare golden.exr out.exr --threshold 0.005 -o difference.png
+
+RustTracer --help lists every option, and RustTracer help <COMMAND> those of a command.
+*/
+
+use clap::{Parser, Subcommand, Args, ArgAction};
+use crate::scene_file::FileSettings;
+use crate::ray::SpawnOffset;
+use crate::vec3::{Vec3, Point3, Float};
+use crate::sky::Sky;
+use crate::examples::NAMES;
+use crate::generator::GeneratorSettings;
+use crate::compare::CompareSettings;
+
+///Settings given on the command line. Any left out keep the values from the scene file, or else from the render settings file or main().
+///
+/// The render options apply to every command, and may come before or after it.
+#[derive(Debug, Clone, Parser)]
+#[command(name = "RustTracer", version, about = "Path traces the scene in main(), a scene file, a built-in example or a generated scene",
+    long_about = "Path traces the scene in main(), a scene file, a built-in example or a generated scene, with the settings in main() unless overridden \
+    by render.toml in the working directory, the scene file or these options, in that order. With no command, renders the scene in main().")]
+pub struct Arguments {
+    #[command(subcommand)]
+    pub command : Option<Command>,
+
+    ///Image width; the height follows the aspect ratio
+    #[arg(long, global = true, value_name = "PIXELS")]
+    pub width : Option<u32>,
+
+    ///Samples per pixel
+    #[arg(long = "spp", global = true, value_name = "SAMPLES")]
+    pub samples_per_pixel : Option<i32>,
+
+    ///Maximum path depth
+    #[arg(long = "depth", global = true, value_name = "BOUNCES")]
+    pub max_depth : Option<i32>,
+
+    ///Random seed, for reproducible images
+    #[arg(long, global = true)]
+    pub seed : Option<u64>,
+
+    ///File to save, as a .png, .exr, .hdr or .pfm, or a .ppm streamed as it renders (- for standard output); a heat map of the differences with compare
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub output : Option<String>,
+
+    ///Distance rays leaving a surface ignore it for, in scene units (0.001 by default): larger for large scenes with shadow acne, smaller for tiny scenes leaking light
+    #[arg(long, global = true, value_name = "DISTANCE")]
+    pub epsilon : Option<Float>,
+
+    ///Start rays leaving a surface the epsilon off it along its normal instead
+    #[arg(long, global = true)]
+    pub normal_offset : bool,
+
+    ///Light the scene with the daylight sky, with the sun in this direction (y is up)
+    #[arg(long, global = true, value_name = "X,Y,Z", value_parser = numbers::<3>, allow_hyphen_values = true)]
+    pub sun : Option<[Float ; 3]>,
+
+    ///Light the scene with the daylight sky, this hazy (from 2 for very clear air to 10)
+    #[arg(long, global = true, value_name = "TURBIDITY")]
+    pub turbidity : Option<Float>,
+
+    ///Threads to render with (all cores by default)
+    #[arg(long, global = true, value_name = "COUNT")]
+    pub threads : Option<usize>,
+
+    ///Render at the lowest priority, so that the machine stays usable
+    #[arg(long, global = true)]
+    pub background : bool,
+
+    ///Render settings file to read instead of render.toml (see config.rs)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config : Option<String>,
+
+    ///Keep decoded images and meshes in DIR, so that later renders of the scene load faster
+    #[arg(long, global = true, value_name = "DIR")]
+    pub cache : Option<String>,
+
+    ///Save a heat map of the noise left in each pixel, as the relative error of its mean luminance, to see where more samples are needed
+    #[arg(long, global = true, value_name = "PATH")]
+    pub variance_map : Option<String>,
+
+    ///Print the rays traced, nodes visited, primitive tests, average path length and shading time per material once the render is done
+    #[arg(long, global = true)]
+    pub stats : bool,
+
+    ///Print the same statistics for every tile as well
+    #[arg(long, global = true)]
+    pub tile_stats : bool,
+
+    ///Log loading the scene, building it and every pass to standard error with how long they took, and every tile as well when given twice (-vv)
+    #[arg(short, long = "verbose", global = true, action = ArgAction::Count)]
+    pub verbosity : u8,
+
+    ///Show the image so far in a window after every pass; closing it or pressing Escape stops the render, keeping what is done
+    #[cfg(feature = "preview")]
+    #[arg(long, global = true)]
+    pub window : bool,
+
+    ///Find where camera rays first hit and trace the shadow rays towards lights on the GPU, or on the CPU if there is no GPU or the scene has media
+    #[cfg(feature = "gpu")]
+    #[arg(long, global = true)]
+    pub gpu : bool,
+
+    ///Log nothing, not even warnings
+    #[arg(short, long, global = true)]
+    pub quiet : bool,
+}
+
+///What to do. Variants include
+///
+/// Render: renders the scene in main(), a scene file or a built-in example.
+///
+/// Generate: renders a scene of randomly placed spheres.
+///
+/// Compare: compares a render with a reference render.
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    ///Renders the scene in main(), or SCENE, a JSON scene file, Mitsuba .xml scene, USD .usda scene or glTF .gltf or .glb scene
+    Render(RenderArguments),
+    ///Renders a scene of randomly placed spheres, the same for the same --seed
+    Generate(GenerateArguments),
+    ///Prints the root mean square error of IMAGE's linear radiance against REFERENCE (two images of the same size, such as .exr or .png),
+    ///and their structural similarity (SSIM, from 0 to 1 for identical images), saves a heat map of where they differ to --output if given,
+    ///and exits with 1 if they differ by more than allowed
+    Compare(CompareArguments),
+}
+
+///Arguments of the render command.
+#[derive(Debug, Clone, Args)]
+pub struct RenderArguments {
+    ///Scene file to render
+    pub scene : Option<String>,
+
+    ///Render a built-in example scene instead
+    #[arg(long = "scene", value_name = "NAME", value_parser = NAMES, conflicts_with = "scene")]
+    pub example : Option<String>,
+
+    ///Render SCENE again whenever it is saved, drawing every pass in the terminal
+    #[arg(long, requires = "scene")]
+    pub watch : bool,
+}
+
+///Arguments of the generate command, whose defaults are those of GeneratorSettings::default().
+#[derive(Debug, Clone, Args)]
+pub struct GenerateArguments {
+    ///Number of spheres
+    #[arg(long, value_name = "N", default_value_t = 500)]
+    pub count : usize,
+
+    ///Weights of diffuse, metal, glass and light spheres
+    #[arg(long, value_name = "D,M,G,L", value_parser = mix, default_value = "0.8,0.15,0.05,0")]
+    pub mix : [Float ; 4],
+
+    ///Box the spheres' centers are spread through
+    #[arg(long, value_name = "X0,Y0,Z0,X1,Y1,Z1", value_parser = numbers::<6>, default_value = "-11,0.2,-11,11,0.2,11")]
+    pub bounds : [Float ; 6],
+
+    ///Range of the spheres' radii
+    #[arg(long, value_name = "MIN,MAX", value_parser = numbers::<2>, default_value = "0.2,0.2")]
+    pub radius : [Float ; 2],
+
+    ///Leave out the ground and sky
+    #[arg(long)]
+    pub no_ground : bool,
+}
+
+///Arguments of the compare command.
+#[derive(Debug, Clone, Args)]
+pub struct CompareArguments {
+    ///Reference image
+    pub reference : String,
+
+    ///Image to compare with it
+    pub image : String,
+
+    ///Largest root mean square error allowed
+    #[arg(long, value_name = "RMSE", default_value_t = 0.01)]
+    pub threshold : Float,
+
+    ///Smallest structural similarity allowed (not checked by default)
+    #[arg(long, value_name = "SSIM")]
+    pub min_ssim : Option<Float>,
+}
+
+impl Arguments {
+
+    ///Returns the scene file to render, if one was given.
+    pub fn scene(&self) -> Option<&str> {
+        match &self.command {
+            Some(Command::Render(render)) => render.scene.as_deref(),
+            _ => None,
+        }
+    }
+
+    ///Returns the built-in example scene to render, if one was given with --scene.
+    pub fn example(&self) -> Option<&str> {
+        match &self.command {
+            Some(Command::Render(render)) => render.example.as_deref(),
+            _ => None,
+        }
+    }
+
+    ///Returns whether to render the scene file again whenever it is saved.
+    pub fn watch(&self) -> bool {
+        matches!(&self.command, Some(Command::Render(render)) if render.watch)
+    }
+
+    ///Returns the settings of the scene to generate, with generate, seeded with --seed if given.
+    pub fn generator(&self) -> Option<GeneratorSettings> {
+        let Some(Command::Generate(generate)) = &self.command else {
+            return None;
+        };
+        let [diffuse, metal, glass, light] = generate.mix;
+        let [x0, y0, z0, x1, y1, z1] = generate.bounds;
+        let [smallest, largest] = generate.radius;
+        let defaults = GeneratorSettings::default();
+        Some(GeneratorSettings {
+            count : generate.count,
+            diffuse,
+            metal,
+            glass,
+            light,
+            min : Point3::new(x0.min(x1), y0.min(y1), z0.min(z1)),
+            max : Point3::new(x0.max(x1), y0.max(y1), z0.max(z1)),
+            radius : (smallest.min(largest), smallest.max(largest)),
+            ground : !generate.no_ground,
+            seed : self.seed.unwrap_or(defaults.seed),
+            ..defaults
+        })
+    }
+
+    ///Returns the images to compare and how closely they must match, with compare.
+    pub fn compare(&self) -> Option<CompareSettings> {
+        let Some(Command::Compare(compare)) = &self.command else {
+            return None;
+        };
+        Some(CompareSettings {images : vec![compare.reference.clone(), compare.image.clone()], threshold : compare.threshold, min_ssim : compare.min_ssim})
+    }
+
+    ///Overrides the settings from a scene file with those given on the command line.
+    pub fn apply(&self, settings : &mut FileSettings) {
+        settings.width = self.width.or(settings.width);
+        settings.samples_per_pixel = self.samples_per_pixel.or(settings.samples_per_pixel);
+        settings.max_depth = self.max_depth.or(settings.max_depth);
+        settings.seed = self.seed.or(settings.seed);
+        settings.output = self.output.clone().or(settings.output.take());
+        settings.epsilon = self.epsilon.or(settings.epsilon);
+        settings.spawn_offset = if self.normal_offset {Some(SpawnOffset::NormalOffset)} else {settings.spawn_offset};
+        if self.sun.is_some() || self.turbidity.is_some() {
+            let sky = settings.sky.unwrap_or_default();
+            let sun = self.sun.map_or(sky.sun_direction, |[x, y, z]| Vec3::new(x, y, z));
+            settings.sky = Some(Sky::new(self.turbidity.unwrap_or(sky.turbidity), sun, sky.ground_albedo));
+        }
+    }
+}
+
+///Parses a comma separated list of exactly N numbers.
+fn numbers<const N : usize>(value : &str) -> Result<[Float ; N], String> {
+    let numbers = value.split(',').map(|x| x.trim().parse().map_err(|_| format!("{} isn't a number", x.trim()))).collect::<Result<Vec<Float>, String>>()?;
+    numbers.try_into().map_err(|_| format!("expected {} numbers separated by commas", N))
+}
+
+///Parses the weights of --mix, which can't be negative or all 0.
+fn mix(value : &str) -> Result<[Float ; 4], String> {
+    let weights = numbers::<4>(value)?;
+    if weights.iter().any(|weight| *weight < 0.0) || weights.iter().sum::<Float>() <= 0.0 {
+        return Err(String::from("the weights can't be negative, or all 0"));
+    }
+    Ok(weights)
+}
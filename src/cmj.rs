@@ -0,0 +1,79 @@
+/*
+Module to store Kensler's correlated multi-jittered sampling pattern.
+*/
+
+use crate::vec3::Float;
+
+///Returns the position (with both coordinates from 0 to 1 non-inclusive) of sample s out of samples in the correlated multi-jittered pattern
+///
+/// numbered p. The samples are stratified both on an m by n grid and along each axis on its own, like the rooks on a chessboard that cannot
+/// take each other, and different patterns shuffle the grid differently.
+pub fn cmj(s : u32, samples : u32, p : u32) -> (Float, Float) {
+    let samples = samples.max(1);
+    let m = (samples as Float).sqrt().ceil().max(1.0) as u32;
+    let n = samples.div_ceil(m);
+
+    //Visit the cells in a random order, so that fewer samples than cells are still spread out
+    let s = permute(s % samples, samples, p.wrapping_mul(0x51633e2d));
+
+    let sx = permute(s % m, m, p.wrapping_mul(0xa511e9b3));
+    let sy = permute(s / m, n, p.wrapping_mul(0x63d83595));
+    let jx = random_float(s, p.wrapping_mul(0xa399d265));
+    let jy = random_float(s, p.wrapping_mul(0x711ad6a5));
+    (
+        (((s % m) as Float + (sy as Float + jx) / n as Float) / m as Float).min(1.0 - Float::EPSILON),
+        (((s / m) as Float + (sx as Float + jy) / m as Float) / n as Float).min(1.0 - Float::EPSILON),
+    )
+}
+
+///Pseudorandom permutation of the numbers below l, applied to i, using the hash numbered p.
+fn permute(i : u32, l : u32, p : u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    //Hash within the next power of 2, retrying until the result lands below l
+    let mut i = i;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+///Pseudorandom number from 0 to 1 non-inclusive, for index i of the sequence numbered p.
+fn random_float(i : u32, p : u32) -> Float {
+    let mut i = i ^ p;
+    i ^= i >> 17;
+    i ^= i >> 10;
+    i = i.wrapping_mul(0xb36534e5);
+    i ^= i >> 12;
+    i ^= i >> 21;
+    i = i.wrapping_mul(0x93fc4795);
+    i ^= 0xdf6e307f;
+    i ^= i >> 17;
+    i = i.wrapping_mul(1 | p >> 18);
+    (i as Float * (1.0 / 4_294_967_808.0)).min(1.0 - Float::EPSILON)
+}
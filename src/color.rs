@@ -0,0 +1,82 @@
+/*
+Module to store the color management. Rendering happens in a linear working space with the Rec.709 (sRGB) primaries and a D65 white point:
+8 bit images are decoded from sRGB into it when loaded, and an output transform encodes it for a display when images are saved.
+*/
+
+use crate::vec3::{Color, Float};
+#[cfg(not(feature = "std"))]
+use crate::math::Real;
+
+///Determines how linear colors in the working space are encoded for a display, after tone mapping. Variants include
+///
+/// Srgb: the sRGB transfer curve, for most monitors and the web.
+///
+/// Rec709: the Rec.709 transfer curve, for HD video.
+///
+/// DisplayP3: converts to the wider Display P3 primaries and applies the sRGB transfer curve, for wide gamut displays such as recent phones and laptops.
+/// Colors outside a display's gamut are clipped.
+#[derive(Debug, Clone, Copy)]
+pub enum OutputTransform {
+    Srgb,
+    Rec709,
+    DisplayP3,
+}
+
+impl OutputTransform {
+
+    ///Encodes a linear color in the working space as display values between 0 and 1.
+    pub fn apply(&self, color : Color) -> Color {
+        match self {
+            OutputTransform::Srgb => map_channels(color, linear_to_srgb),
+            OutputTransform::Rec709 => map_channels(color, linear_to_rec709),
+            OutputTransform::DisplayP3 => map_channels(rec709_to_p3(color), linear_to_srgb),
+        }
+    }
+}
+
+///Perceived brightness of a linear RGB color.
+pub fn luminance(c : Color) -> Float {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+///Colors of the heat map heat_map() draws, from coolest to hottest, evenly spread from 0 to 1.
+const HEAT_STOPS : [(Float, Float, Float) ; 5] = [(0.0, 0.0, 0.02), (0.34, 0.06, 0.43), (0.73, 0.21, 0.33), (0.98, 0.55, 0.04), (0.99, 1.0, 0.64)];
+
+///Returns the color of a value from 0 to 1 on a heat map, from black through purple, red and orange to pale yellow, as display values between 0 and 1,
+///
+/// so that differences and noise stand out in images of them. Values outside 0 to 1 are clamped.
+pub fn heat_map(t : Float) -> Color {
+    let x = t.clamp(0.0, 1.0) * (HEAT_STOPS.len() - 1) as Float;
+    let i = (x as usize).min(HEAT_STOPS.len() - 2);
+    let f = x - i as Float;
+    let ((r0, g0, b0), (r1, g1, b1)) = (HEAT_STOPS[i], HEAT_STOPS[i + 1]);
+    Color::new(r0 + (r1 - r0) * f, g0 + (g1 - g0) * f, b0 + (b1 - b0) * f)
+}
+
+///Decodes an sRGB encoded value between 0 and 1 into linear light.
+pub fn srgb_to_linear(x : Float) -> Float {
+    if x <= 0.04045 {x / 12.92} else {((x + 0.055) / 1.055).powf(2.4)}
+}
+
+///Encodes linear light as an sRGB value.
+pub fn linear_to_srgb(x : Float) -> Float {
+    if x <= 0.0031308 {12.92 * x} else {1.055 * x.powf(1.0 / 2.4) - 0.055}
+}
+
+///Encodes linear light as a Rec.709 value.
+pub fn linear_to_rec709(x : Float) -> Float {
+    if x < 0.018 {4.5 * x} else {1.099 * x.powf(0.45) - 0.099}
+}
+
+///Converts a linear color from the Rec.709 primaries to the Display P3 primaries, which share the D65 white point.
+fn rec709_to_p3(color : Color) -> Color {
+    Color::new(
+        0.8224621 * color.x + 0.177538 * color.y,
+        0.0331941 * color.x + 0.9668058 * color.y,
+        0.0170827 * color.x + 0.0723974 * color.y + 0.9105199 * color.z,
+    )
+}
+
+fn map_channels(color : Color, f : impl Fn(Float) -> Float) -> Color {
+    Color::new(f(color.x.clamp(0.0, 1.0)), f(color.y.clamp(0.0, 1.0)), f(color.z.clamp(0.0, 1.0)))
+}
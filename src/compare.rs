@@ -0,0 +1,176 @@
+/*
+Module to store image comparison, for regression testing: a render is compared with a reference render of the same scene (such as one saved
+before a change to the acceleration structures) by the root mean square error (RMSE) of their linear radiance, and by their structural
+similarity (SSIM, after Wang et al.), a perceptual metric that is 1 for identical images and falls as the brightness, contrast and structure
+of their neighbourhoods differ, while shrugging off differences too fine to see. A heat map shows where they differ.
+*/
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use image::{RgbImage, Rgb};
+use crate::vec3::{Color, dot, Float, wide};
+use crate::color::{luminance, linear_to_srgb, heat_map};
+use crate::render::Image;
+use crate::output::read_radiance;
+use crate::error::Result;
+
+///Radius and standard deviation, in pixels, of the Gaussian window SSIM compares neighbourhoods with.
+const SSIM_RADIUS : usize = 5;
+const SSIM_SIGMA : Float = 1.5;
+
+///Settings for comparing two images from the command line: the paths of the reference and of the image compared with it,
+///
+/// which fails if their RMSE is above threshold, or with min_ssim, if their SSIM is below it.
+#[derive(Debug, Clone)]
+pub struct CompareSettings {
+    pub images : Vec<String>,
+    pub threshold : Float,
+    pub min_ssim : Option<Float>,
+}
+
+impl Default for CompareSettings {
+
+    ///Settings allowing an RMSE of 0.01, which renders of the same scene with the same seed stay well within, and not checking SSIM.
+    fn default() -> CompareSettings {
+        CompareSettings {images : vec![], threshold : 0.01, min_ssim : None}
+    }
+}
+
+///How two images of the same size differ: the RMSE of their linear radiance over every channel of every pixel, their mean SSIM,
+///
+/// and the RMSE of each pixel's channels, in rows from the top.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub rmse : Float,
+    pub ssim : Float,
+    pub errors : Vec<Float>,
+    pub width : u32,
+    pub height : u32,
+}
+
+impl Comparison {
+
+    ///Returns whether the images are within threshold RMSE of each other, and, with min_ssim, at least that similar.
+    pub fn passes(&self, threshold : Float, min_ssim : Option<Float>) -> bool {
+        self.rmse <= threshold && min_ssim.is_none_or(|min_ssim| self.ssim >= min_ssim)
+    }
+
+    ///Returns the pixel that differs most (counted from the top left), with its RMSE.
+    pub fn largest(&self) -> ((u32, u32), Float) {
+        let (index, error) = self.errors.iter().enumerate().fold((0, 0.0), |largest, (index, error)| if *error > largest.1 {(index, *error)} else {largest});
+        ((index as u32 % self.width, index as u32 / self.width), error)
+    }
+
+    ///Draws the RMSE of every pixel as a heat map, scaled so that the pixel that differs most is the hottest. Identical images give a black map.
+    pub fn heat_map(&self) -> RgbImage {
+        let (_, largest) = self.largest();
+        RgbImage::from_fn(self.width, self.height, |i, j| {
+            let error = self.errors[(j * self.width + i) as usize];
+            let c = heat_map(if largest > 0.0 {error / largest} else {0.0});
+            Rgb([c.x, c.y, c.z].map(|x| (x * 255.0).round() as u8))
+        })
+    }
+}
+
+///Loads an image to compare as linear radiance, as output::read_radiance() reads it. Floating point formats, such as OpenEXR,
+///
+/// Radiance .hdr and PFM, hold it already, and images in others, such as PNG, are decoded from sRGB.
+pub fn load(path : &str) -> Result<Image> {
+    let img = read_radiance(Path::new(path))?;
+    let (width, height) = img.dimensions();
+    //Images hold their rows from the bottom
+    let radiance = (0..height).rev().flat_map(|j| (0..width).map(move |i| (i, j))).map(|(i, j)| {
+        let pixel = img.get_pixel(i, j);
+        (Color::new(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float), 1.0)
+    }).collect();
+    Ok(Image::from_radiance(width, height, radiance))
+}
+
+///Compares an image with a reference image of the same size. SSIM is worked out on the luminance of the images as a display would show them,
+///
+/// clipped to white, since that is what it was designed for.
+pub fn compare(reference : &Image, image : &Image) -> Result<Comparison> {
+    if (reference.width, reference.height) != (image.width, image.height) {
+        let message = format!("The images are different sizes, {}x{} and {}x{}", reference.width, reference.height, image.width, image.height);
+        return Err(Error::new(ErrorKind::InvalidInput, message).into());
+    }
+    let (width, height) = (reference.width, reference.height);
+    let (a, b) = (reference.pixels(), image.pixels());
+    let errors = a.iter().zip(&b).map(|((x, _), (y, _))| {
+        let difference = *x - *y;
+        (dot(difference, difference) / 3.0).sqrt()
+    }).collect::<Vec<_>>();
+    let rmse = (errors.iter().map(|error| wide(error * error)).sum::<f64>() / errors.len().max(1) as f64).sqrt() as Float;
+
+    let luma = |pixels : &[(Color, Float)]| pixels.iter().map(|(c, _)| linear_to_srgb(luminance(*c).clamp(0.0, 1.0))).collect::<Vec<_>>();
+    let ssim = ssim(&luma(&a), &luma(&b), width as usize, height as usize);
+    Ok(Comparison {rmse, ssim, errors, width, height})
+}
+
+///Returns the mean SSIM of two grayscale images, given as display values between 0 and 1 in rows.
+fn ssim(x : &[Float], y : &[Float], width : usize, height : usize) -> Float {
+    const C1 : Float = 0.01 * 0.01;
+    const C2 : Float = 0.03 * 0.03;
+    let products = |f : &dyn Fn(usize) -> Float| blur(&(0..x.len()).map(f).collect::<Vec<_>>(), width, height);
+    let (mean_x, mean_y) = (blur(x, width, height), blur(y, width, height));
+    let (xx, yy, xy) = (products(&|i| x[i] * x[i]), products(&|i| y[i] * y[i]), products(&|i| x[i] * y[i]));
+    let total = (0..x.len()).map(|i| {
+        let (mx, my) = (mean_x[i], mean_y[i]);
+        let (variance_x, variance_y, covariance) = (xx[i] - mx * mx, yy[i] - my * my, xy[i] - mx * my);
+        wide((2.0 * mx * my + C1) * (2.0 * covariance + C2) / ((mx * mx + my * my + C1) * (variance_x + variance_y + C2)))
+    }).sum::<f64>();
+    (total / x.len().max(1) as f64) as Float
+}
+
+///Blurs a grayscale image with SSIM's Gaussian window, one axis at a time, reweighting the window where it runs off the image.
+fn blur(values : &[Float], width : usize, height : usize) -> Vec<Float> {
+    let weights = (0..=2 * SSIM_RADIUS).map(|k| {
+        let d = k as Float - SSIM_RADIUS as Float;
+        (-d * d / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp()
+    }).collect::<Vec<_>>();
+    let filter = |value : &dyn Fn(usize) -> Float, length : usize, center : usize| {
+        let (mut sum, mut total) = (0.0, 0.0);
+        for (k, weight) in weights.iter().enumerate() {
+            if let Some(position) = (center + k).checked_sub(SSIM_RADIUS).filter(|position| *position < length) {
+                sum += weight * value(position);
+                total += weight;
+            }
+        }
+        sum / total
+    };
+    let rows = (0..width * height).map(|index| filter(&|i| values[index / width * width + i], width, index % width)).collect::<Vec<_>>();
+    (0..width * height).map(|index| filter(&|j| rows[j * width + index % width], height, index / width)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::env::temp_dir;
+    use crate::vec3::{Color, Float};
+    use crate::color::srgb_to_linear;
+    use crate::render::{Image, SaveSettings};
+    use super::{load, compare};
+
+    ///Saves a 2x2 image in each output format and loads it back, checking nothing is lost on the way. 8 bit formats get the colors of
+    ///
+    /// sRGB codes they store exactly (they round down, so not all do), and floating point ones brighter colors whose channels are powers of two,
+    /// which half floats and RGBE store exactly.
+    #[test]
+    fn output_formats_round_trip() {
+        let directory = temp_dir().join(format!("rust_tracer_compare_{}", std::process::id()));
+        create_dir_all(&directory).unwrap();
+        let image = |colors : [Color ; 4]| Image::from_radiance(2, 2, colors.iter().map(|color| (*color, 1.0)).collect());
+        //Decoded as loading them does, through 32 bit floats
+        let code = |c : u8| srgb_to_linear((c as f32 / 255.0) as Float) as f32 as Float;
+        let display = image([Color::new(0.0, 0.0, 0.0), Color::new(code(254), 0.0, code(128)), Color::new(code(64), code(254), 0.0), Color::new(code(128), code(128), code(64))]);
+        let radiance = image([Color::new(4.0, 0.5, 0.25), Color::new(0.0, 2.0, 1.0), Color::new(16.0, 16.0, 16.0), Color::new(0.125, 0.0, 0.0625)]);
+
+        for (extension, original) in [("png", &display), ("ppm", &display), ("exr", &radiance), ("hdr", &radiance), ("pfm", &radiance)] {
+            let path = directory.join(format!("image.{}", extension)).to_string_lossy().into_owned();
+            original.save(&path, &SaveSettings::default(), &[]).unwrap();
+            let comparison = compare(original, &load(&path).unwrap()).unwrap();
+            assert_eq!(comparison.rmse, 0.0, "{} doesn't round trip", extension);
+        }
+        remove_dir_all(&directory).unwrap();
+    }
+}
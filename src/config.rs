@@ -0,0 +1,174 @@
+/*
+Module to store the render settings file, which holds a project's default settings so they don't have to be changed in main(). RustTracer reads
+render.toml from the working directory if there is one, or the file given with --config, which looks like
+
+[image]
+width = 1920
+aspect_ratio = 1.7778
+output = "renders/shot.exr"
+
+[render]
+samples_per_pixel = 256
+max_depth = 32
+sampler = "sobol"
+seed = 7
+threads = 8
+background = true
+epsilon = 0.01
+normal_offset = true
+cache = ".cache"
+
+[output]
+tone_map = "aces"
+exposure_compensation = 0.5
+transform = "srgb"
+png_bits = 16
+exr_precision = "full"
+exr_compression = "piz"
+
+where every table and setting is optional. Samplers are random, stratified, sobol, halton (with Owen scrambling), halton-faure and cmj;
+tone maps are linear, reinhard, aces and filmic; transforms are srgb, rec709 and display-p3; EXR precisions are half and full,
+and compressions uncompressed, rle, zip and piz. Background renders at the lowest priority, so that the machine stays usable,
+and cache keeps decoded assets in that directory (see cache.rs). Epsilon and normal_offset control how rays leaving surfaces avoid hitting them again
+(see ray::SpawnOffset). Settings left out keep their values from main(); a scene file's settings override these,
+and the command line overrides both.
+*/
+
+use std::fs::read_to_string;
+use std::io::{Error, ErrorKind, Result};
+use serde::Deserialize;
+use crate::scene_file::FileSettings;
+use crate::sampler::SamplerKind;
+use crate::halton::HaltonPermutation;
+use crate::tonemap::ToneMap;
+use crate::color::OutputTransform;
+use crate::output::{ExrPrecision, ExrCompression};
+use crate::vec3::Float;
+use crate::ray::SpawnOffset;
+
+///File read when --config isn't given, if it exists.
+pub const DEFAULT_PATH : &str = "render.toml";
+
+///Settings from a render settings file. Any it leaves out keep the values set in main().
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub settings : FileSettings,
+    pub threads : Option<usize>,
+    pub background : Option<bool>,
+    pub cache : Option<String>,
+    pub sampler : Option<SamplerKind>,
+    pub tone_map : Option<ToneMap>,
+    pub exposure_compensation : Option<Float>,
+    pub transform : Option<OutputTransform>,
+    pub png_bits : Option<u32>,
+    pub exr_precision : Option<ExrPrecision>,
+    pub exr_compression : Option<ExrCompression>,
+}
+
+///Render settings file as written, before names are looked up. Unknown tables and settings are errors, so that typos don't go unnoticed.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    image : ImageTable,
+    render : RenderTable,
+    output : OutputTable,
+}
+
+///The [image] table.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ImageTable {
+    width : Option<u32>,
+    aspect_ratio : Option<Float>,
+    output : Option<String>,
+}
+
+///The [render] table.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RenderTable {
+    samples_per_pixel : Option<u32>,
+    max_depth : Option<u32>,
+    sampler : Option<String>,
+    seed : Option<u64>,
+    threads : Option<usize>,
+    background : Option<bool>,
+    cache : Option<String>,
+    epsilon : Option<Float>,
+    normal_offset : Option<bool>,
+}
+
+///The [output] table.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct OutputTable {
+    tone_map : Option<String>,
+    exposure_compensation : Option<Float>,
+    transform : Option<String>,
+    png_bits : Option<u32>,
+    exr_precision : Option<String>,
+    exr_compression : Option<String>,
+}
+
+impl Config {
+
+    ///Loads a render settings file. Unknown tables, settings and names are errors, so that typos don't go unnoticed.
+    pub fn load(path : &str) -> Result<Config> {
+        let file : File = toml::from_str(&read_to_string(path)?).map_err(|error| invalid(&error.to_string()))?;
+        let name = |key : &str, text : &Option<String>, names : &[&str]| -> Result<Option<usize>> {
+            text.as_ref().map(|text| names.iter().position(|name| name == text)
+                .ok_or_else(|| invalid(&format!("unknown {} {} (expected one of {})", key, text, names.join(", "))))).transpose()
+        };
+        let (image, render, output) = (file.image, file.render, file.output);
+
+        let settings = FileSettings {
+            width : image.width,
+            aspect_ratio : image.aspect_ratio,
+            output : image.output,
+            samples_per_pixel : render.samples_per_pixel.map(|x| x.min(i32::MAX as u32) as i32),
+            max_depth : render.max_depth.map(|x| x.min(i32::MAX as u32) as i32),
+            seed : render.seed,
+            epsilon : render.epsilon,
+            spawn_offset : render.normal_offset.map(|offset| if offset {SpawnOffset::NormalOffset} else {SpawnOffset::Distance}),
+            ..FileSettings::default()
+        };
+        let samplers = [SamplerKind::Random, SamplerKind::Stratified, SamplerKind::Sobol, SamplerKind::Halton(HaltonPermutation::Owen),
+            SamplerKind::Halton(HaltonPermutation::Faure), SamplerKind::CorrelatedMultiJittered];
+        let tone_maps = [ToneMap::Linear, ToneMap::Reinhard, ToneMap::Aces, ToneMap::Filmic];
+        let transforms = [OutputTransform::Srgb, OutputTransform::Rec709, OutputTransform::DisplayP3];
+        let compressions = [ExrCompression::Uncompressed, ExrCompression::Rle, ExrCompression::Zip, ExrCompression::Piz];
+        if output.png_bits.is_some_and(|bits| bits != 8 && bits != 16) {
+            return Err(invalid("output.png_bits must be 8 or 16"));
+        }
+
+        Ok(Config {
+            settings,
+            threads : render.threads,
+            background : render.background,
+            cache : render.cache,
+            sampler : name("render.sampler", &render.sampler, &["random", "stratified", "sobol", "halton", "halton-faure", "cmj"])?.map(|i| samplers[i]),
+            tone_map : name("output.tone_map", &output.tone_map, &["linear", "reinhard", "aces", "filmic"])?.map(|i| tone_maps[i]),
+            exposure_compensation : output.exposure_compensation,
+            transform : name("output.transform", &output.transform, &["srgb", "rec709", "display-p3"])?.map(|i| transforms[i]),
+            png_bits : output.png_bits,
+            exr_precision : name("output.exr_precision", &output.exr_precision, &["half", "full"])?.map(|i| [ExrPrecision::Half, ExrPrecision::Full][i]),
+            exr_compression : name("output.exr_compression", &output.exr_compression, &["uncompressed", "rle", "zip", "piz"])?.map(|i| compressions[i]),
+        })
+    }
+
+    ///Fills in the settings a scene file leaves out with those from this file.
+    pub fn apply(&self, settings : &mut FileSettings) {
+        settings.width = settings.width.or(self.settings.width);
+        settings.aspect_ratio = settings.aspect_ratio.or(self.settings.aspect_ratio);
+        settings.samples_per_pixel = settings.samples_per_pixel.or(self.settings.samples_per_pixel);
+        settings.max_depth = settings.max_depth.or(self.settings.max_depth);
+        settings.seed = settings.seed.or(self.settings.seed);
+        settings.output = settings.output.take().or_else(|| self.settings.output.clone());
+        settings.epsilon = settings.epsilon.or(self.settings.epsilon);
+        settings.spawn_offset = settings.spawn_offset.or(self.settings.spawn_offset);
+    }
+}
+
+fn invalid(message : &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid render settings file: {}", message))
+}
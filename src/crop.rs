@@ -0,0 +1,38 @@
+/*
+Module to store crop windows, which limit rendering to part of the image.
+*/
+
+use crate::vec3::Float;
+
+///Part of the image to render, so that one object can be worked on without paying for the whole frame. Variants include
+///
+/// Pixels: the rectangle from (x0, y0) to (x1, y1) non-inclusive in pixels from the top left corner, as for a sample budget's regions.
+///
+/// Normalized: the same rectangle as fractions of the image's width and height, from 0 to 1, so that it stays put when the resolution changes.
+#[derive(Debug, Clone, Copy)]
+pub enum CropWindow {
+    Pixels(u32, u32, u32, u32),
+    Normalized(Float, Float, Float, Float),
+}
+
+impl CropWindow {
+
+    ///Returns the window's corners in pixels, (x0, y0) and (x1, y1), within an image_width by image_height image.
+    pub fn bounds(&self, image_width : u32, image_height : u32) -> (u32, u32, u32, u32) {
+        let (x0, y0, x1, y1) = match *self {
+            CropWindow::Pixels(x0, y0, x1, y1) => (x0, y0, x1, y1),
+            CropWindow::Normalized(x0, y0, x1, y1) => {
+                let scale = |t : Float, size : u32| (t.clamp(0.0, 1.0) * size as Float).round() as u32;
+                (scale(x0, image_width), scale(y0, image_height), scale(x1, image_width), scale(y1, image_height))
+            },
+        };
+        (x0.min(image_width), y0.min(image_height), x1.min(image_width), y1.min(image_height))
+    }
+
+    ///Returns whether pixel (i, j) of an image_width by image_height image is in the window, counting j from the bottom as the render loop does.
+    pub fn contains(&self, i : u32, j : u32, image_width : u32, image_height : u32) -> bool {
+        let (x0, y0, x1, y1) = self.bounds(image_width, image_height);
+        let y = image_height - j - 1;
+        i >= x0 && i < x1 && y >= y0 && y < y1
+    }
+}
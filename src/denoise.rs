@@ -0,0 +1,80 @@
+/*
+Module to store the built-in denoiser, a joint bilateral filter guided by the albedo, normal and depth of what each pixel sees.
+*/
+
+use rayon::prelude::*;
+use crate::vec3::{Color, Vec3, Float};
+use crate::environment::luminance;
+
+///Albedos below this are treated as this when dividing colors by them, so that dark and black surfaces don't blow up.
+const MIN_ALBEDO : Float = 0.01;
+
+///Settings for the denoiser. Each pixel becomes a weighted average of the pixels within radius of it, where pixels count for less
+///
+/// the farther away they are, and the more their normal, albedo, depth or lighting differ from its own, by as many sigmas as set here.
+/// Smaller sigmas keep edges sharper, but smooth less noise away. Depth differences are relative to the pixel's own depth,
+/// and lighting differences relative to the pixels' combined brightness.
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiseSettings {
+    pub radius : u32,
+    pub normal_sigma : Float,
+    pub albedo_sigma : Float,
+    pub depth_sigma : Float,
+    pub color_sigma : Float,
+}
+
+impl DenoiseSettings {
+
+    ///Creates settings filtering over the given radius, with sigmas that suit most scenes.
+    pub fn new(radius : u32) -> DenoiseSettings {
+        DenoiseSettings {radius, normal_sigma : 0.3, albedo_sigma : 0.1, depth_sigma : 0.1, color_sigma : 1.0}
+    }
+}
+
+///What the denoiser knows about a pixel: its average color, and the average albedo, normal and depth of what it sees (0 where it sees nothing).
+#[derive(Debug, Clone, Copy)]
+pub struct DenoisePixel {
+    pub color : Color,
+    pub albedo : Color,
+    pub normal : Vec3,
+    pub depth : Float,
+}
+
+///Denoises an image of width by height pixels, given row by row, returning the denoised colors in the same order.
+///
+/// Colors are divided by their albedo before filtering and multiplied by it again afterwards, so that only the lighting is smoothed
+/// and textures stay sharp.
+pub fn denoise(pixels : &[DenoisePixel], width : usize, height : usize, settings : DenoiseSettings) -> Vec<Color> {
+    let albedo = |pixel : &DenoisePixel| Color::new(pixel.albedo.x.max(MIN_ALBEDO), pixel.albedo.y.max(MIN_ALBEDO), pixel.albedo.z.max(MIN_ALBEDO));
+    let lighting : Vec<Color> = pixels.iter().map(|pixel| pixel.color / albedo(pixel)).collect();
+    let radius = settings.radius as i64;
+    let spatial_sigma = (settings.radius as Float / 2.0).max(0.5);
+
+    (0..pixels.len()).into_par_iter().map(|index| {
+        let (x, y) = ((index % width) as i64, (index / width) as i64);
+        let center = &pixels[index];
+        let center_lighting = luminance(lighting[index]);
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        let mut total = 0.0;
+
+        for ny in (y - radius).max(0)..(y + radius + 1).min(height as i64) {
+            for nx in (x - radius).max(0)..(x + radius + 1).min(width as i64) {
+                let other_index = ny as usize * width + nx as usize;
+                let other = &pixels[other_index];
+                let (dx, dy) = ((nx - x) as Float, (ny - y) as Float);
+
+                let normal = (center.normal - other.normal).length_squared() / (settings.normal_sigma * settings.normal_sigma);
+                let albedo = (center.albedo - other.albedo).length_squared() / (settings.albedo_sigma * settings.albedo_sigma);
+                let depth = (center.depth - other.depth) / (settings.depth_sigma * center.depth.max(other.depth) + 1e-4);
+                let other_lighting = luminance(lighting[other_index]);
+                let color = (center_lighting - other_lighting) / (settings.color_sigma * (center_lighting + other_lighting) + 1e-4);
+                let distance = (dx * dx + dy * dy) / (spatial_sigma * spatial_sigma);
+
+                let weight = (-0.5 * (distance + normal + albedo + depth * depth + color * color)).exp();
+                sum += lighting[other_index] * weight;
+                total += weight;
+            }
+        }
+        sum / total * albedo(center)
+    }).collect()
+}
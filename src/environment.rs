@@ -0,0 +1,184 @@
+use crate::vec3::consts::PI;
+use std::path::Path;
+use image::ImageResult;
+use crate::vec3::{Vec3, Color, random_2d, Float};
+use crate::output::read_radiance;
+pub use crate::color::luminance;
+
+///Piecewise-constant 1-dimensional distribution built from a list of non-negative weights.
+///
+/// Used to importance sample the rows and columns of an environment map.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    pub func : Vec<Float>,
+    pub cdf : Vec<Float>,
+    pub integral : Float,
+}
+
+impl Distribution1D {
+
+    ///Builds the distribution and its cumulative distribution function from a list of weights.
+    pub fn new(func : Vec<Float>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0 ; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i-1] + func[i-1] / n as Float;
+        }
+        let integral = cdf[n];
+
+        //Fall back to a uniform distribution if every weight is zero
+        if integral == 0.0 {
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as Float / n as Float;
+            }
+        } else {
+            for c in cdf.iter_mut() {
+                *c /= integral;
+            }
+        }
+
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    ///Number of segments in the distribution.
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    ///Maps a uniform random number in [0, 1) to a sample in [0, 1) distributed according to the weights.
+    ///
+    /// Returns the sample, its pdf, and the index of the segment it landed in.
+    pub fn sample(&self, r : Float) -> (Float, Float, usize) {
+        let n = self.count();
+        let offset = self.cdf.partition_point(|c| *c <= r).saturating_sub(1).min(n - 1);
+
+        let mut du = r - self.cdf[offset];
+        let width = self.cdf[offset+1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        ((offset as Float + du) / n as Float, self.pdf(offset), offset)
+    }
+
+    ///Returns the pdf of the segment at the given index.
+    pub fn pdf(&self, index : usize) -> Float {
+        if self.integral == 0.0 {
+            return 1.0;
+        }
+        self.func[index] / self.integral
+    }
+}
+
+///Equirectangular environment map lighting the whole scene from infinitely far away.
+///
+/// Stores a 2-dimensional distribution over the map's pixels so that bright regions (such as the sun) are importance sampled.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub pixels : Vec<Float>,
+    pub width : u32,
+    pub height : u32,
+    pub intensity : Float,
+    pub conditional : Vec<Distribution1D>,
+    pub marginal : Distribution1D,
+}
+
+impl Environment {
+
+    ///Loads an environment map (ideally a Radiance .hdr or OpenEXR file, whose radiance goes beyond white) from disk,
+    ///
+    /// as output::read_radiance() reads it. Images in other formats are decoded from sRGB.
+    pub fn load(path : &str, intensity : Float) -> ImageResult<Environment> {
+        let img = read_radiance(Path::new(path))?;
+        let (width, height) = img.dimensions();
+        let pixels = img.into_raw().into_iter().map(|x| x as Float).collect();
+        Ok(Environment::new(pixels, width, height, intensity))
+    }
+
+    ///Creates an environment map from linear RGB float data, and precomputes its sampling distribution.
+    pub fn new(pixels : Vec<Float>, width : u32, height : u32, intensity : Float) -> Environment {
+        let mut conditional = Vec::with_capacity(height as usize);
+        let mut row_weights = Vec::with_capacity(height as usize);
+
+        for j in 0..height {
+            //Rows near the poles cover less solid angle than rows near the horizon
+            let sin_theta = (PI * (j as Float + 0.5) / height as Float).sin();
+            let row = (0..width).map(|i| {
+                let index = 3 * (j * width + i) as usize;
+                luminance(Color::new(pixels[index], pixels[index+1], pixels[index+2])) * sin_theta
+            }).collect::<Vec<Float>>();
+            let dist = Distribution1D::new(row);
+            row_weights.push(dist.integral);
+            conditional.push(dist);
+        }
+
+        Environment {
+            pixels,
+            width,
+            height,
+            intensity,
+            conditional,
+            marginal : Distribution1D::new(row_weights),
+        }
+    }
+
+    ///Returns the radiance arriving from infinitely far away along the given direction.
+    pub fn value(&self, direction : Vec3) -> Color {
+        let (s, t) = direction_to_st(direction.unit_vector());
+        let i = ((s * self.width as Float) as u32).min(self.width - 1);
+        let j = ((t * self.height as Float) as u32).min(self.height - 1);
+        let index = 3 * (j * self.width + i) as usize;
+        Color::new(self.pixels[index], self.pixels[index+1], self.pixels[index+2]) * self.intensity
+    }
+
+    ///Samples a direction towards the environment, proportionally to its brightness.
+    ///
+    /// Returns the direction along with its solid angle pdf.
+    pub fn sample(&self) -> (Vec3, Float) {
+        let (r1, r2) = random_2d();
+        let (t, pdf_t, j) = self.marginal.sample(r1);
+        let (s, pdf_s, _i) = self.conditional[j].sample(r2);
+
+        let theta = PI * t;
+        let sin_theta = theta.sin();
+        if sin_theta == 0.0 {
+            return (Vec3::new(0.0, 1.0, 0.0), 0.0);
+        }
+
+        (st_to_direction(s, t), pdf_s * pdf_t / (2.0 * PI * PI * sin_theta))
+    }
+
+    ///Returns the solid angle pdf with which sample() would generate the given direction.
+    pub fn pdf_value(&self, direction : Vec3) -> Float {
+        let (s, t) = direction_to_st(direction.unit_vector());
+        let sin_theta = (PI * t).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+
+        let i = ((s * self.width as Float) as usize).min(self.width as usize - 1);
+        let j = ((t * self.height as Float) as usize).min(self.height as usize - 1);
+        self.conditional[j].pdf(i) * self.marginal.pdf(j) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+
+///Maps a unit direction to equirectangular image coordinates, where t = 0 is the top row (straight up).
+///
+/// Uses the same longitude convention as sphere texture coordinates.
+fn direction_to_st(d : Vec3) -> (Float, Float) {
+    let s = ((-d.z).atan2(d.x) + PI) / (2.0 * PI);
+    let t = d.y.clamp(-1.0, 1.0).acos() / PI;
+    (s, t)
+}
+
+///Maps equirectangular image coordinates back to a unit direction.
+fn st_to_direction(s : Float, t : Float) -> Vec3 {
+    let phi = 2.0 * PI * s - PI;
+    let theta = PI * t;
+    Vec3::new(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin())
+}
@@ -0,0 +1,81 @@
+/*
+Module to store the error type of the library, which loading scenes and assets, building scenes and saving images return rather than aborting,
+so that programs using the library, and the command line, can report what went wrong and carry on or exit cleanly.
+Parsers of the file formats (scene files, render settings, Mitsuba, USD and glTF scenes) report invalid files as io::Error of kind InvalidData,
+which become Error::Io.
+*/
+
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use image::ImageError;
+
+///Reasons an operation of the library failed. Variants include
+///
+/// Io: a file couldn't be read or written, or held something invalid.
+///
+/// Image: an image couldn't be decoded or encoded.
+///
+/// Exr: an OpenEXR image couldn't be written.
+///
+/// Png: a PNG image couldn't be written.
+///
+/// Scene: a scene couldn't be built, as when an object uses a texture that was never added.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Image(ImageError),
+    Exr(exr::error::Error),
+    Png(png::EncodingError),
+    Scene(String),
+}
+
+///Result of an operation of the library.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f : &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Image(error) => write!(f, "{}", error),
+            Error::Exr(error) => write!(f, "{}", error),
+            Error::Png(error) => write!(f, "{}", error),
+            Error::Scene(message) => write!(f, "Invalid scene: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Image(error) => Some(error),
+            Error::Exr(error) => Some(error),
+            Error::Png(error) => Some(error),
+            Error::Scene(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error : io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(error : ImageError) -> Error {
+        Error::Image(error)
+    }
+}
+
+impl From<exr::error::Error> for Error {
+    fn from(error : exr::error::Error) -> Error {
+        Error::Exr(error)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(error : png::EncodingError) -> Error {
+        Error::Png(error)
+    }
+}
@@ -0,0 +1,159 @@
+/*
+Module to store the built-in example scenes, which can be rendered by name with --scene, without a scene file. Each shows off different features,
+and with a fixed seed, makes a quick regression scene:
+
+cornell-box: the Cornell box, lit by an area light on its ceiling (emitter sampling and diffuse interreflection);
+random-spheres: a field of small diffuse, metal and glass spheres around three large ones, under a bright sky (depth of field, reflection and refraction);
+solar-system: the sun and inner planets from scene() in main, at its first frame (image textures, and an emissive sphere);
+perlin-spheres: two marble spheres, lit by a rectangular and a spherical light in the dark (Perlin noise);
+smoke-box: the Cornell box with blocks of dark and light smoke in place of its boxes (participating media).
+*/
+
+use std::io::{Error, ErrorKind, Result};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::cache::load_image;
+use crate::vec3::{Vec3, Point3, Color, Float};
+use crate::textures::{Texture, Textures};
+use crate::materials::Material;
+use crate::hitting::Hittable;
+use crate::scene_file::{SceneFile, FileSettings};
+use crate::mitsuba::CONSTANT_RADIUS;
+
+///Names of the example scenes, as given to --scene.
+pub const NAMES : [&str ; 5] = ["cornell-box", "random-spheres", "solar-system", "perlin-spheres", "smoke-box"];
+
+///Builds the example scene with the given name (one of NAMES), with the camera it is meant to be seen from.
+pub fn example(name : &str) -> Result<SceneFile> {
+    match name {
+        "cornell-box" => Ok(cornell_box(false)),
+        "random-spheres" => Ok(random_spheres()),
+        "solar-system" => solar_system(),
+        "perlin-spheres" => Ok(perlin_spheres()),
+        "smoke-box" => Ok(cornell_box(true)),
+        other => Err(Error::new(ErrorKind::InvalidInput, format!("Unknown example scene {} (expected one of {})", other, NAMES.join(", ")))),
+    }
+}
+
+///Camera looking from lookfrom to lookat with +y up, with the given vertical field of view and a pinhole lens.
+fn camera(lookfrom : Point3, lookat : Point3, vfov : Float, aspect_ratio : Float) -> FileSettings {
+    FileSettings {
+        aspect_ratio : Some(aspect_ratio),
+        lookfrom : Some(lookfrom),
+        lookat : Some(lookat),
+        vup : Some(Vec3::new(0.0, 1.0, 0.0)),
+        vfov : Some(vfov),
+        aperture : Some(0.0),
+        ..FileSettings::default()
+    }
+}
+
+fn solid(textures : &mut Textures, r : Float, g : Float, b : Float) -> usize {
+    textures.add(Texture::Solid(Color::new(r, g, b)))
+}
+
+///The Cornell box, 555 units across. With smoke, its boxes are replaced by blocks of smoke, and its light is larger and dimmer to light them.
+fn cornell_box(smoke : bool) -> SceneFile {
+    let mut textures = Textures::new();
+    let red = Material::Lambertian(solid(&mut textures, 0.65, 0.05, 0.05));
+    let white = Material::Lambertian(solid(&mut textures, 0.73, 0.73, 0.73));
+    let green = Material::Lambertian(solid(&mut textures, 0.12, 0.45, 0.15));
+
+    let light = if smoke {
+        Hittable::XZRect(Material::Light(solid(&mut textures, 7.0, 7.0, 7.0)), 113.0, 443.0, 127.0, 432.0, 554.0)
+    } else {
+        Hittable::XZRect(Material::Light(solid(&mut textures, 15.0, 15.0, 15.0)), 213.0, 343.0, 227.0, 332.0, 554.0)
+    };
+    let mut objects = vec![
+        (Hittable::YZRect(green, 0.0, 555.0, 0.0, 555.0, 555.0), false),
+        (Hittable::YZRect(red, 0.0, 555.0, 0.0, 555.0, 0.0), false),
+        (light, true),
+        (Hittable::XZRect(white, 0.0, 555.0, 0.0, 555.0, 0.0), false),
+        (Hittable::XZRect(white, 0.0, 555.0, 0.0, 555.0, 555.0), false),
+        (Hittable::XYRect(white, 0.0, 555.0, 0.0, 555.0, 555.0), false),
+    ];
+
+    let short = Hittable::Box(white, Point3::new(130.0, 0.0, 65.0), Point3::new(295.0, 165.0, 230.0));
+    let tall = Hittable::Box(white, Point3::new(265.0, 0.0, 295.0), Point3::new(430.0, 330.0, 460.0));
+    if smoke {
+        objects.push((Hittable::Medium(Material::Isotropic(solid(&mut textures, 0.0, 0.0, 0.0), 0.0), Box::new(tall), 0.01), false));
+        objects.push((Hittable::Medium(Material::Isotropic(solid(&mut textures, 1.0, 1.0, 1.0), 0.0), Box::new(short), 0.01), false));
+    } else {
+        objects.push((short, false));
+        objects.push((tall, false));
+    }
+
+    SceneFile {settings : camera(Point3::new(278.0, 278.0, -800.0), Point3::new(278.0, 278.0, 0.0), 40.0, 1.0), textures, objects, lights : vec![]}
+}
+
+///The random spheres always come out the same, from a fixed seed.
+fn random_spheres() -> SceneFile {
+    let mut textures = Textures::new();
+    let mut random = StdRng::seed_from_u64(0);
+    let ground = Material::Lambertian(textures.add(Texture::Checker(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9))));
+    let glass = Material::Dielectric(Color::new(1.0, 1.0, 1.0), 1.5, 0.0, 0);
+    let mut objects = vec![(Hittable::Sphere(ground, Point3::new(0.0, -1000.0, 0.0), 1000.0), false)];
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Point3::new(a as Float + 0.9 * random.gen::<Float>(), 0.2, b as Float + 0.9 * random.gen::<Float>());
+            let choice = random.gen::<Float>();
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+                continue;
+            }
+            let mat = if choice < 0.8 {
+                let mut channel = || random.gen::<Float>() * random.gen::<Float>();
+                Material::Lambertian(solid(&mut textures, channel(), channel(), channel()))
+            } else if choice < 0.95 {
+                let mut channel = || random.gen_range(0.5..1.0);
+                Material::Metal(Color::new(channel(), channel(), channel()), random.gen_range(0.0..0.5))
+            } else {
+                glass
+            };
+            objects.push((Hittable::Sphere(mat, center, 0.2), false));
+        }
+    }
+    objects.push((Hittable::Sphere(glass, Point3::new(0.0, 1.0, 0.0), 1.0), false));
+    objects.push((Hittable::Sphere(Material::Lambertian(solid(&mut textures, 0.4, 0.2, 0.1)), Point3::new(-4.0, 1.0, 0.0), 1.0), false));
+    objects.push((Hittable::Sphere(Material::Metal(Color::new(0.7, 0.6, 0.5), 0.0), Point3::new(4.0, 1.0, 0.0), 1.0), false));
+
+    //The sky is an emissive sphere surrounding the scene, found by rays that escape it
+    objects.push((Hittable::Sphere(Material::Light(solid(&mut textures, 0.7, 0.8, 1.0)), Point3::new(0.0, 0.0, 0.0), CONSTANT_RADIUS), false));
+
+    let settings = FileSettings {
+        aperture : Some(0.1),
+        focus_distance : Some(10.0),
+        autofocus : Some(false),
+        ..camera(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 20.0, 1.5)
+    };
+    SceneFile {settings, textures, objects, lights : vec![]}
+}
+
+fn solar_system() -> Result<SceneFile> {
+    let mut textures = Textures::new();
+    let mut image = |path : &str| -> Result<usize> {
+        Ok(textures.add(Texture::Image(load_image(path)?)))
+    };
+    let center = Point3::new(278.0, 278.0, 0.0);
+    let objects = vec![
+        (Hittable::Sphere(Material::Light(image("images/sunmap.jpeg")?), center, 100.0), true),
+        (Hittable::Sphere(Material::Lambertian(image("images/mercurymap.jpeg")?), Point3::new(180.0, 180.0, -50.0), 10.0), false),
+        (Hittable::Sphere(Material::Lambertian(image("images/venusmap.jpeg")?), Point3::new(260.0, 450.0, 20.0), 25.0), false),
+        (Hittable::Sphere(Material::Lambertian(image("images/earthmap.jpeg")?), Point3::new(450.0, 200.0, 10.0), 30.0), false),
+        (Hittable::Sphere(Material::Lambertian(image("images/marsmap.jpeg")?), Point3::new(100.0, 300.0, -25.0), 15.0), false),
+    ];
+    Ok(SceneFile {settings : camera(Point3::new(278.0, 278.0, -800.0), center, 40.0, 1.0), textures, objects, lights : vec![]})
+}
+
+fn perlin_spheres() -> SceneFile {
+    let mut textures = Textures::new();
+    let marble = Material::Lambertian(textures.add(Texture::Noise(Box::default(), 4.0)));
+    let light = Material::Light(solid(&mut textures, 4.0, 4.0, 4.0));
+    let objects = vec![
+        (Hittable::Sphere(marble, Point3::new(0.0, -1000.0, 0.0), 1000.0), false),
+        (Hittable::Sphere(marble, Point3::new(0.0, 2.0, 0.0), 2.0), false),
+        (Hittable::XYRect(light, 3.0, 5.0, 1.0, 3.0, -2.0), true),
+        (Hittable::Sphere(light, Point3::new(0.0, 7.0, 0.0), 2.0), true),
+    ];
+    SceneFile {settings : camera(Point3::new(26.0, 3.0, 6.0), Point3::new(0.0, 2.0, 0.0), 20.0, 1.5), textures, objects, lights : vec![]}
+}
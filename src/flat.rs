@@ -0,0 +1,290 @@
+/*
+Module to store the flattened scene, a Bounding Volume Hierarchy and its spheres and triangles laid out as structures of arrays,
+so that traversal reads only the coordinates it tests rather than whole nodes and Hittable enums.
+*/
+
+use crate::hitting::{Hittable, HitRecord};
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::textures::Textures;
+use crate::bvh::AABB;
+use crate::tree::Tree;
+use crate::vec3::{Vec3, Point3, dot, cross, Float};
+use crate::stats::count_traversal;
+use std::io::Result;
+
+///Maximum depth of the hierarchy that hit() can walk, as for tree::Tree.
+const STACK_SIZE : usize = 64;
+
+///Marks a node that isn't a leaf in FlatNodes::object.
+const INTERIOR : u32 = u32::MAX;
+
+///Where the arrays of a flattened scene keep an object. Variants include
+///
+/// Sphere: a static sphere, by its index in FlatSpheres.
+///
+/// Triangle: a triangle, by its index in FlatTriangles.
+///
+/// Other: any other kind of object, which is tested through Hittable::hit().
+#[derive(Debug, Clone, Copy)]
+pub enum Primitive {
+    Sphere(u32),
+    Triangle(u32),
+    Other,
+}
+
+///Nodes of a flattened Bounding Volume Hierarchy, in depth-first order, so that an interior node's left child directly follows it.
+///
+/// The corners of the boxes are stored one axis at a time (minimum[axis][node]), and leaves hold the index of their object,
+/// or INTERIOR for nodes with children.
+#[derive(Debug, Clone, Default)]
+pub struct FlatNodes {
+    pub minimum : [Vec<Float> ; 3],
+    pub maximum : [Vec<Float> ; 3],
+    pub right : Vec<u32>,
+    pub axis : Vec<u8>,
+    pub object : Vec<u32>,
+}
+
+///Centers, radii and materials of the static spheres in a flattened scene, one axis of the centers at a time.
+#[derive(Debug, Clone, Default)]
+pub struct FlatSpheres {
+    pub center : [Vec<Float> ; 3],
+    pub radius : Vec<Float>,
+    pub material : Vec<Material>,
+}
+
+///First corners and edges of the triangles in a flattened scene, one axis at a time. Texture coordinates and materials,
+///
+/// only needed once a triangle is hit, are read from the object itself.
+#[derive(Debug, Clone, Default)]
+pub struct FlatTriangles {
+    pub corner : [Vec<Float> ; 3],
+    pub edge1 : [Vec<Float> ; 3],
+    pub edge2 : [Vec<Float> ; 3],
+}
+
+///Represents a scene flattened into structures of arrays: a Bounding Volume Hierarchy built as tree::Tree builds one,
+///
+/// and the spheres and triangles it holds. Objects are still built as Hittables, which are kept for every other kind of object
+/// and for the HitRecords of hits, so it finds exactly the hits the Tree would, with fewer cache misses in scenes of many objects.
+#[derive(Debug, Clone, Default)]
+pub struct FlatScene {
+    pub nodes : FlatNodes,
+    pub spheres : FlatSpheres,
+    pub triangles : FlatTriangles,
+    pub objects : Vec<Hittable>,
+    pub primitives : Vec<Primitive>,
+}
+
+impl FlatScene {
+
+    ///Builds a flattened scene from a list of Hittable objects. Fails as Tree::build() does.
+    pub fn build(lst : &mut [Hittable]) -> Result<FlatScene> {
+        let tree = Tree::build(lst)?;
+        let mut flat = FlatScene::default();
+        if !tree.items.is_empty() {
+            flat.flatten(&tree, tree.root);
+        }
+        Ok(flat)
+    }
+
+    ///Recursive helper function that appends the node at index of the tree, and the nodes below it, in depth-first order.
+    fn flatten(&mut self, tree : &Tree, index : usize) {
+        let node = &tree.items[index];
+        let flat_index = self.nodes.right.len();
+        for axis in 0..3 {
+            self.nodes.minimum[axis].push(node.aabb.minimum[axis]);
+            self.nodes.maximum[axis].push(node.aabb.maximum[axis]);
+        }
+        self.nodes.right.push(0);
+        self.nodes.axis.push(node.axis as u8);
+        self.nodes.object.push(INTERIOR);
+
+        if let Some(data) = &node.data {
+            self.nodes.object[flat_index] = self.objects.len() as u32;
+            self.add_object(data);
+            return;
+        }
+        for (side, child) in [node.left, node.right].into_iter().enumerate() {
+            if let Some(child) = child {
+                if side == 1 {
+                    self.nodes.right[flat_index] = self.nodes.right.len() as u32;
+                }
+                self.flatten(tree, child);
+            }
+        }
+    }
+
+    ///Adds an object, copying its shape into the arrays for its kind, if it has any.
+    fn add_object(&mut self, object : &Hittable) {
+        let primitive = match object {
+            Hittable::Sphere(mat, center, radius) => {
+                for axis in 0..3 {
+                    self.spheres.center[axis].push(center[axis]);
+                }
+                self.spheres.radius.push(*radius);
+                self.spheres.material.push(*mat);
+                Primitive::Sphere(self.spheres.radius.len() as u32 - 1)
+            },
+            Hittable::Triangle(_mat, [a, b, c], _uvs) => {
+                let (edge1, edge2) = (*b - *a, *c - *a);
+                for axis in 0..3 {
+                    self.triangles.corner[axis].push(a[axis]);
+                    self.triangles.edge1[axis].push(edge1[axis]);
+                    self.triangles.edge2[axis].push(edge2[axis]);
+                }
+                Primitive::Triangle(self.triangles.corner[0].len() as u32 - 1)
+            },
+            _ => Primitive::Other,
+        };
+        self.objects.push(object.clone());
+        self.primitives.push(primitive);
+    }
+
+    ///Returns the bounding box surrounding every object, or None if there are none.
+    pub fn bounding_box(&self) -> Option<AABB> {
+        if self.nodes.right.is_empty() {
+            return None;
+        }
+        let corner = |corners : &[Vec<Float> ; 3]| Point3::new(corners[0][0], corners[1][0], corners[2][0]);
+        Some(AABB::new(corner(&self.nodes.minimum), corner(&self.nodes.maximum)))
+    }
+
+    ///Returns whether the ray passes through the box of the node at index between t_min and t_max, as AABB::hit() would.
+    fn node_hit(&self, index : usize, r : Ray, t_min : Float, t_max : Float) -> bool {
+        let mut t_mi = t_min;
+        let mut t_ma = t_max;
+        for i in 0..3 {
+            let mut t0 = (self.nodes.minimum[i][index] - r.origin_point[i]) / r.direction[i];
+            let mut t1 = (self.nodes.maximum[i][index] - r.origin_point[i]) / r.direction[i];
+            if r.direction[i].is_sign_negative() {
+                (t0, t1) = (t1, t0);
+            }
+            t_mi = t_mi.max(t0);
+            t_ma = t_ma.min(t1);
+            if t_ma <= t_mi {
+                return false;
+            }
+        }
+        true
+    }
+
+    ///Determines if a ray hits the object at index, filling in rec if it does, with the same arithmetic as Hittable::hit().
+    fn object_hit(&self, index : usize, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, textures : &Textures) -> bool {
+        match self.primitives[index] {
+            Primitive::Sphere(i) => {
+                let i = i as usize;
+                let spheres = &self.spheres;
+                let center = Point3::new(spheres.center[0][i], spheres.center[1][i], spheres.center[2][i]);
+                let radius = spheres.radius[i];
+                let oc = r.origin_point - center;
+                let a = r.direction.length_squared();
+                let half_b = dot(oc, r.direction);
+                let c = oc.length_squared() - radius * radius;
+
+                let discriminant = half_b * half_b - a * c;
+                if discriminant < 0.0 {
+                    return false;
+                }
+                let mut root = (-half_b - discriminant.sqrt()) / a;
+                if root < t_min || t_max < root {
+                    root = (-half_b + discriminant.sqrt()) / a;
+                    if root < t_min || t_max < root {
+                        return false;
+                    }
+                }
+
+                rec.t = root;
+                rec.p = r.at(rec.t);
+                let outward_normal : Vec3 = (rec.p - center) / radius;
+                rec.set_front_face_normal(r, outward_normal);
+                rec.mat = spheres.material[i];
+                self.objects[index].get_uv(outward_normal, &mut rec.u, &mut rec.v);
+                true
+            },
+            Primitive::Triangle(i) => {
+                let i = i as usize;
+                let triangles = &self.triangles;
+                let load = |v : &[Vec<Float> ; 3]| Vec3::new(v[0][i], v[1][i], v[2][i]);
+                let (corner, edge1, edge2) = (load(&triangles.corner), load(&triangles.edge1), load(&triangles.edge2));
+                let p = cross(r.direction, edge2);
+                let determinant = dot(edge1, p);
+                if determinant.abs() < 1e-12 {
+                    return false;
+                }
+                let s = r.origin_point - corner;
+                let beta = dot(s, p) / determinant;
+                if !(0.0..=1.0).contains(&beta) {
+                    return false;
+                }
+                let q = cross(s, edge1);
+                let gamma = dot(r.direction, q) / determinant;
+                if gamma < 0.0 || beta + gamma > 1.0 {
+                    return false;
+                }
+                let t = dot(edge2, q) / determinant;
+                if t < t_min || t > t_max {
+                    return false;
+                }
+
+                let Hittable::Triangle(mat, _, uvs) = &self.objects[index] else {
+                    return false;
+                };
+                let alpha = 1.0 - beta - gamma;
+                rec.u = alpha * uvs[0].0 + beta * uvs[1].0 + gamma * uvs[2].0;
+                rec.v = alpha * uvs[0].1 + beta * uvs[1].1 + gamma * uvs[2].1;
+                rec.t = t;
+                rec.mat = *mat;
+                rec.p = r.at(t);
+                rec.set_front_face_normal(r, cross(edge1, edge2).unit_vector());
+                true
+            },
+            Primitive::Other => self.objects[index].hit(r, t_min, t_max, rec, textures),
+        }
+    }
+
+    ///Determines if a ray hits any object in the scene, filling in rec with the closest hit.
+    ///
+    /// Walks the hierarchy as Tree::hit() does, visiting the child nearer the ray's origin first.
+    pub fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, textures : &Textures) -> bool {
+        if self.nodes.right.is_empty() {
+            return false;
+        }
+
+        let mut stack = [0 ; STACK_SIZE];
+        let mut top = 1;
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        let (mut nodes, mut primitives) = (0, 0);
+
+        while top > 0 {
+            top -= 1;
+            let current = stack[top];
+            nodes += 1;
+            if !self.node_hit(current, r, t_min, closest) {
+                continue;
+            }
+
+            let object = self.nodes.object[current];
+            if object != INTERIOR {
+                primitives += 1;
+                if self.object_hit(object as usize, r, t_min, closest, rec, textures) {
+                    hit_anything = true;
+                    closest = rec.t;
+                    rec.object = object as usize;
+                }
+                continue;
+            }
+
+            //The left child directly follows its parent
+            let (left, right) = (current + 1, self.nodes.right[current] as usize);
+            let (near, far) = if r.direction[self.nodes.axis[current] as usize].is_sign_negative() {(right, left)} else {(left, right)};
+            stack[top] = far;
+            stack[top + 1] = near;
+            top += 2;
+        }
+        count_traversal(nodes, primitives);
+        hit_anything
+    }
+}
@@ -0,0 +1,49 @@
+/*
+Module to store the scene-wide fog, which fades objects into a fog color the further away they are, for depth without enclosing the scene in a medium.
+Fog thins out exponentially with height, so that it can lie in valleys and leave the sky clear, and its density along a ray is worked out exactly
+rather than by stepping along it. Fog only dims and brightens light along rays, without scattering it in new directions, so it casts no shadows.
+*/
+
+use crate::vec3::{Color, Float};
+use crate::ray::Ray;
+
+///Fog of the given color, which is the light it scatters towards the camera where it is thick, and density, which is how much of the light
+///
+/// along a ray it stops per unit of length at the height base. Density falls by a factor of e for every 1 / falloff units above base
+/// (and rises below it), or stays the same everywhere with a falloff of 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Fog {
+    pub color : Color,
+    pub density : Float,
+    pub falloff : Float,
+    pub base : Float,
+}
+
+impl Fog {
+
+    pub fn new(color : Color, density : Float, falloff : Float, base : Float) -> Fog {
+        Fog {color, density, falloff, base}
+    }
+
+    ///Returns how much of the light from a point t along a ray reaches its origin through the fog, and the light the fog adds along the way.
+    ///
+    /// Rays that never hit anything have a t of infinity, and are hidden by fog that doesn't thin out along them.
+    pub fn along(&self, r : Ray, t : Float) -> (Color, Color) {
+        let length = t * r.direction.length();
+        let rise = if length > 0.0 {r.direction.y / r.direction.length()} else {0.0};
+        let start = self.density * (-self.falloff * (r.origin_point.y - self.base)).exp();
+
+        //Density integrated along the ray, falling exponentially with height
+        let k = self.falloff * rise;
+        let depth = if k.abs() < 1e-6 {
+            start * length
+        } else if length.is_infinite() {
+            if k > 0.0 {start / k} else {Float::INFINITY}
+        } else {
+            start * (1.0 - (-k * length).exp()) / k
+        };
+
+        let transmittance = if depth.is_finite() {(-depth).exp()} else {0.0};
+        (Color::new(1.0, 1.0, 1.0) * transmittance, self.color * (1.0 - transmittance))
+    }
+}
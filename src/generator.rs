@@ -0,0 +1,103 @@
+/*
+Module to store the random scene generator, which scatters any number of spheres with a mix of materials through a box, for benchmarks
+and for stress-testing the acceleration structures with tens of thousands of objects. The same settings always generate the same scene.
+*/
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::vec3::{Vec3, Point3, Color, Float};
+use crate::textures::{Texture, Textures};
+use crate::materials::Material;
+use crate::hitting::Hittable;
+use crate::scene_file::{SceneFile, FileSettings};
+use crate::mitsuba::CONSTANT_RADIUS;
+
+///Settings for a generated scene. Its spheres' centers are spread uniformly through the box from min to max, with radii spread uniformly
+///
+/// between the two given, and materials chosen with the given weights, which needn't add up to 1. Diffuse spheres get random colors,
+/// metal spheres random tints and fuzz, and lights random colors of the given brightness; lights are sampled as emitters.
+/// With ground, the spheres sit on a large grey sphere just below the box, and the scene is lit by a sky surrounding it.
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorSettings {
+    pub count : usize,
+    pub diffuse : Float,
+    pub metal : Float,
+    pub glass : Float,
+    pub light : Float,
+    pub brightness : Float,
+    pub min : Point3,
+    pub max : Point3,
+    pub radius : (Float, Float),
+    pub ground : bool,
+    pub seed : u64,
+}
+
+impl Default for GeneratorSettings {
+
+    ///Settings for about the scene of the random-spheres example: small spheres on a ground 22 units across, mostly diffuse.
+    fn default() -> GeneratorSettings {
+        GeneratorSettings {
+            count : 500,
+            diffuse : 0.8,
+            metal : 0.15,
+            glass : 0.05,
+            light : 0.0,
+            brightness : 4.0,
+            min : Point3::new(-11.0, 0.2, -11.0),
+            max : Point3::new(11.0, 0.2, 11.0),
+            radius : (0.2, 0.2),
+            ground : true,
+            seed : 0,
+        }
+    }
+}
+
+///Generates a scene, with a camera looking at the middle of the box from above and in front of it, far enough away to see all of it.
+pub fn generate(settings : &GeneratorSettings) -> SceneFile {
+    let mut random = StdRng::seed_from_u64(settings.seed);
+    let (min, max) = (settings.min, settings.max);
+    let (smallest, largest) = settings.radius;
+    let total = settings.diffuse + settings.metal + settings.glass + settings.light;
+    let glass = Material::Dielectric(Color::new(1.0, 1.0, 1.0), 1.5, 0.0, 0);
+    let mut textures = Textures::new();
+    let mut objects = Vec::with_capacity(settings.count + 2);
+
+    let mut between = |a : Float, b : Float| if a < b {random.gen_range(a..b)} else {a};
+    for _ in 0..settings.count {
+        let center = Point3::new(between(min.x, max.x), between(min.y, max.y), between(min.z, max.z));
+        let radius = between(smallest, largest);
+        let choice = between(0.0, total);
+        let (material, emitter) = if choice < settings.diffuse {
+            let color = Color::new(between(0.0, 1.0) * between(0.0, 1.0), between(0.0, 1.0) * between(0.0, 1.0), between(0.0, 1.0) * between(0.0, 1.0));
+            (Material::Lambertian(textures.add(Texture::Solid(color))), false)
+        } else if choice < settings.diffuse + settings.metal {
+            (Material::Metal(Color::new(between(0.5, 1.0), between(0.5, 1.0), between(0.5, 1.0)), between(0.0, 0.5)), false)
+        } else if choice < settings.diffuse + settings.metal + settings.glass || settings.light <= 0.0 {
+            (glass, false)
+        } else {
+            let color = Color::new(between(0.2, 1.0), between(0.2, 1.0), between(0.2, 1.0));
+            (Material::Light(textures.add(Texture::Solid(color * settings.brightness))), true)
+        };
+        objects.push((Hittable::Sphere(material, center, radius), emitter));
+    }
+
+    let center = (min + max) * 0.5;
+    if settings.ground {
+        let ground = Material::Lambertian(textures.add(Texture::Solid(Color::new(0.5, 0.5, 0.5))));
+        let radius = (50.0 * (max - min).length()).max(1000.0);
+        objects.push((Hittable::Sphere(ground, Point3::new(center.x, min.y - largest - radius, center.z), radius), false));
+        objects.push((Hittable::Sphere(Material::Light(textures.add(Texture::Solid(Color::new(0.7, 0.8, 1.0)))), center, CONSTANT_RADIUS), false));
+    }
+
+    let size = (max - min).length() + 2.0 * largest;
+    let lookfrom = center + Vec3::new(0.0, 0.4, -1.0).unit_vector() * size * 1.2;
+    let settings = FileSettings {
+        lookfrom : Some(lookfrom),
+        lookat : Some(center),
+        vup : Some(Vec3::new(0.0, 1.0, 0.0)),
+        vfov : Some(40.0),
+        aperture : Some(0.0),
+        ..FileSettings::default()
+    };
+    SceneFile {settings, textures, objects, lights : vec![]}
+}
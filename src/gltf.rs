@@ -0,0 +1,609 @@
+/*
+Module to store the importer for glTF 2.0 scenes (.gltf files, with their buffers and images in files beside them or embedded as data URIs,
+and binary .glb files), which loads the subset of them this tracer can represent into a scene_file::SceneFile:
+
+meshes: triangle, strip and fan primitives, split into flat triangles with the texture coordinates their material's textures use,
+placed by the transforms of the nodes that hold them;
+materials: the metallic-roughness model, mapped onto the tracer's materials as described below;
+cameras: the first perspective camera in the scene;
+lights: the point, spot and directional lights of KHR_lights_punctual.
+
+Base color and emissive textures are decoded from sRGB, and metallic-roughness and normal textures read as linear, as glTF specifies, with every factor
+multiplying its texture (and a normal texture's scale scaling the slope of its normals). Materials that are emissive (with KHR_materials_emissive_strength
+scaling their emissive factor) become lights with their emissive texture; those with a KHR_materials_transmission of at least 0.5 become glass
+tinted by their base color, with the index of refraction from KHR_materials_ior; and the rest become Material::Pbr, with their base color,
+metallic-roughness and normal textures, whose reflections are as blurry as roughness squared (the alpha of the GGX distribution roughness
+is defined by, which blurs reflections about as much as other viewers do). A material's textures must all use the same set of texture coordinates.
+
+Textures wrap as their samplers say. Those with a KHR_texture_transform offset and scale show that region of their image, as for one chart
+of a texture atlas, wrapping within the region rather than into the charts around it; each image is loaded once however many regions use it.
+
+Triangles are flat shaded apart from their normal maps, whose tangents follow the texture coordinates (vertex normals and tangents aren't read).
+Occlusion textures are left out, since path tracing works out how much light reaches each point itself, as are alpha modes and rotated
+texture transforms. Sparse accessors, skins and morph targets aren't supported.
+*/
+
+use std::collections::HashMap;
+use std::fs::read;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use serde_json::{Value, Map};
+use crate::vec3::{Vec3, Point3, Color, Float, wide};
+use crate::color::{srgb_to_linear, linear_to_srgb};
+use crate::textures::{Texture, Textures, TextureHandle, Wrap};
+use crate::materials::Material;
+use crate::hitting::Hittable;
+use crate::lights::Light;
+use crate::scene_file::{SceneFile, FileSettings};
+use crate::mitsuba::{Matrix, IDENTITY, multiply, transform_point, transform_vector};
+use crate::usd::quaternion;
+use crate::cache::{self, Corner, content_hash, decode_image};
+
+///Extensions a scene can require and still be imported.
+const EXTENSIONS : [&str ; 5] = ["KHR_lights_punctual", "KHR_materials_emissive_strength", "KHR_materials_transmission", "KHR_materials_ior", "KHR_texture_transform"];
+
+///Loads a glTF scene, along with the buffers and images it refers to.
+pub fn load(path : &str) -> Result<SceneFile> {
+    let bytes = read(path)?;
+    let (json, binary) = if bytes.starts_with(b"glTF") {glb(&bytes)?} else {(serde_json::from_slice(&bytes).map_err(|error| invalid(&error.to_string()))?, None)};
+    let version = json.get("asset").and_then(|asset| asset.get("version")).and_then(Value::as_str).unwrap_or("");
+    if !version.starts_with("2.") {
+        return Err(invalid(&format!("only glTF 2.0 is supported, not version {}", version)));
+    }
+    for extension in list(&json, "extensionsRequired")? {
+        let extension = text(extension)?;
+        if !EXTENSIONS.contains(&extension) {
+            return Err(invalid(&format!("the scene requires {}, which isn't supported", extension)));
+        }
+    }
+
+    let directory = Path::new(path).parent().map_or(PathBuf::new(), Path::to_path_buf);
+    let buffers = list(&json, "buffers")?.iter().map(|buffer| match buffer.get("uri") {
+        Some(uri) => resource(text(uri)?, &directory),
+        None => binary.clone().ok_or_else(|| invalid("a buffer without a uri outside a .glb file")),
+    }).collect::<Result<Vec<_>>>()?;
+
+    //Meshes are cached under a hash of the scene and its buffers
+    let hash = if cache::enabled() {
+        buffers.iter().fold(content_hash(&bytes), |hash, buffer| content_hash(&[hash.to_le_bytes(), content_hash(buffer).to_le_bytes()].concat()))
+    } else {0};
+
+    let mut importer = Importer {
+        json : &json,
+        buffers,
+        hash,
+        directory,
+        materials : HashMap::new(),
+        images : HashMap::new(),
+        textures : HashMap::new(),
+        camera : false,
+        scene : SceneFile {settings : FileSettings::default(), textures : Textures::new(), objects : vec![], lights : vec![]},
+    };
+
+    //Without a scene to show, every node that isn't another's child is shown
+    let scenes = list(&json, "scenes")?;
+    let scene = json.get("scene").map(whole).transpose()?.or(if scenes.is_empty() {None} else {Some(0)});
+    let roots = match scene {
+        Some(scene) => indices(scenes.get(scene).ok_or_else(|| invalid(&format!("scenes[{}] doesn't exist", scene)))?, "nodes")?,
+        None => {
+            let children = list(&json, "nodes")?.iter().map(|node| indices(node, "children")).collect::<Result<Vec<_>>>()?.concat();
+            (0..list(&json, "nodes")?.len()).filter(|node| !children.contains(node)).collect()
+        },
+    };
+    for root in roots {
+        importer.node(root, &IDENTITY, 0)?;
+    }
+    Ok(importer.scene)
+}
+
+///Imports a scene's nodes, keeping the materials converted and images decoded so far.
+struct Importer<'a> {
+    json : &'a Value,
+    buffers : Vec<Vec<u8>>,
+    hash : u64,
+    directory : PathBuf,
+    materials : HashMap<Option<usize>, (Material, bool, usize)>,
+    images : HashMap<usize, TextureHandle>,
+    textures : HashMap<(usize, [u64 ; 3], Encoding), usize>,
+    camera : bool,
+    scene : SceneFile,
+}
+
+///How the values in a texture's image are stored, which says how its factor applies. Variants include
+///
+/// Srgb: colors, encoded as sRGB, which the factor multiplies once decoded.
+///
+/// Linear: data, such as metalness and roughness, which the factor multiplies as it is.
+///
+/// Normal: normals, each axis stored from -1 to 1 as 0 to 1, whose axes the factor multiplies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Encoding {
+    Srgb,
+    Linear,
+    Normal,
+}
+
+impl<'a> Importer<'a> {
+
+    ///Imports a node and its children, given its parent's transform.
+    fn node(&mut self, index : usize, parent : &Matrix, depth : usize) -> Result<()> {
+        if depth > list(self.json, "nodes")?.len() {
+            return Err(invalid("the node hierarchy has a cycle"));
+        }
+        let node = self.item("nodes", index)?;
+        let transform = multiply(parent, &local(node)?);
+        if let Some(mesh) = node.get("mesh") {
+            self.mesh(whole(mesh)?, &transform)?;
+        }
+        if let Some(camera) = node.get("camera") {
+            self.camera(whole(camera)?, &transform)?;
+        }
+        if let Some(light) = node.get("extensions").and_then(|extensions| extensions.get("KHR_lights_punctual")) {
+            self.light(whole(field(light, "light")?)?, &transform)?;
+        }
+        for child in indices(node, "children")? {
+            self.node(child, &transform, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    ///Splits a mesh's primitives into triangles (or loads them from the asset cache) and places them with the transform.
+    ///
+    /// Points and lines have no surface to hit, so they are left out.
+    fn mesh(&mut self, index : usize, transform : &Matrix) -> Result<()> {
+        let mesh = self.item("meshes", index)?;
+        for (number, primitive) in list(mesh, "primitives")?.iter().enumerate() {
+            let mode = primitive.get("mode").map_or(Ok(4), whole)?;
+            if !(4..=6).contains(&mode) {
+                continue;
+            }
+            let (mat, emissive, set) = self.material(primitive.get("material").map(whole).transpose()?)?;
+            let key = content_hash(format!("{:016x} {} {} {}", self.hash, index, number, set).as_bytes());
+            for triangle in cache::triangles(key, || self.triangulate(index, primitive, mode, set))? {
+                let points = triangle.map(|c| transform_point(transform, Point3::new(c[0], c[1], c[2])));
+                self.scene.objects.push((Hittable::Triangle(mat, points, triangle.map(|c| (c[3], c[4]))), emissive));
+            }
+        }
+        Ok(())
+    }
+
+    ///Returns the triangles of one of a mesh's primitives, drawn in the given mode (triangles, a strip or a fan), with their positions
+    ///
+    /// before the node's transform and the texture coordinates of the given set.
+    fn triangulate(&self, index : usize, primitive : &Value, mode : usize, set : usize) -> Result<Vec<[Corner ; 3]>> {
+        let attributes = field(primitive, "attributes")?;
+        let positions = self.accessor(whole(field(attributes, "POSITION")?)?, 3)?;
+        let points = positions.chunks_exact(3).map(|p| [p[0] as Float, p[1] as Float, p[2] as Float]).collect::<Vec<_>>();
+
+        //Texture coordinates start at the top left of the image, where the tracer's start at the bottom left
+        let uvs = match attributes.get(format!("TEXCOORD_{}", set)) {
+            Some(accessor) => Some(self.accessor(whole(accessor)?, 2)?.chunks_exact(2).map(|uv| (uv[0] as Float, 1.0 - uv[1] as Float)).collect::<Vec<_>>()),
+            None => None,
+        };
+        let corners = match primitive.get("indices") {
+            Some(accessor) => self.accessor(whole(accessor)?, 1)?.into_iter().map(|index| index as usize).collect::<Vec<_>>(),
+            None => (0..points.len()).collect(),
+        };
+        if corners.iter().any(|corner| *corner >= points.len() || uvs.as_ref().is_some_and(|uvs| *corner >= uvs.len())) {
+            return Err(invalid(&format!("meshes[{}] has triangles with corners that don't exist", index)));
+        }
+
+        let triangles = match mode {
+            4 => corners.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect::<Vec<_>>(),
+            5 => (2..corners.len()).map(|i| if i % 2 == 0 {[corners[i - 2], corners[i - 1], corners[i]]} else {[corners[i - 1], corners[i - 2], corners[i]]}).collect(),
+            _ => (2..corners.len()).map(|i| [corners[0], corners[i - 1], corners[i]]).collect(),
+        };
+        Ok(triangles.into_iter().map(|triangle| triangle.map(|corner| {
+            let [x, y, z] = points[corner];
+            let (u, v) = uvs.as_ref().map_or((0.0, 0.0), |uvs| uvs[corner]);
+            [x, y, z, u, v]
+        })).collect())
+    }
+
+    ///Returns a material, whether it emits light, and the set of texture coordinates its texture uses. Primitives without one
+    ///
+    /// use the default material, which is a rough white metal.
+    fn material(&mut self, index : Option<usize>) -> Result<(Material, bool, usize)> {
+        if let Some(material) = self.materials.get(&index) {
+            return Ok(*material);
+        }
+        let default = Value::Object(Map::new());
+        let material = match index {
+            Some(index) => self.item("materials", index)?,
+            None => &default,
+        };
+        let pbr = material.get("pbrMetallicRoughness");
+        let extension = |name : &str| material.get("extensions").and_then(|extensions| extensions.get(name));
+        let factor = |json : Option<&Value>, key : &str, default : Float| json.and_then(|json| json.get(key)).map_or(Ok(default), number);
+        let set = |info : &Value| transform(info).and_then(|transform| transform.get("texCoord")).or(info.get("texCoord")).map_or(Ok(0), whole);
+        let base_color = match pbr.and_then(|pbr| pbr.get("baseColorFactor")) {
+            Some(factor) => match numbers(factor)?[..] {
+                [r, g, b, _] => Color::new(r, g, b),
+                _ => return Err(invalid("baseColorFactor needs 4 numbers")),
+            },
+            None => Color::new(1.0, 1.0, 1.0),
+        };
+        let base_texture = pbr.and_then(|pbr| pbr.get("baseColorTexture"));
+
+        let emission = material.get("emissiveFactor").map_or(Ok(Color::new(0.0, 0.0, 0.0)), vector)?
+            * factor(extension("KHR_materials_emissive_strength"), "emissiveStrength", 1.0)?;
+        let converted = if emission.x.max(emission.y).max(emission.z) > 0.0 {
+            match material.get("emissiveTexture") {
+                Some(info) => (Material::Light(self.texture(info, emission, Encoding::Srgb)?), true, set(info)?),
+                None => (Material::Light(self.scene.textures.add(Texture::Solid(emission))), true, 0),
+            }
+        } else if factor(extension("KHR_materials_transmission"), "transmissionFactor", 0.0)? >= 0.5 {
+            (Material::Dielectric(base_color, factor(extension("KHR_materials_ior"), "ior", 1.5)?, 0.0, 0), false, 0)
+        } else {
+            //Roughness is kept in the green channel of the texture, and metalness in the blue one
+            let metallic = factor(pbr, "metallicFactor", 1.0)?;
+            let roughness = factor(pbr, "roughnessFactor", 1.0)?;
+            let metallic_roughness = Color::new(1.0, roughness, metallic);
+            let normal_info = material.get("normalTexture");
+            let infos = [base_texture, pbr.and_then(|pbr| pbr.get("metallicRoughnessTexture")), normal_info];
+            let sets = infos.iter().flatten().map(|info| set(info)).collect::<Result<Vec<_>>>()?;
+            if sets.windows(2).any(|pair| pair[0] != pair[1]) {
+                return Err(invalid(&format!("materials[{}] has textures using different sets of texture coordinates", index.unwrap_or(0))));
+            }
+
+            //Data textures are stored the way sRGB colors are, and Material::Pbr reads them back; without a texture, the factors are solid
+            let mut texture = |info : Option<&Value>, factor : Color, encoding : Encoding| match info {
+                Some(info) => self.texture(info, factor, encoding),
+                None => Ok(self.scene.textures.add(Texture::Solid(match encoding {
+                    Encoding::Srgb => factor,
+                    _ => Color::new(srgb_to_linear(factor.x), srgb_to_linear(factor.y), srgb_to_linear(factor.z)),
+                }))),
+            };
+            let scale = normal_info.map_or(Ok(1.0), |info| factor(Some(info), "scale", 1.0))?;
+            let normal = match normal_info {
+                Some(_) => texture(normal_info, Color::new(scale, scale, 1.0), Encoding::Normal)?,
+                None => texture(None, Color::new(0.5, 0.5, 1.0), Encoding::Linear)?,
+            };
+            let pbr = Material::Pbr(texture(infos[0], base_color, Encoding::Srgb)?, texture(infos[1], metallic_roughness, Encoding::Linear)?, normal);
+            (pbr, false, sets.first().copied().unwrap_or(0))
+        };
+        self.materials.insert(index, converted);
+        Ok(converted)
+    }
+
+    ///Adds a texture, multiplied by a factor as its encoding says, in the region of its image its transform picks out. Textures can't be
+    ///
+    /// brighter than white, so sRGB ones that would be (as emissive textures often are) become their average color instead.
+    fn texture(&mut self, info : &Value, factor : Color, encoding : Encoding) -> Result<usize> {
+        if encoding == Encoding::Srgb && factor.x.max(factor.y).max(factor.z) > 1.0 {
+            let color = average(self.image(info)?, srgb_to_linear) * factor;
+            return Ok(self.scene.textures.add(Texture::Solid(color)));
+        }
+        let key = (self.source(info)?, [factor.x, factor.y, factor.z].map(|x| wide(x).to_bits()), encoding);
+        let id = match self.textures.get(&key) {
+            Some(id) => *id,
+            None => {
+                let mut image = self.image(info)?.clone();
+                if [factor.x, factor.y, factor.z] != [1.0 ; 3] {
+                    let bytes : Vec<u8> = image.pixels.iter().enumerate().map(|(i, byte)| {
+                        let x = *byte as Float / 255.0;
+                        let x = match encoding {
+                            Encoding::Srgb => linear_to_srgb(srgb_to_linear(x) * factor[i % 3]),
+                            Encoding::Linear => x * factor[i % 3],
+                            Encoding::Normal => ((x * 2.0 - 1.0) * factor[i % 3] + 1.0) / 2.0,
+                        };
+                        (x.clamp(0.0, 1.0) * 255.0).round() as u8
+                    }).collect();
+                    image = TextureHandle::new(bytes, image.width, image.height);
+                }
+                let id = self.scene.textures.add(Texture::Image(image));
+                self.textures.insert(key, id);
+                id
+            },
+        };
+
+        let texture = self.item("textures", whole(field(info, "index")?)?)?;
+        let sampler = texture.get("sampler").map(|sampler| self.item("samplers", whole(sampler)?)).transpose()?;
+        let wrap = |key : &str| match sampler.and_then(|sampler| sampler.get(key)).map_or(Ok(10497), whole)? {
+            33071 => Ok(Wrap::Clamp),
+            33648 => Ok(Wrap::Mirror),
+            10497 => Ok(Wrap::Repeat),
+            other => Err(invalid(&format!("unknown wrap mode {}", other))),
+        };
+        let wrap = [wrap("wrapS")?, wrap("wrapT")?];
+
+        //The region's v runs up from the bottom of the image, where glTF's runs down from the top
+        let (offset, scale) = match transform(info) {
+            Some(transform) => {
+                if transform.get("rotation").map_or(Ok(0.0), number)? != 0.0 {
+                    return Err(invalid("rotated texture transforms aren't supported"));
+                }
+                let pair = |key : &str, default : Float| match transform.get(key).map(numbers).transpose()?.as_deref() {
+                    Some([x, y]) => Ok((*x, *y)),
+                    Some(_) => Err(invalid(&format!("a texture transform's {} needs 2 numbers", key))),
+                    None => Ok((default, default)),
+                };
+                (pair("offset", 0.0)?, pair("scale", 1.0)?)
+            },
+            None if wrap == [Wrap::Clamp ; 2] => return Ok(id),
+            None => ((0.0, 0.0), (1.0, 1.0)),
+        };
+        let region = [offset.0, 1.0 - offset.1 - scale.1, offset.0 + scale.0, 1.0 - offset.1];
+        Ok(self.scene.textures.add(Texture::Region(id, region, wrap)))
+    }
+
+    ///Returns the index of the image a texture shows.
+    fn source(&self, info : &Value) -> Result<usize> {
+        let texture = self.item("textures", whole(field(info, "index")?)?)?;
+        whole(texture.get("source").ok_or_else(|| invalid("textures without a PNG or JPEG source aren't supported"))?)
+    }
+
+    ///Returns the image a texture shows, decoding it the first time it is used.
+    fn image(&mut self, info : &Value) -> Result<&TextureHandle> {
+        let source = self.source(info)?;
+        if !self.images.contains_key(&source) {
+            let image = self.item("images", source)?;
+            let bytes = match image.get("uri") {
+                Some(uri) => resource(text(uri)?, &self.directory)?,
+                None => self.view(whole(field(image, "bufferView")?)?)?.to_vec(),
+            };
+            let decoded = decode_image(&bytes).map_err(|error| invalid(&format!("images[{}]: {}", source, error)))?;
+            self.images.insert(source, decoded);
+        }
+        Ok(&self.images[&source])
+    }
+
+    ///Sets the camera from the first camera in the scene, which looks down its -z axis with +y up.
+    fn camera(&mut self, index : usize, transform : &Matrix) -> Result<()> {
+        if self.camera {
+            return Ok(());
+        }
+        self.camera = true;
+        let camera = self.item("cameras", index)?;
+        let perspective = camera.get("perspective").ok_or_else(|| invalid("orthographic cameras aren't supported"))?;
+        let settings = &mut self.scene.settings;
+        settings.lookfrom = Some(transform_point(transform, Point3::new(0.0, 0.0, 0.0)));
+        settings.lookat = Some(transform_point(transform, Point3::new(0.0, 0.0, -1.0)));
+        settings.vup = Some(transform_vector(transform, Vec3::new(0.0, 1.0, 0.0)));
+        settings.vfov = Some(number(field(perspective, "yfov")?)?.to_degrees());
+        settings.aspect_ratio = perspective.get("aspectRatio").map(number).transpose()?.or(settings.aspect_ratio);
+        settings.aperture = Some(0.0);
+        Ok(())
+    }
+
+    ///Imports a light, whose color is multiplied by its intensity. Spot and directional lights shine down their -z axis.
+    fn light(&mut self, index : usize, transform : &Matrix) -> Result<()> {
+        let lights = self.json.get("extensions").and_then(|extensions| extensions.get("KHR_lights_punctual")).and_then(|lights| lights.get("lights"));
+        let light = lights.and_then(Value::as_array).and_then(|lights| lights.get(index)).ok_or_else(|| invalid(&format!("lights[{}] doesn't exist", index)))?;
+        let color = light.get("color").map_or(Ok(Color::new(1.0, 1.0, 1.0)), vector)? * light.get("intensity").map_or(Ok(1.0), number)?;
+        let origin = transform_point(transform, Point3::new(0.0, 0.0, 0.0));
+        let direction = transform_vector(transform, Vec3::new(0.0, 0.0, -1.0)).unit_vector();
+        let spot = light.get("spot");
+        let angle = |key : &str, default : Float| spot.and_then(|spot| spot.get(key)).map_or(Ok(default), number).map(Float::to_degrees);
+
+        let light = match text(field(light, "type")?)? {
+            "point" => Light::Point(origin, color, 2.0, None),
+            "spot" => Light::Spot(origin, direction, angle("innerConeAngle", 0.0)?, angle("outerConeAngle", crate::vec3::consts::FRAC_PI_4)?, color, None),
+            "directional" => Light::Directional(direction, color, 0.0),
+            other => return Err(invalid(&format!("unknown light type {}", other))),
+        };
+        self.scene.lights.push(light);
+        Ok(())
+    }
+
+    ///Reads the values of an accessor's elements one after another, normalizing integers if it says to. Every element must have the given
+    ///
+    /// number of components. Accessors without a buffer view hold zeros.
+    fn accessor(&self, index : usize, components : usize) -> Result<Vec<f64>> {
+        let accessor = self.item("accessors", index)?;
+        if accessor.get("sparse").is_some() {
+            return Err(invalid("sparse accessors aren't supported"));
+        }
+        let expected = ["SCALAR", "VEC2", "VEC3"][components - 1];
+        if text(field(accessor, "type")?)? != expected {
+            return Err(invalid(&format!("accessors[{}] should hold {} elements", index, expected)));
+        }
+        let count = whole(field(accessor, "count")?)?;
+        let Some(view) = accessor.get("bufferView") else {
+            return Ok(vec![0.0 ; count * components]);
+        };
+        let kind = whole(field(accessor, "componentType")?)?;
+        let size = match kind {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            other => return Err(invalid(&format!("unknown component type {}", other))),
+        };
+        let normalized = accessor.get("normalized").and_then(Value::as_bool).unwrap_or(false);
+        let view_index = whole(view)?;
+        let view = self.item("bufferViews", view_index)?;
+        let bytes = self.view(view_index)?;
+        let start = accessor.get("byteOffset").map_or(Ok(0), whole)?;
+        let stride = view.get("byteStride").map_or(Ok(components * size), whole)?;
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let offset = start + element * stride + component * size;
+                let b = bytes.get(offset..offset + size).ok_or_else(|| invalid(&format!("accessors[{}] runs past the end of its buffer view", index)))?;
+                let (value, scale) = match kind {
+                    5120 => (b[0] as i8 as f64, 127.0),
+                    5121 => (b[0] as f64, 255.0),
+                    5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.0),
+                    5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.0),
+                    5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+                    _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+                };
+                values.push(if normalized {(value / scale).max(-1.0)} else {value});
+            }
+        }
+        Ok(values)
+    }
+
+    ///Returns the bytes of a buffer view.
+    fn view(&self, index : usize) -> Result<&[u8]> {
+        let view = self.item("bufferViews", index)?;
+        let buffer = whole(field(view, "buffer")?)?;
+        let buffer = self.buffers.get(buffer).ok_or_else(|| invalid(&format!("buffers[{}] doesn't exist", buffer)))?;
+        let start = view.get("byteOffset").map_or(Ok(0), whole)?;
+        let length = whole(field(view, "byteLength")?)?;
+        buffer.get(start..start + length).ok_or_else(|| invalid(&format!("bufferViews[{}] runs past the end of its buffer", index)))
+    }
+
+    ///Returns an element of one of the scene's top level arrays, such as nodes or meshes.
+    fn item(&self, kind : &str, index : usize) -> Result<&'a Value> {
+        list(self.json, kind)?.get(index).ok_or_else(|| invalid(&format!("{}[{}] doesn't exist", kind, index)))
+    }
+}
+
+///Returns a node's transform, given as a matrix of columns or as a translation, rotation and scale, applied last to first.
+fn local(node : &Value) -> Result<Matrix> {
+    if let Some(matrix) = node.get("matrix") {
+        let values = numbers(matrix)?;
+        if values.len() != 16 {
+            return Err(invalid("a node's matrix needs 16 numbers"));
+        }
+        let mut matrix = IDENTITY;
+        for (column, values) in values.chunks_exact(4).enumerate() {
+            for (row, value) in values.iter().enumerate() {
+                matrix[row][column] = *value;
+            }
+        }
+        return Ok(matrix);
+    }
+    let mut matrix = match node.get("rotation").map(numbers).transpose()?.as_deref() {
+        Some([x, y, z, w]) => quaternion(*w, *x, *y, *z),
+        Some(_) => return Err(invalid("a node's rotation needs a quaternion")),
+        None => IDENTITY,
+    };
+    let scale = node.get("scale").map_or(Ok(Vec3::new(1.0, 1.0, 1.0)), vector)?;
+    let translation = node.get("translation").map_or(Ok(Vec3::new(0.0, 0.0, 0.0)), vector)?;
+    for row in matrix.iter_mut().take(3) {
+        row[0] *= scale.x;
+        row[1] *= scale.y;
+        row[2] *= scale.z;
+    }
+    (matrix[0][3], matrix[1][3], matrix[2][3]) = (translation.x, translation.y, translation.z);
+    Ok(matrix)
+}
+
+///Returns the KHR_texture_transform of a texture, if it has one.
+fn transform(info : &Value) -> Option<&Value> {
+    info.get("extensions").and_then(|extensions| extensions.get("KHR_texture_transform"))
+}
+
+///Average color of an image, with every channel decoded by the given function.
+fn average(image : &TextureHandle, decode : fn(Float) -> Float) -> Color {
+    let mut sum = [0.0f64 ; 3];
+    for pixel in image.pixels.chunks_exact(3) {
+        for (channel, sum) in sum.iter_mut().enumerate() {
+            *sum += wide(decode(pixel[channel] as Float / 255.0));
+        }
+    }
+    let pixels = (image.width as f64 * image.height as f64).max(1.0);
+    Color::new((sum[0] / pixels) as Float, (sum[1] / pixels) as Float, (sum[2] / pixels) as Float)
+}
+
+///Splits a binary .glb file into its JSON chunk and its binary chunk, if it has one.
+fn glb(bytes : &[u8]) -> Result<(Value, Option<Vec<u8>>)> {
+    let word = |offset : usize| bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .ok_or_else(|| invalid("the .glb file is cut short"));
+    if word(4)? != 2 {
+        return Err(invalid(&format!("only glTF 2.0 is supported, not a .glb file of version {}", word(4)?)));
+    }
+    let end = word(8)?.min(bytes.len());
+    let (mut json, mut binary) = (None, None);
+    let mut offset = 12;
+    while offset + 8 <= end {
+        let length = word(offset)?;
+        let chunk = bytes.get(offset + 8..offset + 8 + length).ok_or_else(|| invalid("the .glb file is cut short"))?;
+        match word(offset + 4)? {
+            0x4e4f534a => json = Some(serde_json::from_slice(chunk).map_err(|error| invalid(&error.to_string()))?),
+            0x004e4942 => binary = Some(chunk.to_vec()),
+            _ => {},
+        }
+        offset += 8 + length;
+    }
+    Ok((json.ok_or_else(|| invalid("the .glb file has no JSON chunk"))?, binary))
+}
+
+///Reads the data a URI refers to: a base64 data URI, or a file relative to the scene, whose name may have escapes such as %20.
+fn resource(uri : &str, directory : &Path) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,").ok_or_else(|| invalid("data URIs must be base64 encoded"))?;
+        return base64(encoded);
+    }
+    let mut name = vec![];
+    let mut bytes = uri.bytes();
+    while let Some(byte) = bytes.next() {
+        let escaped = if byte == b'%' {bytes.next().zip(bytes.next())} else {None};
+        match escaped.and_then(|(high, low)| u8::from_str_radix(&format!("{}{}", high as char, low as char), 16).ok()) {
+            Some(decoded) => name.push(decoded),
+            None => name.push(byte),
+        }
+    }
+    read(directory.join(String::from_utf8_lossy(&name).as_ref()))
+}
+
+fn base64(text : &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(invalid("invalid base64 data")),
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+fn field<'a>(json : &'a Value, key : &str) -> Result<&'a Value> {
+    json.get(key).ok_or_else(|| invalid(&format!("missing {}", key)))
+}
+
+///Returns the array under a key, or an empty one if there is none.
+fn list<'a>(json : &'a Value, key : &str) -> Result<&'a [Value]> {
+    match json.get(key) {
+        Some(value) => value.as_array().map(Vec::as_slice).ok_or_else(|| invalid(&format!("{} must be an array", key))),
+        None => Ok(&[]),
+    }
+}
+
+fn indices(json : &Value, key : &str) -> Result<Vec<usize>> {
+    list(json, key)?.iter().map(whole).collect()
+}
+
+fn whole(json : &Value) -> Result<usize> {
+    match json.as_f64() {
+        Some(x) if x >= 0.0 && x.fract() == 0.0 => Ok(x as usize),
+        _ => Err(invalid("expected an index or count")),
+    }
+}
+
+fn number(json : &Value) -> Result<Float> {
+    json.as_f64().map(|x| x as Float).ok_or_else(|| invalid("expected a number"))
+}
+
+fn numbers(json : &Value) -> Result<Vec<Float>> {
+    json.as_array().ok_or_else(|| invalid("expected an array of numbers"))?.iter().map(number).collect()
+}
+
+fn vector(json : &Value) -> Result<Vec3> {
+    match numbers(json)?[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(invalid("expected an array of 3 numbers")),
+    }
+}
+
+fn text(json : &Value) -> Result<&str> {
+    json.as_str().ok_or_else(|| invalid("expected a string"))
+}
+
+fn invalid(message : &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid glTF scene: {}", message))
+}
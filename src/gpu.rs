@@ -0,0 +1,311 @@
+/*
+Module to store the GPU backend (the gpu feature), which uploads a scene's Bounding Volume Hierarchy to the graphics card with wgpu
+and finds the first hits of packets of camera rays, and whether shadow rays are blocked, with a compute shader (gpu.wgsl) walking it
+one ray per invocation. Only where rays hit is worked out on the GPU: each hit is filled in on the CPU by intersecting the ray again
+with the object found, so materials, textures and shading stay this crate's. Bounces are traced on the CPU, as they are too incoherent
+to be worth a round trip each.
+The GPU handles spheres (moving or not), rectangles, boxes and triangles. Scenes with media or custom shapes, or machines without
+a GPU adapter, can't use it, and AcceleratorKind::Gpu falls back to tracing on the CPU with the same hierarchy.
+*/
+
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::mpsc::channel;
+use pollster::block_on;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use crate::hitting::{Hittable, HitRecord};
+use crate::ray::Ray;
+use crate::textures::Textures;
+use crate::tree::{Tree, MAX_PACKET};
+use crate::vec3::Float;
+
+///Rays each invocation group of the shader traces, as set by its workgroup_size.
+const WORKGROUP : u32 = 64;
+
+///Bounding Volume Hierarchy traced on the GPU, along with the same hierarchy on the CPU, which fills in the hits the GPU finds
+///
+/// and traces every ray that isn't part of a packet.
+pub struct GpuTree {
+    pub tree : Tree,
+    adapter : String,
+    device : wgpu::Device,
+    queue : wgpu::Queue,
+    pipeline : wgpu::ComputePipeline,
+    nodes : wgpu::Buffer,
+    primitives : wgpu::Buffer,
+}
+
+impl fmt::Debug for GpuTree {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GpuTree").field("adapter", &self.adapter).field("nodes", &self.tree.items.len()).finish()
+    }
+}
+
+impl Clone for GpuTree {
+    fn clone(&self) -> GpuTree {
+        GpuTree {
+            tree : self.tree.clone(),
+            adapter : self.adapter.clone(),
+            device : self.device.clone(),
+            queue : self.queue.clone(),
+            pipeline : self.pipeline.clone(),
+            nodes : self.nodes.clone(),
+            primitives : self.primitives.clone(),
+        }
+    }
+}
+
+impl GpuTree {
+
+    ///Uploads a hierarchy to the first GPU wgpu finds. Fails if there is none, if the hierarchy holds objects the GPU can't trace,
+    ///
+    /// or if it is too large to upload, handing the hierarchy back along with why.
+    pub fn new(tree : Tree) -> std::result::Result<GpuTree, (Tree, Error)> {
+        match upload(&tree) {
+            Ok((adapter, device, queue, pipeline, nodes, primitives)) => Ok(GpuTree {tree, adapter, device, queue, pipeline, nodes, primitives}),
+            Err(error) => Err((tree, error)),
+        }
+    }
+
+    ///Returns the name of the GPU the hierarchy was uploaded to.
+    pub fn adapter(&self) -> &str {
+        &self.adapter
+    }
+
+    ///Determines which of a packet of rays hit any object, as Tree::hit_packet() does, finding the hits on the GPU.
+    pub fn hit_packet(&self, rays : &[Ray], t_min : Float, t_max : Float, recs : &mut [HitRecord], textures : &Textures) -> u32 {
+        let rays = &rays[..rays.len().min(MAX_PACKET)];
+        let found = match self.trace(rays, t_min, &vec![t_max ; rays.len()], false) {
+            Ok(found) => found,
+            Err(error) => panic!("Failed to trace rays on the GPU: {}", error),
+        };
+        let mut hits = 0;
+        for (k, (r, found)) in rays.iter().zip(found).enumerate() {
+            //The GPU's hits are in 32 bit floats, so rays that graze an object may be found to miss it here, and are traced again on the CPU
+            let Some(index) = found else {
+                continue;
+            };
+            let filled = self.tree.items[index].data.as_ref().is_some_and(|object| object.hit(*r, t_min, t_max, &mut recs[k], textures));
+            if filled {
+                recs[k].object = index;
+            }
+            if filled || self.tree.hit(*r, t_min, t_max, &mut recs[k], self.tree.root, textures) {
+                hits |= 1 << k;
+            }
+        }
+        hits
+    }
+
+    ///Determines which of a packet of shadow rays are blocked before their own t_max, as Tree::occluded_packet() does, on the GPU.
+    pub fn occluded_packet(&self, rays : &[Ray], t_min : Float, t_max : &[Float]) -> u32 {
+        let rays = &rays[..rays.len().min(MAX_PACKET)];
+        match self.trace(rays, t_min, t_max, true) {
+            Ok(found) => found.iter().enumerate().filter(|(_, found)| found.is_some()).fold(0, |blocked, (k, _)| blocked | 1 << k),
+            Err(error) => panic!("Failed to trace rays on the GPU: {}", error),
+        }
+    }
+
+    ///Traces rays on the GPU, returning the index of the node holding the closest object each hits before its t_max,
+    ///
+    /// or of any object blocking it with any_hit.
+    fn trace(&self, rays : &[Ray], t_min : Float, t_max : &[Float], any_hit : bool) -> Result<Vec<Option<usize>>> {
+        if rays.is_empty() || self.tree.items.is_empty() {
+            return Ok(vec![None ; rays.len()]);
+        }
+        let mut params = vec![];
+        for word in [rays.len() as u32, any_hit as u32, narrow(t_min).to_bits(), self.tree.root as u32] {
+            params.extend_from_slice(&word.to_le_bytes());
+        }
+        let mut ray_bytes = Vec::with_capacity(rays.len() * 32);
+        for (r, t_max) in rays.iter().zip(t_max) {
+            let o = r.origin_point;
+            let d = r.direction;
+            for value in [o.x, o.y, o.z, *t_max, d.x, d.y, d.z, r.time] {
+                ray_bytes.extend_from_slice(&narrow(value).to_le_bytes());
+            }
+        }
+
+        let storage = |label, contents : &[u8], usage| self.device.create_buffer_init(&BufferInitDescriptor {label : Some(label), contents, usage});
+        let params = storage("params", &params, wgpu::BufferUsages::UNIFORM);
+        let ray_buffer = storage("rays", &ray_bytes, wgpu::BufferUsages::STORAGE);
+        let size = (rays.len() * 8) as u64;
+        let hits = self.device.create_buffer(&wgpu::BufferDescriptor {label : Some("hits"), size, usage : wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, mapped_at_creation : false});
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {label : Some("readback"), size, usage : wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST, mapped_at_creation : false});
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label : None,
+            layout : &self.pipeline.get_bind_group_layout(0),
+            entries : &[&params, &self.nodes, &self.primitives, &ray_buffer, &hits].iter().enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {binding : binding as u32, resource : buffer.as_entire_binding()}).collect::<Vec<_>>(),
+        });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {label : None});
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {label : None, timestamp_writes : None});
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups((rays.len() as u32).div_ceil(WORKGROUP), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&hits, 0, &readback, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = channel();
+        readback.map_async(wgpu::MapMode::Read, .., move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        self.device.poll(wgpu::PollType::wait_indefinitely()).map_err(|error| Error::other(error.to_string()))?;
+        receiver.recv().map_err(|error| Error::other(error.to_string()))?.map_err(|error| Error::other(error.to_string()))?;
+        let found = {
+            let view = readback.get_mapped_range(..);
+            view.chunks_exact(8).map(|hit| {
+                let node = i32::from_le_bytes([hit[4], hit[5], hit[6], hit[7]]);
+                usize::try_from(node).ok()
+            }).collect()
+        };
+        readback.unmap();
+        Ok(found)
+    }
+}
+
+///Finds a GPU, compiles the shader and uploads the hierarchy's nodes and objects, returning the GPU's name and what was made.
+#[allow(clippy::type_complexity)]
+fn upload(tree : &Tree) -> Result<(String, wgpu::Device, wgpu::Queue, wgpu::ComputePipeline, wgpu::Buffer, wgpu::Buffer)> {
+    //Everything is checked before looking for a GPU, which can be slow
+    let mut nodes = Vec::with_capacity(tree.items.len() * 48);
+    let mut primitives = vec![];
+    let child = |child : Option<usize>| child.map_or(-1, |index| index as i32);
+    for (index, node) in tree.items.iter().enumerate() {
+        let primitive = match &node.data {
+            Some(object) => {
+                let words = primitive(object).ok_or_else(|| unsupported(&format!("object {} is a {}, which the GPU can't trace", index, kind(object))))?;
+                primitives.extend(words.iter().flat_map(|word| narrow(*word).to_le_bytes()));
+                (primitives.len() / 64 - 1) as i32
+            },
+            None => -1,
+        };
+        let (minimum, maximum) = (node.aabb.minimum, node.aabb.maximum);
+        for value in [narrow(minimum.x), narrow(minimum.y), narrow(minimum.z), f32::from_bits(child(node.left) as u32),
+            narrow(maximum.x), narrow(maximum.y), narrow(maximum.z), f32::from_bits(child(node.right) as u32)] {
+            nodes.extend_from_slice(&value.to_le_bytes());
+        }
+        for word in [primitive as u32, node.axis as u32, 0, 0] {
+            nodes.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    //Buffers can't be empty, even when nothing will read them
+    if primitives.is_empty() {
+        primitives.resize(64, 0);
+    }
+    if nodes.is_empty() {
+        nodes.resize(48, 0);
+    }
+
+    //WGPU_BACKEND (e.g. vulkan or gl) picks which graphics APIs are searched for an adapter
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+        .map_err(|error| Error::new(ErrorKind::NotFound, format!("No GPU adapter was found ({})", error)))?;
+    let limits = adapter.limits();
+    let largest = nodes.len().max(primitives.len()) as u64;
+    if largest > limits.max_storage_buffer_binding_size as u64 || largest > limits.max_buffer_size {
+        return Err(unsupported(&format!("the scene needs a {} byte buffer, more than {} allows", largest, adapter.get_info().name)));
+    }
+    let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor {label : Some("RustTracer"), required_limits : limits, ..Default::default()}))
+        .map_err(|error| Error::other(format!("Failed to open {}: {}", adapter.get_info().name, error)))?;
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {label : Some("gpu.wgsl"), source : wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into())});
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label : Some("trace"),
+        layout : None,
+        module : &module,
+        entry_point : Some("main"),
+        compilation_options : Default::default(),
+        cache : None,
+    });
+    let nodes = device.create_buffer_init(&BufferInitDescriptor {label : Some("nodes"), contents : &nodes, usage : wgpu::BufferUsages::STORAGE});
+    let primitives = device.create_buffer_init(&BufferInitDescriptor {label : Some("primitives"), contents : &primitives, usage : wgpu::BufferUsages::STORAGE});
+    Ok((adapter.get_info().name, device, queue, pipeline, nodes, primitives))
+}
+
+///Lays out an object for the shader: a header of its kind, times and radius or plane, and three points, or None if the GPU can't trace it.
+fn primitive(object : &Hittable) -> Option<[Float ; 16]> {
+    let point = |p : crate::vec3::Point3| [p.x, p.y, p.z, 0.0];
+    let [a, b, c, header] = match object {
+        Hittable::Sphere(_, center, radius) => [point(*center), [0.0 ; 4], [0.0 ; 4], [0.0, 0.0, 0.0, *radius]],
+        Hittable::MovingSphere(_, center0, center1, time0, time1, radius) => [point(*center0), point(*center1), [0.0 ; 4], [0.0, *time0, *time1, *radius]],
+        Hittable::XYRect(_, a0, a1, b0, b1, k) => [[*a0, *a1, *b0, *b1], [0.0 ; 4], [0.0 ; 4], [1.0, 2.0, 0.0, *k]],
+        Hittable::XZRect(_, a0, a1, b0, b1, k) => [[*a0, *a1, *b0, *b1], [0.0 ; 4], [0.0 ; 4], [1.0, 1.0, 0.0, *k]],
+        Hittable::YZRect(_, a0, a1, b0, b1, k) => [[*a0, *a1, *b0, *b1], [0.0 ; 4], [0.0 ; 4], [1.0, 0.0, 0.0, *k]],
+        Hittable::Box(_, minimum, maximum) => [point(*minimum), point(*maximum), [0.0 ; 4], [2.0, 0.0, 0.0, 0.0]],
+        Hittable::Triangle(_, [a, b, c], _) => [point(*a), point(*b), point(*c), [3.0, 0.0, 0.0, 0.0]],
+        Hittable::Medium(..) | Hittable::HeterogeneousMedium(..) | Hittable::Custom(_) => return None,
+    };
+    let mut words = [0.0 ; 16];
+    for (chunk, values) in words.chunks_exact_mut(4).zip([header, a, b, c]) {
+        chunk.copy_from_slice(&values);
+    }
+    Some(words)
+}
+
+///Names the kind of an object the GPU can't trace.
+fn kind(object : &Hittable) -> &'static str {
+    match object {
+        Hittable::Medium(..) => "medium",
+        Hittable::HeterogeneousMedium(..) => "heterogeneous medium",
+        _ => "custom shape",
+    }
+}
+
+///Narrows a Float to the f32 the shader works in, whichever type Float is. Values too large for f32 become infinite.
+fn narrow(x : Float) -> f32 {
+    #[cfg(not(feature = "f64"))]
+    return x;
+    #[cfg(feature = "f64")]
+    return x as f32;
+}
+
+fn unsupported(message : &str) -> Error {
+    Error::new(ErrorKind::Unsupported, format!("The scene can't be traced on the GPU: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Material;
+    use crate::vec3::{Color, Point3, Vec3, random_float};
+
+    #[test]
+    fn finds_the_hits_the_cpu_does() {
+        let mat = Material::Metal(Color::new(0.5, 0.5, 0.5), 0.0);
+        let mut objects = (0..200).map(|k| {
+            let center = Point3::new(random_float() * 20.0 - 10.0, random_float() * 20.0 - 10.0, random_float() * 20.0 - 10.0);
+            match k % 4 {
+                0 => Hittable::Sphere(mat, center, 0.5),
+                1 => Hittable::XZRect(mat, center.x, center.x + 1.0, center.z, center.z + 1.0, center.y),
+                2 => Hittable::Box(mat, center, center + Vec3::new(0.7, 0.7, 0.7)),
+                _ => Hittable::Triangle(mat, [center, center + Vec3::new(1.0, 0.0, 0.0), center + Vec3::new(0.0, 1.0, 0.0)], [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]),
+            }
+        }).collect::<Vec<_>>();
+        let gpu = match GpuTree::new(Tree::build(&mut objects).unwrap()) {
+            Ok(gpu) => gpu,
+            //Machines without a GPU adapter can't run this test
+            Err((_, error)) => return eprintln!("skipping: {}", error),
+        };
+        let textures = Textures::new();
+        let rays = (0..MAX_PACKET).map(|_| {
+            let direction = Vec3::new(random_float() - 0.5, random_float() - 0.5, random_float() - 0.5);
+            Ray::new(Point3::new(0.0, 0.0, -20.0) + direction, direction + Vec3::new(0.0, 0.0, 0.5))
+        }).collect::<Vec<_>>();
+        let mut recs = vec![HitRecord::new() ; MAX_PACKET];
+        let hits = gpu.hit_packet(&rays, 0.001, Float::INFINITY, &mut recs, &textures);
+        let blocked = gpu.occluded_packet(&rays, 0.001, &[Float::INFINITY ; MAX_PACKET]);
+        assert_ne!(hits, 0);
+        for (k, (r, rec)) in rays.iter().zip(&recs).enumerate() {
+            let mut expected = HitRecord::new();
+            let hit = gpu.tree.hit(*r, 0.001, Float::INFINITY, &mut expected, gpu.tree.root, &textures);
+            assert_eq!(hits >> k & 1 == 1, hit, "ray {}", k);
+            assert_eq!(blocked >> k & 1 == 1, hit, "ray {}", k);
+            if hit {
+                assert_eq!(rec.object, expected.object, "ray {}", k);
+                assert!((rec.t - expected.t).abs() < 1e-3, "ray {}: {} against {}", k, rec.t, expected.t);
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// Traversal of a Bounding Volume Hierarchy uploaded by gpu.rs, one ray per invocation. Finds the closest object each ray hits,
+// or with any_hit set, whether anything blocks it at all, and writes the hit's distance and the index of the node holding the object
+// (or -1 for a miss). Spheres, rectangles, boxes and triangles are tested as hitting.rs tests them, in 32 bit floats.
+
+struct Params {
+    count : u32,
+    any_hit : u32,
+    t_min : f32,
+    root : u32,
+}
+
+// Interior nodes have left and right children (-1 where one is missing), and leaves the index of their primitive
+struct Node {
+    minimum : vec3<f32>,
+    left : i32,
+    maximum : vec3<f32>,
+    right : i32,
+    primitive : i32,
+    axis : u32,
+    pad0 : u32,
+    pad1 : u32,
+}
+
+// header is (kind, time0, time1, radius or k); kind 0 is a sphere centered at a (moving to b from time0 to time1),
+// 1 a rectangle with bounds a in the plane where axis header.y equals k, 2 a box from a to b, and 3 a triangle a, b, c
+struct Primitive {
+    header : vec4<f32>,
+    a : vec4<f32>,
+    b : vec4<f32>,
+    c : vec4<f32>,
+}
+
+// origin.w is the ray's t_max, and direction.w its time
+struct Ray {
+    origin : vec4<f32>,
+    direction : vec4<f32>,
+}
+
+struct Hit {
+    t : f32,
+    node : i32,
+}
+
+@group(0) @binding(0) var<uniform> params : Params;
+@group(0) @binding(1) var<storage, read> nodes : array<Node>;
+@group(0) @binding(2) var<storage, read> primitives : array<Primitive>;
+@group(0) @binding(3) var<storage, read> rays : array<Ray>;
+@group(0) @binding(4) var<storage, read_write> hits : array<Hit>;
+
+const MISS : f32 = -1.0;
+
+fn hit_box(minimum : vec3<f32>, maximum : vec3<f32>, origin : vec3<f32>, inverse : vec3<f32>, t_min : f32, t_max : f32) -> bool {
+    let t0 = (minimum - origin) * inverse;
+    let t1 = (maximum - origin) * inverse;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let enter = max(max(near.x, near.y), max(near.z, t_min));
+    let exit = min(min(far.x, far.y), min(far.z, t_max));
+    return enter <= exit;
+}
+
+fn hit_sphere(center : vec3<f32>, radius : f32, origin : vec3<f32>, direction : vec3<f32>, t_min : f32, t_max : f32) -> f32 {
+    let oc = origin - center;
+    let a = dot(direction, direction);
+    let half_b = dot(oc, direction);
+    let c = dot(oc, oc) - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return MISS;
+    }
+    var root = (-half_b - sqrt(discriminant)) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrt(discriminant)) / a;
+        if root < t_min || t_max < root {
+            return MISS;
+        }
+    }
+    return root;
+}
+
+fn hit_rect(bounds : vec4<f32>, k : f32, axis : u32, origin : vec3<f32>, direction : vec3<f32>, t_min : f32, t_max : f32) -> f32 {
+    var first = 0u;
+    var second = 1u;
+    if axis == 0u {
+        first = 1u;
+        second = 2u;
+    } else if axis == 1u {
+        second = 2u;
+    }
+    let t = (k - origin[axis]) / direction[axis];
+    if !(t >= t_min && t <= t_max) {
+        return MISS;
+    }
+    let a = origin[first] + t * direction[first];
+    let b = origin[second] + t * direction[second];
+    if a < bounds.x || a > bounds.y || b < bounds.z || b > bounds.w {
+        return MISS;
+    }
+    return t;
+}
+
+fn hit_triangle(a : vec3<f32>, b : vec3<f32>, c : vec3<f32>, origin : vec3<f32>, direction : vec3<f32>, t_min : f32, t_max : f32) -> f32 {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = cross(direction, edge2);
+    let determinant = dot(edge1, p);
+    if abs(determinant) < 1e-12 {
+        return MISS;
+    }
+    let s = origin - a;
+    let beta = dot(s, p) / determinant;
+    if beta < 0.0 || beta > 1.0 {
+        return MISS;
+    }
+    let q = cross(s, edge1);
+    let gamma = dot(direction, q) / determinant;
+    if gamma < 0.0 || beta + gamma > 1.0 {
+        return MISS;
+    }
+    let t = dot(edge2, q) / determinant;
+    if t < t_min || t > t_max {
+        return MISS;
+    }
+    return t;
+}
+
+fn hit_primitive(primitive : Primitive, origin : vec3<f32>, direction : vec3<f32>, time : f32, t_min : f32, t_max : f32) -> f32 {
+    let kind = u32(primitive.header.x);
+    if kind == 0u {
+        var center = primitive.a.xyz;
+        let time0 = primitive.header.y;
+        let time1 = primitive.header.z;
+        if time1 != time0 {
+            center = center + (primitive.b.xyz - center) * ((time - time0) / (time1 - time0));
+        }
+        return hit_sphere(center, primitive.header.w, origin, direction, t_min, t_max);
+    }
+    if kind == 1u {
+        return hit_rect(primitive.a, primitive.header.w, u32(primitive.header.y), origin, direction, t_min, t_max);
+    }
+    if kind == 2u {
+        let minimum = primitive.a.xyz;
+        let maximum = primitive.b.xyz;
+        var closest = t_max;
+        var found = MISS;
+        for (var axis = 0u; axis < 3u; axis++) {
+            var bounds = vec4<f32>(minimum.y, maximum.y, minimum.z, maximum.z);
+            if axis == 1u {
+                bounds = vec4<f32>(minimum.x, maximum.x, minimum.z, maximum.z);
+            } else if axis == 2u {
+                bounds = vec4<f32>(minimum.x, maximum.x, minimum.y, maximum.y);
+            }
+            for (var side = 0u; side < 2u; side++) {
+                let k = select(minimum[axis], maximum[axis], side == 0u);
+                let t = hit_rect(bounds, k, axis, origin, direction, t_min, closest);
+                if t != MISS {
+                    closest = t;
+                    found = t;
+                }
+            }
+        }
+        return found;
+    }
+    return hit_triangle(primitive.a.xyz, primitive.b.xyz, primitive.c.xyz, origin, direction, t_min, t_max);
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id : vec3<u32>) {
+    let index = id.x;
+    if index >= params.count {
+        return;
+    }
+    let ray = rays[index];
+    let origin = ray.origin.xyz;
+    let direction = ray.direction.xyz;
+    // Rays parallel to an axis never cross its slabs, which an infinite inverse makes the box test work out
+    let tiny = vec3<f32>(1e-30);
+    let inverse = 1.0 / select(direction, select(-tiny, tiny, direction >= vec3<f32>(0.0)), abs(direction) < tiny);
+    var closest = ray.origin.w;
+    var found = -1;
+
+    var stack : array<u32, 64>;
+    var top = 1u;
+    stack[0] = params.root;
+    while top > 0u {
+        top -= 1u;
+        let node = nodes[stack[top]];
+        if !hit_box(node.minimum, node.maximum, origin, inverse, params.t_min, closest) {
+            continue;
+        }
+        if node.primitive >= 0 {
+            let t = hit_primitive(primitives[node.primitive], origin, direction, ray.direction.w, params.t_min, closest);
+            if t != MISS {
+                closest = t;
+                found = i32(stack[top]);
+                if params.any_hit != 0u {
+                    break;
+                }
+            }
+            continue;
+        }
+        // Children are sorted along the node's axis, so a ray heading down that axis meets the right child first
+        var near = node.left;
+        var far = node.right;
+        if direction[node.axis] < 0.0 {
+            near = node.right;
+            far = node.left;
+        }
+        if far >= 0 && top < 64u {
+            stack[top] = u32(far);
+            top += 1u;
+        }
+        if near >= 0 && top < 64u {
+            stack[top] = u32(near);
+            top += 1u;
+        }
+    }
+    hits[index] = Hit(closest, found);
+}
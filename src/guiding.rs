@@ -0,0 +1,164 @@
+/*
+Module to store the path guiding structure, which learns where light arrives from while rendering and steers bounces towards it.
+*/
+
+use crate::vec3::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::vec3::{Vec3, Point3, Color, random_float, random_2d, Float};
+use crate::ray::Ray;
+use crate::hitting::HitRecord;
+use crate::environment::Distribution1D;
+use crate::scene::Scene;
+
+///Number of cells along each axis of the spatial grid.
+const SPATIAL_RESOLUTION : usize = 16;
+
+///Number of bins along each axis of the directional histograms.
+const DIRECTIONAL_RESOLUTION : usize = 16;
+
+///Recorded light is added up in fixed point with this many steps per unit, since integer sums (unlike float sums)
+///
+/// come out the same whatever order the threads record in.
+const FIXED_POINT_SCALE : Float = 65536.0;
+
+///Settings for path guiding.
+///
+/// Before the final render, the image is rendered training_passes times with samples_per_pass samples per pixel,
+/// and the light found along every bounce is used to refine the guide. bsdf_fraction is the probability of still scattering
+/// according to the material rather than the guide, which keeps rendering unbiased where the guide has learned little.
+#[derive(Debug, Clone, Copy)]
+pub struct GuidingSettings {
+    pub training_passes : u32,
+    pub samples_per_pass : i32,
+    pub bsdf_fraction : Float,
+}
+
+///Learned distribution of incoming light, stored as a directional histogram for each cell of a grid over the scene.
+///
+/// Directions are binned by the cosine of their angle from the y axis and their angle around it, so every bin covers the same solid angle.
+#[derive(Debug)]
+pub struct Guide {
+    pub minimum : Point3,
+    pub extent : Vec3,
+    pub bsdf_fraction : Float,
+    pub learning : bool,
+    accumulated : Vec<AtomicU64>,
+    distributions : Vec<Distribution1D>,
+}
+
+impl Guide {
+
+    ///Creates an untrained guide covering every object in the scene.
+    pub fn new(scene : &Scene, settings : GuidingSettings) -> Guide {
+        let (minimum, maximum) = match scene.objects.bounding_box() {
+            Some(aabb) => (aabb.minimum, aabb.maximum),
+            None => (Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0)),
+        };
+        let cells = SPATIAL_RESOLUTION * SPATIAL_RESOLUTION * SPATIAL_RESOLUTION;
+        let bins = DIRECTIONAL_RESOLUTION * DIRECTIONAL_RESOLUTION;
+
+        Guide {
+            minimum,
+            extent : maximum - minimum,
+            bsdf_fraction : settings.bsdf_fraction.clamp(0.0, 1.0),
+            learning : true,
+            accumulated : (0..cells * bins).map(|_| AtomicU64::new(0)).collect(),
+            distributions : vec![Distribution1D::new(vec![0.0 ; bins]) ; cells],
+        }
+    }
+
+    ///Adds the light found by a bounce, divided by the pdf of the direction it was found in, to the guide's records.
+    pub fn record(&self, p : Point3, direction : Vec3, weight : Float) {
+        if !self.learning || !weight.is_finite() || weight <= 0.0 {
+            return;
+        }
+        let index = self.cell(p) * DIRECTIONAL_RESOLUTION * DIRECTIONAL_RESOLUTION + direction_to_bin(direction);
+        self.accumulated[index].fetch_add((weight.min(1e9) * FIXED_POINT_SCALE) as u64, Ordering::Relaxed);
+    }
+
+    ///Rebuilds the sampling distributions from everything recorded so far.
+    pub fn rebuild(&mut self) {
+        let bins = DIRECTIONAL_RESOLUTION * DIRECTIONAL_RESOLUTION;
+        for (cell, distribution) in self.distributions.iter_mut().enumerate() {
+            let func = self.accumulated[cell * bins..(cell + 1) * bins].iter().map(|a| a.load(Ordering::Relaxed) as Float / FIXED_POINT_SCALE).collect();
+            *distribution = Distribution1D::new(func);
+        }
+    }
+
+    ///Samples a direction of incoming light at p, proportionally to what the guide has learned.
+    ///
+    /// Cells with nothing recorded are sampled uniformly.
+    pub fn sample(&self, p : Point3) -> Vec3 {
+        let (r1, r2) = random_2d();
+        let (x, _pdf, bin) = self.distributions[self.cell(p)].sample(r1);
+        let n = DIRECTIONAL_RESOLUTION;
+        let within = x * (n * n) as Float - bin as Float;
+
+        let cos = -1.0 + 2.0 * ((bin / n) as Float + r2) / n as Float;
+        let phi = 2.0 * PI * ((bin % n) as Float + within) / n as Float;
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        Vec3::new(sin * phi.cos(), cos, sin * phi.sin())
+    }
+
+    ///Returns the solid angle pdf with which sample() would generate the given direction at p.
+    pub fn pdf(&self, p : Point3, direction : Vec3) -> Float {
+        self.distributions[self.cell(p)].pdf(direction_to_bin(direction)) / (4.0 * PI)
+    }
+
+    ///Chooses the direction of a bounce off a non-specular surface, either by keeping the material's own sample in scattered
+    ///
+    /// or by replacing it with one from the guide. Rescales attenuation to match, and returns the combined pdf of the chosen direction.
+    pub fn scatter(&self, r_in : Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> Float {
+        if random_float() >= self.bsdf_fraction {
+            scattered.direction = self.sample(rec.p);
+        }
+
+        //The material's attenuation is its scattered light divided by its own pdf, so multiplying by that pdf recovers the scattered light
+        let bsdf_pdf = rec.mat.scattering_pdf(r_in, rec, scattered.direction);
+        let pdf = self.bsdf_fraction * bsdf_pdf + (1.0 - self.bsdf_fraction) * self.pdf(rec.p, scattered.direction);
+        if pdf <= 0.0 {
+            *attenuation = Color::new(0.0, 0.0, 0.0);
+            return 0.0;
+        }
+        *attenuation *= bsdf_pdf / pdf;
+        pdf
+    }
+
+    ///Index of the grid cell containing p (points outside the grid use the nearest cell).
+    fn cell(&self, p : Point3) -> usize {
+        let n = SPATIAL_RESOLUTION;
+        let axis = |value : Float, minimum : Float, extent : Float| {
+            if extent <= 0.0 {
+                return 0;
+            }
+            (((value - minimum) / extent * n as Float).max(0.0) as usize).min(n - 1)
+        };
+        let i = axis(p.x, self.minimum.x, self.extent.x);
+        let j = axis(p.y, self.minimum.y, self.extent.y);
+        let k = axis(p.z, self.minimum.z, self.extent.z);
+        (i * n + j) * n + k
+    }
+}
+
+impl Clone for Guide {
+    fn clone(&self) -> Guide {
+        Guide {
+            minimum : self.minimum,
+            extent : self.extent,
+            bsdf_fraction : self.bsdf_fraction,
+            learning : self.learning,
+            accumulated : self.accumulated.iter().map(|a| AtomicU64::new(a.load(Ordering::Relaxed))).collect(),
+            distributions : self.distributions.clone(),
+        }
+    }
+}
+
+///Index of the directional histogram bin containing the given direction.
+fn direction_to_bin(direction : Vec3) -> usize {
+    let n = DIRECTIONAL_RESOLUTION;
+    let d = direction.unit_vector();
+    let row = (((d.y + 1.0) / 2.0 * n as Float).max(0.0) as usize).min(n - 1);
+    let phi = d.z.atan2(d.x).rem_euclid(2.0 * PI);
+    let column = ((phi / (2.0 * PI) * n as Float) as usize).min(n - 1);
+    row * n + column
+}
@@ -0,0 +1,96 @@
+/*
+Module to store the Halton low-discrepancy sequence and the digit permutations that improve or randomize it.
+*/
+
+use std::sync::OnceLock;
+use crate::sobol::{hash, to_unit};
+use crate::vec3::Float;
+
+///Prime bases of the Halton sequence's dimensions. Later dimensions of a sample fall back to random numbers.
+const PRIMES : [u32 ; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+///Number of dimensions of the Halton sequence available.
+pub const HALTON_DIMENSIONS : usize = PRIMES.len();
+
+///How the digits of each Halton point are permuted. Variants include
+///
+/// None: the plain sequence, whose higher dimensions (with large prime bases) fall into visible diagonal patterns.
+///
+/// Faure: Faure's deterministic permutations, which break up those patterns.
+///
+/// Owen: every digit is randomly shifted depending on the digits before it, which randomizes the sequence while keeping it evenly spread.
+#[derive(Debug, Clone, Copy)]
+pub enum HaltonPermutation {
+    None,
+    Faure,
+    Owen,
+}
+
+///Returns the given dimension of the Halton point at index, permuted as requested.
+///
+/// seed decorrelates pixels: the Owen permutation scrambles with it, and the others shift the point by a random offset (wrapping around).
+pub fn halton(index : u32, dimension : usize, permutation : HaltonPermutation, seed : u32) -> Float {
+    let base = PRIMES[dimension];
+    let dimension_seed = hash(seed ^ hash(dimension as u32));
+    match permutation {
+        HaltonPermutation::None => shift(radical_inverse(index, base, |digit, _prefix| digit), dimension_seed),
+        HaltonPermutation::Faure => {
+            let sigma = &faure_permutations()[dimension];
+            shift(radical_inverse(index, base, |digit, _prefix| sigma[digit as usize] as u32), dimension_seed)
+        },
+        HaltonPermutation::Owen => radical_inverse(index, base, |digit, prefix| {
+            (digit + hash(dimension_seed ^ hash(prefix)) % base) % base
+        }),
+    }
+}
+
+///Mirrors the base b digits of index about the decimal point, passing each digit (along with a number identifying the digits
+///
+/// before it) through permute. Digits continue past the end of index as zeros until single precision is exhausted, since permutations may
+/// map zero to something else.
+fn radical_inverse(index : u32, base : u32, permute : impl Fn(u32, u32) -> u32) -> Float {
+    let inverse_base = 1.0 / base as f64;
+    let mut value = 0.0;
+    let mut scale = inverse_base;
+    let mut remaining = index;
+    let mut prefix : u32 = 1;
+    while scale > 1e-8 {
+        let digit = remaining % base;
+        remaining /= base;
+        value += permute(digit, prefix) as f64 * scale;
+        prefix = prefix.wrapping_mul(base).wrapping_add(digit);
+        scale *= inverse_base;
+    }
+    (value as Float).min(1.0 - Float::EPSILON)
+}
+
+///Adds a random offset (derived from seed) to x, wrapping around to stay between 0 and 1.
+fn shift(x : Float, seed : u32) -> Float {
+    let shifted = x + to_unit(seed);
+    (shifted - shifted.floor()).min(1.0 - Float::EPSILON)
+}
+
+///Returns Faure's permutation of the digits of every prime base, building them the first time.
+fn faure_permutations() -> &'static Vec<Vec<u16>> {
+    static PERMUTATIONS : OnceLock<Vec<Vec<u16>>> = OnceLock::new();
+    PERMUTATIONS.get_or_init(|| {
+        //Built up for every base from 2 to the largest prime, since each is defined in terms of smaller ones
+        let largest = PRIMES[PRIMES.len() - 1] as usize;
+        let mut sigma : Vec<Vec<u16>> = vec![vec![], vec![0], vec![0, 1]];
+        for b in 3..=largest {
+            let c = b / 2;
+            let next = if b % 2 == 0 {
+                sigma[c].iter().map(|s| 2 * s).chain(sigma[c].iter().map(|s| 2 * s + 1)).collect()
+            } else {
+                let mut next : Vec<u16> = sigma[b - 1].iter().map(|&s| if s as usize >= c {s + 1} else {s}).collect();
+                next.insert(c, c as u16);
+                next
+            };
+            sigma.push(next);
+        }
+        PRIMES.iter().map(|&p| sigma[p as usize].clone()).collect()
+    })
+}
@@ -1,20 +1,45 @@
-use std::f64::consts::PI;
-use crate::ray_class::Ray;
-use crate::vec_class::{Vec3, Point3, dot};
+use core::f64::consts::PI;
+use core::fmt::Debug;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::ray::{Ray, spawn_origin, t_min, epsilon};
+use crate::vec3::{Vec3, Point3, Color, dot, cross, random_in_cone, random_float, random_range_float, Float, wide};
 use crate::materials::Material;
-use crate::bvh::AABB;
+use crate::bvh::{AABB, surrounding_box};
+use crate::textures::Textures;
+#[cfg(feature = "std")]
+use crate::aov::stable_id;
+#[cfg(not(feature = "std"))]
+use crate::math::Real;
 use libm::{acos, atan2};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
-///Helper struct to store records of ray collisions between surfaces.
+///Helper struct to store records of ray collisions between surfaces. Object is the index of the object hit
+/// 
+/// within the acceleration structure that found it (see Accelerator::object()).
 #[derive(Debug, Clone, Copy)]
 pub struct HitRecord {
     pub p : Point3,
     pub normal : Vec3,
     pub mat : Material,
-    pub t : f32,
-    pub u : f32,
-    pub v : f32,
+    pub t : Float,
+    pub u : Float,
+    pub v : Float,
     pub front_facing : bool,
+    pub object : usize,
+    ///Width of the ray's cone where it hit, in texture coordinates (see Scene::shade()), which image textures pick how blurred a mipmap
+    ///
+    /// to look up by; 0 for the sharpest.
+    pub footprint : Float,
+}
+
+impl Default for HitRecord {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HitRecord {
@@ -23,9 +48,12 @@ impl HitRecord {
             p : Point3::new(0.0, 0.0, 0.0), 
             normal : Vec3::new(0.0, 0.0, 0.0), 
             t : 0.0, front_facing : false, 
-            mat : Material::Lambertian(usize::MAX),
+            //A black mirror, which looks up no textures, until a hit fills in the real material
+            mat : Material::Metal(Color::new(0.0, 0.0, 0.0), 0.0),
             u : 0.0,
             v : 0.0,
+            object : 0,
+            footprint : 0.0,
         }
     }
 
@@ -37,39 +65,112 @@ impl HitRecord {
             self.normal = -outward_normal;
         }
     }
+
+    ///Returns where a ray leaving this hit in the given direction starts (see ray::spawn_origin()). Points inside media have no surface to leave,
+    ///
+    /// so rays scattered there start at the point itself.
+    pub fn spawn_origin(&self, direction : Vec3) -> Point3 {
+        match self.mat {
+            Material::Isotropic(..) | Material::EmissiveIsotropic(..) => self.p,
+            _ => spawn_origin(self.p, self.normal, direction),
+        }
+    }
 }
 
 ///Representation of objects within scenes. Possible object types include:
 /// 
 /// Sphere: a 3-dimensional sphere with uniform radius.
 /// 
+/// MovingSphere: a sphere moving in a straight line from its first center at the first time to its second center at the second time,
+/// which is motion blurred when the camera's shutter is open while it moves. The shutter should open and close between those times.
+/// 
 /// XYRect: a 2-dimensional rectangle positioned at a specific z-coordinate.
 /// 
 /// XZRect: a 2-dimensional rectangle positioned at a specific y-coordinate.
 /// 
 /// YZRect: a 2-dimensional rectangle positioned at a specific x-coordinate.
 /// 
+/// Triangle: a triangle with the given corners, and the texture coordinates (u, v) at each corner. Meshes are stored as many triangles,
+/// 
+/// facing the side their corners wind counterclockwise around.
+/// 
 /// Medium: a constant medium that produces a fog-like effect.
+/// 
+/// HeterogeneousMedium: a medium whose density varies through space, such as smoke or a cloud. Takes a maximum density
+/// 
+/// and the id of a texture of the scene (such as Perlin noise) whose brightness scales it at each point.
+/// 
+/// Custom: geometry defined outside this crate (see Shape), shared between clones. It can't be serialized.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Hittable {
-    Sphere(Material, Point3, f32),
-    XYRect(Material, f32, f32, f32, f32, f32),
-    XZRect(Material, f32, f32, f32, f32, f32),
-    YZRect(Material, f32, f32, f32, f32, f32),
+    Sphere(Material, Point3, Float),
+    MovingSphere(Material, Point3, Point3, Float, Float, Float),
+    XYRect(Material, Float, Float, Float, Float, Float),
+    XZRect(Material, Float, Float, Float, Float, Float),
+    YZRect(Material, Float, Float, Float, Float, Float),
     Box(Material, Point3, Point3),
-    Medium(Material, Box<Hittable>, f32),
+    Triangle(Material, [Point3 ; 3], [(Float, Float) ; 3]),
+    Medium(Material, Box<Hittable>, Float),
+    HeterogeneousMedium(Material, Box<Hittable>, Float, #[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(Arc<dyn Shape>),
+}
+
+///Geometry that programs using this crate can add to scenes as Hittable::Custom, without changing the built-in objects.
+/// 
+/// Shapes fill in the whole HitRecord when hit, including their material and front_facing (see HitRecord::set_front_face_normal()),
+/// and hits that aren't closer than t_max must leave it untouched. Every shape says how it is sampled as an emitter through random_point_on()
+/// and pdf_value(); shapes that are never lights can return any point and a pdf of 0, which keeps them from being sampled.
+pub trait Shape : Debug + Send + Sync {
+
+    ///Determines if a ray hits the shape between t_min and t_max, as Hittable::hit() does.
+    fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord) -> bool;
+
+    ///Returns a box the shape fits inside.
+    fn bounding_box(&self) -> AABB;
+
+    ///Picks a random point on the shape, as seen from origin, as Hittable::random_point_on() does.
+    fn random_point_on(&self, origin : Point3) -> Point3;
+
+    ///Returns the solid angle pdf with which random_point_on() would pick a point in the given direction from origin.
+    fn pdf_value(&self, origin : Point3, direction : Vec3) -> Float;
 }
 
 impl Hittable {
 
+    ///Returns an ID for this object that stays the same from run to run, whatever acceleration structure holds it,
+    /// 
+    /// as long as its shape, position and material don't change.
+    #[cfg(feature = "std")]
+    pub fn id(&self) -> u32 {
+        stable_id(&format!("{:?}", self))
+    }
+
+    ///Returns the ids of the textures this object looks up: its material's, and a heterogeneous medium's density, along with those of the textures
+    ///
+    /// any regions among them show part of (see Textures::referenced()). Custom shapes report none.
+    pub fn texture_ids(&self, textures : &Textures) -> Vec<usize> {
+        let ids = match self {
+            Hittable::Sphere(mat, ..) | Hittable::MovingSphere(mat, ..) | Hittable::XYRect(mat, ..) | Hittable::XZRect(mat, ..) | Hittable::YZRect(mat, ..)
+            | Hittable::Box(mat, ..) | Hittable::Triangle(mat, ..) | Hittable::Medium(mat, ..) => mat.texture_ids(),
+            Hittable::HeterogeneousMedium(mat, _, _, texture_id) => [mat.texture_ids(), vec![*texture_id]].concat(),
+            Hittable::Custom(_) => vec![],
+        };
+        ids.into_iter().flat_map(|id| textures.referenced(id)).collect()
+    }
+
     ///Determines if a ray hits this Hittable object.
     /// 
     /// 
     /// 
     /// A mutable HitRecord reference is also passed as argument,
     /// so that if the function returns true, there is data regarding the details of the collision.
-    pub fn hit(&self, r : Ray, t_min : f32, t_max : f32, rec : &mut HitRecord) -> bool {
+    /// It is left untouched if the function returns false, so callers can pass their closest hit so far without copying it.
+    /// Heterogeneous media look their density up in the scene's textures.
+    pub fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, textures : &Textures) -> bool {
         match self {
+            Hittable::Custom(shape) => shape.hit(r, t_min, t_max, rec),
             Hittable::Sphere(mat, center, radius) => {
                 let oc = r.origin_point - *center;
                 let a = r.direction.length_squared();
@@ -98,156 +199,111 @@ impl Hittable {
                 
                 true
             },
-            Hittable::XYRect(mat, x0, x1, y0, y1, k) => {
-                let t = (*k - r.origin_point.z) / r.direction.z;
-                if t < t_min || t > t_max {
+            Hittable::MovingSphere(mat, center0, center1, time0, time1, radius) => {
+                Hittable::Sphere(*mat, moving_center(*center0, *center1, *time0, *time1, r.time), *radius).hit(r, t_min, t_max, rec, textures)
+            },
+            Hittable::XYRect(mat, x0, x1, y0, y1, k) => hit_rect(r, t_min, t_max, rec, *mat, 2, (*x0, *x1, *y0, *y1, *k)),
+            Hittable::XZRect(mat, x0, x1, z0, z1, k) => hit_rect(r, t_min, t_max, rec, *mat, 1, (*x0, *x1, *z0, *z1, *k)),
+            Hittable::YZRect(mat, y0, y1, z0, z1, k) => hit_rect(r, t_min, t_max, rec, *mat, 0, (*y0, *y1, *z0, *z1, *k)),
+            Hittable::Triangle(mat, [a, b, c], uvs) => {
+
+                //Moller-Trumbore intersection, solving for the distance along the ray and the barycentric coordinates of the hit together
+                let edge1 = *b - *a;
+                let edge2 = *c - *a;
+                let p = cross(r.direction, edge2);
+                let determinant = dot(edge1, p);
+                if determinant.abs() < 1e-12 {
                     return false;
                 }
-                let x = r.origin_point.x + t*r.direction.x;
-                let y = r.origin_point.y + t*r.direction.y;
-                if x < *x0 || x > *x1 || y < *y0 || y > *y1 {
+                let s = r.origin_point - *a;
+                let beta = dot(s, p) / determinant;
+                if !(0.0..=1.0).contains(&beta) {
                     return false;
                 }
-
-                //Record initialization
-                rec.u = (x - *x0) / (*x1 - *x0);
-                rec.v = (y - *y0) / (*y1 - *y0);
-                rec.t = t;
-                rec.mat = *mat;
-                rec.p = r.at(t);
-                rec.set_front_face_normal(r, Vec3::new(0.0, 0.0, 1.0));
-                
-                true
-            },
-            Hittable::XZRect(mat, x0, x1, z0, z1, k) => {
-                let t = (*k - r.origin_point.y) / r.direction.y;
-                if t < t_min || t > t_max {
+                let q = cross(s, edge1);
+                let gamma = dot(r.direction, q) / determinant;
+                if gamma < 0.0 || beta + gamma > 1.0 {
                     return false;
                 }
-                let x = r.origin_point.x + t*r.direction.x;
-                let z = r.origin_point.z + t*r.direction.z;
-                if x < *x0 || x > *x1 || z < *z0 || z > *z1 {
+                let t = dot(edge2, q) / determinant;
+                if t < t_min || t > t_max {
                     return false;
                 }
 
                 //Record initialization
-                rec.u = (x - *x0) / (*x1 - *x0);
-                rec.v = (z - *z0) / (*z1 - *z0);
+                let alpha = 1.0 - beta - gamma;
+                rec.u = alpha * uvs[0].0 + beta * uvs[1].0 + gamma * uvs[2].0;
+                rec.v = alpha * uvs[0].1 + beta * uvs[1].1 + gamma * uvs[2].1;
                 rec.t = t;
                 rec.mat = *mat;
                 rec.p = r.at(t);
-                rec.set_front_face_normal(r, Vec3::new(0.0, 1.0, 0.0));
+                rec.set_front_face_normal(r, cross(edge1, edge2).unit_vector());
 
                 true
             },
-            Hittable::YZRect(mat, y0, y1, z0, z1, k) => {
+            Hittable::Medium(mat, b, density) => {
 
-                let t = (*k - r.origin_point.x) / r.direction.x;
+                let (t0, t1) = match medium_interval(b, r, t_min, t_max, textures) {
+                    Some(interval) => interval,
+                    None => return false,
+                };
 
-                //Make sure t is valid
-                if t < t_min || t > t_max {
-                    return false;
-                }
-                let y = r.origin_point.x + t*r.direction.x;
-                let z = r.origin_point.z + t*r.direction.z;
+                let distance_inside_boundary = (t1 - t0) * r.direction.length();
+                let hit_distance = random_float().ln() / -(*density);
 
-                //Check to see if the expected y and z values are valid
-                if y < *y0 || y > *y1 || z < *z0 || z > *z1 {
+                if hit_distance > distance_inside_boundary {
                     return false;
                 }
 
-                //Hit record initialization
-                rec.u = (y - *y0) / (*y1 - *y0);
-                rec.v = (z - *z0) / (*z1 - *z0);
-                rec.t = t;
-                rec.mat = *mat;
-                rec.p = r.at(t);
-                rec.set_front_face_normal(r, Vec3::new(1.0, 0.0, 0.0));
-
+                rec.t = t0 + hit_distance / r.direction.length();
+                medium_record(r, *mat, rec);
                 true
             },
-            Hittable::Medium(mat, b, density) => {
-
-                let mut rec1 = HitRecord::new();
-                let mut rec2 = HitRecord::new();
-
-                //Make sure rays are hitting object
-                if !b.hit(r, -f32::MAX, f32::MAX, &mut rec1) {
-                    return false;
-                }
-                if !b.hit(r, rec1.t+0.0001, f32::MAX, &mut rec2) {
-                    return false;
-                }
+            Hittable::HeterogeneousMedium(mat, b, max_density, texture_id) => {
 
-                //Ensure record distances are within appropriate bounds.
-                rec1.t = rec1.t.max(t_min);
-                rec2.t = rec2.t.min(t_max);
-                if rec1.t >= rec2.t {
-                    return false;
-                }
-                rec1.t = rec1.t.max(0.0);
-
-                let distance_inside_boundary = (rec2.t - rec1.t) * r.direction.length();
-                let hit_distance = rand::random::<f32>().ln() / -(*density);
+                let (t0, t1) = match medium_interval(b, r, t_min, t_max, textures) {
+                    Some(interval) => interval,
+                    None => return false,
+                };
 
-                if hit_distance > distance_inside_boundary {
-                    return false;
+                //Delta tracking: take steps as if the medium had its maximum density everywhere, 
+                //and treat each collision as real with probability equal to the fraction of that density actually present
+                let length = r.direction.length();
+                let mut t = t0;
+                loop {
+                    t -= (1.0 - random_float()).ln() / (*max_density * length);
+                    if t >= t1 {
+                        return false;
+                    }
+                    let c = textures.value(*texture_id, 0.0, 0.0, r.at(t));
+                    let density = ((c.x + c.y + c.z) / 3.0).clamp(0.0, 1.0);
+                    if random_float() < density {
+                        break;
+                    }
                 }
 
-                //Hit record initialization
-                rec.t = rec1.t + hit_distance / r.direction.length();
-                rec.p = r.at(rec.t);
-                rec.normal = Vec3::new(1.0, 0.0 , 0.0);
-                rec.front_facing = true;
-                rec.mat = *mat;
-
-                return true;
+                rec.t = t;
+                medium_record(r, *mat, rec);
+                true
             },
             Hittable::Box(mat, minimum, maximum) => {
-                
-                //Initialize sides of box
-                let side1 = Hittable::XYRect(*mat, minimum.x, maximum.x, minimum.y, maximum.y, minimum.z);
-                let side2 = Hittable::XYRect(*mat, minimum.x, maximum.x, minimum.y, maximum.y, maximum.z);
-                let side3 = Hittable::XZRect(*mat, minimum.x, maximum.x, minimum.z, maximum.z, minimum.y);
-                let side4 = Hittable::XZRect(*mat, minimum.x, maximum.x, minimum.z, maximum.z, maximum.y);
-                let side5 = Hittable::YZRect(*mat, minimum.y, maximum.y, minimum.z, maximum.z, minimum.x);
-                let side6 = Hittable::YZRect(*mat, minimum.y, maximum.y, minimum.z, maximum.z, maximum.x);
-
-                //Keep track of closest collision out of the sides
-                let mut temp_rec = HitRecord::new();
+
+                //Check collisions with each side, narrowing the range as closer ones are found
+                let sides = [
+                    (0, (minimum.y, maximum.y, minimum.z, maximum.z, maximum.x)),
+                    (1, (minimum.x, maximum.x, minimum.z, maximum.z, maximum.y)),
+                    (2, (minimum.x, maximum.x, minimum.y, maximum.y, maximum.z)),
+                    (0, (minimum.y, maximum.y, minimum.z, maximum.z, minimum.x)),
+                    (1, (minimum.x, maximum.x, minimum.z, maximum.z, minimum.y)),
+                    (2, (minimum.x, maximum.x, minimum.y, maximum.y, minimum.z)),
+                ];
                 let mut closest = t_max;
                 let mut hit_something = false;
-
-                //Check collisions with each side
-                if side6.hit(r, t_min, closest, &mut temp_rec) {
-                    hit_something = true;
-                    closest = temp_rec.t;
-                    *rec = temp_rec;
-                }
-                if side4.hit(r, t_min, closest, &mut temp_rec) {
-                    hit_something = true;
-                    closest = temp_rec.t;
-                    *rec = temp_rec;
-                }
-                if side2.hit(r, t_min, closest, &mut temp_rec) {
-                    hit_something = true;
-                    closest = temp_rec.t;
-                    *rec = temp_rec;
-                }
-                if side5.hit(r, t_min, closest, &mut temp_rec) {
-                    hit_something = true;
-                    closest = temp_rec.t;
-                    *rec = temp_rec;
-                }
-                if side3.hit(r, t_min, closest, &mut temp_rec) {
-                    hit_something = true;
-                    closest = temp_rec.t;
-                    *rec = temp_rec;
-                }
-                if side1.hit(r, t_min, closest, &mut temp_rec) {
-                    hit_something = true;
-                    closest = temp_rec.t;
-                    *rec = temp_rec;
+                for (axis, bounds) in sides {
+                    if hit_rect(r, t_min, closest, rec, *mat, axis, bounds) {
+                        hit_something = true;
+                        closest = rec.t;
+                    }
                 }
 
                 //True if at least one side was hit
@@ -262,6 +318,9 @@ impl Hittable {
             Hittable::Sphere(_mat, center, radius) => {
                 AABB::new(*center - Vec3::new(*radius, *radius, *radius), *center + Vec3::new(*radius, *radius, *radius))
             },
+            Hittable::MovingSphere(mat, center0, center1, _time0, _time1, radius) => {
+                surrounding_box(Hittable::Sphere(*mat, *center0, *radius).bounding_box(), Hittable::Sphere(*mat, *center1, *radius).bounding_box())
+            },
             Hittable::XYRect(_mat, x0, x1, y0, y1, k) => {
                 AABB::new(Point3::new(*x0, *y0, *k-0.001), Point3::new(*x1, *y1, k+0.001))
             },
@@ -271,26 +330,257 @@ impl Hittable {
             Hittable::YZRect(_mat, y0, y1, z0, z1, k) => {
                 AABB::new(Point3::new(*k-0.001, *y0, *z0), Point3::new(*k+0.001, *y1, *z1))
             },
-            Hittable::Medium(_mat, b, _density) => {
+            Hittable::Triangle(_mat, [a, b, c], _uvs) => {
+                let minimum = Point3::new(a.x.min(b.x).min(c.x), a.y.min(b.y).min(c.y), a.z.min(b.z).min(c.z));
+                let maximum = Point3::new(a.x.max(b.x).max(c.x), a.y.max(b.y).max(c.y), a.z.max(b.z).max(c.z));
+                AABB::new(minimum - Vec3::new(0.001, 0.001, 0.001), maximum + Vec3::new(0.001, 0.001, 0.001))
+            },
+            Hittable::Medium(_mat, b, _density) | Hittable::HeterogeneousMedium(_mat, b, _density, _) => {
                 (**b).bounding_box()
             },
             Hittable::Box(_mat, minimum, maximum) => {
                 AABB::new(*minimum, *maximum)
             },
+            Hittable::Custom(shape) => shape.bounding_box(),
         }
     }
     
-    ///Retrieves the appropriate u and v values for spheres (for use in determining color values).
-    pub fn get_uv(&self, p : Point3, u : &mut f32, v : &mut f32) {
+    ///Picks a random point on this object, as seen from origin (for use in sampling emissive objects directly).
+    /// 
+    /// Spheres are sampled uniformly over the cone of directions they subtend, and rectangles and triangles uniformly over their area.
+    /// Other objects cannot be sampled, and return the center of their bounding box.
+    pub fn random_point_on(&self, origin : Point3) -> Point3 {
         match self {
-            Hittable::Sphere(_point, _radius, _mat) => {
-                let theta = acos(-p.y as f64);
-                let phi = atan2(-p.z as f64, p.x as f64) + PI;
+            Hittable::Sphere(_mat, center, radius) => {
+                let to_center = *center - origin;
+                let distance_squared = to_center.length_squared();
+                if distance_squared <= radius * radius {
+                    return *center;
+                }
 
-                *u = (phi / (2.0 * PI)) as f32;
-                *v = (theta / PI) as f32;
+                let cos_max = (1.0 - radius * radius / distance_squared).sqrt();
+                let direction = random_in_cone(to_center.unit_vector(), cos_max);
+                let mut rec = HitRecord::new();
+                if self.sample_hit(Ray::new(origin, direction), 0.0, &mut rec) {
+                    rec.p
+                } else {
+                    *center
+                }
+            },
+            Hittable::XYRect(_mat, x0, x1, y0, y1, k) => Point3::new(random_range_float(*x0, *x1), random_range_float(*y0, *y1), *k),
+            Hittable::XZRect(_mat, x0, x1, z0, z1, k) => Point3::new(random_range_float(*x0, *x1), *k, random_range_float(*z0, *z1)),
+            Hittable::YZRect(_mat, y0, y1, z0, z1, k) => Point3::new(*k, random_range_float(*y0, *y1), random_range_float(*z0, *z1)),
+            Hittable::Triangle(_mat, [a, b, c], _uvs) => {
+                let root = random_float().sqrt();
+                let beta = random_float() * root;
+                *a * (1.0 - root) + *b * beta + *c * (root - beta)
+            },
+            Hittable::Custom(shape) => shape.random_point_on(origin),
+            _ => {
+                let aabb = self.bounding_box();
+                (aabb.minimum + aabb.maximum) / 2.0
             },
-            _ => (),
+        }
+    }
+
+    ///Returns the solid angle pdf with which random_point_on() would pick a point in the given direction from origin.
+    /// 
+    /// This is 0 if the direction misses the object, or if the object cannot be sampled.
+    pub fn pdf_value(&self, origin : Point3, direction : Vec3) -> Float {
+        let mut rec = HitRecord::new();
+        match self {
+            Hittable::Sphere(_mat, center, radius) => {
+                let distance_squared = (*center - origin).length_squared();
+                if distance_squared <= radius * radius || !self.sample_hit(Ray::new(origin, direction), t_min(), &mut rec) {
+                    return 0.0;
+                }
+
+                let cos_max = (1.0 - radius * radius / distance_squared).sqrt();
+                1.0 / (2.0 * crate::vec3::consts::PI * (1.0 - cos_max))
+            },
+            Hittable::XYRect(_, x0, x1, y0, y1, _) | Hittable::XZRect(_, x0, x1, y0, y1, _) | Hittable::YZRect(_, x0, x1, y0, y1, _) => {
+                if !self.sample_hit(Ray::new(origin, direction), t_min(), &mut rec) {
+                    return 0.0;
+                }
+
+                let area = (x1 - x0) * (y1 - y0);
+                let distance_squared = rec.t * rec.t * direction.length_squared();
+                let cosine = dot(direction, rec.normal).abs() / direction.length();
+                distance_squared / (cosine * area)
+            },
+            Hittable::Triangle(_mat, [a, b, c], _uvs) => {
+                if !self.sample_hit(Ray::new(origin, direction), t_min(), &mut rec) {
+                    return 0.0;
+                }
+
+                let area = 0.5 * cross(*b - *a, *c - *a).length();
+                let distance_squared = rec.t * rec.t * direction.length_squared();
+                let cosine = dot(direction, rec.normal).abs() / direction.length();
+                distance_squared / (cosine * area)
+            },
+            Hittable::Custom(shape) => shape.pdf_value(origin, direction),
+            _ => 0.0,
+        }
+    }
+
+    ///Hits the object with a ray while sampling it as an emitter. Only spheres, rectangles and triangles are hit here,
+    ///
+    /// and they look up no textures.
+    fn sample_hit(&self, r : Ray, t_min : Float, rec : &mut HitRecord) -> bool {
+        self.hit(r, t_min, Float::INFINITY, rec, &Textures::new())
+    }
+
+    ///Retrieves the appropriate u and v values for spheres (for use in determining color values).
+    pub fn get_uv(&self, p : Point3, u : &mut Float, v : &mut Float) {
+        if let Hittable::Sphere(_point, _radius, _mat) = self {
+            let theta = acos(wide(-p.y));
+            let phi = atan2(wide(-p.z), wide(p.x)) + PI;
+
+            *u = (phi / (2.0 * PI)) as Float;
+            *v = (theta / PI) as Float;
         };
     }
-}
\ No newline at end of file
+}
+///Center of a moving sphere at the given time.
+fn moving_center(center0 : Point3, center1 : Point3, time0 : Float, time1 : Float, time : Float) -> Point3 {
+    if time1 == time0 {
+        return center0;
+    }
+    center0 + (center1 - center0) * ((time - time0) / (time1 - time0))
+}
+
+///Determines if a ray hits an axis-aligned rectangle lying in the plane where the given axis equals k,
+/// 
+/// spanning [a0, a1] and [b0, b1] along the other two axes (in x, y, z order), which also give the hit's texture coordinates.
+#[inline]
+fn hit_rect(r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, mat : Material, axis : usize, (a0, a1, b0, b1, k) : (Float, Float, Float, Float, Float)) -> bool {
+    let (first, second) = match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+
+    //Make sure t is valid
+    let t = (k - r.origin_point[axis]) / r.direction[axis];
+    if t < t_min || t > t_max {
+        return false;
+    }
+
+    //Check to see if the expected coordinates on the other axes are within the rectangle
+    let a = r.origin_point[first] + t*r.direction[first];
+    let b = r.origin_point[second] + t*r.direction[second];
+    if a < a0 || a > a1 || b < b0 || b > b1 {
+        return false;
+    }
+
+    //Hit record initialization
+    let mut outward_normal = Vec3::new(0.0, 0.0, 0.0);
+    outward_normal[axis] = 1.0;
+    rec.u = (a - a0) / (a1 - a0);
+    rec.v = (b - b0) / (b1 - b0);
+    rec.t = t;
+    rec.mat = mat;
+    rec.p = r.at(t);
+    rec.set_front_face_normal(r, outward_normal);
+
+    true
+}
+
+///Finds the stretch of a ray (clipped to t_min and t_max) that lies inside a medium's boundary, if any.
+fn medium_interval(boundary : &Hittable, r : Ray, t_min : Float, t_max : Float, textures : &Textures) -> Option<(Float, Float)> {
+    let mut rec1 = HitRecord::new();
+    let mut rec2 = HitRecord::new();
+
+    //Make sure rays are hitting object
+    if !boundary.hit(r, -Float::MAX, Float::MAX, &mut rec1, textures) {
+        return None;
+    }
+    if !boundary.hit(r, rec1.t + epsilon() * 0.1, Float::MAX, &mut rec2, textures) {
+        return None;
+    }
+
+    //Ensure record distances are within appropriate bounds.
+    rec1.t = rec1.t.max(t_min);
+    rec2.t = rec2.t.min(t_max);
+    if rec1.t >= rec2.t {
+        return None;
+    }
+    Some((rec1.t.max(0.0), rec2.t))
+}
+
+///Fills in a hit record for a scattering event inside a medium, once rec.t has been set.
+fn medium_record(r : Ray, mat : Material, rec : &mut HitRecord) {
+    rec.p = r.at(rec.t);
+    rec.normal = Vec3::new(1.0, 0.0 , 0.0);
+    rec.front_facing = true;
+    rec.mat = mat;
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::Arc;
+    use crate::vec3::{Vec3, Point3, Color, random_range_float, dot, Float};
+    use crate::ray::Ray;
+    use crate::bvh::AABB;
+    use crate::materials::Material;
+    use crate::textures::{Texture, Textures};
+    use crate::camera::{Camera, StandardCamera};
+    use crate::accelerator::AcceleratorKind;
+    use crate::scene::Scene;
+    use crate::integrator::{Integrator, Clamping};
+    use crate::render::{Renderer, RenderSettings};
+    use super::{Hittable, HitRecord, Shape};
+
+    ///Square facing +z at z = -1, from -2 to 0 across and -2 to 2 up, so that it fills the left half of a camera at the origin looking down -z.
+    #[derive(Debug)]
+    struct Panel(Material);
+
+    impl Shape for Panel {
+        fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord) -> bool {
+            let t = (-1.0 - r.origin_point.z) / r.direction.z;
+            let p = r.at(t);
+            if !(t_min..t_max).contains(&t) || !(-2.0..0.0).contains(&p.x) || p.y.abs() > 2.0 {
+                return false;
+            }
+            (rec.t, rec.p, rec.mat, rec.u, rec.v) = (t, p, self.0, (p.x + 2.0) / 2.0, (p.y + 2.0) / 4.0);
+            rec.set_front_face_normal(r, Vec3::new(0.0, 0.0, 1.0));
+            true
+        }
+
+        fn bounding_box(&self) -> AABB {
+            AABB::new(Point3::new(-2.0, -2.0, -1.001), Point3::new(0.0, 2.0, -0.999))
+        }
+
+        fn random_point_on(&self, _origin : Point3) -> Point3 {
+            Point3::new(random_range_float(-2.0, 0.0), random_range_float(-2.0, 2.0), -1.0)
+        }
+
+        fn pdf_value(&self, origin : Point3, direction : Vec3) -> Float {
+            let mut rec = HitRecord::new();
+            if !self.hit(Ray::new(origin, direction), 0.001, Float::INFINITY, &mut rec) {
+                return 0.0;
+            }
+            let cosine = dot(direction, rec.normal).abs() / direction.length();
+            rec.t * rec.t * direction.length_squared() / (cosine * 8.0)
+        }
+    }
+
+    ///Renders a custom shape that glows, on a black background, and checks it shows up in the half of the image it covers and nowhere else.
+    #[test]
+    fn custom_shapes_render() {
+        let mut textures = Textures::new();
+        let light = Material::Light(textures.add(Texture::Solid(Color::new(1.0, 0.5, 0.25))));
+        let mut objects = vec![Hittable::Custom(Arc::new(Panel(light)))];
+        let accelerator = AcceleratorKind::Bvh.build(&mut objects, &textures).unwrap();
+        let mut world = Scene::new(accelerator, None, textures);
+
+        let camera = StandardCamera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 90.0, 2.0, 0.0, 1.0);
+        let cameras : Vec<Box<dyn Camera>> = vec![Box::new(camera)];
+        let image = Renderer::new(RenderSettings::new(8, 4, 4, Integrator::PathTracer(4, Clamping::None))).render(&mut world, &cameras);
+
+        for (index, (color, alpha)) in image.pixels().into_iter().enumerate() {
+            let expected = if index % 8 < 4 {Color::new(1.0, 0.5, 0.25)} else {Color::new(0.0, 0.0, 0.0)};
+            assert!((color / alpha - expected).length() < 1e-4, "pixel {} is {:?}, not {:?}", index, color / alpha, expected);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs::read_to_string;
+use std::io::{Error, ErrorKind, Result};
+use crate::vec3::{Vec3, dot, orthonormal_basis, Float};
+use crate::vec3::consts::PI;
+
+///Most angles, or tilt pairs, a file may list along either direction. Real fixtures list a few hundred at most,
+///
+/// so larger counts mean a malformed file, which would otherwise have the parser allocate or loop for nothing.
+const MAX_COUNT : usize = 100_000;
+
+///Angular intensity distribution of a real light fixture, loaded from an IES LM-63 photometric file.
+///
+/// Uses type C photometry: vertical angles are measured from the fixture's downward axis (0 to 180 degrees),
+/// and horizontal angles are measured around that axis (0 to 360 degrees).
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    pub vertical_angles : Vec<Float>,
+    pub horizontal_angles : Vec<Float>,
+    pub candela : Vec<Float>,
+    pub max_candela : Float,
+}
+
+impl IesProfile {
+
+    ///Loads a profile from an .ies file.
+    pub fn load(path : &str) -> Result<IesProfile> {
+        IesProfile::parse(&read_to_string(path)?)
+    }
+
+    ///Parses the contents of an .ies file. Keywords before the TILT line are ignored, as are lamp tilt tables.
+    pub fn parse(contents : &str) -> Result<IesProfile> {
+        let tilt_start = contents.find("TILT=").ok_or_else(|| invalid("missing TILT line"))?;
+        let after_tilt = &contents[tilt_start..];
+        let line_end = after_tilt.find('\n').unwrap_or(after_tilt.len());
+        let tilt = after_tilt[5..line_end].trim();
+
+        let mut numbers = after_tilt[line_end..]
+            .split(|c : char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<Float>().map_err(|_| invalid("malformed number")));
+        let mut next = || numbers.next().unwrap_or_else(|| Err(invalid("unexpected end of file")));
+        let count = |value : Float| {
+            if value >= 0.0 && value.fract() == 0.0 && value <= MAX_COUNT as Float {
+                Ok(value as usize)
+            } else {
+                Err(invalid(&format!("{} isn't a count of angles between 0 and {}", value, MAX_COUNT)))
+            }
+        };
+
+        //Skip the lamp tilt table if it is included in the file
+        if tilt == "INCLUDE" {
+            next()?;
+            let pairs = count(next()?)?;
+            for _i in 0..2*pairs {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let num_vertical = count(next()?)?;
+        let num_horizontal = count(next()?)?;
+        let photometric_type = next()?;
+        for _i in 0..7 {
+            next()?;
+        }
+
+        if photometric_type != 1.0 {
+            return Err(invalid("only type C photometry is supported"));
+        }
+        if num_vertical == 0 || num_horizontal == 0 {
+            return Err(invalid("profile has no angles"));
+        }
+
+        let vertical_angles = (0..num_vertical).map(|_| next()).collect::<Result<Vec<Float>>>()?;
+        let horizontal_angles = (0..num_horizontal).map(|_| next()).collect::<Result<Vec<Float>>>()?;
+        let num_candela = num_vertical.checked_mul(num_horizontal).ok_or_else(|| invalid("too many candela values"))?;
+        let candela = (0..num_candela).map(|_| next().map(|c| c * multiplier)).collect::<Result<Vec<Float>>>()?;
+        let max_candela = candela.iter().cloned().fold(0.0, Float::max);
+
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            max_candela,
+        })
+    }
+
+    ///Returns the relative intensity (between 0 and 1) emitted along the unit direction,
+    ///
+    /// for a fixture whose downward axis points along the unit vector axis.
+    pub fn value(&self, direction : Vec3, axis : Vec3) -> Float {
+        if self.max_candela <= 0.0 {
+            return 0.0;
+        }
+
+        let (u, v) = orthonormal_basis(axis);
+        let vertical = dot(direction, axis).clamp(-1.0, 1.0).acos() * 180.0 / PI;
+        let mut horizontal = dot(direction, v).atan2(dot(direction, u)) * 180.0 / PI;
+        if horizontal < 0.0 {
+            horizontal += 360.0;
+        }
+
+        //Fold the horizontal angle according to the symmetry implied by the last horizontal angle
+        let last = *self.horizontal_angles.last().unwrap();
+        if last <= 0.0 {
+            horizontal = 0.0;
+        } else if last <= 90.0 {
+            horizontal %= 180.0;
+            if horizontal > 90.0 {
+                horizontal = 180.0 - horizontal;
+            }
+        } else if last <= 180.0 && horizontal > 180.0 {
+            horizontal = 360.0 - horizontal;
+        }
+
+        let (h0, h1, ht) = interpolation(&self.horizontal_angles, horizontal);
+        let (v0, v1, vt) = interpolation(&self.vertical_angles, vertical);
+        let n = self.vertical_angles.len();
+        let c = |h : usize, v : usize| self.candela[h * n + v];
+
+        let bottom = c(h0, v0) * (1.0 - vt) + c(h0, v1) * vt;
+        let top = c(h1, v0) * (1.0 - vt) + c(h1, v1) * vt;
+        (bottom * (1.0 - ht) + top * ht) / self.max_candela
+    }
+}
+
+///Finds the two entries of a sorted angle list surrounding x, and how far x is between them.
+fn interpolation(angles : &[Float], x : Float) -> (usize, usize, Float) {
+    let last = angles.len() - 1;
+    if x <= angles[0] {
+        return (0, 0, 0.0);
+    }
+    if x >= angles[last] {
+        return (last, last, 0.0);
+    }
+    let i = angles.partition_point(|a| *a <= x) - 1;
+    let width = angles[i+1] - angles[i];
+    (i, i + 1, if width > 0.0 {(x - angles[i]) / width} else {0.0})
+}
+
+fn invalid(message : &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid IES file: {}", message))
+}
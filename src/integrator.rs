@@ -0,0 +1,594 @@
+/*
+Module to store the 'integrator' enum, which determines the color seen along a ray, and its related methods.
+*/
+
+use std::cell::Cell;
+use crate::vec3::{Color, Vec3, Point3, dot, Float};
+use crate::ray::{Ray, t_min, epsilon};
+use crate::hitting::HitRecord;
+use crate::tree::MAX_PACKET;
+use crate::materials::{Material, Interior};
+use crate::scene::Scene;
+use crate::environment::luminance;
+use crate::spectrum::{sample_wavelength, rgb_to_spectral, spectral_to_rgb};
+use crate::aov::{AovSample, Coverage, Deep};
+use crate::stats::{count_path, start_shading, add_shading};
+
+///Determines the color seen along a camera ray. Variants include
+/// 
+/// PathTracer: full global illumination, given the maximum number of bounces and firefly clamping settings.
+/// 
+/// Spectral: the same as PathTracer, but each camera ray carries a single random wavelength, so that dispersive dielectrics split light into colors.
+/// 
+/// Normals: the outward surface normal of whatever the ray hits, mapped from [-1, 1] to [0, 1].
+/// 
+/// Albedo: the color of whatever the ray hits, without any lighting.
+/// 
+/// UV: the texture coordinates of whatever the ray hits, as red (u) and green (v).
+#[derive(Debug, Clone, Copy)]
+pub enum Integrator {
+    PathTracer(i32, Clamping),
+    Spectral(i32, Clamping),
+    Normals,
+    Albedo,
+    UV,
+}
+
+impl Integrator {
+
+    ///Determines the color seen along the ray.
+    pub fn radiance(&self, r : Ray, scene : &Scene) -> Color {
+        match self {
+            Integrator::PathTracer(max_depth, clamping) => return trace(r, scene, *max_depth, None, *clamping),
+            Integrator::Spectral(max_depth, clamping) => {
+                let lambda = sample_wavelength();
+                let mut r = r;
+                r.wavelength = Some(lambda);
+                return spectral_to_rgb(trace(r, scene, *max_depth, None, *clamping).x, lambda);
+            },
+            _ => (),
+        }
+        self.radiance_from(r, first_hit(r, scene), scene)
+    }
+
+    ///Determines the color seen along a ray whose first hit (or None, if it misses every object) has already been found,
+    ///
+    /// as by Accelerator::hit_packet().
+    pub fn radiance_from(&self, r : Ray, hit : Option<HitRecord>, scene : &Scene) -> Color {
+        match self {
+            Integrator::PathTracer(max_depth, clamping) => return shade(r, hit, scene, *max_depth, None, *clamping),
+            Integrator::Spectral(max_depth, clamping) => {
+                let lambda = sample_wavelength();
+                let mut r = r;
+                r.wavelength = Some(lambda);
+                return spectral_to_rgb(shade(r, hit, scene, *max_depth, None, *clamping).x, lambda);
+            },
+            _ => (),
+        }
+
+        let rec = match hit {
+            Some(rec) => rec,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        match self {
+            Integrator::Normals => (outward_normal(&rec) + Vec3::new(1.0, 1.0, 1.0)) * 0.5,
+            Integrator::Albedo => albedo(r, &rec, scene),
+            Integrator::UV => Color::new(rec.u, rec.v, 0.0),
+            Integrator::PathTracer(..) | Integrator::Spectral(..) => unreachable!(),
+        }
+    }
+
+    ///Determines the color seen along the ray along with its opacity, for compositing onto a photograph.
+    /// 
+    /// Rays that miss every object are fully transparent, and shadow catchers are transparent except for the shadows
+    /// 
+    /// and reflected light they receive from the rendered objects. The color is premultiplied by the opacity.
+    pub fn radiance_alpha(&self, r : Ray, scene : &Scene) -> (Color, Float) {
+        self.radiance_alpha_from(r, first_hit(r, scene), scene)
+    }
+
+    ///Determines the color and opacity seen along a ray whose first hit (or None, if it misses every object) has already been found.
+    pub fn radiance_alpha_from(&self, r : Ray, hit : Option<HitRecord>, scene : &Scene) -> (Color, Float) {
+        let rec = match hit {
+            Some(rec) => rec,
+            None => return (Color::new(0.0, 0.0, 0.0), 0.0),
+        };
+        if !matches!(rec.mat, Material::ShadowCatcher(_)) {
+            return (self.radiance_from(r, Some(rec), scene), 1.0);
+        }
+
+        let (lit, unshadowed) = catcher_lighting(scene, r, &rec);
+        let shadow = if unshadowed > 0.0 {(1.0 - lit / unshadowed).clamp(0.0, 1.0)} else {0.0};
+
+        //Light bounced onto the catcher by rendered objects, rather than by the photograph's surroundings
+        let mut scattered = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let mut reflected = Color::new(0.0, 0.0, 0.0);
+        let mut object_rec = HitRecord::new();
+        if rec.mat.scatter(r, &rec, &mut attenuation, &mut scattered, &scene.textures)
+            && scene.hit(Ray {origin_point : rec.spawn_origin(scattered.direction), ..scattered}, t_min(), Float::INFINITY, &mut object_rec)
+            && !matches!(object_rec.mat, Material::ShadowCatcher(_)) {
+            reflected = attenuation * self.radiance(scattered, scene);
+        }
+
+        (reflected, shadow.max(luminance(reflected).min(1.0)))
+    }
+
+    ///Determines the color seen along a ray whose first hit (or None, if it misses every object) has already been found,
+    ///
+    /// as radiance_from() does, along with the values of every pass.
+    pub fn radiance_aovs(&self, r : Ray, hit : Option<HitRecord>, scene : &Scene) -> (Color, AovSample) {
+        let (color, mut aovs) = match self {
+            Integrator::PathTracer(max_depth, clamping) => {
+                let shading = shade_parts(r, hit, scene, *max_depth, None, *clamping);
+                (shading.emitted + shading.reflected, shading.aovs(|c| c))
+            },
+            Integrator::Spectral(max_depth, clamping) => {
+                let lambda = sample_wavelength();
+                let mut r = r;
+                r.wavelength = Some(lambda);
+                let shading = shade_parts(r, hit, scene, *max_depth, None, *clamping);
+                (spectral_to_rgb((shading.emitted + shading.reflected).x, lambda), shading.aovs(|c| spectral_to_rgb(c.x, lambda)))
+            },
+            _ => (self.radiance_from(r, hit, scene), AovSample::new()),
+        };
+        if let Some(rec) = hit {
+            aovs += surface_aovs(r, &rec, scene, color, 1.0);
+        }
+        (color, aovs)
+    }
+
+    ///Determines the color and opacity seen along a ray whose first hit has already been found, as radiance_alpha_from() does,
+    ///
+    /// along with the values of every pass. Light that shadow catchers receive from the rendered objects counts as indirect.
+    pub fn radiance_alpha_aovs(&self, r : Ray, hit : Option<HitRecord>, scene : &Scene) -> (Color, Float, AovSample) {
+        match hit {
+            Some(rec) if matches!(rec.mat, Material::ShadowCatcher(_)) => {
+                let (color, alpha) = self.radiance_alpha_from(r, hit, scene);
+                let mut aovs = surface_aovs(r, &rec, scene, color, alpha);
+                aovs.indirect = color;
+                (color, alpha, aovs)
+            },
+            Some(_) => {
+                let (color, aovs) = self.radiance_aovs(r, hit, scene);
+                (color, 1.0, aovs)
+            },
+            None => (Color::new(0.0, 0.0, 0.0), 0.0, AovSample::new()),
+        }
+    }
+}
+
+///Returns the normal of a hit surface that points out of its object, whichever side the ray hit.
+fn outward_normal(rec : &HitRecord) -> Vec3 {
+    if rec.front_facing {rec.normal} else {-rec.normal}
+}
+
+///Returns the color of a hit surface without any lighting: how much it reflects, or what it emits if it doesn't scatter.
+fn albedo(r : Ray, rec : &HitRecord, scene : &Scene) -> Color {
+    let mut rec = *rec;
+    scene.shade(r, &mut rec);
+    let rec = &rec;
+    let mut scattered = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+    let mut attenuation = Color::new(0.0, 0.0, 0.0);
+    if rec.mat.scatter(r, rec, &mut attenuation, &mut scattered, &scene.textures) {
+        attenuation
+    } else {
+        rec.mat.emitted(rec.u, rec.v, rec.p, &scene.textures)
+    }
+}
+
+///Returns the albedo, normal, depth and ID passes of a camera ray's first hit, and its deep sample, given the color and opacity seen along the ray.
+fn surface_aovs(r : Ray, rec : &HitRecord, scene : &Scene, color : Color, alpha : Float) -> AovSample {
+    let object = scene.objects.object(rec.object).map(|object| object.id());
+    let depth = rec.t * r.direction.length();
+    AovSample {
+        albedo : albedo(r, rec, scene),
+        normal : outward_normal(rec),
+        depth,
+        coverage : 1.0,
+        objects : object.map_or(Coverage::new(), Coverage::single),
+        materials : Coverage::single(rec.mat.id()),
+        deep : Deep::single(object.unwrap_or(0), depth, color, alpha),
+        ..AovSample::new()
+    }
+}
+
+///Light leaving a point along a ray, split into what the point emits and what it reflects, with the part of the reflected light
+///
+/// that came straight from a light source. Emitted and reflected add up to what shade() returns.
+struct Shading {
+    emitted : Color,
+    reflected : Color,
+    direct : Color,
+}
+
+impl Shading {
+
+    ///Returns the lighting passes, after converting each color (from a spectral value, say).
+    fn aovs(&self, convert : impl Fn(Color) -> Color) -> AovSample {
+        AovSample {
+            direct : convert(self.direct),
+            indirect : convert(self.reflected - self.direct),
+            emission : convert(self.emitted),
+            ..AovSample::new()
+        }
+    }
+
+    ///Returns the light as seen from the start of the ray that reached the point, through air (see Scene::air()) that lets the first color of it through,
+    ///
+    /// and scatters the second in along the way, which counts as emitted.
+    fn through(self, air : Option<(Color, Color)>) -> Shading {
+        match air {
+            Some((transmittance, inscattered)) => Shading {
+                emitted : self.emitted * transmittance + inscattered,
+                reflected : self.reflected * transmittance,
+                direct : self.direct * transmittance,
+            },
+            None => self,
+        }
+    }
+}
+
+///Path tracer. `bsdf_pdf` is the pdf with which the previous bounce scattered this ray,
+/// 
+/// or None if it came from the camera or a specular surface (in which case lights cannot have been sampled directly).
+/// 
+/// If the ray carries a wavelength, every color along the path is replaced by its spectral value at that wavelength.
+fn trace(r : Ray, scene : &Scene, depth : i32, bsdf_pdf : Option<Float>, clamping : Clamping) -> Color {
+    if depth <= 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    shade(r, first_hit(r, scene), scene, depth, bsdf_pdf, clamping)
+}
+
+///Returns the closest object the ray hits, if any.
+pub fn first_hit(r : Ray, scene : &Scene) -> Option<HitRecord> {
+    let mut rec = HitRecord::new();
+    scene.hit(r, t_min(), Float::INFINITY, &mut rec).then_some(rec)
+}
+
+///The rest of trace(), once the ray's first hit is known (or None, if it escapes the scene).
+fn shade(r : Ray, hit : Option<HitRecord>, scene : &Scene, depth : i32, bsdf_pdf : Option<Float>, clamping : Clamping) -> Color {
+    let shading = shade_parts(r, hit, scene, depth, bsdf_pdf, clamping);
+    shading.emitted + shading.reflected
+}
+
+///Surface a path bounced off, kept until the light coming back along the path from further on is known.
+struct Bounce {
+    emitted : Color,
+    attenuation : Color,
+    clamping : Clamping,
+    //Air between the bounce and the one before it
+    air : Option<(Color, Color)>,
+    //Light sampled directly and where the bounce was recorded for the path guide, for surfaces that aren't specular
+    direct : Option<Color>,
+    guided : Option<(Point3, Vec3, Float)>,
+}
+
+thread_local! {
+    //Bounces of the path being traced, kept between paths so that tracing doesn't allocate
+    static BOUNCES : Cell<Vec<Bounce>> = const { Cell::new(Vec::new()) };
+}
+
+///Does the work of shade(), keeping the light the hit point emits apart from the light it reflects.
+///
+/// Follows the path one bounce at a time, out until it escapes, stops scattering or runs out of depth, and then works back along it,
+/// adding the light reflected at each bounce (clamped as it arrives there) to the light the bounce emits.
+fn shade_parts(r : Ray, hit : Option<HitRecord>, scene : &Scene, depth : i32, bsdf_pdf : Option<Float>, clamping : Clamping) -> Shading {
+    let black = Color::new(0.0, 0.0, 0.0);
+    if depth <= 0 {
+        return Shading {emitted : black, reflected : black, direct : black};
+    }
+    let mut bounces = BOUNCES.take();
+    let mut interior = Interior::new();
+    let (mut r, mut hit, mut depth, mut bsdf_pdf, mut clamping) = (r, hit, depth, bsdf_pdf, clamping);
+    let mut next = loop {
+        let air = scene.air(r, hit.map_or(Float::INFINITY, |rec| rec.t))
+            .map(|(transmittance, inscattered)| (spectral(transmittance, r.wavelength), spectral(inscattered, r.wavelength)));
+        let mut rec = match hit {
+            Some(rec) => rec,
+            None => {
+                let emitted = match &scene.environment {
+                    Some(env) => {
+                        let radiance = spectral(env.value(r.direction), r.wavelength);
+                        match bsdf_pdf {
+                            Some(pdf) => radiance * power_heuristic(pdf, scene.environment_pdf(r.origin_point, r.direction)),
+                            None => radiance,
+                        }
+                    },
+                    None => black,
+                };
+                break Shading {emitted, reflected : black, direct : black}.through(air);
+            },
+        };
+
+        let shading_start = start_shading();
+        scene.shade(r, &mut rec);
+        let mut scattered = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let mut emitted = spectral(rec.mat.emitted(rec.u, rec.v, rec.p, &scene.textures), r.wavelength);
+        if let Some(pdf) = bsdf_pdf {
+            emitted *= power_heuristic(pdf, scene.emitter_pdf(r.origin_point, r.direction));
+        }
+        if !rec.mat.scatter_nested(r, &rec, &mut interior, &mut attenuation, &mut scattered, &scene.textures) {
+            add_shading(&rec.mat, shading_start);
+            break Shading {emitted, reflected : black, direct : black}.through(air);
+        }
+        attenuation = spectral(attenuation, r.wavelength);
+        scattered.wavelength = r.wavelength;
+        if rec.mat.is_specular() {
+            bounces.push(Bounce {emitted, attenuation, clamping, air, direct : None, guided : None});
+            bsdf_pdf = None;
+        } else {
+            let direct = direct_light(scene, r, &rec, attenuation, true);
+            let pdf = match &scene.guide {
+                Some(guide) => guide.scatter(r, &rec, &mut attenuation, &mut scattered),
+                None => rec.mat.scattering_pdf(r, &rec, scattered.direction),
+            };
+            let guided = scene.guide.is_some().then_some((rec.p, scattered.direction, pdf));
+            bounces.push(Bounce {emitted, attenuation, clamping, air, direct : Some(direct), guided});
+            bsdf_pdf = Some(pdf);
+        }
+        add_shading(&rec.mat, shading_start);
+
+        scattered.origin_point = rec.spawn_origin(scattered.direction);
+        r = scattered;
+        depth -= 1;
+        clamping = clamping.deeper();
+        if depth <= 0 {
+            break Shading {emitted : black, reflected : black, direct : black};
+        }
+        hit = first_hit(r, scene);
+    };
+
+    count_path(bounces.len() as u64);
+    for Bounce {emitted, attenuation, clamping, air, direct, guided} in bounces.drain(..).rev() {
+        let incoming = next.emitted + next.reflected;
+        next = match direct {
+            None => {
+                let indirect = attenuation * incoming;
+                let (_, indirect_scale) = clamping.scales(black, indirect);
+                Shading {emitted, reflected : clamping.apply(black, indirect), direct : attenuation * next.emitted * indirect_scale}
+            },
+            Some(direct) => {
+                if let (Some(guide), Some((p, direction, pdf))) = (&scene.guide, guided) {
+                    guide.record(p, direction, luminance(incoming) / pdf);
+                }
+                //Emitters found by the bounce light this point directly too
+                let (direct_scale, indirect_scale) = clamping.scales(direct, attenuation * incoming);
+                Shading {
+                    emitted,
+                    reflected : clamping.apply(direct, attenuation * incoming),
+                    direct : direct * direct_scale + attenuation * next.emitted * indirect_scale,
+                }
+            },
+        }.through(air);
+    }
+    BOUNCES.set(bounces);
+    next
+}
+
+///Estimates how brightly the environment and analytic lights light a shadow catcher, with and without the shadows of other objects.
+/// 
+/// Returns the luminance of both estimates, which are made from the same light samples.
+fn catcher_lighting(scene : &Scene, r_in : Ray, rec : &HitRecord) -> (Float, Float) {
+    let mut lit = 0.0;
+    let mut unshadowed = 0.0;
+    let mut add = |direction : Vec3, distance : Float, value : Float| {
+        let cos = dot(rec.normal, direction.unit_vector());
+        if cos <= 0.0 || value <= 0.0 {
+            return;
+        }
+        unshadowed += value * cos;
+        let mut shadow_rec = HitRecord::new();
+        if !scene.hit(Ray::new(rec.spawn_origin(direction), direction).with_time(r_in.time), t_min(), distance, &mut shadow_rec) {
+            lit += value * cos;
+        }
+    };
+
+    if let (Some(env), Some((direction, pdf))) = (&scene.environment, scene.sample_environment(rec.p)) {
+        if pdf > 0.0 {
+            add(direction, Float::INFINITY, luminance(env.value(direction)) / pdf);
+        }
+    }
+    for light in &scene.lights {
+        let (direction, distance, incident) = light.sample(rec.p);
+        add(direction, distance - epsilon(), luminance(incident));
+    }
+    (lit, unshadowed)
+}
+
+///Estimates the light arriving directly from every light source in the scene at a non-specular hit.
+/// 
+/// If `mis` is true, the estimate is weighted on the assumption that the caller also follows a scattered ray that might reach the same lights.
+/// 
+/// If the incoming ray r_in carries a wavelength, the lights' colors are replaced by their spectral values at that wavelength.
+pub fn direct_light(scene : &Scene, r_in : Ray, rec : &HitRecord, attenuation : Color, mis : bool) -> Color {
+    sample_environment(scene, r_in, rec, attenuation, mis)
+        + sample_lights(scene, r_in, rec, attenuation)
+        + sample_emitters(scene, r_in, rec, attenuation, mis)
+}
+
+///Estimates the light arriving directly from the environment map at a non-specular hit, 
+/// 
+/// by casting a shadow ray towards an importance sampled direction.
+fn sample_environment(scene : &Scene, r_in : Ray, rec : &HitRecord, attenuation : Color, mis : bool) -> Color {
+    let env = match &scene.environment {
+        Some(env) => env,
+        None => return Color::new(0.0, 0.0, 0.0),
+    };
+
+    let (direction, light_pdf) = match scene.sample_environment(rec.p) {
+        Some(sample) => sample,
+        None => return Color::new(0.0, 0.0, 0.0),
+    };
+    let scatter_pdf = rec.mat.scattering_pdf(r_in, rec, direction);
+    if light_pdf <= 0.0 || scatter_pdf <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let mut shadow_rec = HitRecord::new();
+    if scene.hit(Ray::new(rec.spawn_origin(direction), direction).with_time(r_in.time), t_min(), Float::INFINITY, &mut shadow_rec) {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let weight = if mis {power_heuristic(light_pdf, bounce_pdf(scene, r_in, rec, direction))} else {1.0};
+    attenuation * spectral(env.value(direction), r_in.wavelength) * (scatter_pdf / light_pdf * weight)
+}
+
+///Estimates the light arriving directly from one of the scene's emissive objects at a non-specular hit, 
+/// 
+/// by casting a shadow ray towards a point sampled on it.
+fn sample_emitters(scene : &Scene, r_in : Ray, rec : &HitRecord, attenuation : Color, mis : bool) -> Color {
+    let point = match scene.random_emitter_point(rec.p) {
+        Some(point) => point,
+        None => return Color::new(0.0, 0.0, 0.0),
+    };
+
+    let direction = point - rec.p;
+    let light_pdf = scene.emitter_pdf(rec.p, direction);
+    let scatter_pdf = rec.mat.scattering_pdf(r_in, rec, direction);
+    if light_pdf <= 0.0 || scatter_pdf <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    //Whatever the shadow ray reaches first is what actually lights this point
+    let mut light_rec = HitRecord::new();
+    if !scene.hit(Ray::new(rec.spawn_origin(direction), direction).with_time(r_in.time), t_min(), Float::INFINITY, &mut light_rec) {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let emitted = spectral(light_rec.mat.emitted(light_rec.u, light_rec.v, light_rec.p, &scene.textures), r_in.wavelength);
+
+    let weight = if mis {power_heuristic(light_pdf, bounce_pdf(scene, r_in, rec, direction))} else {1.0};
+    attenuation * emitted * (scatter_pdf / light_pdf * weight)
+}
+
+///Estimates the light arriving directly from the scene's analytic lights at a non-specular hit, 
+/// 
+/// by casting a shadow ray towards each of them. The shadow rays all leave the same point, so they are traced as packets of up to
+/// tree::MAX_PACKET (see Scene::occluded_packet()).
+fn sample_lights(scene : &Scene, r_in : Ray, rec : &HitRecord, attenuation : Color) -> Color {
+    let mut total = Color::new(0.0, 0.0, 0.0);
+    let mut rays = [Ray::new(rec.p, rec.normal) ; MAX_PACKET];
+    let mut distances = [0.0 ; MAX_PACKET];
+    let mut lit = [Color::new(0.0, 0.0, 0.0) ; MAX_PACKET];
+    for lights in scene.lights.chunks(MAX_PACKET) {
+        let mut n = 0;
+        for light in lights {
+            let (direction, distance, incident) = light.sample(rec.p);
+            let scatter_pdf = rec.mat.scattering_pdf(r_in, rec, direction);
+            if scatter_pdf <= 0.0 {
+                continue;
+            }
+            rays[n] = Ray::new(rec.spawn_origin(direction), direction).with_time(r_in.time);
+            distances[n] = distance - epsilon();
+            lit[n] = attenuation * spectral(incident, r_in.wavelength) * scatter_pdf;
+            n += 1;
+        }
+
+        let blocked = scene.occluded_packet(&rays[..n], t_min(), &distances[..n]);
+        for (k, light) in lit[..n].iter().enumerate() {
+            if blocked & (1 << k) == 0 {
+                total += *light;
+            }
+        }
+    }
+    total
+}
+
+
+///Firefly suppression settings, which clamp the brightness of light reflected off surfaces
+/// 
+/// so that rare, very bright paths don't leave single white pixels. Variants include
+/// 
+/// None: no clamping (unbiased, but may converge slowly in scenes with small bright lights).
+/// 
+/// Indirect: clamps light arriving at the first surface hit after bouncing at least once more, leaving direct lighting untouched.
+/// 
+/// PerBounce: clamps the light reflected at every bounce, including direct lighting.
+#[derive(Debug, Clone, Copy)]
+pub enum Clamping {
+    None,
+    Indirect(Float),
+    PerBounce(Float),
+}
+
+impl Clamping {
+
+    ///Combines the direct and indirect light reflected at a surface, clamping them according to these settings.
+    pub fn apply(&self, direct : Color, indirect : Color) -> Color {
+        match self {
+            Clamping::None => direct + indirect,
+            Clamping::Indirect(max) => direct + clamp_radiance(indirect, *max),
+            Clamping::PerBounce(max) => clamp_radiance(direct + indirect, *max),
+        }
+    }
+
+    ///Returns how much apply() scales the direct and indirect light down by, so that light can be split between them after clamping.
+    pub fn scales(&self, direct : Color, indirect : Color) -> (Float, Float) {
+        match self {
+            Clamping::None => (1.0, 1.0),
+            Clamping::Indirect(max) => (1.0, clamp_scale(indirect, *max)),
+            Clamping::PerBounce(max) => {
+                let scale = clamp_scale(direct + indirect, *max);
+                (scale, scale)
+            },
+        }
+    }
+
+    ///Returns the settings to use for the next bounce along a path.
+    pub fn deeper(&self) -> Clamping {
+        match self {
+            Clamping::Indirect(_) => Clamping::None,
+            _ => *self,
+        }
+    }
+}
+
+///Scales a color down so that none of its components exceed max, preserving its hue.
+pub fn clamp_radiance(c : Color, max : Float) -> Color {
+    let largest = c.x.max(c.y).max(c.z);
+    if largest > max {
+        c * (max / largest)
+    } else {
+        c
+    }
+}
+
+///Returns the factor clamp_radiance() scales a color by.
+fn clamp_scale(c : Color, max : Float) -> Float {
+    let largest = c.x.max(c.y).max(c.z);
+    if largest > max {max / largest} else {1.0}
+}
+
+///Returns the pdf with which the path tracer would bounce towards the given direction, 
+/// 
+/// which is the material's own pdf unless bounces are path guided.
+fn bounce_pdf(scene : &Scene, r_in : Ray, rec : &HitRecord, direction : Vec3) -> Float {
+    let scatter_pdf = rec.mat.scattering_pdf(r_in, rec, direction);
+    match &scene.guide {
+        Some(guide) => guide.bsdf_fraction * scatter_pdf + (1.0 - guide.bsdf_fraction) * guide.pdf(rec.p, direction),
+        None => scatter_pdf,
+    }
+}
+
+///Returns the color unchanged when rendering in RGB, or its spectral value at the given wavelength in every channel otherwise.
+fn spectral(c : Color, wavelength : Option<Float>) -> Color {
+    match wavelength {
+        Some(lambda) => {
+            let value = rgb_to_spectral(c, lambda);
+            Color::new(value, value, value)
+        },
+        None => c,
+    }
+}
+
+///Multiple importance sampling weight for a sample drawn with pdf `f`, when it could also have been drawn with pdf `g`.
+pub fn power_heuristic(f : Float, g : Float) -> Float {
+    let f2 = f * f;
+    let g2 = g * g;
+    if f2 + g2 == 0.0 {
+        return 0.0;
+    }
+    f2 / (f2 + g2)
+}
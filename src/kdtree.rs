@@ -0,0 +1,246 @@
+/*
+Module to store the kd-tree, an acceleration structure that splits space with planes rather than splitting the list of objects.
+*/
+
+use crate::hitting::{Hittable, HitRecord};
+use crate::bvh::{AABB, surrounding_box};
+use crate::ray::Ray;
+use crate::textures::Textures;
+use std::io::{Error, ErrorKind, Result};
+use crate::vec3::Float;
+use crate::stats::count_traversal;
+
+///Estimated costs of stepping through an interior node and of testing an object, used to weigh up splitting planes.
+const TRAVERSAL_COST : Float = 1.0;
+const INTERSECTION_COST : Float = 80.0;
+
+///Discount on the cost of splits leaving one side empty, since rays through empty space are cheap.
+const EMPTY_BONUS : Float = 0.5;
+
+///Maximum depth of the tree, which bounds the stack hit() needs to walk it.
+const STACK_SIZE : usize = 64;
+
+///Number of tested objects hit() remembers before it needs to allocate.
+const MAILBOX_SIZE : usize = 32;
+
+///Node of a kd-tree. Variants include
+///
+/// Interior: the axis and position of the plane splitting the node, and the indices of the nodes below and above the plane.
+///
+/// Leaf: the indices of the objects overlapping the node.
+#[derive(Debug, Clone)]
+pub enum KdNode {
+    Interior(usize, Float, usize, usize),
+    Leaf(Vec<usize>),
+}
+
+///Represents a kd-tree of the objects in the scene. Each node is split in two by the plane the surface area heuristic
+///
+/// finds cheapest, and objects crossing it are stored on both sides. Since the two sides never overlap, rays stop
+/// as soon as they hit something, which can beat the Bounding Volume Hierarchy when many large objects overlap.
+#[derive(Debug, Clone)]
+pub struct KdTree {
+    pub objects : Vec<Hittable>,
+    pub nodes : Vec<KdNode>,
+    pub bounds : Option<AABB>,
+}
+
+impl KdTree {
+
+    ///Builds a kd-tree from a list of Hittable objects. An empty list gives an empty tree that nothing hits.
+    ///
+    /// Fails if any object's bounding box has NaN coordinates, since it could not be placed in the tree.
+    pub fn build(lst : &[Hittable]) -> Result<KdTree> {
+        if let Some(index) = lst.iter().position(|item| item.bounding_box().has_nan()) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Object {} has an invalid bounding box", index)));
+        }
+
+        let boxes : Vec<AABB> = lst.iter().map(|item| item.bounding_box().padded()).collect();
+        let mut tree = KdTree {objects : lst.to_vec(), nodes : vec![], bounds : boxes.iter().copied().reduce(surrounding_box)};
+        if let Some(bounds) = tree.bounds {
+            let max_depth = ((8.0 + 1.3 * (lst.len() as Float).log2()) as usize).min(STACK_SIZE);
+            tree.con(&boxes, (0..lst.len()).collect(), bounds, max_depth, 0);
+        }
+        Ok(tree)
+    }
+
+    ///Recursive helper function that builds the node covering bounds from the objects overlapping it, returning its index.
+    ///
+    /// Gives up on splitting after three splits in a row that cost more than they save.
+    fn con(&mut self, boxes : &[AABB], indices : Vec<usize>, bounds : AABB, depth : usize, bad_refines : u32) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(KdNode::Leaf(vec![]));
+        if indices.len() <= 1 || depth == 0 {
+            self.nodes[index] = KdNode::Leaf(indices);
+            return index;
+        }
+
+        let leaf_cost = INTERSECTION_COST * indices.len() as Float;
+        let (axis, split, cost) = match best_split(boxes, &indices, bounds) {
+            Some(split) => split,
+            None => {
+                self.nodes[index] = KdNode::Leaf(indices);
+                return index;
+            },
+        };
+        let bad_refines = if cost > leaf_cost {bad_refines + 1} else {bad_refines};
+        if (cost > 4.0 * leaf_cost && indices.len() < 16) || bad_refines == 3 {
+            self.nodes[index] = KdNode::Leaf(indices);
+            return index;
+        }
+
+        let below : Vec<usize> = indices.iter().copied().filter(|i| boxes[*i].minimum[axis] < split).collect();
+        let above : Vec<usize> = indices.iter().copied().filter(|i| boxes[*i].maximum[axis] > split).collect();
+        let mut below_bounds = bounds;
+        below_bounds.maximum[axis] = split;
+        let mut above_bounds = bounds;
+        above_bounds.minimum[axis] = split;
+
+        let below = self.con(boxes, below, below_bounds, depth - 1, bad_refines);
+        let above = self.con(boxes, above, above_bounds, depth - 1, bad_refines);
+        self.nodes[index] = KdNode::Interior(axis, split, below, above);
+        index
+    }
+
+    ///Returns the bounding box surrounding every object in the tree, or None if it is empty.
+    pub fn bounding_box(&self) -> Option<AABB> {
+        self.bounds
+    }
+
+    ///Determines if a ray hits any object in the kd-tree, filling in rec with the closest hit.
+    ///
+    /// Visits the leaves the ray passes through from nearest to farthest, stopping once the closest hit so far lies before the next one.
+    pub fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, textures : &Textures) -> bool {
+        let (mut t_near, mut t_far) = match self.bounds.and_then(|bounds| bounds.interval(r, t_min, t_max)) {
+            Some(interval) => interval,
+            None => return false,
+        };
+
+        let mut stack = [(0, 0.0, 0.0) ; STACK_SIZE];
+        let mut top = 0;
+        let mut node = 0;
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        let mut mailbox = Mailbox::new();
+        let (mut nodes, mut primitives) = (0, 0);
+
+        while t_near <= closest {
+            nodes += 1;
+            match &self.nodes[node] {
+                KdNode::Interior(axis, split, below, above) => {
+                    let origin = r.origin_point[*axis];
+                    let t_plane = (split - origin) / r.direction[*axis];
+                    let below_first = origin < *split || (origin == *split && r.direction[*axis] <= 0.0);
+                    let (first, second) = if below_first {(*below, *above)} else {(*above, *below)};
+
+                    if t_plane.is_nan() {
+                        //The ray runs along the plane, so objects on either side may touch it
+                        stack[top] = (second, t_near, t_far);
+                        top += 1;
+                        node = first;
+                    } else if t_plane > t_far || t_plane <= 0.0 {
+                        node = first;
+                    } else if t_plane < t_near {
+                        node = second;
+                    } else {
+                        stack[top] = (second, t_plane, t_far);
+                        top += 1;
+                        node = first;
+                        t_far = t_plane;
+                    }
+                    continue;
+                },
+                KdNode::Leaf(indices) => {
+                    for i in indices {
+                        if !mailbox.first_visit(*i) {
+                            continue;
+                        }
+                        primitives += 1;
+                        if self.objects[*i].hit(r, t_min, closest, rec, textures) {
+                            hit_anything = true;
+                            closest = rec.t;
+                            rec.object = *i;
+                        }
+                    }
+                },
+            }
+
+            if top == 0 {
+                break;
+            }
+            top -= 1;
+            (node, t_near, t_far) = stack[top];
+        }
+        count_traversal(nodes, primitives);
+        hit_anything
+    }
+}
+
+///Finds the cheapest plane to split the node covering bounds by the surface area heuristic, returning its axis, position and cost.
+///
+/// Candidate planes lie along the sides of the objects' bounding boxes. Returns None if the node has no area to split or no candidates.
+fn best_split(boxes : &[AABB], indices : &[usize], bounds : AABB) -> Option<(usize, Float, Float)> {
+    let total_area = bounds.surface_area();
+    if !(total_area > 0.0 && total_area.is_finite()) {
+        return None;
+    }
+    let d = bounds.maximum - bounds.minimum;
+
+    let mut best : Option<(usize, Float, Float)> = None;
+    for axis in 0..3 {
+        //Boxes starting at a plane come before boxes ending there
+        let mut edges : Vec<(Float, bool)> = indices.iter().flat_map(|i| [(boxes[*i].minimum[axis], true), (boxes[*i].maximum[axis], false)]).collect();
+        edges.sort_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)));
+
+        let (o1, o2) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut below = 0;
+        let mut above = indices.len();
+        for (t, start) in edges {
+            if !start {
+                above -= 1;
+            }
+            if t > bounds.minimum[axis] && t < bounds.maximum[axis] {
+                let below_area = 2.0 * (d[o1] * d[o2] + (t - bounds.minimum[axis]) * (d[o1] + d[o2]));
+                let above_area = 2.0 * (d[o1] * d[o2] + (bounds.maximum[axis] - t) * (d[o1] + d[o2]));
+                let bonus = if below == 0 || above == 0 {EMPTY_BONUS} else {0.0};
+                let cost = TRAVERSAL_COST + INTERSECTION_COST * (1.0 - bonus) * (below_area * below as Float + above_area * above as Float) / total_area;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, t, cost));
+                }
+            }
+            if start {
+                below += 1;
+            }
+        }
+    }
+    best
+}
+
+///Objects already tested against the current ray. Objects crossing several leaves are only tested once,
+///
+/// which saves time and keeps media, whose hits are random, from getting more than one chance to scatter the ray.
+struct Mailbox {
+    recent : [usize ; MAILBOX_SIZE],
+    count : usize,
+    overflow : Vec<usize>,
+}
+
+impl Mailbox {
+    fn new() -> Mailbox {
+        Mailbox {recent : [0 ; MAILBOX_SIZE], count : 0, overflow : vec![]}
+    }
+
+    ///Records the object at index as tested, returning whether this is the first time.
+    fn first_visit(&mut self, index : usize) -> bool {
+        if self.recent[..self.count].contains(&index) || self.overflow.contains(&index) {
+            return false;
+        }
+        if self.count < MAILBOX_SIZE {
+            self.recent[self.count] = index;
+            self.count += 1;
+        } else {
+            self.overflow.push(index);
+        }
+        true
+    }
+}
@@ -0,0 +1,133 @@
+/*
+Library of the path tracer, so that other programs can build scenes and render them with render::Renderer (prelude gathers what that takes).
+The RustTracer binary renders the scene in main.rs, or a scene file, with it.
+
+Without the std feature, only the core math and intersection code is built (vec3, ray, bvh, hitting, and the materials and textures
+objects carry), under no_std with alloc, so that it can be reused where the rest of the tracer can't run.
+*/
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod error;
+pub mod vec3;
+#[cfg(not(feature = "std"))]
+mod math;
+pub mod ray;
+pub mod hitting;
+#[cfg(feature = "std")]
+pub mod camera;
+pub mod materials;
+pub mod bvh;
+pub mod textures;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod tree;
+#[cfg(feature = "std")]
+pub mod kdtree;
+#[cfg(feature = "std")]
+pub mod bvh4;
+#[cfg(feature = "std")]
+pub mod sbvh;
+#[cfg(feature = "std")]
+pub mod flat;
+#[cfg(feature = "std")]
+pub mod accelerator;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "std")]
+pub mod environment;
+#[cfg(feature = "std")]
+pub mod scene;
+#[cfg(feature = "std")]
+pub mod lights;
+#[cfg(feature = "std")]
+pub mod ies;
+#[cfg(feature = "std")]
+pub mod sppm;
+#[cfg(feature = "std")]
+pub mod mlt;
+#[cfg(feature = "std")]
+pub mod integrator;
+pub mod spectrum;
+#[cfg(feature = "std")]
+pub mod sky;
+#[cfg(feature = "std")]
+pub mod atmosphere;
+#[cfg(feature = "std")]
+pub mod fog;
+#[cfg(feature = "std")]
+pub mod guiding;
+#[cfg(feature = "std")]
+pub mod sun;
+#[cfg(feature = "std")]
+pub mod sampler;
+#[cfg(feature = "std")]
+pub mod sobol;
+#[cfg(feature = "std")]
+pub mod halton;
+#[cfg(feature = "std")]
+pub mod cmj;
+#[cfg(feature = "std")]
+pub mod budget;
+#[cfg(feature = "std")]
+pub mod animation;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
+pub mod tonemap;
+#[cfg(feature = "std")]
+pub mod aov;
+#[cfg(feature = "std")]
+pub mod preview;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod denoise;
+pub mod color;
+#[cfg(feature = "std")]
+pub mod crop;
+#[cfg(feature = "std")]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod presets;
+#[cfg(feature = "std")]
+pub mod scene_file;
+#[cfg(feature = "std")]
+pub mod xml;
+#[cfg(feature = "std")]
+pub mod mesh;
+#[cfg(feature = "std")]
+pub mod mitsuba;
+#[cfg(feature = "std")]
+pub mod usda;
+#[cfg(feature = "std")]
+pub mod usd;
+#[cfg(feature = "std")]
+pub mod gltf;
+#[cfg(feature = "std")]
+pub mod examples;
+#[cfg(feature = "std")]
+pub mod generator;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod cli;
+#[cfg(feature = "std")]
+pub mod logging;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod compare;
+
+//Old names of the vec3 and ray modules, so that programs written against them still build
+#[doc(hidden)]
+pub use crate::vec3 as vec_class;
+#[doc(hidden)]
+pub use crate::ray as ray_class;
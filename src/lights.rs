@@ -0,0 +1,104 @@
+use crate::vec3::{Vec3, Color, Point3, dot, random_in_cone, random_in_unit_sphere, random_in_unit_disk, orthonormal_basis, Float};
+use crate::ray::Ray;
+use crate::ies::IesProfile;
+use crate::vec3::consts::PI;
+use std::sync::Arc;
+
+///Analytic lights, which are sampled directly with shadow rays rather than found by chance. Variants include
+///
+/// Point: light radiating equally in every direction from a single position, given its intensity and falloff exponent
+/// (2.0 is physically based inverse-square falloff, 0.0 is no falloff at all), and an optional IES profile pointing downwards.
+///
+/// Directional: infinitely distant light (such as the sun) shining along a direction, given its irradiance and
+/// angular radius in degrees (0.0 gives hard shadows, larger values give softer shadows).
+///
+/// Spot: light shining from a position along a direction, given its inner and outer cone angles in degrees and its intensity.
+/// Full intensity is emitted inside the inner cone, fading smoothly to nothing at the outer cone.
+/// An optional IES profile is aligned with the spot direction.
+#[derive(Debug, Clone)]
+pub enum Light {
+    Point(Point3, Color, Float, Option<Arc<IesProfile>>),
+    Directional(Vec3, Color, Float),
+    Spot(Point3, Vec3, Float, Float, Color, Option<Arc<IesProfile>>),
+}
+
+impl Light {
+
+    ///Samples the light arriving at point p from this light.
+    ///
+    /// Returns the unit direction towards the light, the distance a shadow ray must travel to reach it, and the incident light.
+    pub fn sample(&self, p : Point3) -> (Vec3, Float, Color) {
+        match self {
+            Light::Point(position, intensity, falloff, profile) => {
+                let to_light = *position - p;
+                let distance = to_light.length();
+                let to_light = to_light / distance;
+                let scale = profile_scale(profile, -to_light, Vec3::new(0.0, -1.0, 0.0));
+                (to_light, distance, *intensity * scale / distance.powf(*falloff))
+            },
+            Light::Directional(direction, irradiance, angular_radius) => {
+                let to_light = -direction.unit_vector();
+                let cos_max = (angular_radius * PI / 180.0).cos();
+                (random_in_cone(to_light, cos_max), Float::INFINITY, *irradiance)
+            },
+            Light::Spot(position, direction, inner, outer, intensity, profile) => {
+                let to_light = *position - p;
+                let distance = to_light.length();
+                let to_light = to_light / distance;
+
+                let cos_angle = dot(-to_light, direction.unit_vector());
+                let cos_inner = (inner * PI / 180.0).cos();
+                let cos_outer = (outer * PI / 180.0).cos();
+                let scale = smoothstep(cos_outer, cos_inner, cos_angle) * profile_scale(profile, -to_light, direction.unit_vector());
+                (to_light, distance, *intensity * scale / (distance * distance))
+            },
+        }
+    }
+
+    ///Emits a photon from this light, for light tracing integrators. Returns the photon's ray and the power it carries.
+    ///
+    /// Directional light is emitted across a disk covering the scene's bounding sphere. Falloff exponents are ignored,
+    /// since photons spreading out from a point naturally follow inverse-square falloff.
+    pub fn sample_emission(&self, scene_center : Point3, scene_radius : Float) -> (Ray, Color) {
+        match self {
+            Light::Point(position, intensity, _falloff, profile) => {
+                let direction = random_in_unit_sphere();
+                let scale = profile_scale(profile, direction, Vec3::new(0.0, -1.0, 0.0));
+                (Ray::new(*position, direction), *intensity * scale * 4.0 * PI)
+            },
+            Light::Directional(direction, irradiance, angular_radius) => {
+                let cos_max = (angular_radius * PI / 180.0).cos();
+                let d = -random_in_cone(-direction.unit_vector(), cos_max);
+                let (u, v) = orthonormal_basis(d);
+                let disk = random_in_unit_disk();
+                let origin = scene_center - d * scene_radius + (u * disk.x + v * disk.y) * scene_radius;
+                (Ray::new(origin, d), *irradiance * PI * scene_radius * scene_radius)
+            },
+            Light::Spot(position, direction, inner, outer, intensity, profile) => {
+                let axis = direction.unit_vector();
+                let cos_inner = (inner * PI / 180.0).cos();
+                let cos_outer = (outer * PI / 180.0).cos();
+                let d = random_in_cone(axis, cos_outer);
+                let scale = smoothstep(cos_outer, cos_inner, dot(d, axis)) * profile_scale(profile, d, axis);
+                (Ray::new(*position, d), *intensity * scale * 2.0 * PI * (1.0 - cos_outer))
+            },
+        }
+    }
+}
+
+///Relative intensity an optional IES profile emits along a direction, given the fixture's downward axis.
+fn profile_scale(profile : &Option<Arc<IesProfile>>, direction : Vec3, axis : Vec3) -> Float {
+    match profile {
+        Some(ies) => ies.value(direction, axis),
+        None => 1.0,
+    }
+}
+
+///Smoothly interpolates from 0 to 1 as x goes from edge0 to edge1.
+fn smoothstep(edge0 : Float, edge1 : Float, x : Float) -> Float {
+    if edge0 >= edge1 {
+        return if x >= edge1 {1.0} else {0.0};
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
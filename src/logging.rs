@@ -0,0 +1,34 @@
+/*
+Module to store the logging of the command line. The library reports what it is doing through the tracing crate: spans around loading scene files,
+building acceleration structures, training the path guide and rendering every pass (or band of rows, when streaming), events with how long each took,
+and debug events with the samples, rays and time of every tile, so that programs embedding it can collect them with a subscriber of their own.
+init() installs one writing them to standard error, where spans are reported with how long they were busy as they close.
+*/
+
+use std::io::{stderr, IsTerminal};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing::level_filters::LevelFilter;
+
+///Returns the level of events logged at a verbosity: warnings only at 0, scene loading, builds and passes at 1, every tile at 2
+///
+/// and everything at 3 or more. Quiet logs nothing at all.
+pub fn level(verbosity : u8, quiet : bool) -> LevelFilter {
+    match (quiet, verbosity) {
+        (true, _) => LevelFilter::OFF,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    }
+}
+
+///Logs events up to the given level to standard error (colored if it is a terminal), with their time and the spans they happened in. Does nothing if a subscriber is already installed.
+pub fn init(level : LevelFilter) {
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(stderr)
+        .with_ansi(stderr().is_terminal())
+        .with_target(false)
+        .with_span_events(FmtSpan::CLOSE)
+        .try_init();
+}
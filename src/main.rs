@@ -1,143 +1,612 @@
-use image::{Rgb, RgbImage, open, DynamicImage};
-
-static mut TEXTURE_LIST : Vec<Texture> = vec![];
-
-pub mod vec_class;
-pub mod ray_class;
-pub mod hitting;
-pub mod camera;
-pub mod materials;
-pub mod bvh;
-pub mod textures;
-pub mod tree;
-
-//Custom modules
-use crate::vec_class::{Vec3, Color, Point3};
-use crate::hitting::Hittable;
-use crate::camera::Camera;
-use crate::materials::{Material};
-use crate::tree::Tree;
-use crate::textures::{Texture, Perlin};
-
-//Utilities
-use rand::Rng;
-use rayon::prelude::*;
-
-struct Pixel {
-    x : u32,
-    y : u32,
-    data : [u8 ; 3],
-}
+use std::fs::read;
+use std::path::{Path, PathBuf};
+use std::fmt::Display;
+use std::process::{Command, exit};
+use rust_tracer::vec3::consts::PI;
+use std::time::{Duration, Instant};
 
-fn add_texture(t : Texture) -> usize {
-    unsafe {
-        TEXTURE_LIST.push(t);
-        TEXTURE_LIST.len()-1
-    }
-}
+//Library modules
+use rust_tracer::*;
+use rust_tracer::vec3::{Vec3, Point3, set_seed, Float};
+use rust_tracer::ray::{SpawnOffset, DEFAULT_EPSILON, set_self_intersection};
+use rust_tracer::hitting::Hittable;
+use rust_tracer::camera::{Camera, StandardCamera, Stereo, Exposure};
+use rust_tracer::materials::{Material};
+use rust_tracer::accelerator::AcceleratorKind;
+use rust_tracer::progress::Progress;
+use rust_tracer::stats::RenderStats;
+use rust_tracer::denoise::DenoiseSettings;
+use rust_tracer::scene::Scene;
+use rust_tracer::integrator::{Integrator, Clamping};
+use rust_tracer::sppm::{SppmSettings, render_sppm};
+use rust_tracer::mlt::{MltSettings, render_mlt};
+use rust_tracer::environment::Environment;
+use rust_tracer::sky::Sky;
+use rust_tracer::atmosphere::Atmosphere;
+use rust_tracer::fog::Fog;
+use rust_tracer::sun::{sun_direction, sun_light};
+use rust_tracer::guiding::GuidingSettings;
+use rust_tracer::sampler::SamplerKind;
+use rust_tracer::budget::SampleBudget;
+use rust_tracer::animation::CameraPath;
+use rust_tracer::textures::{Texture, Textures};
+use rust_tracer::cache::{self, load_image};
+use rust_tracer::tonemap::ToneMap;
+use rust_tracer::color::OutputTransform;
+use rust_tracer::crop::CropWindow;
+use rust_tracer::scene_file::{SceneFile, FileSettings};
+use rust_tracer::cli::Arguments;
+use clap::{Parser, CommandFactory};
+use clap::error::ErrorKind;
+use rust_tracer::config::{Config, DEFAULT_PATH};
+use rust_tracer::output::{ExrSettings, ExrPrecision, ExrCompression, PpmWriter};
+use rust_tracer::aov::{AovLayout, AovSettings, stable_id};
+use rust_tracer::render::{RenderSettings, Renderer, SaveSettings, Image, get_color};
+///Loads the images of the sun and planets and creates their materials, once, so that every frame of an animation shares the same textures.
+fn materials() -> ([Material ; 5], Textures) {
+    let mut textures = Textures::new();
 
-fn clamp(x : f32, minimum : f32, maximum : f32) -> f32 {
-    if x < minimum {
-        return minimum;
-    }
-    if x > maximum {
-        return maximum;
-    }
-    x
-}
+    //Images
+    let sun_img = load_image("images/sunmap.jpeg").or_exit("load texture");
+    let mercury_img = load_image("images/mercurymap.jpeg").or_exit("load texture");
+    let venus_img = load_image("images/venusmap.jpeg").or_exit("load texture");
+    let earth_img = load_image("images/earthmap.jpeg").or_exit("load texture");
+    let mars_img = load_image("images/marsmap.jpeg").or_exit("load texture");
 
-fn get_color(pixel_color : Color, samples : i32) -> (u8, u8, u8) {
-    let r = (pixel_color.x / samples as f32).sqrt();
-    let g = (pixel_color.y / samples as f32).sqrt();
-    let b = (pixel_color.z / samples as f32).sqrt();
-    (
-     (255.0 * clamp(r, 0.0, 0.999)) as u8, 
-     (255.0 * clamp(g, 0.0, 0.999)) as u8, 
-     (255.0 * clamp(b, 0.0, 0.999)) as u8,
-    )
+    //Materials
+    let sun_mat = Material::Light(textures.add(Texture::Image(sun_img)));
+    let mercury_mat = Material::Lambertian(textures.add(Texture::Image(mercury_img)));
+    let venus_mat = Material::Lambertian(textures.add(Texture::Image(venus_img)));
+    let earth_mat = Material::Lambertian(textures.add(Texture::Image(earth_img)));
+    let mars_mat = Material::Lambertian(textures.add(Texture::Image(mars_img)));
+    ([sun_mat, mercury_mat, venus_mat, earth_mat, mars_mat], textures)
 }
 
-fn scene() -> Tree {
+///Builds the scene as it is at the given frame (0 for a still image), with the planets orbiting the sun, one frame to a day.
+fn scene(environment : Option<Environment>, accelerator : AcceleratorKind, (materials, textures) : &([Material ; 5], Textures), frame : Float) -> Scene {
     let mut objs : Vec<Hittable> = vec![];
+    let [sun_mat, mercury_mat, venus_mat, earth_mat, mars_mat] = *materials;
 
-    //Images
-    let sun_img = open("images/sunmap.jpeg").unwrap();
-    let mercury_img = open("images/mercurymap.jpeg").unwrap();
-    let venus_img = open("images/venusmap.jpeg").unwrap();
-    let earth_img = open("images/earthmap.jpeg").unwrap();
-    let mars_img = open("images/marsmap.jpeg").unwrap();
-
-    //Materials
-    let sun_mat = Material::Light(add_texture(Texture::Image(sun_img.clone().into_bytes(), sun_img.clone().width(), sun_img.height())));
-    let mercury_mat = Material::Lambertian(add_texture(Texture::Image(mercury_img.clone().into_bytes(), mercury_img.clone().width(), mercury_img.height())));
-    let venus_mat = Material::Lambertian(add_texture(Texture::Image(venus_img.clone().into_bytes(), venus_img.clone().width(), venus_img.height())));
-    let earth_mat = Material::Lambertian(add_texture(Texture::Image(earth_img.clone().into_bytes(), earth_img.clone().width(), earth_img.height())));
-    let mars_mat = Material::Lambertian(add_texture(Texture::Image(mars_img.clone().into_bytes(), mars_img.clone().width(), mars_img.height())));
+    //Orbits about the axis through the sun towards the camera, with each planet's period in days
+    let center = Point3::new(278.0, 278.0, 0.0);
+    let orbit = |point : Point3, period : Float| animation::orbit(point, center, Vec3::new(0.0, 0.0, -1.0), 2.0 * PI * frame / period);
 
     //Generate objects
-    let sun = Hittable::Sphere(sun_mat, Point3::new(278.0, 278.0, 0.0), 100.0);
-    let mercury = Hittable::Sphere(mercury_mat, Point3::new(180.0, 180.0, -50.0), 10.0);
-    let venus = Hittable::Sphere(venus_mat, Point3::new(260.0, 450.0, 20.0), 25.0);
-    let earth = Hittable::Sphere(earth_mat, Point3::new(450.0, 200.0, 10.0), 30.0);
-    let mars = Hittable::Sphere(mars_mat, Point3::new(100.0, 300.0, -25.0), 15.0);
+    let sun = Hittable::Sphere(sun_mat, center, 100.0);
+    let mercury = Hittable::Sphere(mercury_mat, orbit(Point3::new(180.0, 180.0, -50.0), 88.0), 10.0);
+    let venus = Hittable::Sphere(venus_mat, orbit(Point3::new(260.0, 450.0, 20.0), 225.0), 25.0);
+    let earth = Hittable::Sphere(earth_mat, orbit(Point3::new(450.0, 200.0, 10.0), 365.0), 30.0);
+    let mars = Hittable::Sphere(mars_mat, orbit(Point3::new(100.0, 300.0, -25.0), 687.0), 15.0);
 
-    objs.push(sun);
+    objs.push(sun.clone());
     objs.push(mercury);
     objs.push(venus);
     objs.push(earth);
     objs.push(mars);
     
-    Tree::build(&mut objs)
+    let mut world = Scene::new(accelerator.build(&mut objs, textures).or_exit("build scene"), environment, textures.clone());
+    world.add_emitter(sun);
+    world
 }
 
 fn main() {
 
+    //Command line arguments (see cli.rs, or run with --help), which override the scene file and the settings below
+    let arguments = Arguments::parse();
+    logging::init(logging::level(arguments.verbosity, arguments.quiet));
+
+    //Image comparison (compare on the command line), checking a render against a reference render, as after changing the acceleration structures
+    if let Some(settings) = &arguments.compare() {
+        let reference = compare::load(&settings.images[0]).or_exit("load reference image");
+        let image = compare::load(&settings.images[1]).or_exit("load image");
+        let comparison = compare::compare(&reference, &image).or_exit("compare images");
+        let ((x, y), largest) = comparison.largest();
+        println!("RMSE:               {:.6} (threshold {})", comparison.rmse, settings.threshold);
+        println!("SSIM:               {:.6}{}", comparison.ssim, settings.min_ssim.map_or(String::new(), |min_ssim| format!(" (minimum {})", min_ssim)));
+        println!("Largest difference: {:.6} at ({}, {})", largest, x, y);
+        if let Some(path) = &arguments.output {
+            comparison.heat_map().save(path).or_exit("save heat map");
+        }
+        if !comparison.passes(settings.threshold, settings.min_ssim) {
+            eprintln!("The images differ by more than allowed");
+            exit(1);
+        }
+        return;
+    }
+
+    //Render settings file (see config.rs; render.toml in the working directory, or the file given with --config, overrides the settings below,
+    //and is overridden in turn by the scene file and the command line)
+    let config = match &arguments.config {
+        Some(path) => Config::load(path).or_exit("load render settings file"),
+        None if Path::new(DEFAULT_PATH).exists() => Config::load(DEFAULT_PATH).or_exit("load render settings file"),
+        None => Config::default(),
+    };
+
+    //Thread settings (Some(count) to render with that many threads rather than one for each core, as on shared CI runners,
+    //and true to render at the lowest priority, so that a long render doesn't lock up the machine)
+    let threads : Option<usize> = None;
+    let background = false;
+    let threads = arguments.threads.or(config.threads).or(threads);
+    let background = arguments.background || config.background.unwrap_or(background);
+
+    //Asset cache (Some(directory) to keep decoded images and triangulated glTF meshes there, named by a hash of their files,
+    //so that rendering the same scene again skips decoding them; see cache.rs)
+    let asset_cache : Option<&str> = None;
+    let asset_cache = arguments.cache.as_deref().or(config.cache.as_deref()).or(asset_cache);
+    cache::set_directory(asset_cache.map(PathBuf::from));
+
+    //Scene settings (Some(path) to load the camera, render settings, environment, textures, materials, objects and lights from a JSON scene file,
+    //such as scenes/solar_system.json, or a Mitsuba .xml, USD .usda or glTF .gltf or .glb scene, instead of building them in scene(); any setting the file leaves out keeps its value below.
+    //--scene on the command line builds one of the examples in examples.rs instead, and generate builds a random scene with generator.rs)
+    let scene_file : Option<&str> = None;
+    let scene_file = arguments.scene().or(scene_file);
+    let description = match arguments.example() {
+        Some(name) => Some(examples::example(name).or_exit("build example scene")),
+        None if arguments.generator().is_some() => arguments.generator().as_ref().map(generator::generate),
+        None => scene_file.map(|path| SceneFile::load(path).or_exit("load scene file")),
+    };
+    let mut file = description.as_ref().map_or(FileSettings::default(), |description| description.settings.clone());
+    config.apply(&mut file);
+    arguments.apply(&mut file);
+
     //Image settings
-    let aspect_ratio : f32 = 1.0;
+    let aspect_ratio : Float = 1.0;
     let image_width : u32 = 800;
-    let image_height = ((image_width as f32) / aspect_ratio) as u32;
+    let image_height = ((image_width as Float) / aspect_ratio) as u32;
 
     //Camera settings
     let lookfrom = Point3::new(278.0, 278.0, -800.0);
     let lookat = Point3::new(278.0, 278.0, 0.0);
     let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist = 20.0;
+    let vfov = 40.0;
     let aperture = 0.0;
 
+    //Focus settings (the distance in focus, or with autofocus, whatever is at lookat; use .autofocus_at(&world, u, v) on the camera below to focus on a point of the image instead)
+    let dist = 20.0;
+    let autofocus = true;
+
+    //Environment settings (an equirectangular .hdr or .exr map, or None for a black background)
+    let environment_map : Option<&str> = None;
+    let environment_intensity = 1.0;
+
+    //Sun settings (latitude, longitude, UTC offset, year, month, day and local hour), placing the sun for the sky and sunlight below
+    let sun = sun_direction(40.7, -74.0, -4.0, 2024, 6, 21, 15.0);
+
+    //Sky settings (Some(Sky::new(turbidity, sun, ground albedo)) to light the scene with the Hosek-Wilkie daylight sky instead of an environment map,
+    //or --sun and --turbidity on the command line)
+    let sky : Option<Sky> = None;
+
+    //Atmosphere settings (Some(Atmosphere::earth(sun)), with its fields changed for other planets or hazier air, to light the scene with a sky worked out
+    //from how sunlight scatters in the air instead, which also hazes distant objects; used before the sky and environment map)
+    let atmosphere : Option<Atmosphere> = None;
+
+    //Fog settings (Some(Fog::new(color, density, height falloff, base height)) to fade distant objects into the fog color, thinning out above the base height)
+    let fog : Option<Fog> = None;
+
+    //Sunlight settings (Some illuminance to also light the scene with a directional light from the sun)
+    let sunlight : Option<Float> = None;
+
+    //Acceleration structure (Bvh4 for a 4-wide Bounding Volume Hierarchy tested with SIMD instructions, usually the fastest, SpatialBvh for scenes with long, thin or large objects,
+    //KdTree, which can win when many large objects overlap, or Flat, the Bvh laid out as arrays, for scenes of tens of thousands of spheres or triangles;
+    //with the gpu feature, --gpu picks Gpu, the Bvh traced on the GPU as well)
+    let accelerator = AcceleratorKind::Bvh;
+    #[cfg(feature = "gpu")]
+    let accelerator = if arguments.gpu {AcceleratorKind::Gpu} else {accelerator};
+
+    //Self-intersection settings (how far, in scene units, rays leaving a surface ignore it for: larger for large scenes showing shadow acne, smaller for tiny scenes
+    //leaking light; and SpawnOffset::NormalOffset to start those rays that far off the surface along its normal instead)
+    let epsilon = DEFAULT_EPSILON;
+    let spawn_offset = SpawnOffset::Distance;
+
+    //Random seed (Some to make every run with the same seed produce an identical image, for regression testing and debugging noise)
+    let seed : Option<u64> = None;
+
+    //Settings from the scene file and command line
+    let aspect_ratio = file.aspect_ratio.unwrap_or(aspect_ratio);
+    let image_width = file.width.unwrap_or(image_width);
+    let image_height = if file.width.is_some() || file.aspect_ratio.is_some() {((image_width as Float) / aspect_ratio) as u32} else {image_height};
+    let lookfrom = file.lookfrom.unwrap_or(lookfrom);
+    let lookat = file.lookat.unwrap_or(lookat);
+    let vup = file.vup.unwrap_or(vup);
+    let vfov = file.vfov.unwrap_or(vfov);
+    let aperture = file.aperture.unwrap_or(aperture);
+    let dist = file.focus_distance.unwrap_or(dist);
+    let autofocus = file.autofocus.unwrap_or(autofocus);
+    let environment_map = file.environment_map.as_deref().or(environment_map);
+    let environment_intensity = file.environment_intensity.unwrap_or(environment_intensity);
+    let atmosphere = file.atmosphere.or(atmosphere);
+    let sky = file.sky.or(sky);
+    let fog = file.fog.or(fog);
+    let seed = file.seed.or(seed);
+    let epsilon = file.epsilon.unwrap_or(epsilon);
+    let spawn_offset = file.spawn_offset.unwrap_or(spawn_offset);
+
     //World setup
-    let world : Tree = scene();
+    if let Some(seed) = seed {
+        set_seed(seed);
+    }
+    set_self_intersection(epsilon, spawn_offset);
+    let environment = match (atmosphere, sky) {
+        (Some(atmosphere), _) => Some(atmosphere.bake(2048, 1024, environment_intensity)),
+        (None, Some(sky)) => Some(sky.bake(2048, 1024, environment_intensity)),
+        (None, None) => environment_map.map(|path| Environment::load(path, environment_intensity).or_exit("load environment map")),
+    };
+    //The haze of distant objects is as bright as the sky around them
+    let atmosphere = atmosphere.map(|atmosphere| Atmosphere {sun_intensity : atmosphere.sun_intensity * environment_intensity, ..atmosphere});
+    let materials = description.is_none().then(materials);
+    let build_world = |description : Option<&SceneFile>, frame : Float| {
+        let mut world = match description {
+            Some(description) => description.scene(environment.clone(), accelerator).or_exit("build scene"),
+            None => scene(environment.clone(), accelerator, materials.as_ref().expect("Materials are loaded without a scene file"), frame),
+        };
+        if let Some(light) = sunlight.and_then(|illuminance| sun_light(sun, illuminance)) {
+            world.add_light(light);
+        }
+        world.atmosphere = atmosphere;
+        world.fog = fog;
+        world
+    };
+    let world_at = |frame : Float| build_world(description.as_ref(), frame);
+    let mut world : Scene = world_at(0.0);
     let samples_per_pixel = 1000;
     let max_depth = 1000;
-    let cam = Camera::new(lookfrom.clone(), lookat.clone(), vup, 40.0, aspect_ratio, aperture, dist);
-    let mut img = RgbImage::new(image_width, image_height);
-    println!("P3\n{} {}\n255\n", image_width, image_height);
-
-    let mut xy : Vec<(u32, u32)> = vec![];
-    for x in 0..image_width {
-        for y in 0..image_height {
-            xy.push((x, y));
+    let samples_per_pixel = file.samples_per_pixel.unwrap_or(samples_per_pixel);
+    let max_depth = file.max_depth.unwrap_or(max_depth);
+
+    //Sample budget (SampleBudget::load(path, minimum, maximum) for a grayscale importance map, or SampleBudget::Regions(vec![budget::Region::new(x0, y0, x1, y1, samples)])
+    //to spend more samples per pixel on difficult parts of the image than on the rest)
+    let sample_budget = SampleBudget::Uniform;
+
+    //Sampling mode (Stratified, CorrelatedMultiJittered, Sobol or Halton(HaltonPermutation::Owen) to spread the samples evenly, converging faster than Random)
+    let sampler = SamplerKind::Random;
+    let sampler = config.sampler.unwrap_or(sampler);
+
+    //Shading mode (Spectral for dispersion through dielectrics, or Normals, Albedo or UV to debug geometry and textures)
+    let integrator = Integrator::PathTracer(max_depth, Clamping::None);
+
+    //Photon mapping settings (Some to render with stochastic progressive photon mapping instead of path tracing)
+    let sppm : Option<SppmSettings> = None;
+
+    //Metropolis settings (Some to render with Metropolis light transport instead of path tracing)
+    let mlt : Option<MltSettings> = None;
+
+    //Transparency (true to save the alpha channel, so that shadow catchers and empty background can be composited onto a photograph; with photon mapping
+    //and Metropolis light transport, only the background becomes transparent)
+    let transparent = false;
+
+    //Path guiding settings (Some to learn where light comes from before path tracing, and steer bounces towards it)
+    let guiding : Option<GuidingSettings> = None;
+
+    //Progressive settings (samples added to every pixel per pass, and how many passes between saving the image so far, so that long renders can be checked on or stopped early)
+    let samples_per_pass = 16;
+    let save_every = 4;
+
+    //Preview settings (Some(columns) to draw the image so far in the terminal after every pass, that many characters wide, in 24 bit color,
+    //so that a bad camera angle can be spotted and the render stopped within seconds)
+    let preview : Option<u32> = None;
+
+    //Crop settings (Some(CropWindow::Pixels(x0, y0, x1, y1)) with corners in pixels from the top left, or CropWindow::Normalized with corners
+    //as fractions of the image from 0 to 1, to render only that part of the image at full quality; the rest is left black, or transparent with transparency,
+    //so that the saved image still lines up with full renders; path tracing only)
+    let crop : Option<CropWindow> = None;
+
+    //Packet settings (how many samples through a pixel find their first hits together, with their rays walking the Bounding Volume Hierarchy
+    //as a group of up to 16, or 1 to trace every ray on its own; bounces are always traced one at a time, and the shadow rays towards
+    //the point, spot and directional lights are always traced together as packets, whatever this is set to)
+    let packet_size = 1;
+    //The GPU only traces packets, so camera rays go to it in the largest there are
+    #[cfg(feature = "gpu")]
+    let packet_size = if arguments.gpu {rust_tracer::tree::MAX_PACKET} else {packet_size};
+
+    //Camera projection (StandardCamera::orthographic(lookfrom, lookat, vup, ortho_width, aspect_ratio) for parallel rays, as in technical or isometric views,
+    //or StandardCamera::panorama(lookfrom, lookat, vup) with an aspect ratio of 2 for a 360 degree panorama; add .with_shutter(time0, time1) to motion blur moving objects,
+    //.with_shift(x, y) or .with_tilt(x, y) to shift or tilt the lens, as with a tilt-shift lens, and .with_distortion(k1, k2, p1, p2) to match a real lens's distortion.
+    //Any other type implementing the Camera trait can be used for a custom projection)
+    let camera = |world : &Scene, lookfrom : Point3, lookat : Point3, vfov : Float| -> Box<dyn Camera> {
+        let cam = StandardCamera::new(lookfrom, lookat, vup, vfov, aspect_ratio, aperture, dist);
+        Box::new(if autofocus {cam.autofocus(world, lookat)} else {cam})
+    };
+    let cam = camera(&world, lookfrom, lookat, vfov);
+
+    //Exposure settings (Some(Exposure::new(iso, shutter speed, f-number)) to expose the image like a physical camera, with radiance in candelas
+    //per square meter, or None for a radiance of 1 to appear white)
+    let exposure : Option<Exposure> = None;
+    let exposure = exposure.map_or(1.0, |exposure| exposure.scale());
+
+    //Tone mapping settings (ToneMap::Linear to clip anything brighter than white, or Reinhard, Aces or Filmic to roll bright emitters and highlights
+    //off smoothly; exposure compensation brightens (positive) or darkens (negative) the image by that many stops, and applies to every output format)
+    let tone_map = ToneMap::Linear;
+    let exposure_compensation : Float = 0.0;
+    let tone_map = config.tone_map.unwrap_or(tone_map);
+    let exposure_compensation = config.exposure_compensation.unwrap_or(exposure_compensation);
+    let exposure = exposure * exposure_compensation.exp2();
+
+    //Output transform (OutputTransform::Srgb for most displays, Rec709 for HD video, or DisplayP3 for wide gamut displays, encoding the tone mapped image
+    //for .png and other 8 or 16 bit formats; .exr, .hdr and .pfm images keep the linear working space, with the Rec.709 primaries)
+    let transform = OutputTransform::Srgb;
+    let transform = config.transform.unwrap_or(transform);
+
+    //Stereo settings (Some(Stereo {interocular, layout : camera::StereoLayout::SideBySide or TopBottom}) to render a view for each eye, for VR; path tracing only)
+    let stereo : Option<Stereo> = None;
+
+    //Animation settings (Some((CameraPath::new(vec![animation::Keyframe::new(frame, lookfrom, lookat, vfov), ...]), frames)) to render a fly-through,
+    //with the objects placed by scene() at every frame, saving every frame as frame_0001.png and so on, in the output's format; path tracing only;
+    //a single keyframe holds the camera still, as for a turntable)
+    let animation : Option<(CameraPath, u32)> = None;
+
+    //Video settings (Some((path, frames per second)) to assemble the frames of an animation into a video with ffmpeg, which must be installed)
+    let video : Option<(&str, u32)> = None;
+
+    //Output settings (the file to save, as a .png, or as a .exr, .hdr or .pfm to keep the linear radiance for grading and compositing,
+    //or as a .ppm, or "-" for a .ppm on standard output, to stream the image row by row as it is rendered (see below);
+    //.png files have 8 or 16 bits per channel, where 16 avoids banding in smooth gradients such as dark vignettes and defocus falloff;
+    //.exr files have channels of ExrPrecision::Half or Full and ExrCompression::Uncompressed, Rle, Zip or Piz)
+    let output = "imageTest.png";
+    let output = file.output.as_deref().unwrap_or(output);
+    let png_bits = 8;
+    let exr = ExrSettings::new(ExrPrecision::Half, ExrCompression::Zip);
+    let png_bits = config.png_bits.unwrap_or(png_bits);
+    let exr = ExrSettings::new(config.exr_precision.unwrap_or(exr.precision), config.exr_compression.unwrap_or(exr.compression));
+
+    //Pass settings (the auxiliary images to save alongside the render, for compositing or for denoisers: any of aov::Aov::Albedo, Normal, Depth,
+    //Direct, Indirect and Emission, and ObjectId and MaterialId to select objects and materials in post, as extra layers of a .exr output with AovLayout::Layers, or as separate .exr files with AovLayout::Files; path tracing only)
+    let aovs = AovSettings::new(vec![], AovLayout::Layers);
+
+    //Deep settings (true to also save a deep .exr next to the render, named after it (imageTest.deep.exr), with a sample for each surface seen through each pixel,
+    //its depths and its premultiplied color, for merging with volumetrics and other renders by depth in compositing; rays that escape add no samples; path tracing only)
+    let deep = false;
+
+    //Denoising settings (Some(DenoiseSettings::new(radius)) to smooth away the noise left in the saved image with a filter guided by the albedo,
+    //normal and depth of what each pixel sees, for when no external denoiser is at hand; passes are saved as rendered; path tracing only)
+    let denoise : Option<DenoiseSettings> = None;
+
+    //Variance map settings (Some(path) to also save a heat map of the noise left in each pixel, as the relative error of its mean luminance
+    //(see aov::Aov::Variance, which saves the values themselves), hottest at variance_scale (0.1 for 10%) and above, to see where more samples
+    //are needed; saved with the image, for the last frame of animations; path tracing only)
+    let variance_map : Option<&str> = None;
+    let variance_scale : Float = 0.1;
+    let variance_map = arguments.variance_map.as_deref().or(variance_map);
+    let collect_aovs = !aovs.passes.is_empty() || denoise.is_some() || deep;
+    let save_settings = SaveSettings {exposure, tone_map, transform, transparent, exr, png_bits, aovs, deep};
+
+    //Metadata saved into every image (as text chunks of .png files, attributes of .exr files and comments of .hdr files), recording how it was rendered;
+    //the scene hash identifies this file, which builds the scene and holds every setting, along with the scene file if one is loaded
+    let scene_text = scene_file.map_or(String::new(), |path| String::from_utf8_lossy(&read(path).or_exit("read scene file")).into_owned());
+    let scene_hash = stable_id(&(String::from(include_str!("main.rs")) + scene_text.as_str()));
+    let renderer = match (sppm, mlt) {
+        (Some(settings), _) => format!("{:?}", settings),
+        (None, Some(settings)) => format!("{:?}", settings),
+        (None, None) => format!("{:?}", integrator),
+    };
+    let metadata = |(lookfrom, lookat, vfov) : (Point3, Point3, Float), time : Duration| {
+        let point = |p : Point3| format!("{} {} {}", p.x, p.y, p.z);
+        let focus = if autofocus {String::from("autofocus")} else {format!("focus distance {}", dist)};
+        vec![
+            ("resolution", format!("{}x{}", image_width, image_height)),
+            ("samplesPerPixel", samples_per_pixel.to_string()),
+            ("maxDepth", max_depth.to_string()),
+            ("seed", seed.map_or(String::from("none"), |seed| seed.to_string())),
+            ("renderer", renderer.clone()),
+            ("sampler", format!("{:?}", sampler)),
+            ("camera", format!("lookfrom {}, lookat {}, vfov {}, aperture {}, {}", point(lookfrom), point(lookat), vfov, aperture, focus)),
+            ("sceneHash", format!("{:08x}", scene_hash)),
+            ("renderTime", format!("{:.1}s", time.as_secs_f64())),
+        ]
+    };
+
+    //Render image with photon mapping or Metropolis light transport
+    let start = Instant::now();
+    let pool = || render::thread_pool(threads, background);
+    let radiance = match (sppm, mlt) {
+        (Some(settings), _) => Some(render::install(&pool(), || render_sppm(&world, cam.as_ref(), image_width, image_height, settings, transparent))),
+        (None, Some(settings)) => Some(render::install(&pool(), || render_mlt(&world, cam.as_ref(), image_width, image_height, settings, transparent))),
+        (None, None) => None,
+    };
+    if let Some(radiance) = radiance {
+        let save_settings = SaveSettings {aovs : AovSettings::new(vec![], save_settings.aovs.layout), deep : false, ..save_settings.clone()};
+        Image::from_radiance(image_width, image_height, radiance).save(output, &save_settings, &metadata((lookfrom, lookat, vfov), start.elapsed())).or_exit("save image");
+        return;
+    }
+
+    //Path tracer, rendering every frame with the settings above
+    let renderer = Renderer::new(RenderSettings {
+        width : image_width,
+        height : image_height,
+        samples_per_pixel,
+        samples_per_pass,
+        integrator,
+        sampler,
+        sample_budget,
+        transparent,
+        aovs : collect_aovs,
+        packet_size,
+        stereo,
+        crop,
+        guiding,
+        threads,
+        background,
+    });
+    let (output_width, output_height) = renderer.image_size();
+
+    //Statistics (--stats or --tile-stats on the command line): count the rays traced, the nodes and primitives they were tested against,
+    //the length of paths and the time spent shading each kind of material, printing them once the render is done; path tracing only
+    let stats = (arguments.stats || arguments.tile_stats).then(|| RenderStats::new(arguments.tile_stats));
+
+    //Every frame to render, with its cameras (one for each eye with stereo) and the file to save it to
+    let views = |cam : Box<dyn Camera>| match stereo.and_then(|stereo| stereo.eyes(cam.as_ref())) {
+        Some((left, right)) => vec![left, right],
+        None => vec![cam],
+    };
+    let extension = Path::new(output).extension().and_then(|extension| extension.to_str()).unwrap_or("png");
+    let frames = match &animation {
+        Some((_, count)) => (0..*count).map(|frame| (frame as Float, format!("frame_{:04}.{}", frame + 1, extension))).collect(),
+        None => vec![(0.0, String::from(output))],
+    };
+    let still = views(cam);
+
+    //Converts the image so far to 8 bit colors, row by row from the top left
+    let display = |image : &Image| {
+        let mut display = vec![(0, 0, 0) ; image.xy.len()];
+        for ((i, j), (pixel, alpha, _, _)) in image.xy.iter().zip(&image.accumulated) {
+            display[((output_height - j - 1) * output_width + i) as usize] = if *alpha > 0.0 {get_color(*pixel / *alpha, exposure, tone_map, transform)} else {(0, 0, 0)};
+        }
+        display
+    };
+
+    //Draws the image so far in the terminal, columns characters wide
+    let show = |image : &Image, columns : u32| preview::draw(&display(image), output_width, output_height, columns).or_exit("draw preview");
+
+    //Preview window (--window on the command line, with the preview feature): shows the image so far after every pass, at full size, until it is closed,
+    //which stops the render and saves what is done; falls back to drawing in the terminal when there is no display to open it on
+    #[cfg(feature = "preview")]
+    let mut window = arguments.window.then(|| preview::Window::new("RustTracer", output_width, output_height)).and_then(|window| {
+        window.inspect_err(|error| eprintln!("Failed to open the preview window, drawing in the terminal instead: {}", error)).ok()
+    });
+    #[cfg(feature = "preview")]
+    let preview = if arguments.window && window.is_none() {preview.or(Some(80))} else {preview};
+    //Returns false once the preview window has been closed
+    #[cfg(feature = "preview")]
+    let mut shown = |image : &Image| window.as_mut().is_none_or(|window| window.show(&display(image), output_width, output_height));
+    #[cfg(not(feature = "preview"))]
+    let shown = |_ : &Image| true;
+
+    //Watch mode (--watch on the command line): render the scene file progressively, drawing every pass in the terminal (preview's width, or 80 characters),
+    //and start over with the scene rebuilt whenever the file is saved, until stopped with Ctrl+C. Changes to lookfrom, lookat and vfov move the camera,
+    //while the image size and the other settings stay as they were when it started; path tracing only
+    if arguments.watch() {
+        let Some(path) = scene_file else {
+            Arguments::command().error(ErrorKind::MissingRequiredArgument, "--watch needs a scene file to watch").exit();
+        };
+        let preview = preview.or(Some(80));
+        let modified = || std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let mut version = modified();
+        let mut view = (lookfrom, lookat, vfov);
+        let mut cams = still;
+        loop {
+            let start = Instant::now();
+            let progress = Progress::new(renderer.samples());
+            renderer.render_with(&mut world, &cams, Some(&progress), None, ", watching for changes", |image, pass, passes| {
+                if let Some(columns) = preview {
+                    show(image, columns);
+                    progress.draw(&format!("pass {} of {}, watching for changes", pass + 1, passes));
+                }
+                let open = shown(image);
+                if pass + 1 == passes || !open {
+                    image.save(output, &save_settings, &metadata(view, start.elapsed())).or_exit("save image");
+                }
+                if !open {
+                    progress.finish("window closed");
+                    std::process::exit(0);
+                }
+                modified() == version
+            });
+            progress.finish(if modified() == version {"done, watching for changes"} else {"scene file changed, reloading"});
+
+            //Wait for the file to be saved again, skipping versions that fail to load (as when it is saved half written)
+            let description = loop {
+                while modified() == version {
+                    std::thread::sleep(Duration::from_millis(200));
+                }
+                version = modified();
+                match SceneFile::load(path) {
+                    Ok(description) => break description,
+                    Err(error) => eprintln!("Failed to reload scene file: {}", error),
+                }
+            };
+            //The reloaded file brings its own textures, so the old scene's are freed along with it and ids keep matching names
+            world = build_world(Some(&description), 0.0);
+            let settings = &description.settings;
+            view = (settings.lookfrom.unwrap_or(lookfrom), settings.lookat.unwrap_or(lookat), settings.vfov.unwrap_or(vfov));
+            cams = views(camera(&world, view.0, view.1, view.2));
         }
     }
 
-    //Render image
-    let img_pixels = xy.into_par_iter().map(|(i, j)| {
-        let mut pixel : Color = Color{x : 0.0, y : 0.0, z : 0.0};
-        let mut rng = rand::thread_rng();
+    //Streaming (a .ppm output, or "-"): render the image a band of rows at a time from the top, writing each band as soon as it is done,
+    //so that images too large to hold in memory can be rendered; there is no preview or saving along the way, and passes, deep images
+    //and denoising are skipped; path tracing only, and not for animations
+    if animation.is_none() && (extension == "ppm" || output == "-") {
+        let progress = Progress::new(renderer.samples());
+        let mut writer = PpmWriter::create(Path::new(output), output_width as usize, output_height as usize).or_exit("save image");
+        renderer.render_rows(&mut world, &still, Some(&progress), stats.as_ref(), "", |band| {
+            let pixels = band.iter().map(|(pixel, alpha)| if *alpha > 0.0 {get_color(*pixel / *alpha, exposure, tone_map, transform)} else {(0, 0, 0)}).collect::<Vec<_>>();
+            writer.write_rows(&pixels)
+        }).or_exit("save image");
+        writer.finish().or_exit("save image");
+        progress.finish("done");
+        if let Some(stats) = &stats {
+            eprintln!("{}", stats.report());
+        }
+        return;
+    }
+
+    //Render every frame, adding samples_per_pass samples to every pixel (that still needs them) per pass, until done or the preview window is closed
+    let progress = Progress::new(renderer.samples() * frames.len() as u64);
+    let mut closed = false;
+    for (index, (frame, path)) in frames.iter().enumerate() {
+        let start = Instant::now();
+        let view = animation.as_ref().map_or((lookfrom, lookat, vfov), |(camera_path, _)| camera_path.at(*frame));
+
+        //Animations rebuild the world and cameras at every frame, with the camera moved along its path
+        let mut animated = animation.as_ref().map(|_| {
+            let world = world_at(*frame);
+            let cams = views(camera(&world, view.0, view.1, view.2));
+            (world, cams)
+        });
+        let (world, cams) = match animated.as_mut() {
+            Some((world, cams)) => (world, &*cams),
+            None => (&mut world, &still),
+        };
 
-        for _s in 0..samples_per_pixel {
-            let u : f32 = (i as f32 + rng.gen_range(-1.0..1.0)) / (image_width as f32 - 1.0);
-            let v : f32 = (j as f32 + rng.gen_range(-1.0..1.0)) / (image_height as f32 - 1.0);
-            let r = cam.get_ray(u, v);
-            pixel += r.ray_color(&world, max_depth);
+        let status = format!(", frame {} of {}", index + 1, frames.len());
+        renderer.render_with(world, cams, Some(&progress), stats.as_ref(), &status, |image, pass, passes| {
+            if let Some(columns) = preview {
+                show(image, columns);
+                progress.draw(&format!("pass {} of {}{}", pass + 1, passes, status));
+            }
+            closed = !shown(image);
+            if (pass + 1).is_multiple_of(save_every) || pass + 1 == passes || closed {
+                let denoised = denoise.map(|settings| image.denoised(settings));
+                denoised.as_ref().unwrap_or(image).save(path, &save_settings, &metadata(view, start.elapsed())).or_exit("save image");
+                if let Some(variance_map) = variance_map {
+                    image.variance_map(variance_scale).save(variance_map).or_exit("save variance map");
+                }
+            }
+            !closed
+        });
+        if closed {
+            break;
         }
+    }
+    progress.finish(if closed {"window closed"} else {"done"});
+    if let Some(stats) = &stats {
+        eprintln!("{}", stats.report());
+    }
 
-        let (ir, ig, ib) = get_color(pixel, samples_per_pixel);
-        Pixel{x : i, y : image_height - j - 1, data : [ir, ig, ib]}
-    }).collect::<Vec<_>>();
-    
-    for pix in img_pixels {
-        img.put_pixel(pix.x, pix.y, Rgb(pix.data));
+    //Assemble the frames into a video
+    if let (Some(_), Some((video, fps))) = (&animation, video) {
+        let status = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-framerate", &fps.to_string(), "-i", &format!("frame_%04d.{}", extension), "-pix_fmt", "yuv420p", video])
+            .status();
+        match status {
+            Ok(status) if status.success() => {},
+            Ok(status) => eprintln!("ffmpeg failed to assemble {} ({})", video, status),
+            Err(error) => eprintln!("Failed to run ffmpeg: {}", error),
+        }
     }
+}
 
-    img.save("imageTest.png").expect("Failed to save image");
-}
\ No newline at end of file
+///Ends the program when something the render can't go on without fails, reporting the error rather than panicking.
+trait OrExit<T> {
+
+    ///Returns the value, or prints "Failed to <action>: <error>" and exits.
+    fn or_exit(self, action : &str) -> T;
+}
+
+impl<T, E : Display> OrExit<T> for Result<T, E> {
+    fn or_exit(self, action : &str) -> T {
+        self.unwrap_or_else(|error| {
+            eprintln!("Failed to {}: {}", action, error);
+            exit(1);
+        })
+    }
+}
@@ -1,81 +1,378 @@
 //Module to store the 'material' enum and its related methods
 
-use crate::ray_class::Ray;
-use crate::vec_class::{Color, Point3, dot,  random_in_unit_sphere};
+use crate::ray::Ray;
+use crate::vec3::{Color, Point3, Vec3, dot,  random_in_unit_sphere, random_float, random_2d, orthonormal_basis, Float};
+use crate::vec3::consts::PI;
 use crate::hitting::HitRecord;
-use rand::Rng;
-use super::TEXTURE_LIST;
+use crate::spectrum::{cauchy_ior, blackbody};
+use crate::textures::Textures;
+use crate::color::linear_to_srgb;
+#[cfg(feature = "std")]
+use crate::aov::stable_id;
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+#[cfg(not(feature = "std"))]
+use crate::math::Real;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+///Coolest temperature, in kelvin, that blackbody emission is worked out for. Anything cooler glows too faintly to see.
+const RAMP_MIN : Float = 500.0;
+
+///Hottest temperature, in kelvin, that blackbody emission is worked out for. Anything hotter takes its color.
+const RAMP_MAX : Float = 20000.0;
+
+///Kelvin between the temperatures in the blackbody ramp.
+#[cfg(feature = "std")]
+const RAMP_STEP : Float = 100.0;
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 ///Represent the material of a particular object. This determines how rays and light interact with objects.
+/// 
+/// Dielectric takes a tint, an index of refraction, a dispersion coefficient (see spectrum::cauchy_ior), 
+/// 
+/// which only has an effect when rendering spectrally, and a priority for nesting it inside other dielectrics (see Interior), or 0 not to.
+/// 
+/// Isotropic scatters light inside media. It takes a texture id and the Henyey-Greenstein anisotropy g, 
+/// 
+/// from -1 (scattering back towards the light) through 0 (scattering evenly) to 1 (scattering onwards, like fog glowing around lights).
+/// 
+/// ShadowCatcher stands in for the ground of a photograph that the render will be composited onto. Camera rays only see the shadows
+/// 
+/// and reflections it receives (see Integrator::radiance_alpha), while other rays bounce off it like a Lambertian surface with the given texture.
+///
+/// EmissiveIsotropic scatters light inside media as Isotropic does, and also glows from within with the given Emission, as fire and explosions do.
+/// Every scattering event inside the medium adds the emission there, so denser parts of the medium glow brighter.
+///
+/// Pbr follows the metallic-roughness model of glTF. It takes the texture ids of its base color, of its roughness (in the green channel)
+/// and metalness (in the blue one), and of a tangent space normal map, whose textures are read back to the values stored in their images.
+/// Each hit shades with one of its layers, picked at random (see Material::layer()): metal in the base color, a clear coat, or diffuse.
 pub enum Material {
-    Lambertian(usize),
-    Metal(Color, f32),
-    Dielectric(Color, f32),
-    Light(usize),
-    Isotropic(usize),
+    Lambertian(#[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize),
+    Metal(Color, Float),
+    Dielectric(Color, Float, Float, u32),
+    Light(#[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize),
+    Isotropic(#[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize, Float),
+    ShadowCatcher(#[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize),
+    EmissiveIsotropic(#[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize, Float, Emission),
+    Pbr(
+        #[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize,
+        #[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize,
+        #[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize,
+    ),
+}
+
+///Light given off inside an emissive medium. Variants include
+///
+/// Texture: the color of a texture (such as a Grid) at each point.
+///
+/// Blackbody: the color of a black body at the temperature a texture gives each point, as the fraction of its gray value
+/// of the maximum temperature in kelvin, with brightness growing with the fourth power of the temperature (by the Stefan-Boltzmann law)
+/// up to the given luminance at the maximum temperature. Takes the texture id, the maximum temperature and that luminance.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Emission {
+    Texture(#[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize),
+    Blackbody(#[cfg_attr(feature = "serde", serde(with = "crate::textures::by_name"))] usize, Float, Float),
+}
+
+impl Emission {
+
+    ///Returns the light given off at a point, looking its textures up in the scene's.
+    pub fn value(&self, u : Float, v : Float, p : Point3, textures : &Textures) -> Color {
+        match self {
+            Emission::Texture(texture_id) => textures.value(*texture_id, u, v, p),
+            Emission::Blackbody(texture_id, max_kelvin, intensity) => {
+                let c = textures.value(*texture_id, u, v, p);
+                let fraction = ((c.x + c.y + c.z) / 3.0).max(0.0);
+                blackbody_ramp(fraction * max_kelvin) * (intensity * fraction.powi(4))
+            },
+        }
+    }
+}
+
+///Returns the color of a black body at the given temperature, scaled to a luminance of 1 as spectrum::blackbody() does,
+///
+/// looked up in a table built the first time it is needed, since working each color out takes many samples of the spectrum.
+/// Temperatures below RAMP_MIN are black.
+pub fn blackbody_ramp(kelvin : Float) -> Color {
+    if kelvin.is_nan() || kelvin < RAMP_MIN {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    ramp_lookup(kelvin)
+}
+
+#[cfg(feature = "std")]
+fn ramp_lookup(kelvin : Float) -> Color {
+    static RAMP : OnceLock<Vec<Color>> = OnceLock::new();
+    let ramp = RAMP.get_or_init(|| {
+        let steps = ((RAMP_MAX - RAMP_MIN) / RAMP_STEP) as usize;
+        (0..=steps).map(|i| blackbody(RAMP_MIN + i as Float * RAMP_STEP)).collect()
+    });
+    let x = (kelvin.min(RAMP_MAX) - RAMP_MIN) / RAMP_STEP;
+    let low = (x as usize).min(ramp.len() - 1);
+    let high = (low + 1).min(ramp.len() - 1);
+    ramp[low] + (ramp[high] - ramp[low]) * (x - low as Float)
+}
+
+///Without std there is nowhere to keep the table once built, so colors are worked out whenever they are needed.
+#[cfg(not(feature = "std"))]
+fn ramp_lookup(kelvin : Float) -> Color {
+    blackbody(kelvin.min(RAMP_MAX))
 }
 
 impl Material {
-    ///Scatters the input ray according to an object's material, as well as where it landed.
-    pub fn scatter(&self, r_in : Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool {
+    ///Returns an ID for this material that stays the same from run to run, as long as its parameters
+    /// 
+    /// (and the order textures are added in) don't change.
+    #[cfg(feature = "std")]
+    pub fn id(&self) -> u32 {
+        stable_id(&format!("{:?}", self))
+    }
+
+    ///Returns the ids of the textures this material looks up.
+    pub fn texture_ids(&self) -> Vec<usize> {
+        match self {
+            Material::Lambertian(texture_id) | Material::Light(texture_id) | Material::Isotropic(texture_id, _) | Material::ShadowCatcher(texture_id) => vec![*texture_id],
+            Material::EmissiveIsotropic(texture_id, _, Emission::Texture(emission_id) | Emission::Blackbody(emission_id, ..)) => vec![*texture_id, *emission_id],
+            Material::Pbr(base_id, metallic_roughness_id, normal_id) => vec![*base_id, *metallic_roughness_id, *normal_id],
+            Material::Metal(..) | Material::Dielectric(..) => vec![],
+        }
+    }
+
+    ///Returns the layer of a Pbr material a hit shades with this time, and the normal to shade it with, bent by the normal map
+    ///
+    /// in the frame of the surface's tangent and bitangent (the directions u and v grow in). Metal is picked as often as the surface is metallic,
+    /// and otherwise the clear coat as often as it reflects (by Schlick's approximation, for an index of refraction of 1.5), with both as blurry as
+    /// roughness squared; the rest of the time, the hit is diffuse in the base color. Other materials are returned as they are.
+    pub fn layer(&self, r_in : Ray, rec : &HitRecord, tangent : Vec3, bitangent : Vec3, textures : &Textures) -> (Material, Vec3) {
+        let Material::Pbr(base_id, metallic_roughness_id, normal_id) = self else {
+            return (*self, rec.normal);
+        };
+        let data = |texture_id : usize| {
+            let c = textures.filtered(texture_id, rec.u, rec.v, rec.p, rec.footprint);
+            Color::new(linear_to_srgb(c.x), linear_to_srgb(c.y), linear_to_srgb(c.z))
+        };
+        let metallic_roughness = data(*metallic_roughness_id);
+        let (roughness, metallic) = (metallic_roughness.y.clamp(0.0, 1.0), metallic_roughness.z.clamp(0.0, 1.0));
+        let mapped = data(*normal_id) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+        let normal = tangent * mapped.x + bitangent * mapped.y + rec.normal * mapped.z;
+        let normal = if normal.near_zero() || dot(normal, rec.normal) <= 0.0 {rec.normal} else {normal.unit_vector()};
+
+        let cos = (-dot(r_in.direction.unit_vector(), normal)).clamp(0.0, 1.0);
+        let reflectance = 0.04 + 0.96 * (1.0 - cos).powi(5);
+        let chance = random_float();
+        let layer = if chance < metallic {
+            Material::Metal(textures.filtered(*base_id, rec.u, rec.v, rec.p, rec.footprint), roughness * roughness)
+        } else if chance < metallic + (1.0 - metallic) * reflectance {
+            Material::Metal(Color::new(1.0, 1.0, 1.0), roughness * roughness)
+        } else {
+            Material::Lambertian(*base_id)
+        };
+        (layer, normal)
+    }
+
+    ///Scatters the input ray according to an object's material, as well as where it landed, looking its texture up in the scene's textures.
+    pub fn scatter(&self, r_in : Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray, textures : &Textures) -> bool {
         match self {
-            Material::Lambertian(texture_id) => {
+            //Pbr materials scatter as their diffuse layer until a layer is picked for the hit
+            Material::Lambertian(texture_id) | Material::ShadowCatcher(texture_id) | Material::Pbr(texture_id, ..) => {
                 let mut scatter_dir = rec.normal + random_in_unit_sphere();
                 if scatter_dir.near_zero() {
                     scatter_dir = rec.normal;
                 }
-                *scattered = Ray::new(rec.p, scatter_dir);
-                unsafe {
-                    *attenuation = TEXTURE_LIST[*texture_id].value(rec.u, rec.v, rec.p);
-                }
+                *scattered = Ray::new(rec.p, scatter_dir).with_time(r_in.time);
+                *attenuation = textures.filtered(*texture_id, rec.u, rec.v, rec.p, rec.footprint);
                 true
             },
             Material::Metal(albedo, fuzz) => {
                 let reflected = r_in.direction.unit_vector().reflect(rec.normal);
-                *scattered = Ray::new(rec.p, reflected + random_in_unit_sphere() * (*fuzz));
+                *scattered = Ray::new(rec.p, reflected + random_in_unit_sphere() * (*fuzz)).with_time(r_in.time);
                 *attenuation = *albedo;
                 dot(scattered.direction, rec.normal) > 0.0
             },
-            Material::Dielectric(c, ir) => {
+            Material::Dielectric(c, ir, dispersion, _) => {
                 *attenuation = *c;
-                let refraction_ratio = if rec.front_facing {1.0 / *ir} else {*ir};
-                let mut rng = rand::thread_rng();
-
-                //Schlick's approximation for reflectance
-                let reflectance = |cosine : f32, ref_idx : f32| {
-                    let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-                    r0 *= r0;
-                    r0 + (1.0 - r0) * ((1.0 - cosine) as f32).powf(5.0)
-                };
-
-                let unit_direction = r_in.direction.unit_vector();
-                let cos = if dot(-unit_direction, rec.normal) < 1.0 {dot(-unit_direction, rec.normal)} else {1.0};
-                let sin = (1.0 - cos*cos).sqrt();
-                let dir = if refraction_ratio * sin > 1.0 || reflectance(cos, refraction_ratio) > rng.gen::<f32>() {
-                    unit_direction.reflect(rec.normal)
+                let ir = ior_at(*ir, *dispersion, r_in);
+                let refraction_ratio = if rec.front_facing {1.0 / ir} else {ir};
+                let (dir, _) = refract_or_reflect(r_in, rec, refraction_ratio);
+                *scattered = Ray::new(rec.p, dir).with_time(r_in.time);
+                true
+            },
+            Material::Isotropic(texture_id, g) | Material::EmissiveIsotropic(texture_id, g, _) => {
+                //Invert the Henyey-Greenstein distribution for the angle from the incoming direction
+                let (r1, r2) = random_2d();
+                let cos = if g.abs() < 0.001 {
+                    1.0 - 2.0 * r1
                 } else {
-                    unit_direction.refract(rec.normal, refraction_ratio)
+                    let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * r1);
+                    ((1.0 + g * g - square * square) / (2.0 * g)).clamp(-1.0, 1.0)
                 };
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * PI * r2;
 
-                *scattered = Ray::new(rec.p, dir);
-                true
-            },
-            Material::Isotropic(texture_id) => {
-                *scattered = Ray::new(rec.p, random_in_unit_sphere());
-                unsafe {
-                    *attenuation = TEXTURE_LIST[*texture_id].value(rec.u, rec.v, rec.p);
-                }
+                let w = r_in.direction.unit_vector();
+                let (u, v) = orthonormal_basis(w);
+                *scattered = Ray::new(rec.p, u * (sin * phi.cos()) + v * (sin * phi.sin()) + w * cos).with_time(r_in.time);
+                *attenuation = textures.filtered(*texture_id, rec.u, rec.v, rec.p, rec.footprint);
                 true
             },
             _ => false,
         }
     }
 
-    pub fn emitted(&self, u : f32, v : f32, p : Point3) -> Color {
+    ///Scatters the input ray as scatter() does, except that nested dielectrics (those with a priority above 0) refract between
+    ///
+    /// the medium the ray is in and the one it enters, and are passed straight through where a medium of higher priority overlaps them,
+    /// keeping track of the media the ray is inside of in interior.
+    pub fn scatter_nested(&self, r_in : Ray, rec : &HitRecord, interior : &mut Interior, attenuation : &mut Color, scattered : &mut Ray, textures : &Textures) -> bool {
+        let (c, medium) = match self {
+            Material::Dielectric(c, ir, dispersion, priority) if *priority > 0 => (*c, (*priority, *ir, *dispersion)),
+            _ => return self.scatter(r_in, rec, attenuation, scattered, textures),
+        };
+        let ior = |(_, ir, dispersion) : (u32, Float, Float)| ior_at(ir, dispersion, r_in);
+        let pass_through = |attenuation : &mut Color, scattered : &mut Ray| {
+            *attenuation = Color::new(1.0, 1.0, 1.0);
+            *scattered = Ray::new(rec.p, r_in.direction).with_time(r_in.time);
+            true
+        };
+
+        if rec.front_facing {
+            let outside = interior.highest(None);
+            if outside.is_some_and(|(priority, _, _)| priority > medium.0) {
+                interior.enter(medium);
+                return pass_through(attenuation, scattered);
+            }
+            let (dir, refracted) = refract_or_reflect(r_in, rec, outside.map_or(1.0, ior) / ior(medium));
+            if refracted {
+                interior.enter(medium);
+            }
+            *attenuation = c;
+            *scattered = Ray::new(rec.p, dir).with_time(r_in.time);
+        } else {
+            let index = interior.find(medium);
+            let outside = interior.highest(index);
+            if outside.is_some_and(|(priority, _, _)| priority > medium.0) {
+                interior.leave(index);
+                return pass_through(attenuation, scattered);
+            }
+            let (dir, refracted) = refract_or_reflect(r_in, rec, ior(medium) / outside.map_or(1.0, ior));
+            if refracted {
+                interior.leave(index);
+            }
+            *attenuation = c;
+            *scattered = Ray::new(rec.p, dir).with_time(r_in.time);
+        }
+        true
+    }
+
+    ///Whether this material scatters in a single (or nearly single) direction, meaning lights cannot be sampled directly from it.
+    pub fn is_specular(&self) -> bool {
+        matches!(self, Material::Metal(..) | Material::Dielectric(..))
+    }
+
+    ///Returns the solid angle pdf with which scatter() would generate the given direction. Only meaningful for non-specular materials.
+    pub fn scattering_pdf(&self, r_in : Ray, rec : &HitRecord, direction : Vec3) -> Float {
+        match self {
+            Material::Lambertian(_) | Material::ShadowCatcher(_) | Material::Pbr(..) => {
+                let cos = dot(rec.normal, direction.unit_vector());
+                if cos < 0.0 {0.0} else {cos / PI}
+            },
+            Material::Isotropic(_, g) | Material::EmissiveIsotropic(_, g, _) => henyey_greenstein(*g, dot(r_in.direction.unit_vector(), direction.unit_vector())),
+            _ => 0.0,
+        }
+    }
+
+    ///Returns the light the material gives off at a point, looking its texture up in the scene's textures.
+    pub fn emitted(&self, u : Float, v : Float, p : Point3, textures : &Textures) -> Color {
         match self {
-            Material::Light(texture_id) => unsafe {TEXTURE_LIST[*texture_id].value(u, v, p)},
+            Material::Light(texture_id) => textures.value(*texture_id, u, v, p),
+            Material::EmissiveIsotropic(_, _, emission) => emission.value(u, v, p, textures),
             _ => Color::new(0.0, 0.0, 0.0),
         }
     }
-}
\ No newline at end of file
+}
+
+///Most nested dielectrics a path can be inside of at once. Entering more leaves the extra ones out.
+const MAX_NESTING : usize = 8;
+
+///Nested dielectrics a path is inside of, for Material::scatter_nested(), as their priority, index of refraction and dispersion,
+///
+/// so that overlapping objects, such as the water in a glass and the ice in the water, refract as one interface where they meet.
+/// Wherever media overlap, the one of highest priority fills the overlap, so the glass should have the highest priority, then the ice, then the water,
+/// with the water's surface a little inside the glass's. Objects with the same priority and index of refraction count as one medium.
+/// Paths start outside every medium, in air.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Interior {
+    media : [(u32, Float, Float) ; MAX_NESTING],
+    count : usize,
+}
+
+impl Interior {
+
+    pub fn new() -> Interior {
+        Interior::default()
+    }
+
+    ///Returns the medium of highest priority the path is inside of, leaving out the one at index skip.
+    fn highest(&self, skip : Option<usize>) -> Option<(u32, Float, Float)> {
+        self.media[..self.count].iter().enumerate().filter(|(i, _)| Some(*i) != skip).map(|(_, medium)| *medium).max_by_key(|medium| medium.0)
+    }
+
+    ///Returns where the medium entered most recently that matches the given one is kept, if the path is inside of it.
+    fn find(&self, medium : (u32, Float, Float)) -> Option<usize> {
+        self.media[..self.count].iter().rposition(|entered| entered.0 == medium.0 && entered.1 == medium.1)
+    }
+
+    fn enter(&mut self, medium : (u32, Float, Float)) {
+        if self.count < MAX_NESTING {
+            self.media[self.count] = medium;
+            self.count += 1;
+        }
+    }
+
+    fn leave(&mut self, index : Option<usize>) {
+        if let Some(index) = index {
+            self.media.copy_within(index + 1..self.count, index);
+            self.count -= 1;
+        }
+    }
+}
+
+///Returns the index of refraction of a dielectric at the wavelength the ray carries, if any.
+fn ior_at(ir : Float, dispersion : Float, r_in : Ray) -> Float {
+    match r_in.wavelength {
+        Some(lambda) => cauchy_ior(ir, dispersion, lambda),
+        None => ir,
+    }
+}
+
+///Refracts a ray through a dielectric surface with the given ratio of indices of refraction, or reflects it off the surface as often as
+///
+/// Schlick's approximation of the Fresnel reflectance says (and always past the critical angle). Returns the new direction, and whether it was refracted.
+fn refract_or_reflect(r_in : Ray, rec : &HitRecord, refraction_ratio : Float) -> (Vec3, bool) {
+    //Schlick's approximation for reflectance
+    let reflectance = |cosine : Float, ref_idx : Float| {
+        let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+        r0 *= r0;
+        r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
+    };
+
+    let unit_direction = r_in.direction.unit_vector();
+    let cos = if dot(-unit_direction, rec.normal) < 1.0 {dot(-unit_direction, rec.normal)} else {1.0};
+    let sin = (1.0 - cos*cos).sqrt();
+    if refraction_ratio * sin > 1.0 || reflectance(cos, refraction_ratio) > random_float() {
+        (unit_direction.reflect(rec.normal), false)
+    } else {
+        (unit_direction.refract(rec.normal, refraction_ratio), true)
+    }
+}
+
+///Henyey-Greenstein phase function, giving the density of light scattering by an angle with the given cosine.
+pub fn henyey_greenstein(g : Float, cos : Float) -> Float {
+    let denominator = 1.0 + g * g - 2.0 * g * cos;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
@@ -0,0 +1,36 @@
+/*
+Module to store the float functions of the no_std build. Without std, f32 and f64 have no sqrt, sin, powf and the like,
+so the Real trait gives them the same methods from libm, and the core modules import it when std is off.
+*/
+
+///Float functions std would otherwise provide, with the same names and results to within libm's rounding.
+pub trait Real : Sized {
+    fn sqrt(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn exp(self) -> Self;
+    fn exp_m1(self) -> Self;
+    fn ln(self) -> Self;
+    fn powf(self, n : Self) -> Self;
+    fn powi(self, n : i32) -> Self;
+    fn floor(self) -> Self;
+}
+
+macro_rules! real {
+    ($float : ty, $sqrt : ident, $sin : ident, $cos : ident, $exp : ident, $expm1 : ident, $ln : ident, $pow : ident, $floor : ident) => {
+        impl Real for $float {
+            fn sqrt(self) -> $float {libm::$sqrt(self)}
+            fn sin(self) -> $float {libm::$sin(self)}
+            fn cos(self) -> $float {libm::$cos(self)}
+            fn exp(self) -> $float {libm::$exp(self)}
+            fn exp_m1(self) -> $float {libm::$expm1(self)}
+            fn ln(self) -> $float {libm::$ln(self)}
+            fn powf(self, n : $float) -> $float {libm::$pow(self, n)}
+            fn powi(self, n : i32) -> $float {libm::$pow(self, n as $float)}
+            fn floor(self) -> $float {libm::$floor(self)}
+        }
+    };
+}
+
+real!(f32, sqrtf, sinf, cosf, expf, expm1f, logf, powf, floorf);
+real!(f64, sqrt, sin, cos, exp, expm1, log, pow, floor);
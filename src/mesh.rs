@@ -0,0 +1,255 @@
+/*
+Module to store the loaders for mesh files: Wavefront .obj files, with their v, vt and f lines (other lines, such as normals, groups and materials,
+are skipped), and .ply files, in ASCII or binary, with their vertices' x, y and z, their texture coordinates as u and v (or s and t),
+and their faces' vertex indices. Polygons with more than 3 corners are split into a fan of triangles around their first corner.
+Meshes come out as triangles whose corners hold their position and texture coordinates as written in the file, for importers to place.
+*/
+
+use std::fs::read;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use crate::cache::{self, Corner, content_hash};
+use crate::vec3::Float;
+
+///Loads a mesh file, as an .obj or .ply file by its extension, from the asset cache if it has been parsed before (see cache.rs).
+pub fn load(path : &Path) -> Result<Vec<[Corner ; 3]>> {
+    let bytes = read(path).map_err(|error| Error::new(error.kind(), format!("Failed to load {}: {}", path.display(), error)))?;
+    let parse : fn(&[u8]) -> Result<Vec<[Corner ; 3]>> = match path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("obj") => |bytes| parse_obj(&String::from_utf8_lossy(bytes)),
+        Some("ply") => parse_ply,
+        _ => return Err(invalid(&format!("{} isn't an .obj or .ply file", path.display()))),
+    };
+    if !cache::enabled() {
+        return parse(&bytes);
+    }
+    cache::triangles(content_hash(&bytes), || parse(&bytes))
+}
+
+///Parses the text of an .obj file.
+pub fn parse_obj(text : &str) -> Result<Vec<[Corner ; 3]>> {
+    let mut points = vec![];
+    let mut uvs = vec![];
+    let mut triangles = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+        let floats = |parts : std::str::SplitWhitespace| parts.map(|part| part.parse::<Float>().map_err(|_| invalid(&format!("line {}: malformed number {}", number + 1, part))))
+            .collect::<Result<Vec<_>>>();
+        match parts.next() {
+            Some("v") => match floats(parts)?[..] {
+                [x, y, z, ..] => points.push([x, y, z]),
+                _ => return Err(invalid(&format!("line {}: a vertex needs 3 coordinates", number + 1))),
+            },
+            Some("vt") => match floats(parts)?[..] {
+                [u, v, ..] => uvs.push((u, v)),
+                [u] => uvs.push((u, 0.0)),
+                _ => return Err(invalid(&format!("line {}: texture coordinates without any values", number + 1))),
+            },
+            Some("f") => {
+                //Corners are written as v, v/vt, v//vn or v/vt/vn, counting from 1, or back from the last one so far when negative
+                let corners = parts.map(|corner| {
+                    let mut indices = corner.split('/');
+                    let index = |count : usize, index : Option<&str>| -> Result<Option<usize>> {
+                        let Some(index) = index.filter(|index| !index.is_empty()) else {
+                            return Ok(None);
+                        };
+                        let index = index.parse::<i64>().map_err(|_| invalid(&format!("line {}: malformed index {}", number + 1, index)))?;
+                        let resolved = if index < 0 {count as i64 + index} else {index - 1};
+                        if resolved < 0 || resolved >= count as i64 {
+                            return Err(invalid(&format!("line {}: index {} doesn't exist", number + 1, index)));
+                        }
+                        Ok(Some(resolved as usize))
+                    };
+                    let point = index(points.len(), indices.next())?.ok_or_else(|| invalid(&format!("line {}: a corner without a vertex", number + 1)))?;
+                    let uv = index(uvs.len(), indices.next())?;
+                    let [x, y, z] = points[point];
+                    let (u, v) = uv.map_or((0.0, 0.0), |uv| uvs[uv]);
+                    Ok([x, y, z, u, v])
+                }).collect::<Result<Vec<Corner>>>()?;
+                if corners.len() < 3 {
+                    return Err(invalid(&format!("line {}: a face needs at least 3 corners", number + 1)));
+                }
+                triangles.extend((2..corners.len()).map(|i| [corners[0], corners[i - 1], corners[i]]));
+            },
+            _ => {},
+        }
+    }
+    Ok(triangles)
+}
+
+///Types a .ply file's properties can have.
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+
+    fn parse(name : &str) -> Result<Scalar> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            other => return Err(invalid(&format!("unknown property type {}", other))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+///A property of a .ply element: a name, and its type, or the types of its count and values if it is a list.
+struct Property {
+    name : String,
+    count : Option<Scalar>,
+    scalar : Scalar,
+}
+
+///An element of a .ply file's header, such as vertex or face, with how many there are.
+struct Element {
+    name : String,
+    count : usize,
+    properties : Vec<Property>,
+}
+
+///Reads the values of a .ply file's body one at a time, from text or from bytes in either byte order.
+enum Reader<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary {bytes : &'a [u8], position : usize, big_endian : bool},
+}
+
+impl Reader<'_> {
+
+    fn next(&mut self, scalar : Scalar) -> Result<f64> {
+        match self {
+            Reader::Ascii(values) => {
+                let value = values.next().ok_or_else(|| invalid("the file ends early"))?;
+                value.parse().map_err(|_| invalid(&format!("malformed number {}", value)))
+            },
+            Reader::Binary {bytes, position, big_endian} => {
+                let size = scalar.size();
+                let mut value = [0u8 ; 8];
+                value[..size].copy_from_slice(bytes.get(*position..*position + size).ok_or_else(|| invalid("the file ends early"))?);
+                *position += size;
+                if *big_endian {
+                    value[..size].reverse();
+                }
+                let [a, b, c, d, ..] = value;
+                Ok(match scalar {
+                    Scalar::I8 => a as i8 as f64,
+                    Scalar::U8 => a as f64,
+                    Scalar::I16 => i16::from_le_bytes([a, b]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([a, b]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([a, b, c, d]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([a, b, c, d]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([a, b, c, d]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(value),
+                })
+            },
+        }
+    }
+}
+
+///Parses the bytes of a .ply file.
+pub fn parse_ply(bytes : &[u8]) -> Result<Vec<[Corner ; 3]>> {
+    const END : &[u8] = b"end_header";
+    let end = bytes.windows(END.len()).position(|window| window == END).ok_or_else(|| invalid("the header has no end_header"))?;
+    let body = end + END.len() + bytes[end + END.len()..].iter().position(|byte| *byte == b'\n').ok_or_else(|| invalid("the file ends after its header"))? + 1;
+    let header = String::from_utf8_lossy(&bytes[..end]);
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(invalid("the file doesn't start with ply"));
+    }
+    let mut format = None;
+    let mut elements : Vec<Element> = vec![];
+    for line in lines {
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        match parts[..] {
+            ["format", kind, _] => format = Some(kind.to_string()),
+            ["element", name, count] => elements.push(Element {
+                name : name.to_string(),
+                count : count.parse().map_err(|_| invalid(&format!("malformed element count {}", count)))?,
+                properties : vec![],
+            }),
+            ["property", "list", count, scalar, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("a property before any element"))?;
+                element.properties.push(Property {name : name.to_string(), count : Some(Scalar::parse(count)?), scalar : Scalar::parse(scalar)?});
+            },
+            ["property", scalar, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("a property before any element"))?;
+                element.properties.push(Property {name : name.to_string(), count : None, scalar : Scalar::parse(scalar)?});
+            },
+            ["comment", ..] | ["obj_info", ..] | [] => {},
+            _ => return Err(invalid(&format!("malformed header line {}", line))),
+        }
+    }
+
+    let text;
+    let mut reader = match format.as_deref() {
+        Some("ascii") => {
+            text = String::from_utf8_lossy(&bytes[body..]);
+            Reader::Ascii(text.split_whitespace())
+        },
+        Some("binary_little_endian") => Reader::Binary {bytes, position : body, big_endian : false},
+        Some("binary_big_endian") => Reader::Binary {bytes, position : body, big_endian : true},
+        other => return Err(invalid(&format!("unknown format {}", other.unwrap_or("(none)")))),
+    };
+
+    let mut corners : Vec<Corner> = vec![];
+    let mut triangles = vec![];
+    for element in &elements {
+        let find = |names : &[&str]| element.properties.iter().position(|property| names.contains(&property.name.as_str()));
+        let (x, y, z) = (find(&["x"]), find(&["y"]), find(&["z"]));
+        let (u, v) = (find(&["u", "s", "texture_u"]), find(&["v", "t", "texture_v"]));
+        let indices = find(&["vertex_indices", "vertex_index"]);
+        for _ in 0..element.count {
+            let mut values = vec![0.0 ; element.properties.len()];
+            let mut list = vec![];
+            for (index, property) in element.properties.iter().enumerate() {
+                match property.count {
+                    Some(count) => {
+                        let count = reader.next(count)? as usize;
+                        let items = (0..count).map(|_| reader.next(property.scalar)).collect::<Result<Vec<_>>>()?;
+                        if Some(index) == indices {
+                            list = items;
+                        }
+                    },
+                    None => values[index] = reader.next(property.scalar)?,
+                }
+            }
+            let value = |index : Option<usize>| index.map_or(0.0, |index| values[index] as Float);
+            match element.name.as_str() {
+                "vertex" => corners.push([value(x), value(y), value(z), value(u), value(v)]),
+                "face" => {
+                    let face = list.iter().map(|index| corners.get(*index as usize).copied().ok_or_else(|| invalid(&format!("vertex {} doesn't exist", index))))
+                        .collect::<Result<Vec<_>>>()?;
+                    triangles.extend((2..face.len()).map(|i| [face[0], face[i - 1], face[i]]));
+                },
+                _ => {},
+            }
+        }
+    }
+    Ok(triangles)
+}
+
+fn invalid(message : &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid mesh file: {}", message))
+}
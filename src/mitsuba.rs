@@ -0,0 +1,523 @@
+/*
+Module to store the importer for Mitsuba scenes (.xml files, as written for Mitsuba 0.6 and 3), which loads the subset of them this tracer can represent
+into a scene_file::SceneFile:
+
+sensors: perspective and thinlens, with their film's width and height and their sampler's sample count, and the integrator's maximum depth;
+shapes: spheres, rectangles and cubes whose transforms leave them lined up with the axes, and obj and ply meshes (see mesh.rs), as triangles;
+bsdfs: diffuse, conductor, dielectric and their rough versions, with plastic and principled approximated as diffuse, and twosided, mask and bump map wrappers ignored;
+textures: bitmap and checkerboard;
+emitters: area emitters on shapes, point, spot, directional, envmap and constant.
+
+Defaults (<default name="spp" value="64"/>) are substituted into $spp, and objects can be defined once with an id and used with <ref id="..."/>.
+*/
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use crate::cache::load_image;
+use crate::xml::Element;
+use crate::mesh;
+use crate::vec3::{Vec3, Point3, Color, cross, dot, Float};
+use crate::textures::{Texture, Textures};
+use crate::materials::Material;
+use crate::hitting::Hittable;
+use crate::lights::Light;
+use crate::scene_file::{SceneFile, FileSettings};
+
+///Radius of the sphere standing in for a constant emitter, which surrounds the scene.
+pub const CONSTANT_RADIUS : Float = 1.0e5;
+
+///Approximate color of common metals at normal incidence, for conductors given by material name rather than reflectance.
+const METALS : [(&str, [Float ; 3]) ; 9] = [
+    ("Ag", [0.972, 0.96, 0.915]),
+    ("Al", [0.913, 0.922, 0.924]),
+    ("Au", [1.0, 0.766, 0.336]),
+    ("Cr", [0.549, 0.556, 0.554]),
+    ("Cu", [0.955, 0.638, 0.538]),
+    ("Fe", [0.562, 0.565, 0.578]),
+    ("Ni", [0.66, 0.609, 0.526]),
+    ("Pt", [0.673, 0.637, 0.585]),
+    ("Ti", [0.542, 0.497, 0.449]),
+];
+
+///Indices of refraction of the materials dielectrics can be given by name.
+const IORS : [(&str, Float) ; 12] = [
+    ("vacuum", 1.0),
+    ("air", 1.000277),
+    ("helium", 1.000036),
+    ("hydrogen", 1.000132),
+    ("water", 1.333),
+    ("ethanol", 1.361),
+    ("acrylic", 1.49),
+    ("polypropylene", 1.49),
+    ("bk7", 1.5046),
+    ("pyrex", 1.47),
+    ("fused quartz", 1.458),
+    ("diamond", 2.419),
+];
+
+///Affine transform, as a 4 by 4 matrix in rows.
+pub type Matrix = [[Float ; 4] ; 4];
+
+pub const IDENTITY : Matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+///Loads a Mitsuba scene, along with the images it refers to.
+pub fn load(path : &str) -> Result<SceneFile> {
+    let mut root = Element::parse(&read_to_string(path)?)?;
+    if root.name != "scene" {
+        return Err(invalid("the root element must be a scene"));
+    }
+    let defaults = root.children.iter().filter(|child| child.name == "default")
+        .map(|child| Ok((text(child, "name")?.to_string(), text(child, "value")?.to_string())))
+        .collect::<Result<Vec<_>>>()?;
+    substitute(&mut root, &defaults);
+
+    let mut importer = Importer {
+        directory : Path::new(path).parent().map_or(PathBuf::new(), Path::to_path_buf),
+        textures : HashMap::new(),
+        materials : HashMap::new(),
+        scene : SceneFile {settings : FileSettings::default(), textures : Textures::new(), objects : vec![], lights : vec![]},
+    };
+    for child in &root.children {
+        importer.element(child)?;
+    }
+    Ok(importer.scene)
+}
+
+///Replaces $name in every attribute value with the value of the default of that name, longest names first.
+fn substitute(element : &mut Element, defaults : &[(String, String)]) {
+    let mut defaults = defaults.to_vec();
+    defaults.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+    for (_, value) in &mut element.attributes {
+        for (name, default) in &defaults {
+            *value = value.replace(&format!("${}", name), default);
+        }
+    }
+    for child in &mut element.children {
+        substitute(child, &defaults);
+    }
+}
+
+///Imports a scene's elements, keeping track of the textures and materials defined with ids so far.
+struct Importer {
+    directory : PathBuf,
+    textures : HashMap<String, usize>,
+    materials : HashMap<String, Material>,
+    scene : SceneFile,
+}
+
+impl Importer {
+
+    fn element(&mut self, element : &Element) -> Result<()> {
+        match element.name.as_str() {
+            "default" => {},
+            "integrator" => self.integrator(element)?,
+            "sensor" => self.sensor(element)?,
+            "texture" => {
+                let texture = self.texture(element)?;
+                self.textures.insert(text(element, "id")?.to_string(), texture);
+            },
+            "bsdf" => {
+                let material = self.bsdf(element)?;
+                self.materials.insert(text(element, "id")?.to_string(), material);
+            },
+            "shape" => self.shape(element)?,
+            "emitter" => self.emitter(element)?,
+            other => return Err(invalid(&format!("unsupported element {}", other))),
+        }
+        Ok(())
+    }
+
+    ///Reads the maximum path depth, also from integrators wrapped in others, such as aov. -1 (no limit) keeps the setting in main().
+    fn integrator(&mut self, element : &Element) -> Result<()> {
+        if let Some(depth) = property(element, &["max_depth", "maxDepth"]) {
+            let depth = number(depth)?;
+            if depth >= 0.0 {
+                self.scene.settings.max_depth = Some(depth as i32);
+            }
+        }
+        for child in element.children.iter().filter(|child| child.name == "integrator") {
+            self.integrator(child)?;
+        }
+        Ok(())
+    }
+
+    fn sensor(&mut self, element : &Element) -> Result<()> {
+        let kind = text(element, "type")?;
+        if !matches!(kind, "perspective" | "thinlens") {
+            return Err(invalid(&format!("unsupported sensor type {}", kind)));
+        }
+        let settings = &mut self.scene.settings;
+
+        let film = element.children.iter().find(|child| child.name == "film");
+        let size = |name, default| film.and_then(|film| property(film, &[name])).map_or(Ok(default), number);
+        let (width, height) = (size("width", 768.0)?, size("height", 576.0)?);
+        let aspect_ratio = width / height;
+        settings.width = Some(width as u32);
+        settings.aspect_ratio = Some(aspect_ratio);
+        if let Some(sampler) = element.children.iter().find(|child| child.name == "sampler") {
+            if let Some(count) = property(sampler, &["sample_count", "sampleCount"]) {
+                settings.samples_per_pixel = Some(number(count)? as i32);
+            }
+        }
+
+        //Mitsuba cameras look down their +z axis, with +y up
+        let transform = self::transform(property(element, &["to_world", "toWorld"]))?;
+        let origin = transform_point(&transform, Point3::new(0.0, 0.0, 0.0));
+        settings.lookfrom = Some(origin);
+        settings.lookat = Some(transform_point(&transform, Point3::new(0.0, 0.0, 1.0)));
+        settings.vup = Some(transform_vector(&transform, Vec3::new(0.0, 1.0, 0.0)));
+
+        //The field of view is horizontal by default, where it is vertical here
+        let fov = property(element, &["fov"]).map_or(Ok(45.0), number)?;
+        let tangent = (fov.to_radians() / 2.0).tan();
+        let axis = property(element, &["fov_axis", "fovAxis"]).map_or(Ok("x"), |axis| text(axis, "value"))?;
+        let tangent = match axis {
+            "x" => tangent / aspect_ratio,
+            "y" => tangent,
+            "diagonal" => tangent / (1.0 + aspect_ratio * aspect_ratio).sqrt(),
+            "smaller" => if aspect_ratio < 1.0 {tangent / aspect_ratio} else {tangent},
+            "larger" => if aspect_ratio > 1.0 {tangent / aspect_ratio} else {tangent},
+            other => return Err(invalid(&format!("unsupported fov axis {}", other))),
+        };
+        settings.vfov = Some((2.0 * tangent.atan()).to_degrees());
+
+        if kind == "thinlens" {
+            let radius = property(element, &["aperture_radius", "apertureRadius"]).map_or(Ok(0.0), number)?;
+            settings.aperture = Some(2.0 * radius);
+            settings.focus_distance = Some(property(element, &["focus_distance", "focusDistance"]).map_or(Ok(0.0), number)?);
+            settings.autofocus = Some(false);
+        } else {
+            settings.aperture = Some(0.0);
+        }
+        Ok(())
+    }
+
+    ///Returns the texture a property gives: a color, a texture, or a reference to one, or a solid texture of the default color if it is left out.
+    fn texture_property(&mut self, element : &Element, names : &[&str], default : Color) -> Result<usize> {
+        match property(element, names) {
+            None => Ok(self.scene.textures.add(Texture::Solid(default))),
+            Some(value) => match value.name.as_str() {
+                "texture" => self.texture(value),
+                "ref" => {
+                    let id = text(value, "id")?;
+                    self.textures.get(id).copied().ok_or_else(|| invalid(&format!("unknown texture {}", id)))
+                },
+                _ => Ok(self.scene.textures.add(Texture::Solid(color(value)?))),
+            },
+        }
+    }
+
+    fn texture(&mut self, element : &Element) -> Result<usize> {
+        Ok(match text(element, "type")? {
+            "bitmap" => {
+                let filename = text(property(element, &["filename"]).ok_or_else(|| invalid("bitmap without a filename"))?, "value")?;
+                self.scene.textures.add(Texture::Image(load_image(self.directory.join(filename)).map_err(|error| invalid(&error.to_string()))?))
+            },
+            "checkerboard" => {
+                let odd = property(element, &["color0"]).map_or(Ok(Color::new(0.4, 0.4, 0.4)), color)?;
+                let even = property(element, &["color1"]).map_or(Ok(Color::new(0.2, 0.2, 0.2)), color)?;
+                self.scene.textures.add(Texture::Checker(odd, even))
+            },
+            other => return Err(invalid(&format!("unsupported texture type {}", other))),
+        })
+    }
+
+    fn bsdf(&mut self, element : &Element) -> Result<Material> {
+        let kind = text(element, "type")?;
+        let float = |names : &[&str], default : Float| property(element, names).map_or(Ok(default), number);
+        Ok(match kind {
+            "twosided" | "mask" | "bumpmap" | "normalmap" => {
+                let inner = element.children.iter().find(|child| child.name == "bsdf" || child.name == "ref").ok_or_else(|| invalid(&format!("{} without a bsdf", kind)))?;
+                self.material(inner)?
+            },
+            "diffuse" | "roughdiffuse" => Material::Lambertian(self.texture_property(element, &["reflectance"], Color::new(0.5, 0.5, 0.5))?),
+            "plastic" | "roughplastic" => Material::Lambertian(self.texture_property(element, &["diffuse_reflectance", "diffuseReflectance"], Color::new(0.5, 0.5, 0.5))?),
+            "principled" => Material::Lambertian(self.texture_property(element, &["base_color", "baseColor"], Color::new(0.5, 0.5, 0.5))?),
+            "conductor" | "roughconductor" => {
+                let color = match property(element, &["specular_reflectance", "specularReflectance"]) {
+                    Some(reflectance) => color(reflectance)?,
+                    None => {
+                        let name = property(element, &["material"]).map_or(Ok("none"), |name| text(name, "value"))?;
+                        METALS.iter().find(|(metal, _)| *metal == name).map_or(Color::new(1.0, 1.0, 1.0), |(_, [r, g, b])| Color::new(*r, *g, *b))
+                    },
+                };
+                let roughness = if kind == "roughconductor" {float(&["alpha"], 0.1)?} else {0.0};
+                Material::Metal(color, roughness)
+            },
+            "dielectric" | "roughdielectric" | "thindielectric" => {
+                let interior = ior(property(element, &["int_ior", "intIOR"]), 1.5046)?;
+                let exterior = ior(property(element, &["ext_ior", "extIOR"]), 1.000277)?;
+                Material::Dielectric(Color::new(1.0, 1.0, 1.0), interior / exterior, 0.0, 0)
+            },
+            other => return Err(invalid(&format!("unsupported bsdf type {}", other))),
+        })
+    }
+
+    ///Returns the material a bsdf or a reference to one gives.
+    fn material(&mut self, element : &Element) -> Result<Material> {
+        if element.name == "ref" {
+            let id = text(element, "id")?;
+            return self.materials.get(id).copied().ok_or_else(|| invalid(&format!("unknown bsdf {}", id)));
+        }
+        self.bsdf(element)
+    }
+
+    fn shape(&mut self, element : &Element) -> Result<()> {
+        let area = element.children.iter().find(|child| child.name == "emitter");
+        let mat = match area {
+            Some(emitter) => {
+                let radiance = property(emitter, &["radiance"]).ok_or_else(|| invalid("area emitter without a radiance"))?;
+                Material::Light(self.scene.textures.add(Texture::Solid(color(radiance)?)))
+            },
+            None => match element.children.iter().find(|child| child.name == "bsdf" || child.name == "ref") {
+                Some(bsdf) => self.material(bsdf)?,
+                None => Material::Lambertian(self.scene.textures.add(Texture::Solid(Color::new(0.5, 0.5, 0.5)))),
+            },
+        };
+
+        let transform = self::transform(property(element, &["to_world", "toWorld"]))?;
+        let object = match text(element, "type")? {
+            "sphere" => {
+                let center = property(element, &["center"]).map_or(Ok(Point3::new(0.0, 0.0, 0.0)), vector)?;
+                let radius = property(element, &["radius"]).map_or(Ok(1.0), number)?;
+                let scale = transform_vector(&transform, Vec3::new(1.0, 0.0, 0.0)).length();
+                Hittable::Sphere(mat, transform_point(&transform, center), radius * scale)
+            },
+            "rectangle" => {
+                let (min, max) = bounds(&transform, &[(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (1.0, 1.0, 0.0), (-1.0, 1.0, 0.0)]);
+                let flat = |a : Float, b : Float| (a - b).abs() <= 1e-4 * (1.0 + a.abs());
+                if flat(min.z, max.z) {
+                    Hittable::XYRect(mat, min.x, max.x, min.y, max.y, min.z)
+                } else if flat(min.y, max.y) {
+                    Hittable::XZRect(mat, min.x, max.x, min.z, max.z, min.y)
+                } else if flat(min.x, max.x) {
+                    Hittable::YZRect(mat, min.y, max.y, min.z, max.z, min.x)
+                } else {
+                    return Err(invalid("only rectangles lined up with the axes are supported"));
+                }
+            },
+            "cube" => {
+                if !aligned(&transform) {
+                    return Err(invalid("only cubes lined up with the axes are supported"));
+                }
+                let corners = [(-1.0, -1.0, -1.0), (1.0, 1.0, 1.0)];
+                let (min, max) = bounds(&transform, &corners);
+                Hittable::Box(mat, min, max)
+            },
+            kind @ ("obj" | "ply") => {
+                let filename = text(property(element, &["filename"]).ok_or_else(|| invalid(&format!("{} shape without a filename", kind)))?, "value")?;
+                let triangles = mesh::load(&self.directory.join(filename)).map_err(|error| invalid(&error.to_string()))?;
+                //Mitsuba's texture coordinates start at the top left of the image, and its obj shapes flip the files' (which start at the bottom left,
+                //as the tracer's do) unless told not to
+                let flip = kind == "ply" || property(element, &["flip_tex_coords", "flipTexCoords"]).is_some_and(|flip| flip.attribute("value") == Some("false"));
+                for triangle in triangles {
+                    let points = triangle.map(|c| transform_point(&transform, Point3::new(c[0], c[1], c[2])));
+                    let uvs = triangle.map(|c| (c[3], if flip {1.0 - c[4]} else {c[4]}));
+                    self.scene.objects.push((Hittable::Triangle(mat, points, uvs), area.is_some()));
+                }
+                return Ok(());
+            },
+            other => return Err(invalid(&format!("unsupported shape type {}", other))),
+        };
+        self.scene.objects.push((object, area.is_some()));
+        Ok(())
+    }
+
+    fn emitter(&mut self, element : &Element) -> Result<()> {
+        let transform = self::transform(property(element, &["to_world", "toWorld"]))?;
+        let intensity = || property(element, &["intensity"]).map_or(Ok(Color::new(1.0, 1.0, 1.0)), color);
+        match text(element, "type")? {
+            "point" => {
+                let position = property(element, &["position"]).map_or(Ok(Point3::new(0.0, 0.0, 0.0)), vector)?;
+                self.scene.lights.push(Light::Point(transform_point(&transform, position), intensity()?, 2.0, None));
+            },
+            "spot" => {
+                let outer = property(element, &["cutoff_angle", "cutoffAngle"]).map_or(Ok(20.0), number)?;
+                let inner = property(element, &["beam_width", "beamWidth"]).map_or(Ok(outer * 0.75), number)?;
+                let position = transform_point(&transform, Point3::new(0.0, 0.0, 0.0));
+                let direction = transform_vector(&transform, Vec3::new(0.0, 0.0, 1.0));
+                self.scene.lights.push(Light::Spot(position, direction, inner, outer, intensity()?, None));
+            },
+            "directional" => {
+                let direction = property(element, &["direction"]).map_or(Ok(Vec3::new(0.0, 0.0, 1.0)), vector)?;
+                let irradiance = property(element, &["irradiance"]).map_or(Ok(Color::new(1.0, 1.0, 1.0)), color)?;
+                self.scene.lights.push(Light::Directional(transform_vector(&transform, direction), irradiance, 0.0));
+            },
+            "envmap" => {
+                let filename = text(property(element, &["filename"]).ok_or_else(|| invalid("envmap without a filename"))?, "value")?;
+                self.scene.settings.environment_map = Some(self.directory.join(filename).to_string_lossy().into_owned());
+                self.scene.settings.environment_intensity = Some(property(element, &["scale"]).map_or(Ok(1.0), number)?);
+            },
+            //A constant background becomes an emissive sphere surrounding the scene, found by rays that escape it
+            "constant" => {
+                let radiance = property(element, &["radiance"]).map_or(Ok(Color::new(1.0, 1.0, 1.0)), color)?;
+                let mat = Material::Light(self.scene.textures.add(Texture::Solid(radiance)));
+                self.scene.objects.push((Hittable::Sphere(mat, Point3::new(0.0, 0.0, 0.0), CONSTANT_RADIUS), false));
+            },
+            other => return Err(invalid(&format!("unsupported emitter type {}", other))),
+        }
+        Ok(())
+    }
+}
+
+///Returns the child giving a property, under any of the names it can have (Mitsuba 3 uses snake_case where 0.6 uses camelCase).
+fn property<'a>(element : &'a Element, names : &[&str]) -> Option<&'a Element> {
+    element.children.iter().find(|child| child.attribute("name").is_some_and(|name| names.contains(&name)))
+}
+
+fn text<'a>(element : &'a Element, attribute : &str) -> Result<&'a str> {
+    element.attribute(attribute).ok_or_else(|| invalid(&format!("{} without a {}", element.name, attribute)))
+}
+
+///Reads the numbers of a value, which can be separated by commas or spaces.
+fn numbers(value : &str) -> Result<Vec<Float>> {
+    value.split(|c : char| c == ',' || c.is_whitespace()).filter(|part| !part.is_empty())
+        .map(|part| part.parse().map_err(|_| invalid(&format!("malformed number {}", part)))).collect()
+}
+
+fn number(element : &Element) -> Result<Float> {
+    match numbers(text(element, "value")?)?[..] {
+        [x] => Ok(x),
+        _ => Err(invalid("expected a single number")),
+    }
+}
+
+///Reads a point or vector, from its value or from its x, y and z attributes.
+fn vector(element : &Element) -> Result<Vec3> {
+    if let Some(value) = element.attribute("value") {
+        return match numbers(value)?[..] {
+            [x, y, z] => Ok(Vec3::new(x, y, z)),
+            _ => Err(invalid("expected 3 numbers")),
+        };
+    }
+    let axis = |name| element.attribute(name).map_or(Ok(0.0), |value| value.trim().parse().map_err(|_| invalid(&format!("malformed number {}", value))));
+    Ok(Vec3::new(axis("x")?, axis("y")?, axis("z")?))
+}
+
+///Reads an rgb, spectrum or float color, where a single value is a shade of grey.
+fn color(element : &Element) -> Result<Color> {
+    if !matches!(element.name.as_str(), "rgb" | "srgb" | "spectrum" | "float") {
+        return Err(invalid(&format!("expected a color, not {}", element.name)));
+    }
+    match numbers(text(element, "value")?)?[..] {
+        [x] => Ok(Color::new(x, x, x)),
+        [r, g, b] => Ok(Color::new(r, g, b)),
+        _ => Err(invalid("only colors with 1 or 3 values are supported")),
+    }
+}
+
+///Reads an index of refraction, given as a number or a material's name.
+fn ior(element : Option<&Element>, default : Float) -> Result<Float> {
+    let Some(element) = element else {
+        return Ok(default);
+    };
+    let value = text(element, "value")?;
+    match IORS.iter().find(|(name, _)| *name == value) {
+        Some((_, ior)) => Ok(*ior),
+        None => number(element),
+    }
+}
+
+///Builds a transform from its operations, each applied after the ones before it.
+fn transform(element : Option<&Element>) -> Result<Matrix> {
+    let mut matrix = IDENTITY;
+    for operation in element.map_or(&[][..], |element| &element.children) {
+        let step = match operation.name.as_str() {
+            "translate" => {
+                let offset = vector(operation)?;
+                [[1.0, 0.0, 0.0, offset.x], [0.0, 1.0, 0.0, offset.y], [0.0, 0.0, 1.0, offset.z], [0.0, 0.0, 0.0, 1.0]]
+            },
+            "scale" => {
+                let scale = match operation.attribute("value").map(numbers).transpose()?.as_deref() {
+                    Some([s]) => Vec3::new(*s, *s, *s),
+                    Some(_) => vector(operation)?,
+                    None => {
+                        let axis = |name| operation.attribute(name).map_or(Ok(1.0), |value| value.trim().parse().map_err(|_| invalid(&format!("malformed number {}", value))));
+                        Vec3::new(axis("x")?, axis("y")?, axis("z")?)
+                    },
+                };
+                [[scale.x, 0.0, 0.0, 0.0], [0.0, scale.y, 0.0, 0.0], [0.0, 0.0, scale.z, 0.0], [0.0, 0.0, 0.0, 1.0]]
+            },
+            "rotate" => {
+                let axis = vector(operation)?.unit_vector();
+                let angle = text(operation, "angle")?.trim().parse::<Float>().map_err(|_| invalid("malformed angle"))?.to_radians();
+                rotation(axis, angle)
+            },
+            "matrix" => match numbers(text(operation, "value")?)?[..] {
+                [a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p] => [[a, b, c, d], [e, f, g, h], [i, j, k, l], [m, n, o, p]],
+                [a, b, c, d, e, f, g, h, i] => [[a, b, c, 0.0], [d, e, f, 0.0], [g, h, i, 0.0], [0.0, 0.0, 0.0, 1.0]],
+                _ => return Err(invalid("a matrix needs 9 or 16 numbers")),
+            },
+            "lookat" => {
+                let point = |name| numbers(text(operation, name)?).and_then(|values| match values[..] {
+                    [x, y, z] => Ok(Vec3::new(x, y, z)),
+                    _ => Err(invalid("expected 3 numbers")),
+                });
+                let origin = point("origin")?;
+                let direction = (point("target")? - origin).unit_vector();
+                let up = operation.attribute("up").map_or(Ok(Vec3::new(0.0, 1.0, 0.0)), |_| point("up"))?;
+                let left = cross(up, direction).unit_vector();
+                let up = cross(direction, left);
+                [
+                    [left.x, up.x, direction.x, origin.x],
+                    [left.y, up.y, direction.y, origin.y],
+                    [left.z, up.z, direction.z, origin.z],
+                    [0.0, 0.0, 0.0, 1.0],
+                ]
+            },
+            other => return Err(invalid(&format!("unsupported transform {}", other))),
+        };
+        matrix = multiply(&step, &matrix);
+    }
+    Ok(matrix)
+}
+
+///Rotation by an angle in radians about a unit axis.
+pub fn rotation(axis : Vec3, angle : Float) -> Matrix {
+    let (sin, cos) = angle.sin_cos();
+    let (x, y, z) = (axis.x, axis.y, axis.z);
+    let t = 1.0 - cos;
+    [
+        [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+        [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+        [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn multiply(a : &Matrix, b : &Matrix) -> Matrix {
+    let mut product = [[0.0 ; 4] ; 4];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+pub fn transform_point(matrix : &Matrix, p : Point3) -> Point3 {
+    transform_vector(matrix, p) + Vec3::new(matrix[0][3], matrix[1][3], matrix[2][3])
+}
+
+pub fn transform_vector(matrix : &Matrix, v : Vec3) -> Vec3 {
+    let row = |r : [Float ; 4]| dot(Vec3::new(r[0], r[1], r[2]), v);
+    Vec3::new(row(matrix[0]), row(matrix[1]), row(matrix[2]))
+}
+
+///Returns the smallest and largest coordinates of the given points once transformed.
+pub fn bounds(matrix : &Matrix, points : &[(Float, Float, Float)]) -> (Point3, Point3) {
+    let points = points.iter().map(|(x, y, z)| transform_point(matrix, Point3::new(*x, *y, *z))).collect::<Vec<_>>();
+    let fold = |f : fn(Float, Float) -> Float| points.iter().skip(1).fold(points[0], |a, b| Vec3::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z)));
+    (fold(Float::min), fold(Float::max))
+}
+
+///Returns whether a transform keeps boxes lined up with the axes: every axis must map onto an axis.
+pub fn aligned(matrix : &Matrix) -> bool {
+    (0..3).all(|column| (0..3).filter(|row| matrix[*row][column].abs() > 1e-6).count() == 1)
+}
+
+fn invalid(message : &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid Mitsuba scene: {}", message))
+}
@@ -0,0 +1,247 @@
+use std::cell::RefCell;
+use crate::vec3::consts::PI;
+use std::rc::Rc;
+use rayon::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::vec3::{Color, set_sampler, sample_with, seed_stream, random_float, Float};
+use crate::sampler::Sampler;
+use crate::integrator::{Integrator, Clamping, first_hit};
+use crate::environment::{Distribution1D, luminance};
+use crate::camera::Camera;
+use crate::scene::Scene;
+
+///Number of camera rays traced through every pixel to find how much of it the scene covers, for transparent images.
+const COVERAGE_SAMPLES : u32 = 16;
+
+///Settings for primary sample space Metropolis light transport.
+///
+/// Rather than tracing independent paths, each Markov chain keeps a current path and proposes small changes to the random numbers
+/// that generated it, so once a path finds light through a difficult route (such as a keyhole) nearby paths are explored too.
+/// Occasionally a completely new path is proposed instead (a large step), so that the whole image is still covered.
+#[derive(Debug, Clone, Copy)]
+pub struct MltSettings {
+    pub bootstrap_samples : usize,
+    pub chains : usize,
+    pub mutations_per_pixel : usize,
+    pub large_step_probability : Float,
+    pub sigma : Float,
+    pub max_depth : i32,
+}
+
+///One coordinate of a path in primary sample space, along with the state needed to undo a rejected mutation.
+#[derive(Debug, Clone, Copy)]
+struct PrimarySample {
+    value : Float,
+    last_modification : u64,
+    value_backup : Float,
+    modify_backup : u64,
+}
+
+///Supplies the random numbers used to trace a path, mutating them lazily as they are requested.
+struct MltSampler {
+    samples : Vec<PrimarySample>,
+    index : usize,
+    current_iteration : u64,
+    large_step : bool,
+    last_large_step_iteration : u64,
+    sigma : Float,
+    large_step_probability : Float,
+    rng : StdRng,
+}
+
+impl MltSampler {
+
+    ///Creates a sampler whose first path is generated entirely from the random numbers seeded by seed.
+    fn new(seed : u64, sigma : Float, large_step_probability : Float) -> MltSampler {
+        MltSampler {
+            samples : vec![],
+            index : 0,
+            current_iteration : 0,
+            large_step : true,
+            last_large_step_iteration : 0,
+            sigma,
+            large_step_probability,
+            rng : StdRng::seed_from_u64(seed),
+        }
+    }
+
+    ///Begins proposing a new path, deciding whether it will be a small mutation or a large step.
+    fn start_iteration(&mut self) {
+        self.current_iteration += 1;
+        self.large_step = self.rng.gen::<Float>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    ///Returns the next coordinate of the proposed path.
+    fn next(&mut self) -> Float {
+        //Coordinates the path has never used before start out uniformly random
+        if self.index >= self.samples.len() {
+            let value = self.rng.gen::<Float>();
+            let iteration = self.current_iteration;
+            self.samples.push(PrimarySample {value, last_modification : iteration, value_backup : value, modify_backup : iteration});
+            self.index += 1;
+            return value;
+        }
+        let sample = &mut self.samples[self.index];
+        self.index += 1;
+
+        //Coordinates not used since the last large step still need to catch up with it
+        if sample.last_modification < self.last_large_step_iteration {
+            sample.value = self.rng.gen::<Float>();
+            sample.last_modification = self.last_large_step_iteration;
+        }
+
+        sample.value_backup = sample.value;
+        sample.modify_backup = sample.last_modification;
+        if self.large_step {
+            sample.value = self.rng.gen::<Float>();
+        } else {
+            //Apply every small mutation this coordinate missed at once, as a single wider normal distribution
+            let missed = (self.current_iteration - sample.last_modification) as Float;
+            let u1 = 1.0 - self.rng.gen::<Float>();
+            let u2 = self.rng.gen::<Float>();
+            let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+            sample.value += normal * self.sigma * missed.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.last_modification = self.current_iteration;
+        sample.value
+    }
+
+    ///Keeps the proposed path.
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.current_iteration;
+        }
+    }
+
+    ///Discards the proposed path, restoring the coordinates of the current one.
+    fn reject(&mut self) {
+        for sample in self.samples.iter_mut() {
+            if sample.last_modification == self.current_iteration {
+                sample.value = sample.value_backup;
+                sample.last_modification = sample.modify_backup;
+            }
+        }
+        self.current_iteration -= 1;
+    }
+}
+
+impl Sampler for Rc<RefCell<MltSampler>> {
+    fn get_1d(&mut self) -> Float {
+        self.borrow_mut().next()
+    }
+}
+
+///Traces the path described by the sampler's coordinates: the first two pick the point on the image, and the rest drive the path tracer.
+///
+/// Returns the image coordinates and the light carried by the path, which is none if the path escapes from the camera and transparent is set.
+fn trace_path(sampler : &Rc<RefCell<MltSampler>>, scene : &Scene, cam : &dyn Camera, max_depth : i32, transparent : bool) -> (Float, Float, Color) {
+    set_sampler(Some(Box::new(sampler.clone())));
+
+    let u = sampler.borrow_mut().next();
+    let v = sampler.borrow_mut().next();
+    let integrator = Integrator::PathTracer(max_depth, Clamping::None);
+    let r = sample_with(|sampler| cam.get_ray(u, v, sampler));
+    let radiance = if transparent {integrator.radiance_alpha(r, scene).0} else {integrator.radiance(r, scene)};
+
+    set_sampler(None);
+    (u, v, radiance)
+}
+
+///Renders the scene with primary sample space Metropolis light transport, returning the radiance and opacity of every pixel.
+///
+/// Pixels are stored row by row, starting from the bottom row of the image. With transparent, paths that escape from the camera
+/// carry no light, so radiance is premultiplied by the opacity, which is the fraction of the pixel the scene covers; otherwise every pixel is opaque.
+pub fn render_mlt(scene : &Scene, cam : &dyn Camera, image_width : u32, image_height : u32, settings : MltSettings, transparent : bool) -> Vec<(Color, Float)> {
+    let pixel_count = (image_width * image_height) as usize;
+    let alpha = if transparent {coverage(scene, cam, image_width, image_height)} else {vec![1.0 ; pixel_count]};
+
+    //Bootstrap: estimate the overall image brightness, and find good starting paths for the chains
+    let weights = (0..settings.bootstrap_samples).into_par_iter().map(|seed| {
+        let sampler = Rc::new(RefCell::new(MltSampler::new(seed as u64, settings.sigma, settings.large_step_probability)));
+        luminance(trace_path(&sampler, scene, cam, settings.max_depth, transparent).2).max(0.0)
+    }).collect::<Vec<Float>>();
+    let brightness = weights.iter().sum::<Float>() / settings.bootstrap_samples.max(1) as Float;
+    if brightness <= 0.0 || !brightness.is_finite() {
+        return alpha.into_iter().map(|a| (Color::new(0.0, 0.0, 0.0), a)).collect();
+    }
+    let bootstrap = Distribution1D::new(weights);
+
+    let total_mutations = settings.mutations_per_pixel * pixel_count;
+    let mutations_per_chain = total_mutations / settings.chains.max(1);
+
+    //Run the chains in one group per thread, each group splatting every path it visits onto its own copy of the image
+    let groups = rayon::current_num_threads().min(settings.chains).max(1);
+    let film = (0..groups).into_par_iter().map(|group| {
+        let mut film = vec![Color::new(0.0, 0.0, 0.0) ; pixel_count];
+        for chain in (group..settings.chains).step_by(groups) {
+            run_chain(chain as u64, &bootstrap, mutations_per_chain, scene, cam, image_width, image_height, settings, transparent, &mut film);
+        }
+        film
+    }).collect::<Vec<Vec<Color>>>().into_iter().reduce(|mut a, b| {
+        //Summed in a fixed order, so that the result doesn't depend on thread scheduling
+        for (x, y) in a.iter_mut().zip(b) {
+            *x += y;
+        }
+        a
+    }).unwrap_or_else(|| vec![Color::new(0.0, 0.0, 0.0) ; pixel_count]);
+
+    let scale = brightness * pixel_count as Float / (mutations_per_chain * settings.chains.max(1)) as Float;
+    film.into_iter().zip(alpha).map(|(c, a)| (c * scale, a)).collect()
+}
+
+///Returns the fraction of every pixel the scene covers, rather than the background, from COVERAGE_SAMPLES camera rays through random points of it.
+fn coverage(scene : &Scene, cam : &dyn Camera, image_width : u32, image_height : u32) -> Vec<Float> {
+    (0..(image_width * image_height) as usize).into_par_iter().map(|index| {
+        seed_stream(&[index as u64]);
+        let i = index as u32 % image_width;
+        let j = index as u32 / image_width;
+        let hits = (0..COVERAGE_SAMPLES).filter(|_| {
+            let u = (i as Float + random_float()) / image_width as Float;
+            let v = (j as Float + random_float()) / image_height as Float;
+            first_hit(sample_with(|sampler| cam.get_ray(u, v, sampler)), scene).is_some()
+        }).count();
+        hits as Float / COVERAGE_SAMPLES as Float
+    }).collect()
+}
+
+///Runs a single Markov chain, starting from a bootstrap path, and splats every path it visits onto film.
+#[allow(clippy::too_many_arguments)]
+fn run_chain(chain : u64, bootstrap : &Distribution1D, mutations : usize, scene : &Scene, cam : &dyn Camera, image_width : u32, image_height : u32, settings : MltSettings, transparent : bool, film : &mut [Color]) {
+    let mut splat = |u : Float, v : Float, c : Color| {
+        let i = ((u * image_width as Float) as usize).min(image_width as usize - 1);
+        let j = ((v * image_height as Float) as usize).min(image_height as usize - 1);
+        film[j * image_width as usize + i] += c;
+    };
+
+    let mut rng = StdRng::seed_from_u64(chain);
+    let (_x, _pdf, seed) = bootstrap.sample(rng.gen::<Float>());
+    let sampler = Rc::new(RefCell::new(MltSampler::new(seed as u64, settings.sigma, settings.large_step_probability)));
+    let (mut u, mut v, mut current) = trace_path(&sampler, scene, cam, settings.max_depth, transparent);
+
+    for _mutation in 0..mutations {
+        sampler.borrow_mut().start_iteration();
+        let (proposed_u, proposed_v, proposed) = trace_path(&sampler, scene, cam, settings.max_depth, transparent);
+
+        let current_lum = luminance(current);
+        let proposed_lum = luminance(proposed);
+        let accept = if current_lum > 0.0 {(proposed_lum / current_lum).clamp(0.0, 1.0)} else {1.0};
+
+        //Splat both paths, weighted by how likely each is to be the chain's next state
+        if accept > 0.0 && proposed_lum > 0.0 {
+            splat(proposed_u, proposed_v, proposed * (accept / proposed_lum));
+        }
+        if current_lum > 0.0 {
+            splat(u, v, current * ((1.0 - accept) / current_lum));
+        }
+
+        if rng.gen::<Float>() < accept {
+            (u, v, current) = (proposed_u, proposed_v, proposed);
+            sampler.borrow_mut().accept();
+        } else {
+            sampler.borrow_mut().reject();
+        }
+    }
+}
@@ -0,0 +1,488 @@
+/*
+Module to store the writers for high dynamic range image formats (OpenEXR, Radiance RGBE and PFM), which keep the rendered radiance rather than 8 bit display colors,
+the streaming writer for binary PPM images, which takes rows as they are rendered, and the reader turning images back into linear radiance.
+*/
+
+use std::path::Path;
+use std::fs::{File, rename, read};
+use std::io::{BufReader, BufWriter, Write, Result, Error, ErrorKind, stdout};
+use std::path::PathBuf;
+use exr::prelude::{Image, Layer, LayerAttributes, Encoding, SpecificChannels, AnyChannels, AnyChannel, FlatSamples, WritableImage, IntoSample,
+    Compression, SmallVec, Vec2, Text, AttributeValue, f16};
+use image::{open, DynamicImage, ImageResult, Rgb32FImage, Rgb};
+use image::codecs::hdr::HdrDecoder;
+use crate::vec3::{Color, Float};
+use crate::color::srgb_to_linear;
+
+///Floats as the formats store them, 32 bits even when the math is done in f64.
+type Stored = f32;
+
+///Channels of a deep image, in the alphabetical order the format requires.
+const DEEP_CHANNELS : [&str ; 6] = ["A", "B", "G", "R", "Z", "ZBack"];
+
+///Determines how many bits each channel of an OpenEXR image uses. Variants include
+///
+/// Half: 16 bit floats, with about 3 significant digits. Enough for almost any grading, at half the size.
+///
+/// Full: 32 bit floats.
+#[derive(Debug, Clone, Copy)]
+pub enum ExrPrecision {
+    Half,
+    Full,
+}
+
+///Determines how the pixels of an OpenEXR image are compressed. All are lossless. Variants include
+///
+/// Uncompressed: largest files, quickest to write.
+///
+/// Rle: run length encoding, which only helps with large flat areas.
+///
+/// Zip: zlib compression of 16 scan lines at a time. A good default.
+///
+/// Piz: wavelet compression, usually the smallest for noisy renders.
+#[derive(Debug, Clone, Copy)]
+pub enum ExrCompression {
+    Uncompressed,
+    Rle,
+    Zip,
+    Piz,
+}
+
+///Settings for images saved as OpenEXR (.exr) files.
+#[derive(Debug, Clone, Copy)]
+pub struct ExrSettings {
+    pub precision : ExrPrecision,
+    pub compression : ExrCompression,
+}
+
+impl ExrSettings {
+
+    ///Creates settings writing channels with the given precision and compression.
+    pub fn new(precision : ExrPrecision, compression : ExrCompression) -> ExrSettings {
+        ExrSettings {precision, compression}
+    }
+}
+
+///Extra image written into an OpenEXR file, such as a render pass. Its channels are named after the layer ("albedo.R" and so on),
+///
+/// or just by channel if the layer's name is empty. Values hold every channel of a pixel in turn, row by row from the top left.
+#[derive(Debug, Clone)]
+pub struct ExrLayer {
+    pub name : &'static str,
+    pub channels : &'static [&'static str],
+    pub values : Vec<Float>,
+    pub precision : ExrPrecision,
+}
+
+///Writes an OpenEXR image, given its pixels' linear radiance and opacity row by row from the top left, any extra layers, and the metadata to store as header attributes.
+///
+/// With alpha, the image gets an A channel and its colors are premultiplied by it, as compositing software expects.
+#[allow(clippy::too_many_arguments)]
+pub fn write_exr(path : &Path, pixels : &[(Color, Float)], width : usize, height : usize, alpha : bool, settings : ExrSettings, layers : &[ExrLayer],
+    metadata : &[(&str, String)]) -> exr::error::UnitResult {
+    if !layers.is_empty() {
+        let channels : &'static [&'static str] = if alpha {&["R", "G", "B", "A"]} else {&["R", "G", "B"]};
+        let values = pixels.iter().flat_map(|(color, a)| [color.x, color.y, color.z, *a].into_iter().take(channels.len())).collect();
+        let beauty = ExrLayer {name : "", channels, values, precision : settings.precision};
+        return write_layers(path, width, height, settings, std::iter::once(&beauty).chain(layers), metadata);
+    }
+
+    match settings.precision {
+        ExrPrecision::Half => write_channels(path, pixels, width, height, alpha, encoding(settings), attributes(metadata), |x| f16::from_f32(x as Stored)),
+        ExrPrecision::Full => write_channels(path, pixels, width, height, alpha, encoding(settings), attributes(metadata), |x| x as Stored),
+    }
+}
+
+///Writes an OpenEXR image holding only the given layers, such as a render pass on its own. Each layer keeps its own precision.
+pub fn write_layers<'a>(path : &Path, width : usize, height : usize, settings : ExrSettings, layers : impl IntoIterator<Item = &'a ExrLayer>,
+    metadata : &[(&str, String)]) -> exr::error::UnitResult {
+    let mut channels = SmallVec::new();
+    for layer in layers {
+        let count = layer.channels.len();
+        for (c, channel) in layer.channels.iter().enumerate() {
+            let name = if layer.name.is_empty() {channel.to_string()} else {format!("{}.{}", layer.name, channel)};
+            let values = layer.values.iter().skip(c).step_by(count).copied();
+            let samples = match layer.precision {
+                ExrPrecision::Half => FlatSamples::F16(values.map(|x| f16::from_f32(x as Stored)).collect()),
+                ExrPrecision::Full => FlatSamples::F32(values.map(|x| x as Stored).collect()),
+            };
+            channels.push(AnyChannel::new(name.as_str(), samples));
+        }
+    }
+    let layer = Layer::new((width, height), attributes(metadata), encoding(settings), AnyChannels::sort(channels));
+    Image::from_layer(layer).write().to_file(path)
+}
+
+///Sample of a deep image: a surface seen through a pixel, spanning the depths from front to back, with its color premultiplied by its opacity.
+#[derive(Debug, Clone, Copy)]
+pub struct DeepSample {
+    pub front : Float,
+    pub back : Float,
+    pub color : Color,
+    pub alpha : Float,
+}
+
+impl DeepSample {
+
+    ///Returns the values of the sample's channels, in the order of DEEP_CHANNELS.
+    fn channels(&self) -> [Stored ; 6] {
+        [self.alpha, self.color.z, self.color.y, self.color.x, self.front, self.back].map(|x| x as Stored)
+    }
+}
+
+///Writes a deep OpenEXR image, given each pixel's samples row by row from the top left, nearest first.
+///
+/// Every sample gets A, R, G and B channels, with the colors premultiplied by A, and Z and ZBack channels for its depths, all as uncompressed 32 bit floats,
+/// so that compositing software can merge the image with volumetrics and other renders by depth. Written by hand, as the exr crate can't write deep images.
+pub fn write_deep(path : &Path, pixels : &[Vec<DeepSample>], width : usize, height : usize, metadata : &[(&str, String)]) -> Result<()> {
+    let mut chlist = vec![];
+    for name in DEEP_CHANNELS {
+        chlist.extend(name.as_bytes());
+        //Null terminator, 32 bit float type, linear flag and padding, and sampling of 1 in x and y
+        chlist.push(0);
+        chlist.extend(2i32.to_le_bytes());
+        chlist.extend([0 ; 4]);
+        chlist.extend(1i32.to_le_bytes());
+        chlist.extend(1i32.to_le_bytes());
+    }
+    chlist.push(0);
+    let window : Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|x| x.to_le_bytes()).collect();
+    let max_samples = pixels.iter().map(Vec::len).max().unwrap_or(0) as i32;
+
+    let mut header = vec![];
+    attribute(&mut header, "channels", "chlist", &chlist);
+    attribute(&mut header, "chunkCount", "int", &(height as i32).to_le_bytes());
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "maxSamplesPerPixel", "int", &max_samples.to_le_bytes());
+    attribute(&mut header, "name", "string", b"deep");
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0 ; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "type", "string", b"deepscanline");
+    attribute(&mut header, "version", "int", &1i32.to_le_bytes());
+    for (key, value) in metadata {
+        attribute(&mut header, key, "string", value.as_bytes());
+    }
+    header.push(0);
+
+    //Magic number, then version 2 with the flag for deep data
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&20000630i32.to_le_bytes())?;
+    file.write_all(&(2i32 | 0x800).to_le_bytes())?;
+    file.write_all(&header)?;
+
+    //Offset of each scan line's chunk: its y, the sizes of its tables, its pixel offset table and its samples
+    let rows : Vec<&[Vec<DeepSample>]> = pixels.chunks(width).collect();
+    let data_size = |row : &[Vec<DeepSample>]| (row.iter().map(Vec::len).sum::<usize>() * DEEP_CHANNELS.len() * 4) as u64;
+    let mut offset = (8 + header.len() + 8 * height) as u64;
+    for row in &rows {
+        file.write_all(&offset.to_le_bytes())?;
+        offset += 28 + 4 * width as u64 + data_size(row);
+    }
+
+    for (y, row) in rows.iter().enumerate() {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(4 * width as u64).to_le_bytes())?;
+        file.write_all(&data_size(row).to_le_bytes())?;
+        file.write_all(&data_size(row).to_le_bytes())?;
+
+        //Each pixel's offset table entry counts the samples up to and including its own
+        let mut total = 0;
+        for pixel in row.iter() {
+            total += pixel.len() as i32;
+            file.write_all(&total.to_le_bytes())?;
+        }
+        for c in 0..DEEP_CHANNELS.len() {
+            for sample in row.iter().flatten() {
+                file.write_all(&sample.channels()[c].to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+///Writes a Radiance RGBE (.hdr) image, given its pixels' linear radiance row by row from the top left.
+///
+/// Each pixel shares one exponent between its channels, so dim channels next to a bright one lose precision. Opacity is dropped.
+/// Scan lines are run length encoded, except in images too narrow or too wide for the format's encoding to apply. Metadata is stored as comments in the header.
+pub fn write_hdr(path : &Path, pixels : &[(Color, Float)], width : usize, height : usize, metadata : &[(&str, String)]) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "#?RADIANCE")?;
+    for (key, value) in metadata {
+        writeln!(file, "# {}: {}", key, value)?;
+    }
+    write!(file, "FORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+    for row in pixels.chunks(width) {
+        let encoded : Vec<[u8 ; 4]> = row.iter().map(|(color, _)| rgbe(*color)).collect();
+        if !(8..=0x7fff).contains(&width) {
+            for pixel in encoded {
+                file.write_all(&pixel)?;
+            }
+            continue;
+        }
+
+        //Each scan line starts with a marker and its width, followed by each component of every pixel in turn
+        file.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+        for component in 0..4 {
+            let bytes : Vec<u8> = encoded.iter().map(|pixel| pixel[component]).collect();
+            write_runs(&mut file, &bytes)?;
+        }
+    }
+    file.flush()
+}
+
+///Writes a PNG image with 8 or 16 bits per channel, storing the metadata as text chunks.
+pub fn write_png(path : &Path, img : &DynamicImage, metadata : &[(&str, String)]) -> std::result::Result<(), png::EncodingError> {
+    let color = img.color();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), img.width(), img.height());
+    encoder.set_color(if color.has_alpha() {png::ColorType::Rgba} else {png::ColorType::Rgb});
+    let sixteen = color.bytes_per_pixel() / color.channel_count() == 2;
+    encoder.set_depth(if sixteen {png::BitDepth::Sixteen} else {png::BitDepth::Eight});
+    for (key, value) in metadata {
+        encoder.add_text_chunk(key.to_string(), value.clone())?;
+    }
+
+    //PNG stores 16 bit values big endian
+    let mut writer = encoder.write_header()?;
+    if sixteen {
+        let bytes : Vec<u8> = img.as_bytes().chunks(2).flat_map(|pair| u16::from_ne_bytes([pair[0], pair[1]]).to_be_bytes()).collect();
+        writer.write_image_data(&bytes)
+    } else {
+        writer.write_image_data(img.as_bytes())
+    }
+}
+
+///Writes a Portable Float Map (.pfm) image, given its pixels' linear radiance row by row from the top left.
+///
+/// Stores every channel as a 32 bit float, with rows from the bottom up as the format requires. Opacity is dropped.
+pub fn write_pfm(path : &Path, pixels : &[(Color, Float)], width : usize, height : usize) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    //A negative scale marks the floats as little endian
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in pixels.chunks(width).rev() {
+        for (color, _) in row {
+            for channel in [color.x, color.y, color.z] {
+                file.write_all(&(channel as Stored).to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+///Reads an image as linear radiance, row by row from the top. Floating point formats (OpenEXR, Radiance .hdr and PFM) hold it already,
+///
+/// and images in others, such as PNG and JPEG, are decoded from sRGB. Radiance .hdr images are read in full here,
+/// since image::open() clips them to 8 bits, and PFM images, which it doesn't read at all.
+pub fn read_radiance(path : &Path) -> ImageResult<Rgb32FImage> {
+    let extension = path.extension().map_or(String::new(), |extension| extension.to_string_lossy().to_lowercase());
+    if extension == "pfm" {
+        return Ok(read_pfm(path)?);
+    }
+    if extension == "hdr" {
+        let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+        let (width, height) = (decoder.metadata().width, decoder.metadata().height);
+        let pixels = decoder.read_image_hdr()?;
+        return Ok(Rgb32FImage::from_fn(width, height, |i, j| pixels[(j * width + i) as usize]));
+    }
+
+    let img = open(path)?;
+    let linear = matches!(img, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+    let mut img = img.into_rgb32f();
+    if !linear {
+        for x in img.iter_mut() {
+            *x = srgb_to_linear(*x as Float) as Stored;
+        }
+    }
+    Ok(img)
+}
+
+///Reads a PFM image, in color (PF, as write_pfm() writes) or grayscale (Pf) and in either byte order, into rows from the top.
+fn read_pfm(path : &Path) -> Result<Rgb32FImage> {
+    let bytes = read(path)?;
+    let invalid = |message : &str| Error::new(ErrorKind::InvalidData, format!("Invalid PFM image: {}", message));
+
+    //The header is four fields separated by whitespace, with a single whitespace character between it and the floats
+    let mut fields = Vec::with_capacity(4);
+    let mut position = 0;
+    while fields.len() < 4 {
+        while bytes.get(position).is_some_and(u8::is_ascii_whitespace) {
+            position += 1;
+        }
+        let start = position;
+        while bytes.get(position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("its header is cut short"));
+        }
+        fields.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+    }
+    let channels = match fields[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("it doesn't start with PF or Pf")),
+    };
+    let size = |field : &str| field.parse::<u32>().map_err(|_| invalid("its size isn't a whole number"));
+    let (width, height) = (size(&fields[1])?, size(&fields[2])?);
+    let scale : Stored = fields[3].parse().map_err(|_| invalid("its scale isn't a number"))?;
+
+    let count = width as usize * height as usize * channels;
+    let data = bytes.get(position + 1..).unwrap_or_default();
+    if data.len() < count * 4 {
+        return Err(invalid("it holds fewer pixels than its size"));
+    }
+    //A negative scale marks the floats as little endian
+    let floats : Vec<Stored> = data.chunks_exact(4).take(count).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if scale < 0.0 {Stored::from_le_bytes(b)} else {Stored::from_be_bytes(b)}
+    }).collect();
+    //Rows are stored from the bottom
+    Ok(Rgb32FImage::from_fn(width, height, |i, j| {
+        let start = ((height - 1 - j) as usize * width as usize + i as usize) * channels;
+        let pixel = &floats[start..start + channels];
+        Rgb([pixel[0], pixel[channels / 2], pixel[channels - 1]])
+    }))
+}
+
+///Binary Portable Pixmap (P6) image being written a few rows at a time, from the top down, so that the whole image never has to be held in memory.
+///
+/// Channels have 8 bits; opacity and metadata are dropped, as the format can't hold them. Images are written to a temporary file next to the path
+/// and renamed once every row is in, or streamed to standard output if the path is "-", as for piping into other programs.
+pub struct PpmWriter {
+    writer : BufWriter<Box<dyn Write>>,
+    width : usize,
+    rows_left : usize,
+    partial : Option<(PathBuf, PathBuf)>,
+}
+
+impl PpmWriter {
+
+    ///Starts an image of the given size, writing its header.
+    pub fn create(path : &Path, width : usize, height : usize) -> Result<PpmWriter> {
+        let (output, partial) : (Box<dyn Write>, _) = if path == Path::new("-") {
+            (Box::new(stdout()), None)
+        } else {
+            let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("ppm");
+            let partial = path.with_extension(format!("partial.{}", extension));
+            (Box::new(File::create(&partial)?), Some((partial, path.to_path_buf())))
+        };
+        let mut writer = BufWriter::new(output);
+        write!(writer, "P6\n{} {}\n255\n", width, height)?;
+        Ok(PpmWriter {writer, width, rows_left : height, partial})
+    }
+
+    ///Writes one or more whole rows of pixels, left to right.
+    pub fn write_rows(&mut self, pixels : &[(u8, u8, u8)]) -> Result<()> {
+        let rows = pixels.len() / self.width.max(1);
+        if rows * self.width != pixels.len() || rows > self.rows_left {
+            return Err(Error::new(ErrorKind::InvalidInput, "PPM rows don't fit the image"));
+        }
+        for (r, g, b) in pixels {
+            self.writer.write_all(&[*r, *g, *b])?;
+        }
+        self.rows_left -= rows;
+        Ok(())
+    }
+
+    ///Finishes the image, which must have all of its rows, moving it into place.
+    pub fn finish(mut self) -> Result<()> {
+        if self.rows_left > 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("PPM image is missing {} rows", self.rows_left)));
+        }
+        self.writer.flush()?;
+        drop(self.writer);
+        match self.partial {
+            Some((partial, path)) => rename(partial, path),
+            None => Ok(()),
+        }
+    }
+}
+
+///Encodes a color as RGBE: three 8 bit mantissas sharing the exponent of the brightest channel.
+fn rgbe(color : Color) -> [u8 ; 4] {
+    let brightest = color.x.max(color.y).max(color.z);
+    if brightest < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let (mantissa, exponent) = libm::frexpf(brightest as Stored);
+    let scale = mantissa as Float * 256.0 / brightest;
+    [(color.x.max(0.0) * scale) as u8, (color.y.max(0.0) * scale) as u8, (color.z.max(0.0) * scale) as u8, (exponent + 128) as u8]
+}
+
+///Run length encodes one component of a scan line: runs of 4 or more equal bytes are stored as a count above 128 and the byte,
+///
+/// and the bytes between them as a count of up to 128 and the bytes themselves.
+fn write_runs(file : &mut impl Write, bytes : &[u8]) -> Result<()> {
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let run = bytes[i..].iter().take(127).take_while(|b| **b == bytes[i]).count();
+        if run >= 4 {
+            write_literals(file, &bytes[literal_start..i])?;
+            file.write_all(&[128 + run as u8, bytes[i]])?;
+            literal_start = i + run;
+        }
+        i += run;
+    }
+    write_literals(file, &bytes[literal_start..])
+}
+
+fn write_literals(file : &mut impl Write, bytes : &[u8]) -> Result<()> {
+    for chunk in bytes.chunks(128) {
+        file.write_all(&[chunk.len() as u8])?;
+        file.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+///Appends an OpenEXR header attribute: its name and type, null terminated, then the size of its value and the value.
+fn attribute(header : &mut Vec<u8>, name : &str, kind : &str, value : &[u8]) {
+    for text in [name, kind] {
+        header.extend(text.as_bytes());
+        header.push(0);
+    }
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+///Returns the attributes of an OpenEXR layer holding the given metadata, as text attributes.
+fn attributes(metadata : &[(&str, String)]) -> LayerAttributes {
+    let mut attributes = LayerAttributes {software_name : Some(Text::from("RustTracer")), ..LayerAttributes::default()};
+    for (key, value) in metadata {
+        attributes.other.insert(Text::from(*key), AttributeValue::Text(Text::from(value.as_str())));
+    }
+    attributes
+}
+
+fn encoding(settings : ExrSettings) -> Encoding {
+    Encoding {
+        compression : match settings.compression {
+            ExrCompression::Uncompressed => Compression::Uncompressed,
+            ExrCompression::Rle => Compression::RLE,
+            ExrCompression::Zip => Compression::ZIP16,
+            ExrCompression::Piz => Compression::PIZ,
+        },
+        ..Encoding::default()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_channels<T : IntoSample>(path : &Path, pixels : &[(Color, Float)], width : usize, height : usize, alpha : bool,
+    encoding : Encoding, attributes : LayerAttributes, sample : fn(Float) -> T) -> exr::error::UnitResult {
+    if alpha {
+        let channels = SpecificChannels::rgba(|Vec2(x, y) : Vec2<usize>| {
+            let (color, a) = pixels[y * width + x];
+            (sample(color.x), sample(color.y), sample(color.z), sample(a))
+        });
+        Image::from_layer(Layer::new((width, height), attributes, encoding, channels)).write().to_file(path)
+    } else {
+        let channels = SpecificChannels::rgb(|Vec2(x, y) : Vec2<usize>| {
+            let (color, _) = pixels[y * width + x];
+            (sample(color.x), sample(color.y), sample(color.z))
+        });
+        Image::from_layer(Layer::new((width, height), attributes, encoding, channels)).write().to_file(path)
+    }
+}
@@ -0,0 +1,31 @@
+/*
+Module to store the prelude, which gathers what programs embedding the tracer use to build a scene and render it, so that
+
+use rust_tracer::prelude::*;
+
+is the only import they need. Everything else stays in its own module. Without the std feature, it holds the core math and intersection types only.
+*/
+
+pub use crate::vec3::{Vec3, Point3, Color, Float};
+pub use crate::ray::Ray;
+#[cfg(feature = "std")]
+pub use crate::camera::{Camera, StandardCamera};
+pub use crate::materials::Material;
+pub use crate::textures::{Texture, Textures};
+pub use crate::hitting::Hittable;
+#[cfg(feature = "std")]
+pub use crate::lights::Light;
+#[cfg(feature = "std")]
+pub use crate::environment::Environment;
+#[cfg(feature = "std")]
+pub use crate::accelerator::AcceleratorKind;
+#[cfg(feature = "std")]
+pub use crate::scene::Scene;
+#[cfg(feature = "std")]
+pub use crate::scene_file::SceneFile;
+#[cfg(feature = "std")]
+pub use crate::integrator::{Integrator, Clamping};
+#[cfg(feature = "std")]
+pub use crate::render::{Renderer, RenderSettings, SaveSettings, Image};
+#[cfg(feature = "std")]
+pub use crate::error::Error;
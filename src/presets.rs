@@ -0,0 +1,74 @@
+/*
+Module to store the material and texture presets, which give common materials physically sensible parameters by name, so that scenes
+don't have to guess them. Metals take the color of their reflectance at normal incidence, dielectrics their index of refraction at 587.6nm
+and Cauchy dispersion coefficient (see spectrum::cauchy_ior), and diffuse materials their measured albedo, all in the linear working space.
+
+Skin and plastics are Lambertian, as there are no subsurface or coated materials yet: skin lacks its soft translucency,
+and plastic its glossy highlight.
+*/
+
+use crate::vec3::{Color, Float};
+use crate::textures::{Texture, Textures};
+use crate::materials::Material;
+
+///Names of the material presets.
+pub const MATERIAL_NAMES : [&str ; 27] = [
+    "gold", "silver", "copper", "aluminium", "iron", "chrome", "brushed-gold", "brushed-copper", "brushed-aluminium", "brushed-steel",
+    "glass", "flint-glass", "water", "ice", "diamond", "sapphire", "quartz", "acrylic",
+    "skin", "white-plastic", "red-plastic", "black-plastic", "snow", "white-paint", "concrete", "charcoal", "marble",
+];
+
+///Names of the texture presets.
+pub const TEXTURE_NAMES : [&str ; 5] = ["marble", "fine-marble", "wide-marble", "checker", "black-white-checker"];
+
+///Returns the material preset with the given name (one of MATERIAL_NAMES), adding the texture it needs to textures if it is diffuse.
+pub fn material(name : &str, textures : &mut Textures) -> Option<Material> {
+    let mut diffuse = |r : Float, g : Float, b : Float| Some(Material::Lambertian(textures.add(Texture::Solid(Color::new(r, g, b)))));
+    let dielectric = |ior : Float, dispersion : Float| Some(Material::Dielectric(Color::new(1.0, 1.0, 1.0), ior, dispersion, 0));
+    match name {
+        "gold" => Some(Material::Metal(Color::new(1.0, 0.766, 0.336), 0.0)),
+        "silver" => Some(Material::Metal(Color::new(0.972, 0.960, 0.915), 0.0)),
+        "copper" => Some(Material::Metal(Color::new(0.955, 0.638, 0.538), 0.0)),
+        "aluminium" => Some(Material::Metal(Color::new(0.913, 0.922, 0.924), 0.0)),
+        "iron" => Some(Material::Metal(Color::new(0.560, 0.570, 0.580), 0.0)),
+        "chrome" => Some(Material::Metal(Color::new(0.550, 0.556, 0.554), 0.0)),
+        "brushed-gold" => Some(Material::Metal(Color::new(1.0, 0.766, 0.336), 0.25)),
+        "brushed-copper" => Some(Material::Metal(Color::new(0.955, 0.638, 0.538), 0.25)),
+        "brushed-aluminium" => Some(Material::Metal(Color::new(0.913, 0.922, 0.924), 0.25)),
+        "brushed-steel" => Some(Material::Metal(Color::new(0.630, 0.620, 0.600), 0.3)),
+
+        "glass" => dielectric(1.5168, 0.0042),
+        "flint-glass" => dielectric(1.62, 0.0094),
+        "water" => dielectric(1.333, 0.0031),
+        "ice" => dielectric(1.309, 0.0030),
+        "diamond" => dielectric(2.417, 0.0135),
+        "sapphire" => dielectric(1.768, 0.0060),
+        "quartz" => dielectric(1.544, 0.0040),
+        "acrylic" => dielectric(1.491, 0.0045),
+
+        "skin" => diffuse(0.61, 0.42, 0.33),
+        "white-plastic" => diffuse(0.80, 0.80, 0.80),
+        "red-plastic" => diffuse(0.60, 0.04, 0.03),
+        "black-plastic" => diffuse(0.03, 0.03, 0.03),
+        "snow" => diffuse(0.90, 0.90, 0.92),
+        "white-paint" => diffuse(0.85, 0.85, 0.83),
+        "concrete" => diffuse(0.45, 0.44, 0.42),
+        "charcoal" => diffuse(0.04, 0.04, 0.04),
+        "marble" => texture("marble").map(|marble| Material::Lambertian(textures.add(marble))),
+        _ => None,
+    }
+}
+
+///Returns the texture preset with the given name (one of TEXTURE_NAMES). The marbles are Perlin noise with veins of different widths,
+///
+/// and the checkers alternate in cells about a third of a unit across.
+pub fn texture(name : &str) -> Option<Texture> {
+    match name {
+        "marble" => Some(Texture::Noise(Box::default(), 4.0)),
+        "fine-marble" => Some(Texture::Noise(Box::default(), 12.0)),
+        "wide-marble" => Some(Texture::Noise(Box::default(), 1.0)),
+        "checker" => Some(Texture::Checker(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9))),
+        "black-white-checker" => Some(Texture::Checker(Color::new(0.02, 0.02, 0.02), Color::new(0.8, 0.8, 0.8))),
+        _ => None,
+    }
+}
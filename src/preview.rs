@@ -0,0 +1,83 @@
+/*
+Module to store the live preview, which draws the image rendered so far in the terminal while rendering continues,
+or with the preview feature, shows it in a window.
+*/
+
+use std::io::{Write, Result, stderr};
+use crate::vec3::Float;
+
+///Draws an image in the terminal, shrunk to the given number of columns, with 24 bit color escape codes.
+///
+/// Each character shows two pixels, one above the other, as the colors of an upper half block and of the space behind it, so pixels come out about square.
+/// The screen is cleared first, so each call replaces the last. Pixels are 8 bit colors, row by row from the top left.
+pub fn draw(pixels : &[(u8, u8, u8)], width : u32, height : u32, columns : u32) -> Result<()> {
+    let columns = columns.clamp(1, width.max(1));
+    let rows = ((height as Float * columns as Float / width.max(1) as Float).round() as u32).max(1);
+    let mut text = String::from("\x1b[2J\x1b[H");
+    for row in (0..rows).step_by(2) {
+        for column in 0..columns {
+            let (r, g, b) = average(pixels, width, height, columns, rows, column, row);
+            text += &format!("\x1b[38;2;{};{};{}m", r, g, b);
+            if row + 1 < rows {
+                let (r, g, b) = average(pixels, width, height, columns, rows, column, row + 1);
+                text += &format!("\x1b[48;2;{};{};{}m", r, g, b);
+            }
+            text.push('\u{2580}');
+        }
+        text += "\x1b[0m\n";
+    }
+
+    let mut err = stderr().lock();
+    err.write_all(text.as_bytes())?;
+    err.flush()
+}
+
+///Returns the average color of the pixels behind one cell of the shrunken image, columns by rows cells in all.
+fn average(pixels : &[(u8, u8, u8)], width : u32, height : u32, columns : u32, rows : u32, column : u32, row : u32) -> (u8, u8, u8) {
+    let (x0, x1) = (column * width / columns, ((column + 1) * width / columns).max(column * width / columns + 1));
+    let (y0, y1) = (row * height / rows, ((row + 1) * height / rows).max(row * height / rows + 1));
+    let mut sum = [0u32 ; 3];
+    let mut count = 0;
+    for y in y0..y1.min(height) {
+        for x in x0..x1.min(width) {
+            let (r, g, b) = pixels[(y * width + x) as usize];
+            sum[0] += r as u32;
+            sum[1] += g as u32;
+            sum[2] += b as u32;
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return (0, 0, 0);
+    }
+    ((sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8)
+}
+
+///A window showing the image rendered so far, at full size, replaced after every pass.
+#[cfg(feature = "preview")]
+pub struct Window {
+    window : minifb::Window,
+    buffer : Vec<u32>,
+}
+
+#[cfg(feature = "preview")]
+impl Window {
+
+    ///Opens a window for an image of the given size, failing when there is no display to open it on.
+    pub fn new(title : &str, width : u32, height : u32) -> Result<Window> {
+        let window = minifb::Window::new(title, width as usize, height as usize, minifb::WindowOptions::default())
+            .map_err(|error| std::io::Error::other(error.to_string()))?;
+        Ok(Window {window, buffer : vec![0 ; (width * height) as usize]})
+    }
+
+    ///Shows an image the size the window was opened with, with pixels as passed to draw().
+    ///
+    /// Returns false once the window has been closed or Escape pressed in it, so that the render can be stopped.
+    pub fn show(&mut self, pixels : &[(u8, u8, u8)], width : u32, height : u32) -> bool {
+        for (target, (r, g, b)) in self.buffer.iter_mut().zip(pixels) {
+            *target = u32::from_be_bytes([0, *r, *g, *b]);
+        }
+        self.window.update_with_buffer(&self.buffer, width as usize, height as usize).is_ok()
+            && self.window.is_open() && !self.window.is_key_down(minifb::Key::Escape)
+    }
+}
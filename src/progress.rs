@@ -0,0 +1,71 @@
+/*
+Module to store the progress display, which shows how far a render has got, how long it has left, and how fast it is going, as an indicatif bar.
+*/
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+
+///Layout of the bar: the percentage of samples done, the time taken and the time left as indicatif estimates it, and the samples traced per second,
+///
+/// followed by the message, which holds the rays traced per second and the status.
+const TEMPLATE : &str = "[{bar:30}] {percent:>3}% | {elapsed_precise} elapsed, {eta_precise} left | samples {samples_per_sec}/s | {msg}";
+
+///Progress of a render, which can be updated from many threads at once. Drawn on a single line of the terminal (and not at all
+///
+/// when stderr isn't one) as a bar with the percentage of samples done, the time taken and the time left, and the samples and rays traced per second.
+#[derive(Debug)]
+pub struct Progress {
+    bar : ProgressBar,
+    rays : AtomicU64,
+}
+
+impl Progress {
+
+    ///Starts timing a render of the given number of samples.
+    pub fn new(total : u64) -> Progress {
+        let bar = ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::stderr());
+        let style = ProgressStyle::with_template(TEMPLATE).expect("The progress template is invalid").progress_chars("#>-")
+            .with_key("samples_per_sec", |state : &ProgressState, w : &mut dyn Write| {
+                let _ = w.write_str(&format_rate(state.per_sec()));
+            });
+        bar.set_style(style);
+        Progress {bar, rays : AtomicU64::new(0)}
+    }
+
+    ///Records that samples more samples have been traced, with the given number of rays. The bar redraws itself no more than
+    ///
+    /// a few times a second, so that drawing never slows the render down. Status is shown after the bar, such as which pass is being rendered.
+    pub fn add(&self, samples : u64, rays : u64, status : &str) {
+        self.rays.fetch_add(rays, Ordering::Relaxed);
+        self.bar.set_message(self.message(status));
+        self.bar.inc(samples);
+    }
+
+    ///Redraws the display with a new status.
+    pub fn draw(&self, status : &str) {
+        self.bar.set_message(self.message(status));
+        self.bar.tick();
+    }
+
+    ///Draws the display one last time and leaves it on its line.
+    pub fn finish(&self, status : &str) {
+        self.bar.finish_with_message(self.message(status));
+    }
+
+    ///Returns the rays traced per second and the status, which follow the bar.
+    fn message(&self, status : &str) -> String {
+        let seconds = self.bar.elapsed().as_secs_f64().max(1e-9);
+        format!("rays {}/s | {}", format_rate(self.rays.load(Ordering::Relaxed) as f64 / seconds), status)
+    }
+}
+
+///Formats a rate with a metric prefix, such as 12.3M.
+fn format_rate(rate : f64) -> String {
+    match rate {
+        r if r >= 1e9 => format!("{:.1}G", r / 1e9),
+        r if r >= 1e6 => format!("{:.1}M", r / 1e6),
+        r if r >= 1e3 => format!("{:.1}k", r / 1e3),
+        r => format!("{:.0}", r),
+    }
+}
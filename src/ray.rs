@@ -0,0 +1,100 @@
+/*
+Module to store the 'ray' class and its related methods.
+*/
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::vec3::{Point3, Vec3, Float, dot, wide};
+
+///Self-intersection epsilon used unless set_self_intersection() is given another, which suits scenes from about a unit to a few thousand across.
+pub const DEFAULT_EPSILON : Float = 0.001;
+
+static EPSILON : AtomicU64 = AtomicU64::new(wide(DEFAULT_EPSILON).to_bits());
+static NORMAL_OFFSET : AtomicBool = AtomicBool::new(false);
+
+///How rays leaving a surface keep from hitting it again through rounding error. Variants include
+///
+/// Distance: ignores hits closer to the ray's origin than the epsilon, which lets light leak through objects thinner than it
+/// in tiny scenes, and leaves shadow acne in large scenes where it is smaller than the rounding error.
+///
+/// NormalOffset: starts rays the epsilon off the surface along its normal, on the side they leave by, and only ignores hits
+/// within a thousandth of the epsilon, so that rays leaving at grazing angles don't skip past the geometry next to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpawnOffset {
+    #[default]
+    Distance,
+    NormalOffset,
+}
+
+///Sets the self-intersection epsilon, in scene units, and how it is applied, for every render from then on.
+///
+/// The setting is process-wide: it applies to every scene rendered afterwards, on every thread.
+pub fn set_self_intersection(epsilon : Float, offset : SpawnOffset) {
+    EPSILON.store(wide(epsilon).to_bits(), Ordering::Relaxed);
+    NORMAL_OFFSET.store(offset == SpawnOffset::NormalOffset, Ordering::Relaxed);
+}
+
+///Returns the self-intersection epsilon.
+pub fn epsilon() -> Float {
+    f64::from_bits(EPSILON.load(Ordering::Relaxed)) as Float
+}
+
+///Returns how close to its origin hits along a ray are ignored.
+pub fn t_min() -> Float {
+    if NORMAL_OFFSET.load(Ordering::Relaxed) {epsilon() * 0.001} else {epsilon()}
+}
+
+///Returns where a ray leaving a surface at p with the given normal in the given direction starts: p itself, or with SpawnOffset::NormalOffset,
+///
+/// p moved the epsilon along the normal, to the side the direction points to.
+pub fn spawn_origin(p : Point3, normal : Vec3, direction : Vec3) -> Point3 {
+    if !NORMAL_OFFSET.load(Ordering::Relaxed) {
+        return p;
+    }
+    let offset = normal * epsilon();
+    if dot(direction, normal) >= 0.0 {p + offset} else {p - offset}
+}
+
+///Implementation of rays. Primary structure responsible for the ray tracing effects generated.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin_point : Point3,
+    pub direction : Vec3,
+    ///Wavelength (in nanometers) of the light this ray carries in spectral rendering, or None when rendering in RGB.
+    pub wavelength : Option<Float>,
+    ///Moment (between the camera's shutter open and close times) at which this ray travels, for motion blur.
+    pub time : Float,
+    ///Angle (in radians) the cone this ray stands for widens by per unit it travels, such as the angle a pixel covers for camera rays (see Camera::spread()),
+    ///
+    /// which image textures blur their lookups by (see HitRecord::footprint). 0 for a thin ray, such as those scattered off surfaces.
+    pub spread : Float,
+}
+
+impl Ray {
+
+    ///Initializes a new ray, given a starting point and a direction.
+    pub fn new(o : Point3, d : Vec3) -> Ray {
+        Ray {
+            origin_point : o,
+            direction : d,
+            wavelength : None,
+            time : 0.0,
+            spread : 0.0,
+        }
+    }
+
+    ///Returns this ray travelling at the given time instead.
+    pub fn with_time(self, time : Float) -> Ray {
+        Ray {time, ..self}
+    }
+
+    ///Returns this ray standing for a cone that widens by the given angle per unit it travels.
+    pub fn with_spread(self, spread : Float) -> Ray {
+        Ray {spread, ..self}
+    }
+
+    /// Returns the point at which this ray would be after a certain period.
+    pub fn at(&self, ti : Float) -> Point3 {
+        self.origin_point + self.direction * ti
+    }
+
+}
\ No newline at end of file
@@ -0,0 +1,695 @@
+/*
+Module to store the renderer, which path traces a scene into an image with the given settings, and the image it produces,
+which can be saved in any of the supported formats. This is the entry point for programs embedding the tracer.
+*/
+
+use image::{Rgb, RgbImage, Rgba, RgbaImage, ImageBuffer, DynamicImage};
+use std::ops::Range;
+use std::fs::rename;
+use std::path::Path;
+use web_time::Instant;
+use tracing::{debug, info, info_span, warn};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use crate::vec3::{Vec3, Color, random_2d, seed_stream, set_sampler, with_sampler, sample_with, take_sampler, save_generator, restore_generator, fork_generator, Float};
+use crate::hitting::HitRecord;
+use crate::ray::t_min;
+use crate::error;
+use crate::tree::MAX_PACKET;
+use crate::camera::{Camera, Stereo};
+use crate::accelerator::rays_traced;
+use crate::progress::Progress;
+use crate::stats::{RenderStats, counters};
+use crate::denoise::{DenoiseSettings, DenoisePixel, denoise};
+use crate::scene::Scene;
+use crate::integrator::{Integrator, first_hit};
+use crate::guiding::{GuidingSettings, Guide};
+use crate::sampler::SamplerKind;
+use crate::budget::SampleBudget;
+use crate::tonemap::ToneMap;
+use crate::color::{OutputTransform, heat_map};
+use crate::crop::CropWindow;
+use crate::output::{ExrSettings, ExrPrecision, ExrCompression, ExrLayer, DeepSample, write_exr, write_layers, write_deep, write_png, write_hdr, write_pfm, PpmWriter};
+use crate::aov::{AovLayout, AovSettings, AovSample};
+
+///Width and height of the tiles images are rendered in, in pixels. Each tile is rendered by one thread at a time, straight into its part of the image.
+pub const TILE_SIZE : u32 = 32;
+
+struct Pixel {
+    x : u32,
+    y : u32,
+    data : [u8 ; 3],
+    alpha : u8,
+}
+
+fn clamp(x : Float, minimum : Float, maximum : Float) -> Float {
+    if x < minimum {
+        return minimum;
+    }
+    if x > maximum {
+        return maximum;
+    }
+    x
+}
+
+///Converts a pixel's average radiance into 8 bit color values, scaling it by the exposure (the radiance that appears white is 1 / exposure),
+///
+/// fitting it to the display with the tone map and encoding it with the output transform.
+pub fn get_color(radiance : Color, exposure : Float, tone_map : ToneMap, transform : OutputTransform) -> (u8, u8, u8) {
+    let encoded = transform.apply(tone_map.apply(radiance * exposure));
+    (
+     (255.0 * clamp(encoded.x, 0.0, 0.999)) as u8,
+     (255.0 * clamp(encoded.y, 0.0, 0.999)) as u8,
+     (255.0 * clamp(encoded.z, 0.0, 0.999)) as u8,
+    )
+}
+
+///Converts a pixel's average radiance into 16 bit color values, as get_color() does for 8 bit ones.
+pub fn get_color16(radiance : Color, exposure : Float, tone_map : ToneMap, transform : OutputTransform) -> (u16, u16, u16) {
+    let encoded = transform.apply(tone_map.apply(radiance * exposure));
+    let channel = |x : Float| (65535.0 * clamp(x, 0.0, 1.0)).round() as u16;
+    (channel(encoded.x), channel(encoded.y), channel(encoded.z))
+}
+
+///Settings for rendering an image with the path tracer. The image is width by height, or holds a view of that size for each eye with stereo.
+///
+/// Samples are added to every pixel samples_per_pass (at least 1) at a time, up to samples_per_pixel (or as many as the sample budget gives it).
+/// With transparent, pixels also get an opacity, and with aovs, the passes of aov::AovSample are collected alongside the color.
+/// Packet_size samples through a pixel find their first hits together. Crop renders only part of the image, and guiding trains a path guide before rendering.
+/// Threads sets how many threads render (all cores by default), and with background, they run at the lowest priority, so that other programs stay responsive.
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub width : u32,
+    pub height : u32,
+    pub samples_per_pixel : i32,
+    pub samples_per_pass : i32,
+    pub integrator : Integrator,
+    pub sampler : SamplerKind,
+    pub sample_budget : SampleBudget,
+    pub transparent : bool,
+    pub aovs : bool,
+    pub packet_size : usize,
+    pub stereo : Option<Stereo>,
+    pub crop : Option<CropWindow>,
+    pub guiding : Option<GuidingSettings>,
+    pub threads : Option<usize>,
+    pub background : bool,
+}
+
+impl RenderSettings {
+
+    ///Settings for a plain render, with random sampling and the same number of samples in every pixel.
+    pub fn new(width : u32, height : u32, samples_per_pixel : i32, integrator : Integrator) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            samples_per_pixel,
+            samples_per_pass : 16,
+            integrator,
+            sampler : SamplerKind::Random,
+            sample_budget : SampleBudget::Uniform,
+            transparent : false,
+            aovs : false,
+            packet_size : 1,
+            stereo : None,
+            crop : None,
+            guiding : None,
+            threads : None,
+            background : false,
+        }
+    }
+
+    ///Returns a pool of threads to render with, or None to render on rayon's global pool, when neither the number of threads nor the priority is set.
+    pub fn thread_pool(&self) -> Option<ThreadPool> {
+        thread_pool(self.threads, self.background)
+    }
+}
+
+///Returns a pool of the given number of threads (or one for each core), running at the lowest priority with background,
+///
+/// or None if neither is set (or the threads can't be started), for running work that uses rayon (as photon mapping and Metropolis light transport do) with the render settings.
+pub fn thread_pool(threads : Option<usize>, background : bool) -> Option<ThreadPool> {
+    if threads.is_none() && !background {
+        return None;
+    }
+    let mut builder = ThreadPoolBuilder::new().num_threads(threads.unwrap_or(0));
+    if background {
+        builder = builder.start_handler(|_| lower_priority());
+    }
+    match builder.build() {
+        Ok(pool) => Some(pool),
+        Err(error) => {
+            warn!("Failed to start the render threads, rendering on the global pool instead: {}", error);
+            None
+        },
+    }
+}
+
+///Lowers the calling thread's priority as far as it goes. Does nothing except on Unix.
+fn lower_priority() {
+    #[cfg(unix)]
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
+
+///Runs op on the pool, if there is one, or else on the calling thread, so that its parallel iterators use the pool's threads.
+pub fn install<R : Send>(pool : &Option<ThreadPool>, op : impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+///Settings for saving an image: its exposure, tone map and output transform, whether it keeps its alpha channel,
+///
+/// the settings for .exr and .png files, and the passes and deep samples to save alongside it.
+#[derive(Debug, Clone)]
+pub struct SaveSettings {
+    pub exposure : Float,
+    pub tone_map : ToneMap,
+    pub transform : OutputTransform,
+    pub transparent : bool,
+    pub exr : ExrSettings,
+    pub png_bits : u32,
+    pub aovs : AovSettings,
+    pub deep : bool,
+}
+
+impl Default for SaveSettings {
+    ///Returns the settings main() starts from: no exposure change, a linear tone map into sRGB, an opaque image,
+    ///
+    /// half float .exr channels with ZIP compression, 8 bit .png channels and no passes or deep samples.
+    fn default() -> SaveSettings {
+        SaveSettings {
+            exposure : 1.0,
+            tone_map : ToneMap::Linear,
+            transform : OutputTransform::Srgb,
+            transparent : false,
+            exr : ExrSettings::new(ExrPrecision::Half, ExrCompression::Zip),
+            png_bits : 8,
+            aovs : AovSettings::new(vec![], AovLayout::Layers),
+            deep : false,
+        }
+    }
+}
+
+///Rendered image, as the pixels in xy (counted from the bottom left), where each entry of accumulated holds the summed (premultiplied) radiance,
+///
+/// opacity, sample count and passes of the pixel at the same position in xy. Rendered images list their pixels tile by tile.
+/// Pixels left out, as with a crop window, are black.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width : u32,
+    pub height : u32,
+    pub xy : Vec<(u32, u32)>,
+    pub accumulated : Vec<(Color, Float, i32, AovSample)>,
+}
+
+impl Image {
+
+    ///Wraps the average radiance and opacity of every pixel, in rows from the bottom, as photon mapping and Metropolis light transport return them.
+    pub fn from_radiance(width : u32, height : u32, radiance : Vec<(Color, Float)>) -> Image {
+        let xy = (0..width * height).map(|index| (index % width, index / width)).collect::<Vec<_>>();
+        let accumulated = radiance.into_iter().map(|(pixel, alpha)| (pixel, alpha, 1, AovSample::new())).collect::<Vec<_>>();
+        Image {width, height, xy, accumulated}
+    }
+
+    ///Returns the average (premultiplied) radiance and opacity of every pixel, in rows from the top.
+    pub fn pixels(&self) -> Vec<(Color, Float)> {
+        let mut pixels = vec![(Color::new(0.0, 0.0, 0.0), 0.0) ; (self.width * self.height) as usize];
+        for ((i, j), (pixel, alpha, samples, _)) in self.xy.iter().zip(&self.accumulated) {
+            let samples = (*samples).max(1) as Float;
+            pixels[((self.height - j - 1) * self.width + i) as usize] = (*pixel / samples, alpha / samples);
+        }
+        pixels
+    }
+
+    ///Saves the image to path. The image is written to a temporary file first, so stopping the render never leaves a half written image behind.
+    ///
+    /// Paths ending in .exr, .hdr or .pfm get the linear radiance (scaled by the exposure, but not tone mapped) as an OpenEXR, Radiance RGBE or PFM image,
+    /// and any other extension an image with png_bits (8 or 16) bits per channel. Only .png and .tiff files can hold 16.
+    /// The passes in aovs become extra layers of .exr images, or separate .exr files, and with deep, a deep .exr is saved next to the image.
+    /// Paths ending in .ppm, or "-" for standard output, get a binary PPM image with 8 bits per channel.
+    /// Metadata is stored in every format that can hold it: .png, .exr and .hdr.
+    pub fn save(&self, path : &str, settings : &SaveSettings, metadata : &[(&str, String)]) -> error::Result<()> {
+        let Image {width : image_width, height : image_height, xy, accumulated} = self;
+        let (image_width, image_height) = (*image_width, *image_height);
+        let SaveSettings {exposure, tone_map, transform, transparent, exr, png_bits, ref aovs, deep} = *settings;
+        let path = Path::new(path);
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("png").to_lowercase();
+        let partial = path.with_extension(format!("partial.{}", extension));
+        let (width, height) = (image_width as usize, image_height as usize);
+
+        let layers = aovs.passes.iter().map(|aov| {
+            let channels = aov.channels();
+            let mut values = vec![0.0 ; width * height * channels.len()];
+            for ((i, j), (_, _, samples, passes)) in xy.iter().zip(accumulated) {
+                let index = ((image_height - j - 1) * image_width + i) as usize * channels.len();
+                passes.pass(*aov, (*samples).max(1) as Float, exposure, &mut values[index..index + channels.len()]);
+            }
+            let precision = if aov.full_precision() {ExrPrecision::Full} else {exr.precision};
+            ExrLayer {name : aov.name(), channels, values, precision}
+        }).collect::<Vec<_>>();
+
+        let layered = extension == "exr" && matches!(aovs.layout, AovLayout::Layers);
+        if !layered {
+            for layer in &layers {
+                let pass_path = path.with_extension(format!("{}.exr", layer.name));
+                let pass_partial = path.with_extension(format!("{}.partial.exr", layer.name));
+                let unnamed = ExrLayer {name : "", ..layer.clone()};
+                write_layers(&pass_partial, width, height, exr, [&unnamed], metadata)?;
+                rename(&pass_partial, pass_path)?;
+            }
+        }
+
+        if deep {
+            let mut pixels : Vec<Vec<DeepSample>> = vec![vec![] ; width * height];
+            for ((i, j), (_, _, samples, passes)) in xy.iter().zip(accumulated) {
+                pixels[((image_height - j - 1) * image_width + i) as usize] = passes.deep.samples((*samples).max(1) as Float, exposure);
+            }
+            let deep_partial = path.with_extension("deep.partial.exr");
+            write_deep(&deep_partial, &pixels, width, height, metadata)?;
+            rename(&deep_partial, path.with_extension("deep.exr"))?;
+        }
+
+        if extension == "ppm" || path == Path::new("-") {
+            let pixels = self.pixels().iter().map(|(pixel, alpha)| {
+                if *alpha > 0.0 {get_color(*pixel / *alpha, exposure, tone_map, transform)} else {(0, 0, 0)}
+            }).collect::<Vec<_>>();
+            let mut writer = PpmWriter::create(path, width, height)?;
+            writer.write_rows(&pixels)?;
+            writer.finish()?;
+            return Ok(());
+        }
+
+        if matches!(extension.as_str(), "exr" | "hdr" | "pfm") {
+            let mut pixels = vec![(Color::new(0.0, 0.0, 0.0), 0.0) ; width * height];
+            for ((i, j), (pixel, alpha, samples, _)) in xy.iter().zip(accumulated) {
+                let samples = (*samples).max(1) as Float;
+                pixels[((image_height - j - 1) * image_width + i) as usize] = (*pixel * (exposure / samples), alpha / samples);
+            }
+            let layers = if layered {&layers[..]} else {&[]};
+            match extension.as_str() {
+                "exr" => write_exr(&partial, &pixels, width, height, transparent, exr, layers, metadata)?,
+                "hdr" => write_hdr(&partial, &pixels, width, height, metadata)?,
+                _ => write_pfm(&partial, &pixels, width, height)?,
+            }
+            rename(&partial, path)?;
+            return Ok(());
+        }
+
+        //PNG images are written with the metadata, and other formats without it
+        let save = |img : DynamicImage| -> error::Result<()> {
+            if extension == "png" {
+                write_png(&partial, &img, metadata)?;
+            } else {
+                img.save(&partial)?;
+            }
+            rename(&partial, path)?;
+            Ok(())
+        };
+
+        if png_bits == 16 {
+            let mut img = ImageBuffer::<Rgba<u16>, Vec<u16>>::new(image_width, image_height);
+            for ((i, j), (pixel, alpha, samples, _)) in xy.iter().zip(accumulated) {
+                let (r, g, b) = if *alpha > 0.0 {get_color16(*pixel / *alpha, exposure, tone_map, transform)} else {(0, 0, 0)};
+                let a = (65535.0 * clamp(alpha / (*samples).max(1) as Float, 0.0, 1.0)).round() as u16;
+                img.put_pixel(*i, image_height - j - 1, Rgba([r, g, b, a]));
+            }
+            if transparent {
+                save(DynamicImage::ImageRgba16(img))?;
+            } else {
+                let opaque : ImageBuffer<Rgb<u16>, Vec<u16>> = ImageBuffer::from_fn(image_width, image_height, |x, y| {
+                    let [r, g, b, _] = img.get_pixel(x, y).0;
+                    Rgb([r, g, b])
+                });
+                save(DynamicImage::ImageRgb16(opaque))?;
+            }
+            return Ok(());
+        }
+
+        let img_pixels = xy.iter().zip(accumulated).map(|((i, j), (pixel, alpha, samples, _))| {
+            //Undo the premultiplication, so partially transparent pixels keep their true color
+            let (ir, ig, ib) = if *alpha > 0.0 {get_color(*pixel / *alpha, exposure, tone_map, transform)} else {(0, 0, 0)};
+            let ia = (255.0 * clamp(alpha / (*samples).max(1) as Float, 0.0, 1.0)) as u8;
+            Pixel{x : *i, y : image_height - j - 1, data : [ir, ig, ib], alpha : ia}
+        });
+
+        if transparent {
+            let mut transparent_img = RgbaImage::new(image_width, image_height);
+            for pix in img_pixels {
+                transparent_img.put_pixel(pix.x, pix.y, Rgba([pix.data[0], pix.data[1], pix.data[2], pix.alpha]));
+            }
+            save(DynamicImage::ImageRgba8(transparent_img))
+        } else {
+            let mut img = RgbImage::new(image_width, image_height);
+            for pix in img_pixels {
+                img.put_pixel(pix.x, pix.y, Rgb(pix.data));
+            }
+            save(DynamicImage::ImageRgb8(img))
+        }
+    }
+
+    ///Returns a copy of the image with the noise in its colors smoothed away by the denoiser, which needs the passes of a render with aovs.
+    pub fn denoised(&self, settings : DenoiseSettings) -> Image {
+        let (image_width, image_height) = (self.width, self.height);
+        let index = |(i, j) : (u32, u32)| ((image_height - j - 1) * image_width + i) as usize;
+        let mut pixels = vec![DenoisePixel {color : Color::new(0.0, 0.0, 0.0), albedo : Color::new(0.0, 0.0, 0.0), normal : Vec3::new(0.0, 0.0, 0.0), depth : 0.0} ; self.accumulated.len()];
+        for ((i, j), (pixel, _, samples, passes)) in self.xy.iter().zip(&self.accumulated) {
+            let samples = (*samples).max(1) as Float;
+            pixels[index((*i, *j))] = DenoisePixel {
+                color : *pixel / samples,
+                albedo : passes.albedo / samples,
+                normal : passes.normal / samples,
+                depth : if passes.coverage > 0.0 {passes.depth / passes.coverage} else {0.0},
+            };
+        }
+
+        let denoised = denoise(&pixels, image_width as usize, image_height as usize, settings);
+        let accumulated = self.xy.iter().zip(&self.accumulated).map(|((i, j), (_, alpha, samples, passes))| {
+            (denoised[index((*i, *j))] * (*samples).max(1) as Float, *alpha, *samples, *passes)
+        }).collect();
+        Image {accumulated, ..self.clone()}
+    }
+
+    ///Draws the relative error of every pixel (see AovSample::relative_error()) as a heat map, hottest at the given relative error and above,
+    ///
+    /// to show where noise remains and more samples would help. Pixels left out or with a single sample are black.
+    pub fn variance_map(&self, scale : Float) -> RgbImage {
+        let mut img = RgbImage::new(self.width, self.height);
+        for ((i, j), (_, _, samples, passes)) in self.xy.iter().zip(&self.accumulated) {
+            let c = heat_map(passes.relative_error((*samples).max(1) as Float) / scale);
+            img.put_pixel(*i, self.height - j - 1, Rgb([c.x, c.y, c.z].map(|x| (x * 255.0).round() as u8)));
+        }
+        img
+    }
+}
+
+///Path tracer rendering scenes with the given settings.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    pub settings : RenderSettings,
+}
+
+impl Renderer {
+
+    pub fn new(settings : RenderSettings) -> Renderer {
+        Renderer {settings}
+    }
+
+    ///Returns the size of the rendered image, which holds both eyes' views with stereo.
+    pub fn image_size(&self) -> (u32, u32) {
+        let RenderSettings {width, height, stereo, ..} = self.settings;
+        stereo.map_or((width, height), |stereo| stereo.image_size(width, height))
+    }
+
+    ///Returns the total number of samples in an image, for timing renders with progress::Progress.
+    pub fn samples(&self) -> u64 {
+        //Counted pixel by pixel, without listing them, as streamed images may be too large to list
+        let (output_width, output_height) = self.image_size();
+        let RenderSettings {crop, ref sample_budget, samples_per_pixel, ..} = self.settings;
+        (0..output_height).into_par_iter().map(|y| (0..output_width).filter(|x| crop.is_none_or(|crop| crop.contains(*x, y, output_width, output_height)))
+            .map(|x| sample_budget.samples(x, y, output_width, output_height, samples_per_pixel).max(1) as u64).sum::<u64>()).sum()
+    }
+
+    ///Renders the scene as seen by the cameras: one, or one for each eye (left, then right) with stereo.
+    pub fn render(&self, world : &mut Scene, cams : &[Box<dyn Camera>]) -> Image {
+        self.render_with(world, cams, None, None, "", |_, _, _| true)
+    }
+
+    ///Renders the scene as render() does, reporting the samples traced to progress (with status shown after the pass being rendered)
+    ///
+    /// and the work done in every tile to stats, and calling on_pass with the image so far, the pass and the number of passes after every pass,
+    /// so that it can be previewed or saved. Rendering stops early, returning the image so far, if on_pass returns false.
+    pub fn render_with(&self, world : &mut Scene, cams : &[Box<dyn Camera>], progress : Option<&Progress>, stats : Option<&RenderStats>, status : &str,
+        mut on_pass : impl FnMut(&Image, u32, u32) -> bool) -> Image {
+        let settings = &self.settings;
+        let (output_width, output_height) = self.image_size();
+        let (xy, totals, tiles) = self.pixels();
+        //A pass adds at least one sample, so that a samples_per_pass of 0 still finishes
+        let samples_per_pass = settings.samples_per_pass.max(1);
+        let passes = ((totals.iter().copied().max().unwrap_or(0) + samples_per_pass - 1) / samples_per_pass) as u32;
+        let pool = settings.thread_pool();
+        let _span = info_span!("render", width = output_width, height = output_height, passes).entered();
+
+        self.train_guide(world, cams, &pool);
+        let world = &*world;
+
+        let mut image = Image {width : output_width, height : output_height, accumulated : vec![(Color::new(0.0, 0.0, 0.0), 0.0, 0, AovSample::new()) ; xy.len()], xy};
+        for pass in 0..passes {
+            let first = pass as i32 * samples_per_pass;
+            let status = format!("pass {} of {}{}", pass + 1, passes, status);
+            let pass_span = info_span!("pass", pass = pass + 1);
+            let entered = pass_span.enter();
+            let start = Instant::now();
+
+            //Each tile adds its samples straight into its own part of the image
+            let (mut sums, mut xy_left, mut totals_left) = (&mut image.accumulated[..], &image.xy[..], &totals[..]);
+            let mut parts = Vec::with_capacity(tiles.len());
+            for size in &tiles {
+                let (tile_sums, rest) = sums.split_at_mut(*size);
+                let ((tile_xy, xy_rest), (tile_totals, totals_rest)) = (xy_left.split_at(*size), totals_left.split_at(*size));
+                parts.push((tile_sums, tile_xy, tile_totals));
+                (sums, xy_left, totals_left) = (rest, xy_rest, totals_rest);
+            }
+            install(&pool, || parts.into_par_iter().for_each(|(tile_sums, tile_xy, tile_totals)| {
+                let tile_start = Instant::now();
+                let rays = rays_traced();
+                let work = counters();
+                let mut traced = 0;
+                for ((sum, (i, j)), total) in tile_sums.iter_mut().zip(tile_xy).zip(tile_totals) {
+                    let last = (first + samples_per_pass).min(*total);
+                    if first >= last {
+                        continue;
+                    }
+                    let (pixel, alpha, passes) = self.sample_pixel(cams, *i, *j, 0, first..last, *total, world);
+                    sum.0 += pixel;
+                    sum.1 += alpha;
+                    sum.2 += last - first;
+                    sum.3 += passes;
+                    traced += (last - first) as u64;
+                }
+                let rays = rays_traced() - rays;
+                if let Some(progress) = progress {
+                    progress.add(traced, rays, &status);
+                }
+                if let Some((i, j)) = tile_xy.first() {
+                    let corner = (i - i % TILE_SIZE, j - j % TILE_SIZE);
+                    debug!(parent : &pass_span, x = corner.0, y = corner.1, samples = traced, rays, elapsed = ?tile_start.elapsed(), "rendered tile");
+                    if let Some(stats) = stats {
+                        stats.add(corner, counters() - work);
+                    }
+                }
+            }));
+            info!(elapsed = ?start.elapsed(), "rendered pass");
+            drop(entered);
+            if !on_pass(&image, pass, passes) {
+                break;
+            }
+        }
+        image
+    }
+
+    ///Renders the scene as render() does, but a band of rows at a time from the top, tracing every sample of a pixel at once, and calls on_rows
+    ///
+    /// with each band's average (premultiplied) radiance and opacity as soon as it is done, so that images too large to hold in memory
+    /// can be streamed to a file. Pixels outside the crop window are black. Rendering stops, returning the error, if on_rows fails.
+    /// The work done in every tile is reported to stats, with the tile's top left corner.
+    pub fn render_rows<E>(&self, world : &mut Scene, cams : &[Box<dyn Camera>], progress : Option<&Progress>, stats : Option<&RenderStats>, status : &str,
+        mut on_rows : impl FnMut(&[(Color, Float)]) -> Result<(), E>) -> Result<(), E> {
+        let settings = &self.settings;
+        let (output_width, output_height) = self.image_size();
+        let pool = settings.thread_pool();
+        let _span = info_span!("render_rows", width = output_width, height = output_height).entered();
+        self.train_guide(world, cams, &pool);
+        let world = &*world;
+
+        //Bands are a row of tiles, each rendered into a buffer of its own and then copied into the band's rows
+        for top in (0..output_height).step_by(TILE_SIZE as usize) {
+            let rows = TILE_SIZE.min(output_height - top);
+            let status = format!("rows {} to {} of {}{}", top + 1, top + rows, output_height, status);
+            let band_span = info_span!("band", top, rows);
+            let entered = band_span.enter();
+            let start = Instant::now();
+            let tiles = install(&pool, || (0..output_width).step_by(TILE_SIZE as usize).collect::<Vec<_>>().into_par_iter().map(|left| {
+                let columns = TILE_SIZE.min(output_width - left);
+                let tile_start = Instant::now();
+                let rays = rays_traced();
+                let work = counters();
+                let mut traced = 0;
+                let tile = (0..rows * columns).map(|index| {
+                    let (i, j) = (left + index % columns, output_height - 1 - top - index / columns);
+                    if settings.crop.is_some_and(|crop| !crop.contains(i, j, output_width, output_height)) {
+                        return (Color::new(0.0, 0.0, 0.0), 0.0);
+                    }
+                    let total = settings.sample_budget.samples(i, j, output_width, output_height, settings.samples_per_pixel).max(1);
+                    let (pixel, alpha, _) = self.sample_pixel(cams, i, j, 0, 0..total, total, world);
+                    traced += total as u64;
+                    (pixel / total as Float, alpha / total as Float)
+                }).collect::<Vec<_>>();
+                let rays = rays_traced() - rays;
+                if let Some(progress) = progress {
+                    progress.add(traced, rays, &status);
+                }
+                debug!(parent : &band_span, x = left, y = output_height - 1 - top, samples = traced, rays, elapsed = ?tile_start.elapsed(), "rendered tile");
+                if let Some(stats) = stats {
+                    stats.add((left, output_height - 1 - top), counters() - work);
+                }
+                (left, columns, tile)
+            }).collect::<Vec<_>>());
+            info!(elapsed = ?start.elapsed(), "rendered band");
+            drop(entered);
+
+            let mut band = vec![(Color::new(0.0, 0.0, 0.0), 0.0) ; (rows * output_width) as usize];
+            for (left, columns, tile) in tiles {
+                for (row, pixels) in tile.chunks(columns as usize).enumerate() {
+                    let start = row * output_width as usize + left as usize;
+                    band[start..start + pixels.len()].copy_from_slice(pixels);
+                }
+            }
+            on_rows(&band)?;
+        }
+        Ok(())
+    }
+
+    ///Trains the path guide, if there is one, refining it after every pass, on the pool's threads.
+    fn train_guide(&self, world : &mut Scene, cams : &[Box<dyn Camera>], pool : &Option<ThreadPool>) {
+        if let Some(guiding) = self.settings.guiding {
+            let _span = info_span!("train_guide", passes = guiding.training_passes).entered();
+            let (xy, _, _) = self.pixels();
+            world.guide = Some(Guide::new(world, guiding));
+            for pass in 0..guiding.training_passes {
+                install(pool, || xy.par_iter().for_each(|(i, j)| {
+                    self.sample_pixel(cams, *i, *j, pass + 1, 0..guiding.samples_per_pass, guiding.samples_per_pass, world);
+                }));
+                if let Some(guide) = world.guide.as_mut() {
+                    guide.rebuild();
+                }
+            }
+            if let Some(guide) = world.guide.as_mut() {
+                guide.learning = false;
+            }
+        }
+    }
+
+    ///Returns the pixels to render (counted from the bottom left, inside the crop window) tile by tile, how many samples each gets,
+    ///
+    /// and how many pixels each tile has, leaving out tiles entirely outside the crop window.
+    fn pixels(&self) -> (Vec<(u32, u32)>, Vec<i32>, Vec<usize>) {
+        let (output_width, output_height) = self.image_size();
+        let mut xy : Vec<(u32, u32)> = vec![];
+        let mut tiles = vec![];
+        for bottom in (0..output_height).step_by(TILE_SIZE as usize) {
+            for left in (0..output_width).step_by(TILE_SIZE as usize) {
+                let start = xy.len();
+                for y in bottom..(bottom + TILE_SIZE).min(output_height) {
+                    for x in left..(left + TILE_SIZE).min(output_width) {
+                        if self.settings.crop.is_none_or(|crop| crop.contains(x, y, output_width, output_height)) {
+                            xy.push((x, y));
+                        }
+                    }
+                }
+                if xy.len() > start {
+                    tiles.push(xy.len() - start);
+                }
+            }
+        }
+        let totals = xy.iter().map(|(i, j)| self.settings.sample_budget.samples(*i, *j, output_width, output_height, self.settings.samples_per_pixel).max(1)).collect::<Vec<_>>();
+        (xy, totals, tiles)
+    }
+
+    ///Returns the sum of the (premultiplied) radiance, opacity and passes of the given samples out of total through a pixel.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_pixel(&self, cams : &[Box<dyn Camera>], i : u32, j : u32, pass : u32, samples : Range<i32>, total : i32, world : &Scene) -> (Color, Float, AovSample) {
+        let RenderSettings {width : image_width, height : image_height, integrator, sampler, transparent, aovs : collect_aovs, packet_size, stereo, ..} = self.settings;
+        let mut pixel : Color = Color::new(0.0, 0.0, 0.0);
+        let mut alpha = 0.0;
+        let mut passes = AovSample::new();
+        set_sampler(Some(sampler.create(total)));
+        with_sampler(|sampler| sampler.start_pixel(i, j));
+        let (cam, x, y) = match stereo {
+            Some(stereo) => {
+                let (is_right, x, y) = stereo.locate(i, j, image_width, image_height);
+                (&cams[if is_right {cams.len() - 1} else {0}], x, y)
+            },
+            None => (&cams[0], i, j),
+        };
+
+        let spread = cam.spread(image_height);
+        let camera_ray = |s : i32| {
+            with_sampler(|sampler| sampler.start_sample(s as u32));
+            let (dx, dy) = random_2d();
+            let u : Float = (x as Float + dx) / image_width as Float;
+            let v : Float = (y as Float + dy) / image_height as Float;
+            sample_with(|sampler| cam.get_ray(u, v, sampler)).with_spread(spread)
+        };
+
+        //Color, opacity and passes of a camera ray whose first hit has been found
+        let shade_ray = |r, hit| match (transparent, !collect_aovs) {
+            (false, true) => (integrator.radiance_from(r, hit, world), 1.0, AovSample::new()),
+            (true, true) => {
+                let (color, a) = integrator.radiance_alpha_from(r, hit, world);
+                (color, a, AovSample::new())
+            },
+            (false, false) => {
+                let (color, aovs) = integrator.radiance_aovs(r, hit, world);
+                (color, 1.0, aovs)
+            },
+            (true, false) => integrator.radiance_alpha_aovs(r, hit, world),
+        };
+
+        if packet_size <= 1 {
+            for s in samples {
+                seed_stream(&[pass as u64, i as u64, j as u64, s as u64]);
+                let r = camera_ray(s);
+                let (color, a, aovs) = if collect_aovs {
+                    shade_ray(r, first_hit(r, world))
+                } else if transparent {
+                    let (color, a) = integrator.radiance_alpha(r, world);
+                    (color, a, AovSample::new())
+                } else {
+                    (integrator.radiance(r, world), 1.0, AovSample::new())
+                };
+                pixel += color;
+                alpha += a;
+                passes += aovs;
+                passes.add_luminance(color);
+            }
+            set_sampler(None);
+            return (pixel, alpha, passes);
+        }
+
+        let samples : Vec<i32> = samples.collect();
+        for packet in samples.chunks(packet_size.min(MAX_PACKET)) {
+            //Save the random numbers before each camera ray, so that its path can carry on exactly as if it had been traced alone
+            let mut states = Vec::with_capacity(packet.len());
+            let mut rays = Vec::with_capacity(packet.len());
+            for s in packet {
+                seed_stream(&[pass as u64, i as u64, j as u64, *s as u64]);
+                states.push(save_generator());
+                rays.push(camera_ray(*s));
+            }
+
+            //Media draw random numbers when hit, so first hits get numbers of their own rather than ones the paths will go on to use
+            let pixel_sampler = take_sampler();
+            restore_generator(fork_generator());
+            let mut recs = [HitRecord::new() ; MAX_PACKET];
+            let hits = world.hit_packet(&rays, t_min(), Float::INFINITY, &mut recs);
+            set_sampler(pixel_sampler);
+
+            for (k, (s, state)) in packet.iter().zip(states).enumerate() {
+                restore_generator(state);
+                camera_ray(*s);
+                let hit = (hits & (1 << k) != 0).then_some(recs[k]);
+                let (color, a, aovs) = shade_ray(rays[k], hit);
+                pixel += color;
+                alpha += a;
+                passes += aovs;
+                passes.add_luminance(color);
+            }
+        }
+        set_sampler(None);
+        (pixel, alpha, passes)
+    }
+}
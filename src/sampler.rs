@@ -0,0 +1,213 @@
+/*
+Module to store the 'sampler' trait, which supplies every random number used while rendering, and the samplers implementing it.
+*/
+
+use crate::vec3::{uniform_float, Float};
+use crate::sobol::{SOBOL_DIMENSIONS, sobol, hash};
+use crate::halton::{HALTON_DIMENSIONS, HaltonPermutation, halton};
+use crate::cmj::cmj;
+
+///Source of the random numbers behind each sample through a pixel. The first two numbers of a sample
+///
+/// pick its position within the pixel, the next two its position on the lens, and the rest drive the path it traces.
+/// While rendering, the current thread's sampler is installed with vec3::set_sampler(), and random_float() and random_2d() draw from it.
+pub trait Sampler {
+
+    ///Begins the samples of pixel (i, j).
+    fn start_pixel(&mut self, _i : u32, _j : u32) {}
+
+    ///Begins the given sample of the current pixel, starting again from its first number.
+    fn start_sample(&mut self, _index : u32) {}
+
+    ///Returns the next number of the current sample, between 0 and 1 non-inclusive.
+    fn get_1d(&mut self) -> Float;
+
+    ///Returns the next two numbers of the current sample.
+    fn get_2d(&mut self) -> (Float, Float) {
+        let x = self.get_1d();
+        (x, self.get_1d())
+    }
+}
+
+///Determines how the random numbers behind each sample through a pixel are chosen. Variants include
+///
+/// Random: every number is independently random.
+///
+/// Stratified: the pixel is split into an N by N grid (N being the square root of the samples per pixel, rounded down),
+///
+/// and one sample lands at a random position within each cell, so no part of the pixel is left out by chance.
+/// Any samples left over once every cell has one are placed randomly, as are all numbers after the first two.
+///
+/// Sobol: the samples of each pixel follow the Sobol low-discrepancy sequence, scrambled differently for every pixel,
+///
+/// which spreads them evenly over the pixel, the lens and the first few bounces at once. Works best with a power of 2 samples per pixel.
+///
+/// Halton: the samples of each pixel follow the Halton low-discrepancy sequence, with the given digit permutation.
+/// Covers more dimensions than Sobol, and works well with any number of samples per pixel.
+///
+/// CorrelatedMultiJittered: Kensler's pattern for the pixel and lens positions, which are stratified both on a grid and along each axis,
+///
+/// with the rest of the numbers random. A middle ground between Stratified and the low-discrepancy sequences.
+#[derive(Debug, Clone, Copy)]
+pub enum SamplerKind {
+    Random,
+    Stratified,
+    Sobol,
+    Halton(HaltonPermutation),
+    CorrelatedMultiJittered,
+}
+
+impl SamplerKind {
+
+    ///Creates a sampler of this kind for pixels taking the given number of samples each.
+    pub fn create(&self, samples : i32) -> Box<dyn Sampler> {
+        let samples = samples.max(1) as u32;
+        match self {
+            SamplerKind::Random => Box::new(RandomSampler),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {samples, index : 0, dimension : 0}),
+            SamplerKind::Sobol => Box::new(SobolSampler {seed : 0, index : 0, dimension : 0}),
+            SamplerKind::Halton(permutation) => Box::new(HaltonSampler {permutation : *permutation, seed : 0, index : 0, dimension : 0}),
+            SamplerKind::CorrelatedMultiJittered => Box::new(CmjSampler {samples, seed : 0, index : 0, dimension : 0}),
+        }
+    }
+}
+
+///Seed scrambling the samples of pixel (i, j), so that neighboring pixels don't share the same pattern.
+fn pixel_seed(i : u32, j : u32) -> u32 {
+    hash(i ^ hash(j))
+}
+
+///Draws every number independently at random.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomSampler;
+
+impl Sampler for RandomSampler {
+    fn get_1d(&mut self) -> Float {
+        uniform_float()
+    }
+}
+
+///Jitters each sample within its own cell of an N by N grid over the pixel, drawing every other number at random.
+#[derive(Debug, Clone, Copy)]
+pub struct StratifiedSampler {
+    samples : u32,
+    index : u32,
+    dimension : u32,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, index : u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let n = (self.samples as Float).sqrt() as u32;
+        self.dimension += 1;
+        if self.dimension > 2 || self.index >= n * n {
+            return uniform_float();
+        }
+        let cell = if self.dimension == 1 {self.index % n} else {self.index / n};
+        (cell as Float + uniform_float()) / n as Float
+    }
+}
+
+///Draws the first numbers of each sample from the scrambled Sobol sequence, and the rest at random.
+#[derive(Debug, Clone, Copy)]
+pub struct SobolSampler {
+    seed : u32,
+    index : u32,
+    dimension : usize,
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, i : u32, j : u32) {
+        self.seed = pixel_seed(i, j);
+    }
+
+    fn start_sample(&mut self, index : u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        self.dimension += 1;
+        if self.dimension > SOBOL_DIMENSIONS {
+            return uniform_float();
+        }
+        sobol(self.index, self.dimension - 1, self.seed)
+    }
+}
+
+///Draws the first numbers of each sample from the permuted Halton sequence, and the rest at random.
+#[derive(Debug, Clone, Copy)]
+pub struct HaltonSampler {
+    permutation : HaltonPermutation,
+    seed : u32,
+    index : u32,
+    dimension : usize,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel(&mut self, i : u32, j : u32) {
+        self.seed = pixel_seed(i, j);
+    }
+
+    fn start_sample(&mut self, index : u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        self.dimension += 1;
+        if self.dimension > HALTON_DIMENSIONS {
+            return uniform_float();
+        }
+        halton(self.index, self.dimension - 1, self.permutation, self.seed)
+    }
+}
+
+///Draws the pixel and lens positions of each sample from correlated multi-jittered patterns, and the rest at random.
+#[derive(Debug, Clone, Copy)]
+pub struct CmjSampler {
+    samples : u32,
+    seed : u32,
+    index : u32,
+    dimension : u32,
+}
+
+impl Sampler for CmjSampler {
+    fn start_pixel(&mut self, i : u32, j : u32) {
+        self.seed = pixel_seed(i, j);
+    }
+
+    fn start_sample(&mut self, index : u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        self.dimension += 1;
+        match self.dimension {
+            1 => cmj(self.index, self.samples, self.seed).0,
+            2 => cmj(self.index, self.samples, self.seed).1,
+            3 => cmj(self.index, self.samples, hash(self.seed)).0,
+            4 => cmj(self.index, self.samples, hash(self.seed)).1,
+            _ => uniform_float(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        //Both halves of a 2D pattern are computed together when the request lines up with one
+        let seed = match self.dimension {
+            0 => self.seed,
+            2 => hash(self.seed),
+            _ => {
+                let x = self.get_1d();
+                return (x, self.get_1d());
+            },
+        };
+        self.dimension += 2;
+        cmj(self.index, self.samples, seed)
+    }
+}
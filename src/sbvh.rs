@@ -0,0 +1,279 @@
+/*
+Module to store the spatial split builder, which builds Bounding Volume Hierarchies whose leaves may hold pieces of large objects.
+*/
+
+use crate::hitting::Hittable;
+use crate::bvh::{AABB, surrounding_box};
+use crate::tree::{Tree, Node};
+use std::io::{Error, ErrorKind, Result};
+use crate::vec3::Float;
+
+///Number of slices each axis of a node is cut into when looking for a spatial split.
+const BINS : usize = 32;
+
+///Spatial splits are only tried when the boxes of the best object split overlap by more than this fraction of the whole scene's surface area.
+const MIN_OVERLAP : Float = 0.00001;
+
+///Depth below which nodes are split in half rather than by the surface area heuristic, so that Tree::hit()'s stack can never overflow.
+const MAX_SAH_DEPTH : usize = 32;
+
+///Part of an object assigned to a node: the object's index, and the bounding box of the part.
+#[derive(Debug, Clone, Copy)]
+struct Reference {
+    index : usize,
+    aabb : AABB,
+}
+
+impl Reference {
+    fn centroid(&self, axis : usize) -> Float {
+        (self.aabb.minimum[axis] + self.aabb.maximum[axis]) / 2.0
+    }
+}
+
+///Way of splitting a node's references in two. Variants include
+///
+/// Object: sort the references along an axis and split them after the given count.
+///
+/// Spatial: cut space along an axis at the given position, splitting references that cross the plane.
+#[derive(Debug, Clone, Copy)]
+enum Split {
+    Object(usize, usize),
+    Spatial(usize, Float),
+}
+
+///Builds a Bounding Volume Hierarchy from a list of Hittable objects, choosing each split by the surface area heuristic.
+///
+/// Besides splitting the list of objects, nodes may be split through space, with objects crossing the plane stored on both sides
+/// with their bounding boxes clipped. This keeps long, thin and large objects from inflating the boxes of the nodes around them.
+/// Media are never split, since their hits are random. An empty list gives an empty hierarchy that nothing hits,
+/// and the build fails if any object's bounding box has NaN coordinates.
+pub fn build(lst : &[Hittable]) -> Result<Tree> {
+    if let Some(index) = lst.iter().position(|item| item.bounding_box().has_nan()) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Object {} has an invalid bounding box", index)));
+    }
+
+    let references : Vec<Reference> = lst.iter().enumerate().map(|(index, item)| Reference {index, aabb : item.bounding_box().padded()}).collect();
+    match union(&references) {
+        Some(bounds) => {
+            let mut builder = Builder {objects : lst, items : vec![], root_area : bounds.surface_area()};
+            let root = builder.con(references, 0);
+            Ok(Tree {items : builder.items, root})
+        },
+        None => Ok(Tree {items : vec![], root : 0}),
+    }
+}
+
+///State of a build in progress: the objects being placed, the nodes made so far, and the surface area of the whole scene.
+struct Builder<'a> {
+    objects : &'a [Hittable],
+    items : Vec<Node>,
+    root_area : Float,
+}
+
+impl Builder<'_> {
+
+    ///Recursive helper function that builds the node holding the given references, returning its index.
+    fn con(&mut self, mut references : Vec<Reference>, depth : usize) -> usize {
+        if references.len() == 1 {
+            let reference = references[0];
+            let next = self.items.len();
+            self.items.push(Node {left : None, right : None, aabb : reference.aabb, data : Some(self.objects[reference.index].clone()), axis : 0});
+            return next;
+        }
+
+        let bounds = union(&references).unwrap();
+        let split = if depth >= MAX_SAH_DEPTH {None} else {self.best_split(&mut references, bounds)};
+        let (axis, left, right) = match split.and_then(|split| self.partition(&mut references, split)) {
+            Some(partition) => partition,
+            None => median_split(references, bounds),
+        };
+
+        let left = self.con(left, depth + 1);
+        let right = self.con(right, depth + 1);
+        let next = self.items.len();
+        let aabb = surrounding_box(self.items[left].aabb, self.items[right].aabb);
+        self.items.push(Node {left : Some(left), right : Some(right), aabb, data : None, axis});
+        next
+    }
+
+    ///Finds the cheapest object split, and the cheapest spatial split if the object split's children would overlap noticeably.
+    fn best_split(&self, references : &mut [Reference], bounds : AABB) -> Option<Split> {
+        let (object_cost, object_split, overlap) = best_object_split(references)?;
+        if overlap / self.root_area > MIN_OVERLAP {
+            if let Some((spatial_cost, spatial_split)) = self.best_spatial_split(references, bounds) {
+                if spatial_cost < object_cost {
+                    return Some(spatial_split);
+                }
+            }
+        }
+        Some(object_split)
+    }
+
+    ///Finds the cheapest plane through the node at the boundaries between BINS equal slices along each axis.
+    ///
+    /// Each reference is clipped to every slice it crosses, except media, which are counted whole in the slice holding their center.
+    fn best_spatial_split(&self, references : &[Reference], bounds : AABB) -> Option<(Float, Split)> {
+        let mut best : Option<(Float, Split)> = None;
+        for axis in 0..3 {
+            let start = bounds.minimum[axis];
+            let extent = bounds.maximum[axis] - start;
+            if !(extent > 0.0 && extent.is_finite()) {
+                continue;
+            }
+            let bin = |x : Float| (((x - start) / extent * BINS as Float) as usize).min(BINS - 1);
+            let plane = |b : usize| start + extent * b as Float / BINS as Float;
+
+            let mut boxes : [Option<AABB> ; BINS] = [None ; BINS];
+            let mut entries = [0 ; BINS];
+            let mut exits = [0 ; BINS];
+            for reference in references {
+                let (first, last) = if self.splittable(reference) {
+                    (bin(reference.aabb.minimum[axis]), bin(reference.aabb.maximum[axis]))
+                } else {
+                    let b = bin(reference.centroid(axis));
+                    boxes[b] = Some(grow(boxes[b], reference.aabb));
+                    entries[b] += 1;
+                    exits[b] += 1;
+                    continue;
+                };
+                for (b, bin_box) in boxes.iter_mut().enumerate().take(last + 1).skip(first) {
+                    *bin_box = Some(grow(*bin_box, clip(reference.aabb, axis, plane(b), plane(b + 1))));
+                }
+                entries[first] += 1;
+                exits[last] += 1;
+            }
+
+            //Sweep from the right to find the boxes and counts to the right of each plane, then from the left to price each one
+            let mut right_boxes : [Option<AABB> ; BINS] = [None ; BINS];
+            let mut right_counts = [0 ; BINS];
+            let mut right_box = None;
+            let mut right_count = 0;
+            for b in (1..BINS).rev() {
+                right_box = boxes[b].map(|aabb| grow(right_box, aabb)).or(right_box);
+                right_count += exits[b];
+                right_boxes[b] = right_box;
+                right_counts[b] = right_count;
+            }
+
+            let mut left_box = None;
+            let mut left_count = 0;
+            for b in 1..BINS {
+                left_box = boxes[b - 1].map(|aabb| grow(left_box, aabb)).or(left_box);
+                left_count += entries[b - 1];
+                if let (Some(left), Some(right)) = (left_box, right_boxes[b]) {
+                    let cost = left.surface_area() * left_count as Float + right.surface_area() * right_counts[b] as Float;
+                    if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+                        best = Some((cost, Split::Spatial(axis, plane(b))));
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    ///Divides the references in two with the given split, or returns None if either side would be empty or hold every reference.
+    fn partition(&self, references : &mut [Reference], split : Split) -> Option<(usize, Vec<Reference>, Vec<Reference>)> {
+        let (axis, left, right) = match split {
+            Split::Object(axis, count) => {
+                references.sort_by(|a, b| a.centroid(axis).total_cmp(&b.centroid(axis)));
+                (axis, references[..count].to_vec(), references[count..].to_vec())
+            },
+            Split::Spatial(axis, position) => {
+                let mut left = vec![];
+                let mut right = vec![];
+                for reference in references.iter() {
+                    if !self.splittable(reference) {
+                        if reference.centroid(axis) < position {left.push(*reference)} else {right.push(*reference)}
+                    } else if reference.aabb.maximum[axis] <= position {
+                        left.push(*reference);
+                    } else if reference.aabb.minimum[axis] >= position {
+                        right.push(*reference);
+                    } else {
+                        left.push(Reference {index : reference.index, aabb : clip(reference.aabb, axis, Float::NEG_INFINITY, position)});
+                        right.push(Reference {index : reference.index, aabb : clip(reference.aabb, axis, position, Float::INFINITY)});
+                    }
+                }
+                (axis, left, right)
+            },
+        };
+
+        let n = references.len();
+        if left.is_empty() || right.is_empty() || left.len() == n || right.len() == n {
+            return None;
+        }
+        Some((axis, left, right))
+    }
+
+    ///Returns whether the object behind a reference may be split across nodes.
+    fn splittable(&self, reference : &Reference) -> bool {
+        !matches!(self.objects[reference.index], Hittable::Medium(..) | Hittable::HeterogeneousMedium(..))
+    }
+}
+
+///Finds the cheapest way to split the references in two after sorting them along an axis, returning its cost,
+///
+/// the split, and the surface area of the overlap between the two sides' boxes.
+fn best_object_split(references : &mut [Reference]) -> Option<(Float, Split, Float)> {
+    let n = references.len();
+    let mut best : Option<(Float, Split, Float)> = None;
+    for axis in 0..3 {
+        references.sort_by(|a, b| a.centroid(axis).total_cmp(&b.centroid(axis)));
+
+        let mut right_boxes = vec![references[n - 1].aabb ; n];
+        for i in (0..n - 1).rev() {
+            right_boxes[i] = surrounding_box(references[i].aabb, right_boxes[i + 1]);
+        }
+
+        let mut left_box = references[0].aabb;
+        for count in 1..n {
+            let right_box = right_boxes[count];
+            let cost = left_box.surface_area() * count as Float + right_box.surface_area() * (n - count) as Float;
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, Split::Object(axis, count), overlap(left_box, right_box)));
+            }
+            left_box = surrounding_box(left_box, references[count].aabb);
+        }
+    }
+    best
+}
+
+///Splits the references in half by their centers along the longest axis of bounds. Always leaves both sides with fewer references.
+fn median_split(mut references : Vec<Reference>, bounds : AABB) -> (usize, Vec<Reference>, Vec<Reference>) {
+    let d = bounds.maximum - bounds.minimum;
+    let axis = if d.x >= d.y && d.x >= d.z {0} else if d.y >= d.z {1} else {2};
+    references.sort_by(|a, b| a.centroid(axis).total_cmp(&b.centroid(axis)));
+    let right = references.split_off(references.len() / 2);
+    (axis, references, right)
+}
+
+fn union(references : &[Reference]) -> Option<AABB> {
+    references.iter().map(|reference| reference.aabb).reduce(surrounding_box)
+}
+
+fn grow(aabb : Option<AABB>, other : AABB) -> AABB {
+    match aabb {
+        Some(aabb) => surrounding_box(aabb, other),
+        None => other,
+    }
+}
+
+///Returns the part of a box between low and high along an axis.
+fn clip(aabb : AABB, axis : usize, low : Float, high : Float) -> AABB {
+    let mut clipped = aabb;
+    clipped.minimum[axis] = aabb.minimum[axis].max(low);
+    clipped.maximum[axis] = aabb.maximum[axis].min(high);
+    clipped
+}
+
+///Returns the surface area of the region two boxes share, or 0 if they don't overlap.
+fn overlap(a : AABB, b : AABB) -> Float {
+    let mut shared = a;
+    for axis in 0..3 {
+        shared.minimum[axis] = a.minimum[axis].max(b.minimum[axis]);
+        shared.maximum[axis] = a.maximum[axis].min(b.maximum[axis]);
+        if shared.maximum[axis] <= shared.minimum[axis] {
+            return 0.0;
+        }
+    }
+    shared.surface_area()
+}
@@ -0,0 +1,198 @@
+use crate::accelerator::Accelerator;
+use crate::hitting::{Hittable, HitRecord};
+use crate::vec3::{Vec3, Point3, Color, random_float, dot, cross, orthonormal_basis, Float};
+use crate::materials::Material;
+use crate::environment::Environment;
+use crate::lights::Light;
+use crate::guiding::Guide;
+use crate::atmosphere::Atmosphere;
+use crate::fog::Fog;
+use crate::ray::Ray;
+use crate::textures::Textures;
+use crate::vec3::consts::{PI, SQRT_2};
+
+///Everything a ray can interact with while rendering: the objects (stored in an acceleration structure), the textures their materials
+///
+/// and media refer to by id, and the lights illuminating them, along with the path guide learned for them (if any), and the atmosphere and fog hazing distant objects (if any).
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub objects : Accelerator,
+    pub textures : Textures,
+    pub environment : Option<Environment>,
+    pub lights : Vec<Light>,
+    pub emitters : Vec<Hittable>,
+    pub portals : Vec<Hittable>,
+    pub guide : Option<Guide>,
+    pub atmosphere : Option<Atmosphere>,
+    pub fog : Option<Fog>,
+}
+
+impl Scene {
+    pub fn new(objects : Accelerator, environment : Option<Environment>, textures : Textures) -> Scene {
+        Scene {
+            objects,
+            textures,
+            environment,
+            lights : vec![],
+            emitters : vec![],
+            portals : vec![],
+            guide : None,
+            atmosphere : None,
+            fog : None,
+        }
+    }
+
+    ///Determines if a ray hits any of the scene's objects, filling in rec with the closest hit.
+    pub fn hit(&self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord) -> bool {
+        self.objects.hit(r, t_min, t_max, rec, &self.textures)
+    }
+
+    ///Works out how wide the cone of the ray r that made a hit is there in texture coordinates (see HitRecord::footprint), from how far it went
+    ///
+    /// and how many scene units a unit of the object's texture coordinates spans, for spheres, rectangles and triangles (other objects
+    /// are looked up at their sharpest). Then picks the layer a hit on a layered material (see Material::layer()) shades with this time,
+    /// bending its normal by the material's normal map. Triangles' tangents follow their texture coordinates, while other shapes' are arbitrary.
+    pub fn shade(&self, r : Ray, rec : &mut HitRecord) {
+        rec.footprint = 0.0;
+        if r.spread > 0.0 {
+            let scale = match self.objects.object(rec.object) {
+                Some(Hittable::Sphere(_, _, radius) | Hittable::MovingSphere(.., radius)) => Some(PI * SQRT_2 * radius.abs()),
+                Some(Hittable::XYRect(_, a0, a1, b0, b1, _) | Hittable::XZRect(_, a0, a1, b0, b1, _) | Hittable::YZRect(_, a0, a1, b0, b1, _)) =>
+                    Some(((a1 - a0) * (b1 - b0)).abs().sqrt()),
+                Some(Hittable::Triangle(_, [a, b, c], [uv0, uv1, uv2])) => {
+                    let area = cross(*b - *a, *c - *a).length();
+                    let uv_area = ((uv1.0 - uv0.0) * (uv2.1 - uv0.1) - (uv2.0 - uv0.0) * (uv1.1 - uv0.1)).abs();
+                    (uv_area > 0.0).then(|| (area / uv_area).sqrt())
+                },
+                _ => None,
+            };
+            if let Some(scale) = scale.filter(|scale| *scale > 0.0) {
+                //Cones meeting a surface at a slant cover more of it, up to 4 times as much, as lookups blur evenly in every direction
+                let direction = r.direction.unit_vector();
+                let cos = dot(direction, rec.normal).abs().max(0.25);
+                rec.footprint = rec.t * r.direction.length() * r.spread / (cos * scale);
+            }
+        }
+        if !matches!(rec.mat, Material::Pbr(..)) {
+            return;
+        }
+        let (tangent, bitangent) = match self.objects.object(rec.object) {
+            Some(Hittable::Triangle(_, [a, b, c], [uv0, uv1, uv2])) => {
+                let (edge1, edge2) = (*b - *a, *c - *a);
+                let (du1, dv1, du2, dv2) = (uv1.0 - uv0.0, uv1.1 - uv0.1, uv2.0 - uv0.0, uv2.1 - uv0.1);
+                let determinant = du1 * dv2 - du2 * dv1;
+                let tangent = (edge1 * dv2 - edge2 * dv1) / determinant;
+                let tangent = tangent - rec.normal * dot(rec.normal, tangent);
+                if determinant.abs() < 1e-12 || tangent.near_zero() {
+                    orthonormal_basis(rec.normal)
+                } else {
+                    let tangent = tangent.unit_vector();
+                    let bitangent = cross(rec.normal, tangent);
+                    let handedness = dot(bitangent, (edge2 * du1 - edge1 * du2) / determinant);
+                    (tangent, if handedness < 0.0 {-bitangent} else {bitangent})
+                }
+            },
+            _ => orthonormal_basis(rec.normal),
+        };
+        (rec.mat, rec.normal) = rec.mat.layer(r, rec, tangent, bitangent, &self.textures);
+    }
+
+    ///Determines which of a packet of at most tree::MAX_PACKET rays hit any of the scene's objects, as Accelerator::hit_packet() does.
+    pub fn hit_packet(&self, rays : &[Ray], t_min : Float, t_max : Float, recs : &mut [HitRecord]) -> u32 {
+        self.objects.hit_packet(rays, t_min, t_max, recs, &self.textures)
+    }
+
+    ///Determines which of a packet of at most tree::MAX_PACKET shadow rays are blocked before their own t_max, as Accelerator::occluded_packet() does.
+    pub fn occluded_packet(&self, rays : &[Ray], t_min : Float, t_max : &[Float]) -> u32 {
+        self.objects.occluded_packet(rays, t_min, t_max, &self.textures)
+    }
+
+    ///Returns how much of the light from a point t along the ray reaches its origin through the air, and the light the air scatters in
+    ///
+    /// along the way, or None if the scene's air is clear. Rays that escape the scene have a t of infinity, and are only dimmed by fog,
+    /// since the atmosphere's sky already includes its haze. Light sampled directly isn't dimmed by the air on its way to a surface.
+    pub fn air(&self, r : Ray, t : Float) -> Option<(Color, Color)> {
+        let atmosphere = self.atmosphere.filter(|_| t.is_finite()).map(|atmosphere| atmosphere.aerial_perspective(r, t));
+        let fog = self.fog.map(|fog| fog.along(r, t));
+        match (atmosphere, fog) {
+            //The fog is taken to lie in front of the atmosphere's haze
+            (Some((haze, hazed)), Some((fogged, fog))) => Some((haze * fogged, hazed * fogged + fog)),
+            (air, None) | (None, air) => air,
+        }
+    }
+
+    ///Adds an analytic light to the scene.
+    pub fn add_light(&mut self, light : Light) {
+        self.lights.push(light);
+    }
+
+    ///Registers an emissive object so it can be sampled directly. The object must also be part of the scene's objects.
+    pub fn add_emitter(&mut self, emitter : Hittable) {
+        self.emitters.push(emitter);
+    }
+
+    ///Picks a point on a random emitter, as seen from origin.
+    pub fn random_emitter_point(&self, origin : Point3) -> Option<Point3> {
+        if self.emitters.is_empty() {
+            return None;
+        }
+        let index = ((random_float() * self.emitters.len() as Float) as usize).min(self.emitters.len() - 1);
+        Some(self.emitters[index].random_point_on(origin))
+    }
+
+    ///Returns the solid angle pdf with which random_emitter_point() would pick a point in the given direction from origin.
+    pub fn emitter_pdf(&self, origin : Point3, direction : Vec3) -> Float {
+        if self.emitters.is_empty() {
+            return 0.0;
+        }
+        let total : Float = self.emitters.iter().map(|e| e.pdf_value(origin, direction)).sum();
+        total / self.emitters.len() as Float
+    }
+
+    ///Registers a portal: a rectangle (such as a window or doorway) that the environment lights the scene through.
+    /// 
+    /// Once any portals are registered, the environment is sampled through them rather than over the whole sky,
+    /// which greatly reduces noise in interiors lit by the sky. Portals are not part of the scene's objects.
+    pub fn add_portal(&mut self, portal : Hittable) {
+        self.portals.push(portal);
+    }
+
+    ///Samples a direction towards the environment as seen from origin, through a random portal if there are any.
+    /// 
+    /// Returns the direction along with its solid angle pdf.
+    pub fn sample_environment(&self, origin : Point3) -> Option<(Vec3, Float)> {
+        let env = self.environment.as_ref()?;
+        if self.portals.is_empty() {
+            return Some(env.sample());
+        }
+
+        let index = ((random_float() * self.portals.len() as Float) as usize).min(self.portals.len() - 1);
+        let direction = self.portals[index].random_point_on(origin) - origin;
+        Some((direction, self.environment_pdf(origin, direction)))
+    }
+
+    ///Returns the solid angle pdf with which sample_environment() would pick the given direction from origin.
+    pub fn environment_pdf(&self, origin : Point3, direction : Vec3) -> Float {
+        let env = match &self.environment {
+            Some(env) => env,
+            None => return 0.0,
+        };
+        if self.portals.is_empty() {
+            return env.pdf_value(direction);
+        }
+
+        let total : Float = self.portals.iter().map(|p| p.pdf_value(origin, direction)).sum();
+        total / self.portals.len() as Float
+    }
+
+    ///Returns the center and radius of a sphere enclosing every object in the scene.
+    pub fn bounding_sphere(&self) -> (Point3, Float) {
+        match self.objects.bounding_box() {
+            Some(aabb) => {
+                let center = (aabb.minimum + aabb.maximum) / 2.0;
+                (center, (aabb.maximum - center).length())
+            },
+            None => (Point3::new(0.0, 0.0, 0.0), 1.0),
+        }
+    }
+}
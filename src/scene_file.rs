@@ -0,0 +1,494 @@
+/*
+Module to store the scene file loader, which reads a scene's camera, render settings, environment, textures, materials, objects and lights
+from a JSON file, so that scenes can be changed without recompiling. A file looks like
+
+{
+    "render" : {"width" : 800, "aspect_ratio" : 1.0, "samples_per_pixel" : 1000, "max_depth" : 50, "seed" : 7, "output" : "image.png"},
+    "camera" : {"lookfrom" : [278, 278, -800], "lookat" : [278, 278, 0], "vup" : [0, 1, 0], "vfov" : 40, "aperture" : 0, "focus_distance" : 20, "autofocus" : true},
+    "environment" : {"map" : "sky.hdr", "intensity" : 1.0},
+    "textures" : {
+        "earth" : {"type" : "image", "path" : "../images/earthmap.jpeg"},
+        "grey" : {"type" : "solid", "color" : [0.5, 0.5, 0.5]},
+        "tiles" : {"type" : "checker", "odd" : [0, 0, 0], "even" : [1, 1, 1]},
+        "marble" : {"type" : "noise", "scale" : 4},
+        "veins" : {"type" : "preset", "name" : "fine-marble"},
+        "atlas" : {"type" : "image", "path" : "atlas.png"},
+        "bark" : {"type" : "region", "texture" : "atlas", "min" : [0, 0], "max" : [0.5, 1], "wrap" : ["repeat", "clamp"]},
+        "heat" : {"type" : "grid", "size" : [2, 2, 1], "min" : [0, 0, 0], "max" : [555, 555, 555], "values" : [0, 0.5, 0.8, 1]}
+    },
+    "materials" : {
+        "earth" : {"type" : "lambertian", "texture" : "earth"},
+        "steel" : {"type" : "metal", "color" : [0.8, 0.8, 0.8], "fuzz" : 0.1},
+        "glass" : {"type" : "dielectric", "ior" : 1.5, "color" : [1, 1, 1], "dispersion" : 0.0, "priority" : 0},
+        "lamp" : {"type" : "light", "texture" : "grey"},
+        "fog" : {"type" : "isotropic", "texture" : "grey", "g" : 0.0},
+        "ground" : {"type" : "shadow_catcher", "texture" : "grey"},
+        "fire" : {"type" : "emissive_isotropic", "texture" : "grey", "g" : 0.0, "temperature" : "heat", "max_temperature" : 2500, "intensity" : 4},
+        "gold" : {"type" : "preset"},
+        "ring" : {"type" : "preset", "name" : "brushed-gold"}
+    },
+    "objects" : [
+        {"type" : "sphere", "material" : "earth", "center" : [450, 200, 10], "radius" : 30},
+        {"type" : "xz_rect", "material" : "lamp", "x0" : 213, "x1" : 343, "z0" : 227, "z1" : 332, "k" : 554, "emitter" : true},
+        {"type" : "medium", "material" : "fog", "density" : 0.01, "boundary" : {"type" : "box", "min" : [0, 0, 0], "max" : [555, 555, 555]}}
+    ],
+    "lights" : [
+        {"type" : "point", "position" : [278, 500, 278], "color" : [10, 10, 10], "falloff" : 2}
+    ]
+}
+
+where every section, and every setting in render, camera and environment, is optional. Render can also set the self-intersection "epsilon",
+with "normal_offset" to start rays leaving surfaces that far off them (see ray::SpawnOffset), for scenes much larger or smaller than the default suits. In place of a map, the environment can have an "atmosphere"
+lighting the scene with a sky worked out from how sunlight scatters in the air, which also hazes distant objects (see atmosphere.rs), given its sun_direction
+and any of planet_radius, height, rayleigh_height, mie_height, rayleigh_scattering, mie_scattering, mie_g, sun_intensity, altitude, scale and ground_albedo.
+Or it can have a "sky", the analytic daylight sky of sky.rs, given any of its sun_direction, turbidity (from 2 for very clear air to 10 for haze) and ground_albedo.
+It can also have a "fog" fading distant objects into its color, as {"color" : [0.7, 0.75, 0.8], "density" : 0.01, "falloff" : 0.5, "base" : 0}, where all but density
+can be left out (see fog.rs). Other objects are moving_sphere (center0, center1, time0, time1, radius),
+xy_rect (x0, x1, y0, y1, k), yz_rect (y0, y1, z0, z1, k), box (min, max) and heterogeneous_medium (boundary, density, texture);
+other lights are directional (direction, color, angle) and spot (position, direction, inner, outer, color), and point and spot lights can take an "ies" file.
+Region textures show part of an earlier texture, such as one chart of a texture atlas, from its min to its max texture coordinates,
+with coordinates outside 0 to 1 wrapping within the region (repeat, mirror or clamp, for both axes or each in turn).
+Grid textures hold values (x fastest, then y, then z) through a box in space, such as a simulated fire's temperatures.
+Dielectrics with a priority above 0 can be nested inside each other, as water in a glass is (see materials::Interior).
+Emissive isotropic materials are media that glow from within, either with the color of an "emission" texture, or with the color of a black body
+at the fraction of max_temperature their "temperature" texture gives, reaching the given intensity (1 if left out) at max_temperature.
+Pbr materials follow glTF's metallic-roughness model (see Material::Pbr), with their base color "texture", and either a "metallic_roughness" texture
+or "metallic" and "roughness" numbers (0 and 0.5 if left out), and optionally a tangent space "normal" map.
+Textures and materials of type preset use the preset of the given name, or else of their own name (see presets.rs), such as gold or glass.
+Objects with "emitter" set are sampled directly as lights. Paths are relative to the scene file.
+
+Objects and lights can also be placed procedurally, by a Rhai "script" (see script.rs) written as a string, or as an array of lines
+joined into one, which runs once the file's own objects are built, adding more with the same descriptions. For example, a grid of spheres
+with random materials, leaving out those too close to the center:
+
+"script" : [
+    "for i in 0..22 {",
+    "    for j in 0..22 {",
+    "        let x = i - 11 + random(0, 0.9);",
+    "        let z = j - 11 + random(0, 0.9);",
+    "        if x * x + z * z > 4 {",
+    "            sphere(pick([\"matte\", \"steel\", \"glass\"]), [x, 0.2, z], 0.2);",
+    "        }",
+    "    }",
+    "}"
+]
+*/
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::cache::load_image;
+use tracing::{info, info_span};
+use serde_json::Value;
+use crate::vec3::{Vec3, Point3, Color, Float};
+use crate::ray::SpawnOffset;
+use crate::color::srgb_to_linear;
+use crate::textures::{Texture, Textures, Wrap, VoxelGrid};
+use crate::materials::{Material, Emission};
+use crate::hitting::Hittable;
+use crate::lights::Light;
+use crate::ies::IesProfile;
+use crate::environment::Environment;
+use crate::atmosphere::Atmosphere;
+use crate::sky::Sky;
+use crate::fog::Fog;
+use crate::accelerator::AcceleratorKind;
+use crate::scene::Scene;
+use crate::presets;
+use crate::script;
+use crate::error;
+
+///Settings a scene file can give. Any it leaves out keep the values set in main().
+#[derive(Debug, Clone, Default)]
+pub struct FileSettings {
+    pub width : Option<u32>,
+    pub aspect_ratio : Option<Float>,
+    pub samples_per_pixel : Option<i32>,
+    pub max_depth : Option<i32>,
+    pub seed : Option<u64>,
+    pub output : Option<String>,
+    pub lookfrom : Option<Point3>,
+    pub lookat : Option<Point3>,
+    pub vup : Option<Vec3>,
+    pub vfov : Option<Float>,
+    pub aperture : Option<Float>,
+    pub focus_distance : Option<Float>,
+    pub autofocus : Option<bool>,
+    pub epsilon : Option<Float>,
+    pub spawn_offset : Option<SpawnOffset>,
+    pub environment_map : Option<String>,
+    pub environment_intensity : Option<Float>,
+    pub atmosphere : Option<Atmosphere>,
+    pub sky : Option<Sky>,
+    pub fog : Option<Fog>,
+}
+
+///Scene loaded from a file: its settings, the textures its materials refer to, its objects (with whether each is an emitter) and its lights.
+///Textures are loaded with the file, so building the scene again, as for every frame of an animation, doesn't load them again.
+#[derive(Debug, Clone)]
+pub struct SceneFile {
+    pub settings : FileSettings,
+    pub textures : Textures,
+    pub objects : Vec<(Hittable, bool)>,
+    pub lights : Vec<Light>,
+}
+
+impl SceneFile {
+
+    ///Loads a scene file, along with the images and IES profiles it refers to. Files ending in .xml are imported as Mitsuba scenes,
+    /// files ending in .usda or .usd as USD scenes, and files ending in .gltf or .glb as glTF scenes.
+    pub fn load(path : &str) -> Result<SceneFile> {
+        let _span = info_span!("load_scene", path).entered();
+        let extension = Path::new(path).extension().map_or(String::new(), |extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_str() {
+            "xml" => return crate::mitsuba::load(path),
+            "usda" | "usd" => return crate::usd::load(path),
+            "gltf" | "glb" => return crate::gltf::load(path),
+            _ => {},
+        }
+        let directory = Path::new(path).parent().map_or(PathBuf::new(), Path::to_path_buf);
+        SceneFile::parse(&read_to_string(path)?, &directory)
+    }
+
+    ///Reads a JSON scene file's text, loading the files it refers to from paths relative to directory.
+    pub fn parse(text : &str, directory : &Path) -> Result<SceneFile> {
+        let json : Value = serde_json::from_str(text).map_err(|error| invalid(&error.to_string()))?;
+        let resolve = |file : &str| directory.join(file);
+
+        let settings = settings(&json, &resolve)?;
+
+        let mut added = Textures::new();
+        let mut textures = HashMap::new();
+        for (name, description) in entries(&json, "textures")? {
+            let texture = texture(name, description, &textures, &resolve)?;
+            textures.insert(name.as_str(), added.add_named(name, texture));
+        }
+
+        let mut materials = HashMap::new();
+        for (name, description) in entries(&json, "materials")? {
+            materials.insert(name.as_str(), material(name, description, &textures, &mut added)?);
+        }
+
+        let mut objects = vec![];
+        let builder = Builder {materials : &materials, textures : &textures};
+        for description in list(&json, "objects")? {
+            objects.push(builder.objects(description)?);
+        }
+        let mut lights = list(&json, "lights")?.iter().map(|description| light(description, &resolve)).collect::<Result<Vec<_>>>()?;
+
+        if let Some(source) = json.get("script") {
+            let placed = script::run(&lines(source)?, settings.seed.unwrap_or(0))?;
+            for description in &placed.objects {
+                objects.push(builder.objects(description)?);
+            }
+            for description in &placed.lights {
+                lights.push(light(description, &resolve)?);
+            }
+        }
+        info!(textures = textures.len(), materials = materials.len(), objects = objects.len(), lights = lights.len(), "loaded scene file");
+        Ok(SceneFile {settings, textures : added, objects, lights})
+    }
+
+    ///Builds the scene, with the given environment and acceleration structure.
+    pub fn scene(&self, environment : Option<Environment>, accelerator : AcceleratorKind) -> error::Result<Scene> {
+        let mut objects : Vec<Hittable> = self.objects.iter().map(|(object, _)| object.clone()).collect();
+        let mut world = Scene::new(accelerator.build(&mut objects, &self.textures)?, environment, self.textures.clone());
+        for (object, emitter) in &self.objects {
+            if *emitter {
+                world.add_emitter(object.clone());
+            }
+        }
+        for light in &self.lights {
+            world.add_light(light.clone());
+        }
+        Ok(world)
+    }
+}
+
+fn settings(json : &Value, resolve : &impl Fn(&str) -> PathBuf) -> Result<FileSettings> {
+    let render = json.get("render");
+    let camera = json.get("camera");
+    let environment = json.get("environment");
+    let render_number = |key| optional(render, key, number);
+    let camera_number = |key| optional(camera, key, number);
+    let path = |value : &Value| text(value).map(|file| resolve(file).to_string_lossy().into_owned());
+
+    Ok(FileSettings {
+        width : render_number("width")?.map(|x| x as u32),
+        aspect_ratio : render_number("aspect_ratio")?,
+        samples_per_pixel : render_number("samples_per_pixel")?.map(|x| x as i32),
+        max_depth : render_number("max_depth")?.map(|x| x as i32),
+        seed : render_number("seed")?.map(|x| x as u64),
+        output : optional(render, "output", |value| text(value).map(String::from))?,
+        lookfrom : optional(camera, "lookfrom", vector)?,
+        lookat : optional(camera, "lookat", vector)?,
+        vup : optional(camera, "vup", vector)?,
+        vfov : camera_number("vfov")?,
+        aperture : camera_number("aperture")?,
+        focus_distance : camera_number("focus_distance")?,
+        autofocus : optional(camera, "autofocus", |value| value.as_bool().ok_or_else(|| invalid("autofocus must be true or false")))?,
+        epsilon : render_number("epsilon")?,
+        spawn_offset : optional(render, "normal_offset", |value| match value.as_bool() {
+            Some(true) => Ok(SpawnOffset::NormalOffset),
+            Some(false) => Ok(SpawnOffset::Distance),
+            None => Err(invalid("normal_offset must be true or false")),
+        })?,
+        environment_map : optional(environment, "map", path)?,
+        environment_intensity : optional(environment, "intensity", number)?,
+        atmosphere : optional(environment, "atmosphere", atmosphere)?,
+        sky : optional(environment, "sky", |sky| {
+            let defaults = Sky::default();
+            Ok(Sky::new(
+                optional(Some(sky), "turbidity", number)?.unwrap_or(defaults.turbidity),
+                optional(Some(sky), "sun_direction", vector)?.unwrap_or(defaults.sun_direction),
+                optional(Some(sky), "ground_albedo", vector)?.unwrap_or(defaults.ground_albedo),
+            ))
+        })?,
+        fog : optional(environment, "fog", |fog| Ok(Fog::new(
+            optional(Some(fog), "color", vector)?.unwrap_or(Color::new(0.5, 0.5, 0.5)),
+            number(field(fog, "density")?)?,
+            optional(Some(fog), "falloff", number)?.unwrap_or(0.0),
+            optional(Some(fog), "base", number)?.unwrap_or(0.0),
+        )))?,
+    })
+}
+
+fn atmosphere(description : &Value) -> Result<Atmosphere> {
+    let mut atmosphere = Atmosphere::earth(vector(field(description, "sun_direction")?)?);
+    let settings = [
+        ("planet_radius", &mut atmosphere.planet_radius),
+        ("height", &mut atmosphere.height),
+        ("rayleigh_height", &mut atmosphere.rayleigh_height),
+        ("mie_height", &mut atmosphere.mie_height),
+        ("mie_scattering", &mut atmosphere.mie_scattering),
+        ("mie_g", &mut atmosphere.mie_g),
+        ("sun_intensity", &mut atmosphere.sun_intensity),
+        ("altitude", &mut atmosphere.altitude),
+        ("scale", &mut atmosphere.scale),
+    ];
+    for (key, setting) in settings {
+        if let Some(value) = optional(Some(description), key, number)? {
+            *setting = value;
+        }
+    }
+    if let Some(color) = optional(Some(description), "rayleigh_scattering", vector)? {
+        atmosphere.rayleigh_scattering = color;
+    }
+    if let Some(color) = optional(Some(description), "ground_albedo", vector)? {
+        atmosphere.ground_albedo = color;
+    }
+    if atmosphere.planet_radius <= 0.0 || atmosphere.height <= 0.0 || atmosphere.rayleigh_height <= 0.0 || atmosphere.mie_height <= 0.0 {
+        return Err(invalid("atmosphere planet_radius, height, rayleigh_height and mie_height must be above 0"));
+    }
+    Ok(atmosphere)
+}
+
+fn texture(name : &str, description : &Value, textures : &HashMap<&str, usize>, resolve : &impl Fn(&str) -> PathBuf) -> Result<Texture> {
+    Ok(match kind(description)? {
+        "image" => {
+            Texture::Image(load_image(resolve(text(field(description, "path")?)?)).map_err(|error| invalid(&error.to_string()))?)
+        },
+        "solid" => Texture::Solid(vector(field(description, "color")?)?),
+        "checker" => Texture::Checker(vector(field(description, "odd")?)?, vector(field(description, "even")?)?),
+        "noise" => Texture::Noise(Box::default(), number(field(description, "scale")?)?),
+        "region" => {
+            let corner = |key : &str| match field(description, key)?.as_array().map(Vec::as_slice) {
+                Some([u, v]) => Ok((number(u)?, number(v)?)),
+                _ => Err(invalid(&format!("{} must be an array of 2 numbers", key))),
+            };
+            let ((u0, v0), (u1, v1)) = (corner("min")?, corner("max")?);
+            if u0 >= u1 || v0 >= v1 {
+                return Err(invalid(&format!("texture {} has a region with nothing in it", name)));
+            }
+            let wrap = |json : &Value| match text(json)? {
+                "repeat" => Ok(Wrap::Repeat),
+                "mirror" => Ok(Wrap::Mirror),
+                "clamp" => Ok(Wrap::Clamp),
+                other => Err(invalid(&format!("unknown wrap {} (expected repeat, mirror or clamp)", other))),
+            };
+            let wrap = match description.get("wrap") {
+                Some(Value::Array(axes)) if axes.len() == 2 => [wrap(&axes[0])?, wrap(&axes[1])?],
+                Some(json) => [wrap(json)? ; 2],
+                None => [Wrap::Repeat ; 2],
+            };
+            Texture::Region(reference(description, "texture", textures)?, [u0, v0, u1, v1], wrap)
+        },
+        "grid" => {
+            let size = match field(description, "size")?.as_array().map(Vec::as_slice) {
+                Some([x, y, z]) => [x, y, z].map(|n| n.as_f64().filter(|n| *n >= 1.0 && n.fract() == 0.0).map(|n| n as usize)),
+                _ => [None ; 3],
+            };
+            let [Some(x), Some(y), Some(z)] = size else {
+                return Err(invalid(&format!("texture {} must have a size of 3 whole numbers of at least 1", name)));
+            };
+            let values = field(description, "values")?.as_array().ok_or_else(|| invalid("values must be an array"))?
+                .iter().map(number).collect::<Result<Vec<_>>>()?;
+            if values.len() != x * y * z {
+                return Err(invalid(&format!("texture {} has {} values, but a {}x{}x{} grid needs {}", name, values.len(), x, y, z, x * y * z)));
+            }
+            Texture::Grid(VoxelGrid::new(values, [x, y, z], vector(field(description, "min")?)?, vector(field(description, "max")?)?))
+        },
+        "preset" => {
+            let preset = description.get("name").map(text).transpose()?.unwrap_or(name);
+            presets::texture(preset).ok_or_else(|| invalid(&format!("unknown texture preset {} (expected one of {})", preset, presets::TEXTURE_NAMES.join(", "))))?
+        },
+        other => return Err(invalid(&format!("unknown texture type {}", other))),
+    })
+}
+
+fn material(name : &str, description : &Value, textures : &HashMap<&str, usize>, added : &mut Textures) -> Result<Material> {
+    let texture = || reference(description, "texture", textures);
+    Ok(match kind(description)? {
+        "lambertian" => Material::Lambertian(texture()?),
+        "metal" => Material::Metal(vector(field(description, "color")?)?, number(field(description, "fuzz")?)?),
+        "dielectric" => Material::Dielectric(
+            optional(Some(description), "color", vector)?.unwrap_or(Color::new(1.0, 1.0, 1.0)),
+            number(field(description, "ior")?)?,
+            optional(Some(description), "dispersion", number)?.unwrap_or(0.0),
+            optional(Some(description), "priority", number)?.unwrap_or(0.0).max(0.0) as u32,
+        ),
+        "light" => Material::Light(texture()?),
+        "isotropic" => Material::Isotropic(texture()?, optional(Some(description), "g", number)?.unwrap_or(0.0)),
+        "shadow_catcher" => Material::ShadowCatcher(texture()?),
+        "pbr" => {
+            //Data is stored the way sRGB colors are, for Material::Pbr to read back
+            let data = |color : Color, added : &mut Textures| added.add(Texture::Solid(Color::new(srgb_to_linear(color.x), srgb_to_linear(color.y), srgb_to_linear(color.z))));
+            let metallic_roughness = match description.get("metallic_roughness") {
+                Some(_) => reference(description, "metallic_roughness", textures)?,
+                None => {
+                    let metallic = optional(Some(description), "metallic", number)?.unwrap_or(0.0);
+                    let roughness = optional(Some(description), "roughness", number)?.unwrap_or(0.5);
+                    data(Color::new(1.0, roughness.clamp(0.0, 1.0), metallic.clamp(0.0, 1.0)), added)
+                },
+            };
+            let normal = match description.get("normal") {
+                Some(_) => reference(description, "normal", textures)?,
+                None => data(Color::new(0.5, 0.5, 1.0), added),
+            };
+            Material::Pbr(texture()?, metallic_roughness, normal)
+        },
+        "emissive_isotropic" => {
+            let emission = match (description.get("emission"), description.get("temperature")) {
+                (Some(_), None) => Emission::Texture(reference(description, "emission", textures)?),
+                (None, Some(_)) => Emission::Blackbody(
+                    reference(description, "temperature", textures)?,
+                    number(field(description, "max_temperature")?)?,
+                    optional(Some(description), "intensity", number)?.unwrap_or(1.0),
+                ),
+                _ => return Err(invalid(&format!("material {} must have either an emission or a temperature texture", name))),
+            };
+            Material::EmissiveIsotropic(texture()?, optional(Some(description), "g", number)?.unwrap_or(0.0), emission)
+        },
+        "preset" => {
+            let preset = description.get("name").map(text).transpose()?.unwrap_or(name);
+            presets::material(preset, added).ok_or_else(|| invalid(&format!("unknown material preset {} (expected one of {})", preset, presets::MATERIAL_NAMES.join(", "))))?
+        },
+        other => return Err(invalid(&format!("unknown material type {}", other))),
+    })
+}
+
+///Builds objects from their descriptions, looking up the materials and textures they use by name.
+struct Builder<'a> {
+    materials : &'a HashMap<&'a str, Material>,
+    textures : &'a HashMap<&'a str, usize>,
+}
+
+impl Builder<'_> {
+
+    ///Builds an object, with whether it is sampled as an emitter.
+    fn objects(&self, description : &Value) -> Result<(Hittable, bool)> {
+        let emitter = description.get("emitter").map_or(Ok(false), |value| value.as_bool().ok_or_else(|| invalid("emitter must be true or false")))?;
+        Ok((self.object(description)?, emitter))
+    }
+
+    fn object(&self, description : &Value) -> Result<Hittable> {
+        let mat = reference(description, "material", self.materials)?;
+        let get = |key| number(field(description, key)?);
+        let point = |key| vector(field(description, key)?);
+        Ok(match kind(description)? {
+            "sphere" => Hittable::Sphere(mat, point("center")?, get("radius")?),
+            "moving_sphere" => Hittable::MovingSphere(mat, point("center0")?, point("center1")?, get("time0")?, get("time1")?, get("radius")?),
+            "xy_rect" => Hittable::XYRect(mat, get("x0")?, get("x1")?, get("y0")?, get("y1")?, get("k")?),
+            "xz_rect" => Hittable::XZRect(mat, get("x0")?, get("x1")?, get("z0")?, get("z1")?, get("k")?),
+            "yz_rect" => Hittable::YZRect(mat, get("y0")?, get("y1")?, get("z0")?, get("z1")?, get("k")?),
+            "box" => Hittable::Box(mat, point("min")?, point("max")?),
+            "medium" => Hittable::Medium(mat, Box::new(self.object(field(description, "boundary")?)?), get("density")?),
+            "heterogeneous_medium" => Hittable::HeterogeneousMedium(mat, Box::new(self.object(field(description, "boundary")?)?), get("density")?,
+                reference(description, "texture", self.textures)?),
+            other => return Err(invalid(&format!("unknown object type {}", other))),
+        })
+    }
+}
+
+fn light(description : &Value, resolve : &impl Fn(&str) -> PathBuf) -> Result<Light> {
+    let get = |key| number(field(description, key)?);
+    let point = |key| vector(field(description, key)?);
+    let ies = || optional(Some(description), "ies", |value| {
+        let path = resolve(text(value)?);
+        IesProfile::load(&path.to_string_lossy()).map(Arc::new)
+    });
+    Ok(match kind(description)? {
+        "point" => Light::Point(point("position")?, point("color")?, optional(Some(description), "falloff", number)?.unwrap_or(2.0), ies()?),
+        "directional" => Light::Directional(point("direction")?, point("color")?, optional(Some(description), "angle", number)?.unwrap_or(0.0)),
+        "spot" => Light::Spot(point("position")?, point("direction")?, get("inner")?, get("outer")?, point("color")?, ies()?),
+        other => return Err(invalid(&format!("unknown light type {}", other))),
+    })
+}
+
+///Returns the entries of an object in the file, such as its textures, or none if it is left out.
+fn entries<'a>(json : &'a Value, key : &str) -> Result<Vec<(&'a String, &'a Value)>> {
+    json.get(key).map_or(Ok(vec![]), |value| Ok(value.as_object().ok_or_else(|| invalid(&format!("{} must be an object", key)))?.iter().collect()))
+}
+
+///Returns the values of an array in the file, such as its objects, or none if it is left out.
+fn list<'a>(json : &'a Value, key : &str) -> Result<&'a [Value]> {
+    json.get(key).map_or(Ok(&[]), |value| value.as_array().map(Vec::as_slice).ok_or_else(|| invalid(&format!("{} must be an array", key))))
+}
+
+///Returns the value of a key that may be left out, read with the given function.
+fn optional<T>(json : Option<&Value>, key : &str, read : impl Fn(&Value) -> Result<T>) -> Result<Option<T>> {
+    json.and_then(|json| json.get(key)).map(read).transpose()
+}
+
+fn field<'a>(json : &'a Value, key : &str) -> Result<&'a Value> {
+    json.get(key).ok_or_else(|| invalid(&format!("missing {}", key)))
+}
+
+fn kind(json : &Value) -> Result<&str> {
+    text(field(json, "type")?)
+}
+
+///Looks up the texture or material a key names.
+fn reference<T : Copy>(json : &Value, key : &str, names : &HashMap<&str, T>) -> Result<T> {
+    let name = text(field(json, key)?)?;
+    names.get(name).copied().ok_or_else(|| invalid(&format!("unknown {} {}", key, name)))
+}
+
+///Reads text written as a string, or as an array of lines.
+fn lines(json : &Value) -> Result<String> {
+    match json.as_array() {
+        Some(lines) => Ok(lines.iter().map(text).collect::<Result<Vec<_>>>()?.join("\n")),
+        None => text(json).map(String::from),
+    }
+}
+
+fn number(json : &Value) -> Result<Float> {
+    json.as_f64().map(|x| x as Float).ok_or_else(|| invalid("expected a number"))
+}
+
+fn text(json : &Value) -> Result<&str> {
+    json.as_str().ok_or_else(|| invalid("expected a string"))
+}
+
+fn vector(json : &Value) -> Result<Vec3> {
+    match json.as_array().map(Vec::as_slice) {
+        Some([x, y, z]) => Ok(Vec3::new(number(x)?, number(y)?, number(z)?)),
+        _ => Err(invalid("expected an array of 3 numbers")),
+    }
+}
+
+fn invalid(message : &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid scene file: {}", message))
+}
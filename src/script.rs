@@ -0,0 +1,94 @@
+/*
+Module to store the scripts of scene files, run with the Rhai scripting language as the file is loaded, so that objects and lights can be placed
+procedurally. Scripts add objects and lights with the same descriptions the file's objects and lights have, either whole with
+
+add(#{type : "medium", material : "fog", density : 0.01, boundary : #{type : "box", material : "fog", min : [0, 0, 0], max : [555, 555, 555]}});
+light(#{type : "point", position : [278, 500, 278], color : [10, 10, 10]});
+
+or through a function for each kind of object: sphere(material, center, radius), moving_sphere(material, center0, center1, time0, time1, radius),
+xy_rect(material, x0, x1, y0, y1, k), xz_rect(material, x0, x1, z0, z1, k), yz_rect(material, y0, y1, z0, z1, k) and box(material, min, max),
+with points written as arrays of 3 numbers. random() returns a number between 0 and 1, random(minimum, maximum) one between those,
+and pick(array) one of an array's values, all drawn from a generator seeded with the render seed (or 0), so a script always builds the same scene.
+Rhai's own functions (sin, sqrt, floor, PI() and so on), loops and conditions are all available.
+*/
+
+use std::cell::RefCell;
+use std::io::{Error, ErrorKind, Result};
+use std::rc::Rc;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rhai::{Engine, Dynamic, Array, Map, EvalAltResult, FLOAT, INT};
+use serde_json::Value;
+
+///Descriptions of the objects and lights a script added, in the order it added them, to be built as the file's own are.
+#[derive(Debug, Clone, Default)]
+pub struct Placed {
+    pub objects : Vec<Value>,
+    pub lights : Vec<Value>,
+}
+
+///Runs a script, with random numbers drawn from a generator seeded with seed, returning what it placed.
+pub fn run(source : &str, seed : u64) -> Result<Placed> {
+    let placed = Rc::new(RefCell::new(Placed::default()));
+    let random = Rc::new(RefCell::new(StdRng::seed_from_u64(seed)));
+    let mut engine = Engine::new();
+
+    let objects = placed.clone();
+    engine.register_fn("add", move |description : Map| -> std::result::Result<(), Box<EvalAltResult>> {
+        objects.borrow_mut().objects.push(rhai::serde::from_dynamic(&description.into())?);
+        Ok(())
+    });
+    let lights = placed.clone();
+    engine.register_fn("light", move |description : Map| -> std::result::Result<(), Box<EvalAltResult>> {
+        lights.borrow_mut().lights.push(rhai::serde::from_dynamic(&description.into())?);
+        Ok(())
+    });
+
+    let shape = |kind : &'static str, keys : &'static [&'static str]| {
+        let objects = placed.clone();
+        move |values : Vec<Dynamic>| -> std::result::Result<(), Box<EvalAltResult>> {
+            let mut description = Map::new();
+            description.insert("type".into(), kind.into());
+            description.extend(keys.iter().map(|key| (*key).into()).zip(values));
+            objects.borrow_mut().objects.push(rhai::serde::from_dynamic(&description.into())?);
+            Ok(())
+        }
+    };
+    let add = shape("sphere", &["material", "center", "radius"]);
+    engine.register_fn("sphere", move |material : Dynamic, center : Dynamic, radius : Dynamic| add(vec![material, center, radius]));
+    let add = shape("moving_sphere", &["material", "center0", "center1", "time0", "time1", "radius"]);
+    engine.register_fn("moving_sphere", move |material : Dynamic, center0 : Dynamic, center1 : Dynamic, time0 : Dynamic, time1 : Dynamic, radius : Dynamic|
+        add(vec![material, center0, center1, time0, time1, radius]));
+    let add = shape("xy_rect", &["material", "x0", "x1", "y0", "y1", "k"]);
+    engine.register_fn("xy_rect", move |material : Dynamic, x0 : Dynamic, x1 : Dynamic, y0 : Dynamic, y1 : Dynamic, k : Dynamic| add(vec![material, x0, x1, y0, y1, k]));
+    let add = shape("xz_rect", &["material", "x0", "x1", "z0", "z1", "k"]);
+    engine.register_fn("xz_rect", move |material : Dynamic, x0 : Dynamic, x1 : Dynamic, z0 : Dynamic, z1 : Dynamic, k : Dynamic| add(vec![material, x0, x1, z0, z1, k]));
+    let add = shape("yz_rect", &["material", "y0", "y1", "z0", "z1", "k"]);
+    engine.register_fn("yz_rect", move |material : Dynamic, y0 : Dynamic, y1 : Dynamic, z0 : Dynamic, z1 : Dynamic, k : Dynamic| add(vec![material, y0, y1, z0, z1, k]));
+    let add = shape("box", &["material", "min", "max"]);
+    engine.register_fn("box", move |material : Dynamic, min : Dynamic, max : Dynamic| add(vec![material, min, max]));
+
+    let generator = random.clone();
+    engine.register_fn("random", move || -> FLOAT {generator.borrow_mut().gen()});
+    let generator = random.clone();
+    engine.register_fn("random", move |minimum : Dynamic, maximum : Dynamic| -> std::result::Result<FLOAT, Box<EvalAltResult>> {
+        let (minimum, maximum) = (number(&minimum)?, number(&maximum)?);
+        Ok(minimum + (maximum - minimum) * generator.borrow_mut().gen::<FLOAT>())
+    });
+    let generator = random;
+    engine.register_fn("pick", move |values : Array| -> std::result::Result<Dynamic, Box<EvalAltResult>> {
+        if values.is_empty() {
+            return Err("pick needs at least one value to pick from".into());
+        }
+        let index = generator.borrow_mut().gen_range(0..values.len());
+        Ok(values[index].clone())
+    });
+
+    engine.run(source).map_err(|error| Error::new(ErrorKind::InvalidData, format!("Invalid scene file: script failed: {}", error)))?;
+    Ok(placed.take())
+}
+
+///Reads a number given to a script function, which may be written as a whole number.
+fn number(value : &Dynamic) -> std::result::Result<FLOAT, Box<EvalAltResult>> {
+    value.as_float().or_else(|_| value.as_int().map(|x : INT| x as FLOAT)).map_err(|kind| format!("expected a number, not {}", kind).into())
+}
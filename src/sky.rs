@@ -0,0 +1,312 @@
+/*
+Module to store the analytic daylight sky of Hosek and Wilkie ("An Analytic Model for Full Spectral Sky-Dome Radiance", 2012), which can be baked
+into an environment map to light a scene. Their model gives the sky's radiance in each color channel, at an angle theta from the zenith and gamma
+from the sun, as an extension of Perez's formula with nine parameters, adding the aureole around the sun and the brightening towards the horizon:
+
+F(theta, gamma) = (1 + A e^(B / (cos theta + 0.01))) (C + D e^(E gamma) + F cos^2 gamma + G chi(H, gamma) + I cos^(1/2) theta),
+chi(g, a) = (1 + cos^2 a) / (1 + g^2 - 2 g cos a)^(3/2)
+
+Rather than read from the tables Hosek and Wilkie fitted to their simulations, the parameters are fitted to the single scattering atmosphere
+of atmosphere.rs (see Sky::fit()), with its aerosols scaled to the turbidity, so that this sky matches the atmosphere's sun and haze.
+Radiance is in the atmosphere's units, so an environment intensity of 1 gives a reasonable daylight exposure.
+*/
+
+use crate::vec3::consts::PI;
+use crate::vec3::{Vec3, Color, dot, cross, Float, wide};
+use crate::environment::Environment;
+use crate::atmosphere::Atmosphere;
+
+///Angular radius of the sun's disc, in degrees.
+const SUN_ANGULAR_RADIUS : Float = 0.2667;
+
+///Turbidity the atmosphere's clear day (Atmosphere::earth()) has, which other turbidities scale its aerosols from.
+const EARTH_TURBIDITY : Float = 2.0;
+
+///Angles from the zenith, and from the plane through the sun and the zenith, the atmosphere is sampled at to fit the sky.
+const FIT_ELEVATIONS : usize = 16;
+const FIT_AZIMUTHS : usize = 16;
+
+///Steps of the simplex search for the parameters the fit can't solve for directly.
+const FIT_STEPS : usize = 400;
+
+///Analytic clear sky, lit by a sun in the given direction.
+///
+/// Turbidity describes how hazy the air is, from about 2 (very clear) to 10 (hazy), and ground_albedo is the color of the ground below the horizon.
+#[derive(Debug, Clone, Copy)]
+pub struct Sky {
+    pub turbidity : Float,
+    pub sun_direction : Vec3,
+    pub ground_albedo : Color,
+}
+
+///The nine parameters of the Hosek-Wilkie model for each of red, green and blue, fitted to a sky by Sky::fit().
+#[derive(Debug, Clone, Copy)]
+pub struct SkyModel {
+    pub parameters : [[Float ; 9] ; 3],
+    pub sun_direction : Vec3,
+}
+
+impl Default for Sky {
+    ///A clear afternoon sky, with the sun 30 degrees above the horizon.
+    fn default() -> Self {
+        Sky::new(3.0, Vec3::new(0.5, 0.5, -0.707), Color::new(0.3, 0.3, 0.3))
+    }
+}
+
+impl Sky {
+
+    ///Creates a sky, given its turbidity, the direction towards the sun, and the color of the ground.
+    pub fn new(turbidity : Float, sun_direction : Vec3, ground_albedo : Color) -> Sky {
+        Sky {
+            turbidity : turbidity.clamp(1.0, 10.0),
+            sun_direction : sun_direction.unit_vector(),
+            ground_albedo,
+        }
+    }
+
+    ///Returns the atmosphere the sky is fitted to: the Earth's, with as much aerosol as the turbidity calls for by the Angstrom coefficient
+    ///
+    /// Preetham, Shirley and Smits relate it to.
+    pub fn atmosphere(&self) -> Atmosphere {
+        let angstrom = |turbidity : Float| 0.04608 * turbidity - 0.04586;
+        let earth = Atmosphere::earth(self.sun_direction);
+        Atmosphere {
+            mie_scattering : earth.mie_scattering * angstrom(self.turbidity) / angstrom(EARTH_TURBIDITY),
+            ground_albedo : self.ground_albedo,
+            ..earth
+        }
+    }
+
+    ///Fits the Hosek-Wilkie model to the sky's atmosphere, sampled over the sky on the sun's side (the sky is symmetric about the sun's vertical plane).
+    ///
+    /// For each color channel, A, B, E and H are searched for with the Nelder-Mead simplex method, solving for the rest by weighted least squares
+    /// at every step, since the model is linear in them; the weights fit the relative error, so the dim sky away from the sun matters as much as the aureole.
+    pub fn fit(&self) -> SkyModel {
+        let atmosphere = self.atmosphere();
+        let sun = self.sun_direction;
+        let toward = Vec3::new(sun.x, 0.0, sun.z);
+        let toward = if toward.near_zero() {Vec3::new(1.0, 0.0, 0.0)} else {toward.unit_vector()};
+        let side = cross(Vec3::new(0.0, 1.0, 0.0), toward);
+
+        let mut samples = Vec::with_capacity(FIT_ELEVATIONS * FIT_AZIMUTHS);
+        for i in 0..FIT_ELEVATIONS {
+            let cos_theta = (i as Float + 0.5) / FIT_ELEVATIONS as Float;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            for j in 0..FIT_AZIMUTHS {
+                let phi = PI * j as Float / (FIT_AZIMUTHS - 1) as Float;
+                let direction = Vec3::new(0.0, cos_theta, 0.0) + (toward * phi.cos() + side * phi.sin()) * sin_theta;
+                let gamma = dot(direction, sun).clamp(-1.0, 1.0).acos();
+                samples.push((wide(cos_theta), wide(gamma), atmosphere.radiance(direction)));
+            }
+        }
+
+        let mut parameters = [[0.0 ; 9] ; 3];
+        for (channel, parameters) in parameters.iter_mut().enumerate() {
+            let targets = samples.iter().map(|(cos_theta, gamma, c)| (*cos_theta, *gamma, wide(c[channel]))).collect::<Vec<_>>();
+            let best = minimize([-1.0, -0.3, -2.0, 0.7], [0.5, 0.2, 1.0, 0.1], |nonlinear| solve(&targets, nonlinear).1);
+            let (linear, _) = solve(&targets, best);
+            let [a, b, e, h] = best;
+            let [c, d, f, g, i] = linear;
+            *parameters = [a, b, c, d, e, f, g, h, i].map(|x| x as Float);
+        }
+        SkyModel {parameters, sun_direction : sun}
+    }
+
+    ///Bakes the sky, the sun's disc and the ground into an equirectangular environment map, so that they are importance sampled as a light.
+    pub fn bake(&self, width : u32, height : u32, intensity : Float) -> Environment {
+        let model = self.fit();
+        let mut pixels = vec![0.0 ; 3 * (width * height) as usize];
+        let pixel_solid_angle = |j : u32| {
+            (2.0 * PI / width as Float) * (PI / height as Float) * (PI * (j as Float + 0.5) / height as Float).sin()
+        };
+
+        //Sky above the horizon, also adding up the light it casts onto the ground
+        let mut ground_irradiance = Color::new(0.0, 0.0, 0.0);
+        for j in 0..height {
+            for i in 0..width {
+                let direction = pixel_direction(i, j, width, height);
+                if direction.y <= 0.0 {
+                    continue;
+                }
+                let c = model.radiance(direction);
+                ground_irradiance += c * (direction.y * pixel_solid_angle(j));
+                set_pixel(&mut pixels, width, i, j, c);
+            }
+        }
+
+        //The sun is much smaller than a pixel, so its light is spread over the pixel containing it
+        let sun = self.atmosphere().sun_irradiance();
+        ground_irradiance += sun * self.sun_direction.y.max(0.0);
+        if self.sun_direction.y > 0.0 {
+            let d = self.sun_direction;
+            let s = ((-d.z).atan2(d.x) + PI) / (2.0 * PI);
+            let t = d.y.acos() / PI;
+            let i = ((s * width as Float) as u32).min(width - 1);
+            let j = ((t * height as Float) as u32).min(height - 1);
+            let index = 3 * (j * width + i) as usize;
+            let c = Color::new(pixels[index], pixels[index+1], pixels[index+2]) + sun * (1.0 / pixel_solid_angle(j));
+            set_pixel(&mut pixels, width, i, j, c);
+        }
+
+        //Diffuse ground below the horizon
+        let ground = self.ground_albedo * ground_irradiance * (1.0 / PI);
+        for j in 0..height {
+            for i in 0..width {
+                if pixel_direction(i, j, width, height).y <= 0.0 {
+                    set_pixel(&mut pixels, width, i, j, ground);
+                }
+            }
+        }
+
+        Environment::new(pixels, width, height, intensity)
+    }
+
+    ///Returns the radiance of the sun's disc, dimmed and reddened by the atmosphere it passes through.
+    pub fn sun_radiance(&self) -> Color {
+        self.atmosphere().sun_irradiance() * (1.0 / (2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.to_radians().cos())))
+    }
+}
+
+impl SkyModel {
+
+    ///Returns the radiance of the sky (without the sun's disc) along a direction, which is taken to be just above the horizon if below it.
+    pub fn radiance(&self, direction : Vec3) -> Color {
+        let d = direction.unit_vector();
+        let cos_theta = wide(d.y.max(0.0));
+        let gamma = wide(dot(d, self.sun_direction).clamp(-1.0, 1.0).acos());
+        let channel = |p : &[Float ; 9]| {
+            let [a, b, c, dd, e, f, g, h, i] = p.map(wide);
+            let value = basis(cos_theta, gamma, [a, b, e, h]).iter().zip([c, dd, f, g, i]).map(|(x, w)| x * w).sum::<f64>();
+            value.max(0.0) as Float
+        };
+        Color::new(channel(&self.parameters[0]), channel(&self.parameters[1]), channel(&self.parameters[2]))
+    }
+}
+
+///Returns the five terms of the model that C, D, F, G and I multiply, at an angle from the zenith (as its cosine) and from the sun, given A, B, E and H.
+fn basis(cos_theta : f64, gamma : f64, [a, b, e, h] : [f64 ; 4]) -> [f64 ; 5] {
+    let cos_gamma = gamma.cos();
+    let zenith = 1.0 + a * (b / (cos_theta + 0.01)).exp();
+    let chi = (1.0 + cos_gamma * cos_gamma) / (1.0 + h * h - 2.0 * h * cos_gamma).powf(1.5);
+    [1.0, (e * gamma).exp(), cos_gamma * cos_gamma, chi, cos_theta.sqrt()].map(|x| zenith * x)
+}
+
+///Solves for C, D, F, G and I by least squares of the relative error, given A, B, E and H, returning them with the sum of the squared errors.
+fn solve(targets : &[(f64, f64, f64)], nonlinear : [f64 ; 4]) -> ([f64 ; 5], f64) {
+    let mut normal = [[0.0 ; 6] ; 5];
+    for (cos_theta, gamma, target) in targets {
+        let weight = 1.0 / target.max(1e-6);
+        let terms = basis(*cos_theta, *gamma, nonlinear).map(|x| x * weight);
+        for (row, x) in normal.iter_mut().zip(terms) {
+            for (column, y) in row.iter_mut().zip(terms) {
+                *column += x * y;
+            }
+            row[5] += x * target * weight;
+        }
+    }
+    //A little damping keeps terms that barely vary over the sky (as when E or H are near 0) from blowing up
+    let trace = (0..5).map(|i| normal[i][i]).sum::<f64>();
+    for (i, row) in normal.iter_mut().enumerate() {
+        row[i] += 1e-9 * trace + 1e-30;
+    }
+
+    //Gaussian elimination with partial pivoting
+    for column in 0..5 {
+        let pivot = (column..5).max_by(|a, b| normal[*a][column].abs().total_cmp(&normal[*b][column].abs())).unwrap();
+        normal.swap(column, pivot);
+        for row in column + 1..5 {
+            let factor = normal[row][column] / normal[column][column];
+            let pivot = normal[column];
+            for (value, pivot) in normal[row][column..].iter_mut().zip(&pivot[column..]) {
+                *value -= factor * pivot;
+            }
+        }
+    }
+    let mut linear = [0.0 ; 5];
+    for row in (0..5).rev() {
+        linear[row] = (normal[row][5] - (row + 1..5).map(|k| normal[row][k] * linear[k]).sum::<f64>()) / normal[row][row];
+    }
+
+    let error = targets.iter().map(|(cos_theta, gamma, target)| {
+        let value = basis(*cos_theta, *gamma, nonlinear).iter().zip(linear).map(|(x, w)| x * w).sum::<f64>();
+        ((value - target) / target.max(1e-6)).powi(2)
+    }).sum::<f64>();
+    (linear, if error.is_finite() {error} else {f64::INFINITY})
+}
+
+///Returns A, B, E and H, within the ranges they make sense in, that minimize error, by the Nelder-Mead simplex method from a starting point and step.
+fn minimize(start : [f64 ; 4], step : [f64 ; 4], error : impl Fn([f64 ; 4]) -> f64) -> [f64 ; 4] {
+    let clamp = |p : [f64 ; 4]| [p[0].clamp(-5.0, 5.0), p[1].clamp(-5.0, -0.001), p[2].clamp(-20.0, 0.0), p[3].clamp(0.0, 0.95)];
+    let evaluate = |p : [f64 ; 4]| {
+        let p = clamp(p);
+        (p, error(p))
+    };
+    let mut simplex = (0..5).map(|i| {
+        let mut p = start;
+        if i > 0 {
+            p[i - 1] += step[i - 1];
+        }
+        evaluate(p)
+    }).collect::<Vec<_>>();
+
+    let along = |a : [f64 ; 4], b : [f64 ; 4], t : f64| [0, 1, 2, 3].map(|k| a[k] + (b[k] - a[k]) * t);
+    for _ in 0..FIT_STEPS {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let centroid = [0, 1, 2, 3].map(|k| simplex[..4].iter().map(|(p, _)| p[k]).sum::<f64>() / 4.0);
+        let worst = simplex[4];
+        let reflected = evaluate(along(worst.0, centroid, 2.0));
+        if reflected.1 < simplex[0].1 {
+            let expanded = evaluate(along(worst.0, centroid, 3.0));
+            simplex[4] = if expanded.1 < reflected.1 {expanded} else {reflected};
+        } else if reflected.1 < simplex[3].1 {
+            simplex[4] = reflected;
+        } else {
+            let contracted = evaluate(along(worst.0, centroid, 0.5));
+            if contracted.1 < worst.1 {
+                simplex[4] = contracted;
+            } else {
+                let best = simplex[0].0;
+                for vertex in &mut simplex[1..] {
+                    *vertex = evaluate(along(best, vertex.0, 0.5));
+                }
+            }
+        }
+    }
+    simplex.into_iter().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap().0
+}
+
+///Direction through the center of a pixel of an equirectangular map, where the top row is straight up.
+fn pixel_direction(i : u32, j : u32, width : u32, height : u32) -> Vec3 {
+    let phi = 2.0 * PI * (i as Float + 0.5) / width as Float - PI;
+    let theta = PI * (j as Float + 0.5) / height as Float;
+    Vec3::new(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin())
+}
+
+fn set_pixel(pixels : &mut [Float], width : u32, i : u32, j : u32, c : Color) {
+    let index = 3 * (j * width + i) as usize;
+    pixels[index] = c.x;
+    pixels[index+1] = c.y;
+    pixels[index+2] = c.z;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_follows_atmosphere() {
+        for (turbidity, sun) in [(2.0, Vec3::new(0.5, 0.5, -0.707)), (6.0, Vec3::new(0.0, 0.9, 0.4)), (3.0, Vec3::new(-0.9, 0.1, 0.2))] {
+            let sky = Sky::new(turbidity, sun, Color::new(0.3, 0.3, 0.3));
+            let (model, atmosphere) = (sky.fit(), sky.atmosphere());
+            let errors = (0..400).map(|k| {
+                let theta = ((k / 40) as Float + 0.5) / 10.0 * PI / 2.0;
+                let phi = (k % 40) as Float / 40.0 * 2.0 * PI;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                let (fitted, simulated) = (model.radiance(direction), atmosphere.radiance(direction));
+                ((fitted.y - simulated.y) / simulated.y).abs()
+            }).collect::<Vec<_>>();
+            let mean = errors.iter().sum::<Float>() / errors.len() as Float;
+            assert!(mean < 0.1, "turbidity {} sun {:?}: mean relative error {}", turbidity, sun, mean);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+/*
+Module to store the Sobol low-discrepancy sequence, along with the scrambling that decorrelates it between pixels.
+*/
+
+use std::sync::OnceLock;
+use crate::vec3::Float;
+
+///Number of dimensions of the Sobol sequence available. Later dimensions of a sample fall back to random numbers.
+pub const SOBOL_DIMENSIONS : usize = 16;
+
+///Degree, coefficients and initial direction numbers of the primitive polynomial for each dimension after the first,
+///
+/// from Joe and Kuo's tables.
+const POLYNOMIALS : [(u32, u32, [u32 ; 6]) ; SOBOL_DIMENSIONS - 1] = [
+    (1, 0, [1, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49]),
+    (6, 13, [1, 1, 1, 15, 21, 21]),
+    (6, 16, [1, 3, 1, 13, 27, 49]),
+];
+
+///Returns the generator matrices (as 32 direction numbers each) of every dimension, building them the first time.
+fn matrices() -> &'static Vec<[u32 ; 32]> {
+    static MATRICES : OnceLock<Vec<[u32 ; 32]>> = OnceLock::new();
+    MATRICES.get_or_init(|| {
+        let mut matrices = Vec::with_capacity(SOBOL_DIMENSIONS);
+
+        //The first dimension is the van der Corput sequence
+        let mut first = [0 ; 32];
+        for (k, v) in first.iter_mut().enumerate() {
+            *v = 1 << (31 - k);
+        }
+        matrices.push(first);
+
+        for (degree, coefficients, initial) in POLYNOMIALS {
+            let s = degree as usize;
+            let mut m = [0u32 ; 32];
+            m[..s].copy_from_slice(&initial[..s]);
+            for k in s..32 {
+                m[k] = m[k - s] ^ (m[k - s] << s);
+                for i in 1..s {
+                    if (coefficients >> (s - 1 - i)) & 1 == 1 {
+                        m[k] ^= m[k - i] << i;
+                    }
+                }
+            }
+
+            let mut v = [0 ; 32];
+            for k in 0..32 {
+                v[k] = m[k] << (31 - k);
+            }
+            matrices.push(v);
+        }
+        matrices
+    })
+}
+
+///Returns the given dimension of the Sobol point at index, scrambled by seed so that different seeds give
+///
+/// independent looking (but still evenly spread) sets of points.
+pub fn sobol(index : u32, dimension : usize, seed : u32) -> Float {
+    let matrix = &matrices()[dimension];
+    let mut bits = 0;
+    let mut i = index;
+    let mut k = 0;
+    while i != 0 {
+        if i & 1 == 1 {
+            bits ^= matrix[k];
+        }
+        i >>= 1;
+        k += 1;
+    }
+    to_unit(nested_uniform_scramble(bits, hash(seed ^ dimension as u32)))
+}
+
+///Owen scrambling, which randomly flips digits of x depending on the digits above them, using Burley's hash-based construction.
+pub fn nested_uniform_scramble(x : u32, seed : u32) -> u32 {
+    let mut v = x.reverse_bits();
+    v = v.wrapping_add(seed);
+    v ^= v.wrapping_mul(0x6c50b47c);
+    v ^= v.wrapping_mul(0xb82f1e52);
+    v ^= v.wrapping_mul(0xc7afe638);
+    v ^= v.wrapping_mul(0x8d22f6e6);
+    v.reverse_bits()
+}
+
+///Mixes the bits of an integer, for turning pixel coordinates and dimensions into unrelated seeds.
+pub fn hash(x : u32) -> u32 {
+    let mut v = x;
+    v ^= v >> 16;
+    v = v.wrapping_mul(0x7feb352d);
+    v ^= v >> 15;
+    v = v.wrapping_mul(0x846ca68b);
+    v ^= v >> 16;
+    v
+}
+
+///Maps 32 random bits to a number between 0 and 1 non-inclusive.
+pub fn to_unit(bits : u32) -> Float {
+    ((bits >> 8) as Float / (1u32 << 24) as Float).min(1.0 - Float::EPSILON)
+}
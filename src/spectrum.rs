@@ -0,0 +1,93 @@
+/*
+Module to store the conversions needed for spectral rendering, where each ray carries a single wavelength of light.
+*/
+
+use crate::vec3::{Color, random_float, Float};
+use crate::color::luminance;
+#[cfg(not(feature = "std"))]
+use crate::math::Real;
+
+///Shortest wavelength (in nanometers) sampled by spectral rendering.
+pub const LAMBDA_MIN : Float = 380.0;
+
+///Longest wavelength (in nanometers) sampled by spectral rendering.
+pub const LAMBDA_MAX : Float = 780.0;
+
+///Integral of the CIE luminance curve over the sampled wavelengths, used so that a constant spectrum of 1 has a luminance of 1.
+const Y_INTEGRAL : Float = 106.919_73;
+
+///Linear sRGB color of a constant spectrum of 1, used to white balance so that white surfaces stay white.
+const WHITE : [Float ; 3] = [1.200_536_3, 0.949_666_4, 0.907_828_7];
+
+///Picks a wavelength uniformly at random between LAMBDA_MIN and LAMBDA_MAX.
+pub fn sample_wavelength() -> Float {
+    LAMBDA_MIN + (LAMBDA_MAX - LAMBDA_MIN) * random_float()
+}
+
+///Returns the value at the given wavelength of a smooth spectrum with the given RGB color.
+///
+/// The red, green and blue basis spectra sum to 1 at every wavelength, so white stays a constant spectrum.
+pub fn rgb_to_spectral(c : Color, lambda : Float) -> Float {
+    let blue = 1.0 - smoothstep(480.0, 520.0, lambda);
+    let red = smoothstep(570.0, 610.0, lambda);
+    let green = 1.0 - red - blue;
+    c.x * red + c.y * green + c.z * blue
+}
+
+///Converts the light carried by a ray of a single, uniformly sampled wavelength into a linear RGB color.
+///
+/// Averaging many of these over random wavelengths gives the color of the full spectrum.
+pub fn spectral_to_rgb(value : Float, lambda : Float) -> Color {
+    let (x, y, z) = cie_xyz(lambda);
+    let scale = value * (LAMBDA_MAX - LAMBDA_MIN) / Y_INTEGRAL;
+    let (x, y, z) = (x * scale, y * scale, z * scale);
+
+    let r = 3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z;
+    let g = -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z;
+    let b = 0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z;
+    Color::new(r / WHITE[0], g / WHITE[1], b / WHITE[2])
+}
+
+///Linear RGB color of a black body at the given temperature (in kelvin), scaled to a luminance of 1.
+pub fn blackbody(kelvin : Float) -> Color {
+    let steps = 80;
+    let mut total = Color::new(0.0, 0.0, 0.0);
+    for i in 0..steps {
+        let lambda = LAMBDA_MIN + (LAMBDA_MAX - LAMBDA_MIN) * (i as Float + 0.5) / steps as Float;
+
+        //Planck's law, without the constant factors since the result is normalized anyway
+        let micrometers = lambda / 1000.0;
+        let planck = 1.0 / (micrometers.powi(5) * ((14_387.77 / (lambda * kelvin)) * 1000.0).exp_m1());
+        total += spectral_to_rgb(planck, lambda);
+    }
+    let total = Color::new(total.x.max(0.0), total.y.max(0.0), total.z.max(0.0));
+    total / luminance(total)
+}
+
+///Index of refraction at the given wavelength, from Cauchy's equation.
+///
+/// ir is the index at 587.6nm (the usual reference wavelength), and dispersion is Cauchy's B coefficient in square micrometers
+/// (about 0.004 for common glass, 0.0 for no dispersion).
+pub fn cauchy_ior(ir : Float, dispersion : Float, lambda : Float) -> Float {
+    let micrometers = lambda / 1000.0;
+    ir + dispersion * (1.0 / (micrometers * micrometers) - 1.0 / (0.5876 * 0.5876))
+}
+
+///CIE 1931 color matching functions, using the multi-lobe Gaussian fit of Wyman, Sloan and Shirley.
+pub fn cie_xyz(lambda : Float) -> (Float, Float, Float) {
+    let g = |mu : Float, sigma1 : Float, sigma2 : Float| {
+        let sigma = if lambda < mu {sigma1} else {sigma2};
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    let x = 1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+fn smoothstep(edge0 : Float, edge1 : Float, x : Float) -> Float {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
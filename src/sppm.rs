@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use crate::vec3::consts::PI;
+use rayon::prelude::*;
+use crate::vec3::{Vec3, Color, Point3, dot, cross, random_in_unit_sphere, random_in_unit_disk, orthonormal_basis, random_float, random_range_float, seed_stream, sample_with, Float};
+use crate::ray::{Ray, t_min};
+use crate::hitting::{Hittable, HitRecord};
+use crate::materials::{Material, Interior, henyey_greenstein};
+use crate::camera::Camera;
+use crate::scene::Scene;
+use crate::textures::Textures;
+use crate::integrator::{direct_light, first_hit};
+
+///Settings for stochastic progressive photon mapping.
+///
+/// Each iteration finds the surface seen through every pixel, then scatters photons from the lights and gathers those landing
+/// within a radius of each visible point. The radius shrinks as photons accumulate, so the result converges like a path tracer would,
+/// while still resolving caustics seen through Dielectric objects.
+#[derive(Debug, Clone, Copy)]
+pub struct SppmSettings {
+    pub iterations : u32,
+    pub photons_per_iteration : usize,
+    pub initial_radius : Float,
+    pub max_depth : i32,
+}
+
+///The first non-specular surface seen through a pixel during an iteration.
+///
+/// For points inside a medium, `medium` holds the direction the camera ray was travelling and the medium's anisotropy, 
+/// and `bsdf` holds its albedo, since the light it scatters depends on the direction photons arrive from.
+#[derive(Debug, Clone, Copy)]
+struct VisiblePoint {
+    p : Point3,
+    normal : Vec3,
+    medium : Option<(Vec3, Float)>,
+    bsdf : Color,
+    beta : Color,
+}
+
+///Photon statistics gathered for a single pixel across iterations, and how many of its camera rays hit the scene.
+#[derive(Debug, Clone, Copy)]
+struct SppmPixel {
+    visible : Option<VisiblePoint>,
+    hits : Float,
+    radius : Float,
+    photons : Float,
+    flux : Color,
+    direct : Color,
+}
+
+///Fraction of newly gathered photons kept when shrinking the gather radius.
+const ALPHA : Float = 2.0 / 3.0;
+
+///Renders the scene with stochastic progressive photon mapping, returning the radiance and opacity of every pixel.
+///
+/// Pixels are stored row by row, starting from the bottom row of the image. With transparent, camera rays that escape the scene
+/// add nothing, so radiance is premultiplied by the opacity, which is the fraction of rays that hit; otherwise every pixel is opaque.
+pub fn render_sppm(scene : &Scene, cam : &dyn Camera, image_width : u32, image_height : u32, settings : SppmSettings, transparent : bool) -> Vec<(Color, Float)> {
+    let mut pixels = vec![SppmPixel {
+        visible : None,
+        hits : 0.0,
+        radius : settings.initial_radius,
+        photons : 0.0,
+        flux : Color::new(0.0, 0.0, 0.0),
+        direct : Color::new(0.0, 0.0, 0.0),
+    } ; (image_width * image_height) as usize];
+    let (scene_center, scene_radius) = scene.bounding_sphere();
+
+    for iteration in 0..settings.iterations {
+
+        //Camera pass: find the visible point of every pixel, and the light reaching the camera directly
+        pixels.par_iter_mut().enumerate().for_each(|(index, pixel)| {
+            seed_stream(&[iteration as u64, 0, index as u64]);
+            let i = index as u32 % image_width;
+            let j = index as u32 / image_width;
+            let u : Float = (i as Float + random_float()) / (image_width as Float - 1.0);
+            let v : Float = (j as Float + random_float()) / (image_height as Float - 1.0);
+
+            let ray = sample_with(|sampler| cam.get_ray(u, v, sampler));
+            if transparent && first_hit(ray, scene).is_none() {
+                pixel.visible = None;
+                return;
+            }
+            pixel.hits += 1.0;
+
+            let (direct, visible) = find_visible_point(ray, scene, settings.max_depth);
+            pixel.direct += direct;
+            pixel.visible = visible;
+        });
+
+        //Photon pass: scatter photons through the scene, recording which visible points they land near
+        let grid = VisibleGrid::build(&pixels);
+        let contributions = (0..settings.photons_per_iteration).into_par_iter().map(|photon| {
+            seed_stream(&[iteration as u64, 1, photon as u64]);
+            let mut found = Vec::new();
+            if let Some((ray, power)) = emit_photon(scene, scene_center, scene_radius) {
+                trace_photon(ray.with_time(sample_with(|sampler| cam.sample_time(sampler))), power, scene, &pixels, &grid, settings.max_depth, &mut found);
+            }
+            found
+        }).collect::<Vec<Vec<(usize, Color)>>>();
+
+        let mut gathered = vec![(Color::new(0.0, 0.0, 0.0), 0.0) ; pixels.len()];
+        for (index, phi) in contributions.into_iter().flatten() {
+            gathered[index].0 += phi;
+            gathered[index].1 += 1.0;
+        }
+
+        //Shrink the radius of every pixel that gathered photons, keeping only a fraction of the new photons
+        for (pixel, (phi, m)) in pixels.iter_mut().zip(gathered) {
+            if m == 0.0 {
+                continue;
+            }
+            if let Some(vp) = pixel.visible {
+                let photons = pixel.photons + ALPHA * m;
+                let radius = pixel.radius * (photons / (pixel.photons + m)).sqrt();
+                pixel.flux = (pixel.flux + vp.beta * phi) * ((radius * radius) / (pixel.radius * pixel.radius));
+                pixel.photons = photons;
+                pixel.radius = radius;
+            }
+        }
+    }
+
+    let emitted = settings.iterations as Float * settings.photons_per_iteration as Float;
+    pixels.iter().map(|pixel| {
+        (pixel.direct / settings.iterations as Float + pixel.flux / (emitted * PI * pixel.radius * pixel.radius), pixel.hits / settings.iterations as Float)
+    }).collect()
+}
+
+///Follows a camera ray through specular bounces until it reaches a diffuse surface or medium.
+///
+/// Returns the light reaching the camera along the way (including direct lighting at the visible point), and the visible point itself.
+fn find_visible_point(r : Ray, scene : &Scene, max_depth : i32) -> (Color, Option<VisiblePoint>) {
+    let mut ray = r;
+    let mut beta = Color::new(1.0, 1.0, 1.0);
+    let mut interior = Interior::new();
+    let mut direct = Color::new(0.0, 0.0, 0.0);
+
+    for _depth in 0..max_depth {
+        let mut rec = HitRecord::new();
+        if !scene.hit(ray, t_min(), Float::INFINITY, &mut rec) {
+            if let Some(env) = &scene.environment {
+                direct += beta * env.value(ray.direction);
+            }
+            break;
+        }
+        scene.shade(ray, &mut rec);
+
+        direct += beta * rec.mat.emitted(rec.u, rec.v, rec.p, &scene.textures);
+        let mut scattered = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        if !rec.mat.scatter_nested(ray, &rec, &mut interior, &mut attenuation, &mut scattered, &scene.textures) {
+            break;
+        }
+
+        if !rec.mat.is_specular() {
+            direct += beta * direct_light(scene, ray, &rec, attenuation, false);
+            let (medium, bsdf) = match rec.mat {
+                Material::Isotropic(_, g) | Material::EmissiveIsotropic(_, g, _) => (Some((ray.direction, g)), attenuation),
+                _ => (None, attenuation / PI),
+            };
+            return (direct, Some(VisiblePoint {p : rec.p, normal : rec.normal, medium, bsdf, beta}));
+        }
+
+        beta = beta * attenuation;
+        scattered.origin_point = rec.spawn_origin(scattered.direction);
+        ray = scattered;
+    }
+
+    (direct, None)
+}
+
+///Picks a random light source in the scene and emits a photon from it, returning the photon's ray and power.
+fn emit_photon(scene : &Scene, scene_center : Point3, scene_radius : Float) -> Option<(Ray, Color)> {
+    let sources = scene.emitters.len() + scene.lights.len() + if scene.environment.is_some() {1} else {0};
+    if sources == 0 {
+        return None;
+    }
+
+    let index = ((random_float() * sources as Float) as usize).min(sources - 1);
+    let (ray, power) = if index < scene.emitters.len() {
+        emit_from_object(&scene.emitters[index], &scene.textures)?
+    } else if index < scene.emitters.len() + scene.lights.len() {
+        scene.lights[index - scene.emitters.len()].sample_emission(scene_center, scene_radius)
+    } else {
+        let env = scene.environment.as_ref()?;
+        let (to_light, pdf) = env.sample();
+        if pdf <= 0.0 {
+            return None;
+        }
+        let (u, v) = orthonormal_basis(to_light);
+        let disk = random_in_unit_disk();
+        let origin = scene_center + to_light * scene_radius + (u * disk.x + v * disk.y) * scene_radius;
+        (Ray::new(origin, -to_light), env.value(to_light) * PI * scene_radius * scene_radius / pdf)
+    };
+
+    //Account for the probability of picking this light
+    Some((ray, power * sources as Float))
+}
+
+///Emits a photon from a random point on an emissive sphere, rectangle or triangle, in a cosine-weighted direction.
+fn emit_from_object(object : &Hittable, textures : &Textures) -> Option<(Ray, Color)> {
+    let side = if random_float() < 0.5 {1.0} else {-1.0};
+
+    //Rectangles and triangles emit from both faces, so each face is picked half of the time
+    let (p, normal, area) = match object {
+        Hittable::Sphere(_mat, center, radius) => {
+            let n = random_in_unit_sphere();
+            (*center + n * *radius, n, 4.0 * PI * radius * radius)
+        },
+        Hittable::XYRect(_mat, x0, x1, y0, y1, k) => {
+            (Point3::new(random_range_float(*x0, *x1), random_range_float(*y0, *y1), *k), Vec3::new(0.0, 0.0, side), 2.0 * (x1 - x0) * (y1 - y0))
+        },
+        Hittable::XZRect(_mat, x0, x1, z0, z1, k) => {
+            (Point3::new(random_range_float(*x0, *x1), *k, random_range_float(*z0, *z1)), Vec3::new(0.0, side, 0.0), 2.0 * (x1 - x0) * (z1 - z0))
+        },
+        Hittable::YZRect(_mat, y0, y1, z0, z1, k) => {
+            (Point3::new(*k, random_range_float(*y0, *y1), random_range_float(*z0, *z1)), Vec3::new(side, 0.0, 0.0), 2.0 * (y1 - y0) * (z1 - z0))
+        },
+        Hittable::Triangle(_mat, [a, b, c], _uvs) => {
+            let normal = cross(*b - *a, *c - *a);
+            (object.random_point_on(*a), normal.unit_vector() * side, normal.length())
+        },
+        _ => return None,
+    };
+
+    //Look up the emitted light (which may be textured) by hitting the surface from just above the sampled point
+    let mut rec = HitRecord::new();
+    if !object.hit(Ray::new(p + normal * 0.001, -normal), 0.0, 0.002, &mut rec, textures) {
+        return None;
+    }
+    let emitted = rec.mat.emitted(rec.u, rec.v, rec.p, textures);
+
+    let mut direction = normal + random_in_unit_sphere();
+    if direction.near_zero() {
+        direction = normal;
+    }
+    Some((Ray::new(p, direction), emitted * area * PI))
+}
+
+///Follows a photon through the scene, recording its contribution to every visible point it lands near after its first bounce.
+fn trace_photon(r : Ray, power : Color, scene : &Scene, pixels : &[SppmPixel], grid : &VisibleGrid, max_depth : i32, found : &mut Vec<(usize, Color)>) {
+    let mut ray = r;
+    let mut beta = power;
+    let mut interior = Interior::new();
+
+    for depth in 0..max_depth {
+        let mut rec = HitRecord::new();
+        if !scene.hit(ray, t_min(), Float::INFINITY, &mut rec) {
+            return;
+        }
+        scene.shade(ray, &mut rec);
+
+        //Light arriving directly from a light source is already handled by direct lighting at the visible points
+        if depth > 0 && !rec.mat.is_specular() {
+            let incoming = -ray.direction.unit_vector();
+            for index in grid.lookup(rec.p) {
+                let vp = match pixels[*index].visible {
+                    Some(vp) => vp,
+                    None => continue,
+                };
+                let radius = pixels[*index].radius;
+                if (vp.p - rec.p).length_squared() > radius * radius {
+                    continue;
+                }
+                let bsdf = match vp.medium {
+                    Some((direction, g)) => vp.bsdf * henyey_greenstein(g, dot(-incoming, -direction.unit_vector())),
+                    None if dot(incoming, vp.normal) <= 0.0 => continue,
+                    None => vp.bsdf,
+                };
+                found.push((*index, bsdf * beta));
+            }
+        }
+
+        let mut scattered = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        if !rec.mat.scatter_nested(ray, &rec, &mut interior, &mut attenuation, &mut scattered, &scene.textures) {
+            return;
+        }
+        beta = beta * attenuation;
+        scattered.origin_point = rec.spawn_origin(scattered.direction);
+        ray = scattered;
+    }
+}
+
+///Uniform hash grid over the visible points, letting photons find the points within gathering distance quickly.
+struct VisibleGrid {
+    cell_size : Float,
+    cells : HashMap<(i32, i32, i32), Vec<usize>>,
+}
+
+impl VisibleGrid {
+
+    ///Inserts every visible point into each grid cell its gather radius overlaps.
+    fn build(pixels : &[SppmPixel]) -> VisibleGrid {
+        let max_radius = pixels.iter().filter(|p| p.visible.is_some()).map(|p| p.radius).fold(0.0, Float::max);
+        let mut grid = VisibleGrid {
+            cell_size : (2.0 * max_radius).max(Float::EPSILON),
+            cells : HashMap::new(),
+        };
+
+        for (index, pixel) in pixels.iter().enumerate() {
+            if let Some(vp) = pixel.visible {
+                let r = Vec3::new(pixel.radius, pixel.radius, pixel.radius);
+                let low = grid.cell(vp.p - r);
+                let high = grid.cell(vp.p + r);
+                for x in low.0..=high.0 {
+                    for y in low.1..=high.1 {
+                        for z in low.2..=high.2 {
+                            grid.cells.entry((x, y, z)).or_default().push(index);
+                        }
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn cell(&self, p : Point3) -> (i32, i32, i32) {
+        ((p.x / self.cell_size).floor() as i32, (p.y / self.cell_size).floor() as i32, (p.z / self.cell_size).floor() as i32)
+    }
+
+    ///Returns the indices of the visible points that might be within gathering distance of p.
+    fn lookup(&self, p : Point3) -> &[usize] {
+        match self.cells.get(&self.cell(p)) {
+            Some(indices) => indices,
+            None => &[],
+        }
+    }
+}
@@ -0,0 +1,211 @@
+/*
+Module to store render statistics: how many rays were traced, how many nodes and primitives they were tested against, how long paths were,
+and how long was spent shading each kind of material, so that changes to the acceleration structures and materials can be measured.
+*/
+
+use std::cell::Cell;
+use std::ops::{AddAssign, Sub};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use web_time::{Duration, Instant};
+use crate::materials::Material;
+use crate::accelerator::rays_traced;
+
+///Names of the kinds of material shading time is kept for, in the order of the Material enum.
+pub const MATERIAL_NAMES : [&str ; 8] = ["lambertian", "metal", "dielectric", "light", "isotropic", "shadow catcher", "emissive medium", "pbr"];
+
+///Whether shading is timed, which costs a clock read per bounce, so it is only done while statistics are being collected.
+static TIMING : AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    ///Counts of the work this thread has done.
+    static COUNTERS : Cell<Counters> = const { Cell::new(Counters::new()) };
+}
+
+///Counts of the work done while rendering, by one thread or added up over many. Nodes are the nodes of the acceleration structure whose boxes rays
+///
+/// were tested against (or, for the kd-tree, the nodes they walked through), primitives the objects they were tested against, and paths and bounces
+/// the paths traced by the path tracer and the surfaces they scattered off. Shading holds the time spent shading each kind of material in MATERIAL_NAMES.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    pub rays : u64,
+    pub nodes : u64,
+    pub primitives : u64,
+    pub paths : u64,
+    pub bounces : u64,
+    pub shading : [Duration ; MATERIAL_NAMES.len()],
+}
+
+impl Counters {
+
+    pub const fn new() -> Counters {
+        Counters {rays : 0, nodes : 0, primitives : 0, paths : 0, bounces : 0, shading : [Duration::ZERO ; MATERIAL_NAMES.len()]}
+    }
+
+    ///Returns the average number of bounces per path.
+    pub fn average_path_length(&self) -> f64 {
+        if self.paths > 0 {self.bounces as f64 / self.paths as f64} else {0.0}
+    }
+}
+
+impl Default for Counters {
+    fn default() -> Counters {
+        Counters::new()
+    }
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, other : Counters) {
+        self.rays += other.rays;
+        self.nodes += other.nodes;
+        self.primitives += other.primitives;
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+        for (time, other) in self.shading.iter_mut().zip(other.shading) {
+            *time += other;
+        }
+    }
+}
+
+impl Sub for Counters {
+    type Output = Counters;
+
+    fn sub(self, other : Counters) -> Counters {
+        let mut shading = self.shading;
+        for (time, other) in shading.iter_mut().zip(other.shading) {
+            *time = time.saturating_sub(other);
+        }
+        Counters {
+            rays : self.rays - other.rays,
+            nodes : self.nodes - other.nodes,
+            primitives : self.primitives - other.primitives,
+            paths : self.paths - other.paths,
+            bounces : self.bounces - other.bounces,
+            shading,
+        }
+    }
+}
+
+///Returns the work the calling thread has done so far. The difference between two calls is the work done in between.
+pub fn counters() -> Counters {
+    Counters {rays : rays_traced(), ..COUNTERS.with(|counters| counters.get())}
+}
+
+///Records that a ray was tested against the given numbers of nodes and primitives on its way through an acceleration structure.
+pub fn count_traversal(nodes : u64, primitives : u64) {
+    COUNTERS.with(|counters| {
+        let mut current = counters.get();
+        current.nodes += nodes;
+        current.primitives += primitives;
+        counters.set(current);
+    });
+}
+
+///Records that a path was traced, scattering off the given number of surfaces.
+pub fn count_path(bounces : u64) {
+    COUNTERS.with(|counters| {
+        let mut current = counters.get();
+        current.paths += 1;
+        current.bounces += bounces;
+        counters.set(current);
+    });
+}
+
+///Returns when shading started, if shading is being timed, for add_shading() to record once it is done.
+pub fn start_shading() -> Option<Instant> {
+    TIMING.load(Ordering::Relaxed).then(Instant::now)
+}
+
+///Records the time since start spent shading a hit on the given material.
+pub fn add_shading(material : &Material, start : Option<Instant>) {
+    if let Some(start) = start {
+        let elapsed = start.elapsed();
+        COUNTERS.with(|counters| {
+            let mut current = counters.get();
+            current.shading[material_index(material)] += elapsed;
+            counters.set(current);
+        });
+    }
+}
+
+fn material_index(material : &Material) -> usize {
+    match material {
+        Material::Lambertian(_) => 0,
+        Material::Metal(..) => 1,
+        Material::Dielectric(..) => 2,
+        Material::Light(_) => 3,
+        Material::Isotropic(..) => 4,
+        Material::ShadowCatcher(_) => 5,
+        Material::EmissiveIsotropic(..) => 6,
+        Material::Pbr(..) => 7,
+    }
+}
+
+///Statistics of a render, collected from every thread, in total and optionally for every tile. Creating one turns on the timing of shading,
+///
+/// which stays on until it is dropped.
+#[derive(Debug)]
+pub struct RenderStats {
+    per_tile : bool,
+    total : Mutex<Counters>,
+    tiles : Mutex<Vec<((u32, u32), Counters)>>,
+    start : Instant,
+}
+
+impl RenderStats {
+
+    ///Starts collecting statistics, keeping each tile's as well as the total with per_tile.
+    pub fn new(per_tile : bool) -> RenderStats {
+        TIMING.store(true, Ordering::Relaxed);
+        RenderStats {per_tile, total : Mutex::new(Counters::new()), tiles : Mutex::new(vec![]), start : Instant::now()}
+    }
+
+    ///Adds the work done rendering the tile whose bottom left (or, for bands streamed from the top, top left) pixel is at corner.
+    pub fn add(&self, corner : (u32, u32), counters : Counters) {
+        *self.total.lock().unwrap() += counters;
+        if self.per_tile {
+            let mut tiles = self.tiles.lock().unwrap();
+            match tiles.iter_mut().find(|(tile, _)| *tile == corner) {
+                Some((_, tile)) => *tile += counters,
+                None => tiles.push((corner, counters)),
+            }
+        }
+    }
+
+    ///Returns the work done over the whole render so far.
+    pub fn total(&self) -> Counters {
+        *self.total.lock().unwrap()
+    }
+
+    ///Returns a report of the statistics, one line to a figure, followed by a line for every tile with per_tile.
+    pub fn report(&self) -> String {
+        let total = self.total();
+        let seconds = self.start.elapsed().as_secs_f64().max(1e-9);
+        let per_ray = |count : u64| if total.rays > 0 {count as f64 / total.rays as f64} else {0.0};
+        let mut lines = vec![
+            format!("Rays traced:        {} ({:.0} per second)", total.rays, total.rays as f64 / seconds),
+            format!("Nodes visited:      {} ({:.1} per ray)", total.nodes, per_ray(total.nodes)),
+            format!("Primitive tests:    {} ({:.1} per ray)", total.primitives, per_ray(total.primitives)),
+            format!("Paths traced:       {} ({:.2} bounces on average)", total.paths, total.average_path_length()),
+        ];
+        let shading = total.shading.iter().sum::<Duration>().as_secs_f64().max(1e-9);
+        for (name, time) in MATERIAL_NAMES.iter().zip(total.shading).filter(|(_, time)| !time.is_zero()) {
+            lines.push(format!("Shading {:<16} {:.3}s ({:.1}%)", format!("{}:", name), time.as_secs_f64(), 100.0 * time.as_secs_f64() / shading));
+        }
+
+        let mut tiles = self.tiles.lock().unwrap().clone();
+        tiles.sort_by_key(|((x, y), _)| (*y, *x));
+        for ((x, y), tile) in tiles {
+            let per_ray = |count : u64| if tile.rays > 0 {count as f64 / tile.rays as f64} else {0.0};
+            lines.push(format!("Tile at ({}, {}): {} rays, {:.1} nodes and {:.1} primitives per ray, {:.2} bounces per path",
+                x, y, tile.rays, per_ray(tile.nodes), per_ray(tile.primitives), tile.average_path_length()));
+        }
+        lines.join("\n")
+    }
+}
+
+impl Drop for RenderStats {
+    fn drop(&mut self) {
+        TIMING.store(false, Ordering::Relaxed);
+    }
+}
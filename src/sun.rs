@@ -0,0 +1,70 @@
+/*
+Module to store helpers that place the sun in the sky for a given place, date and time.
+*/
+
+use crate::vec3::consts::PI;
+use crate::vec3::{Vec3, Float};
+use crate::lights::Light;
+pub use crate::spectrum::blackbody;
+
+///Angular radius of the sun's disc, in degrees.
+const SUN_ANGULAR_RADIUS : Float = 0.2667;
+
+///Returns the unit direction towards the sun, where +y is up, +x is east and -z is north.
+///
+/// latitude and longitude are in degrees (north and east are positive), utc_offset is the local time zone in hours
+/// (for example -4.0 for daylight saving time in New York), and hour is the local clock time (15.5 for half past three in the afternoon).
+/// Follows the NOAA solar position equations, which are accurate to within a few hundredths of a degree.
+pub fn sun_direction(latitude : Float, longitude : Float, utc_offset : Float, year : i32, month : u32, day : u32, hour : Float) -> Vec3 {
+    //Fractional year, in radians
+    let days_in_year = if is_leap_year(year) {366.0} else {365.0};
+    let gamma = 2.0 * PI / days_in_year * (day_of_year(year, month, day) as Float - 1.0 + (hour - utc_offset - 12.0) / 24.0);
+
+    //Equation of time (in minutes) and solar declination (in radians)
+    let equation_of_time = 229.18 * (0.000075 + 0.001868 * gamma.cos() - 0.032077 * gamma.sin()
+        - 0.014615 * (2.0 * gamma).cos() - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos() + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos() + 0.00148 * (3.0 * gamma).sin();
+
+    //Hour angle, which is zero at solar noon and grows through the afternoon
+    let true_solar_time = hour * 60.0 + equation_of_time + 4.0 * longitude - 60.0 * utc_offset;
+    let hour_angle = (true_solar_time / 4.0 - 180.0).to_radians();
+
+    let lat = latitude.to_radians();
+    let east = -declination.cos() * hour_angle.sin();
+    let north = declination.sin() * lat.cos() - declination.cos() * lat.sin() * hour_angle.cos();
+    let up = lat.sin() * declination.sin() + lat.cos() * declination.cos() * hour_angle.cos();
+    Vec3::new(east, up, -north).unit_vector()
+}
+
+///Rough correlated color temperature (in kelvin) of direct sunlight with the sun in the given direction,
+///
+/// from about 2000K at the horizon to about 5800K with the sun high in the sky.
+pub fn sun_temperature(direction : Vec3) -> Float {
+    let elevation = direction.unit_vector().y.clamp(0.0, 1.0).asin().to_degrees();
+    2000.0 + 3800.0 * (1.0 - (-elevation / 8.0).exp())
+}
+
+///Creates a directional light for the sun in the given direction, colored by its temperature.
+///
+/// illuminance is the brightness of the sun when it is straight overhead, and dims as it sets. Returns None when the sun is below the horizon.
+pub fn sun_light(direction : Vec3, illuminance : Float) -> Option<Light> {
+    let d = direction.unit_vector();
+    if d.y <= 0.0 {
+        return None;
+    }
+    let color = blackbody(sun_temperature(d)) * illuminance * (1.0 - (-d.y * 10.0).exp());
+    Some(Light::Directional(-d, color, SUN_ANGULAR_RADIUS))
+}
+
+fn is_leap_year(year : i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+///Day of the year, counting January 1st as day 1.
+fn day_of_year(year : i32, month : u32, day : u32) -> u32 {
+    let month_lengths = [31, if is_leap_year(year) {29} else {28}, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let month = month.clamp(1, 12) as usize;
+    month_lengths[..month - 1].iter().sum::<u32>() + day
+}
@@ -1,5 +1,19 @@
-use crate::vec_class::{Vec3, Color, Point3, dot};
-use rand::Rng;
+use core::fmt::Debug;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use image::RgbImage;
+#[cfg(not(feature = "std"))]
+use crate::math::Real;
+use crate::vec3::{Vec3, Color, Point3, dot, random_float, Float};
+use crate::vec3::consts::LOG2_E;
+use crate::color::{srgb_to_linear, linear_to_srgb};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 ///Stores the different variants of solid textures. Variants include
 /// 
@@ -9,43 +23,421 @@ use rand::Rng;
 /// 
 /// Noise: uses Perlin noise to render a pseudo-random texture of black and white.
 /// 
-/// Image: Renders an image onto a surface.
+/// Image: Renders an image onto a surface, given its 8 bit RGB values, which are decoded from sRGB into linear colors.
+/// The pixels are shared rather than copied when the texture is cloned (see TextureHandle).
+///
+/// Region: renders the rectangle [u0, v0, u1, v1] of another texture of the scene (flipped where u1 or v1 is the smaller), given its id, such as one chart of a texture atlas,
+/// so that the materials of many meshes (or parts of one) can share a single image. Texture coordinates from 0 to 1 span the region,
+/// and those outside it wrap around within the region as the Wrap for each axis says, so they never reach the charts next to it.
+///
+/// Grid: a grid of values through a box in space (see VoxelGrid), such as the density or temperature of a simulated fire, shown in gray.
+///
+/// Custom: a texture defined outside this crate (see Pattern), shared between clones. It can't be serialized.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Texture {
     Solid(Color),
     Checker(Color, Color),
-    Noise(Perlin, f32),
-    Image(Vec<u8>, u32, u32),
+    Noise(Box<Perlin>, Float),
+    Image(TextureHandle),
+    Region(#[cfg_attr(feature = "serde", serde(with = "by_name"))] usize, [Float ; 4], [Wrap ; 2]),
+    Grid(VoxelGrid),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(Arc<dyn Pattern>),
+}
+
+///Procedural texture that programs using this crate can add as Texture::Custom (with Textures::add(), like any other), without changing the built-in textures.
+///
+/// Patterns are looked up from every rendering thread at once, so any state they keep must be shared safely.
+pub trait Pattern : Debug + Send + Sync {
+
+    ///Returns the color at texture coordinates (u, v) and point p, as Texture::value() does, given the textures of the scene
+    ///
+    /// for patterns that show others by id.
+    fn value(&self, u : Float, v : Float, p : Point3, textures : &Textures) -> Color;
+}
+
+///Built-in textures are patterns too, so that code taking a Pattern, such as one blending two others, can be given either kind.
+impl Pattern for Texture {
+    fn value(&self, u : Float, v : Float, p : Point3, textures : &Textures) -> Color {
+        Texture::value(self, u, v, p, textures)
+    }
+}
+
+///Textures of a scene, which materials and objects refer to by id, in the order they were added. Each scene owns its own,
+///
+/// so scenes can be built and rendered on different threads at once, and their textures are freed along with them.
+/// Textures can also be added under a name, which is how scene files refer to them, and how they are serialized (with the serde feature, see by_name).
+#[derive(Debug, Clone, Default)]
+pub struct Textures {
+    list : Vec<Texture>,
+    names : Vec<(String, usize)>,
+}
+
+impl Textures {
+
+    pub fn new() -> Textures {
+        Textures::default()
+    }
+
+    ///Adds a texture, returning its id for materials such as materials::Material::Lambertian to use.
+    pub fn add(&mut self, t : Texture) -> usize {
+        self.list.push(t);
+        self.list.len() - 1
+    }
+
+    ///Adds a texture as add() does, under a name, returning its id. A texture added later under the same name takes the name over.
+    pub fn add_named(&mut self, name : &str, t : Texture) -> usize {
+        let id = self.add(t);
+        self.names.retain(|(other, _)| other != name);
+        self.names.push((String::from(name), id));
+        id
+    }
+
+    ///Returns the id of the texture added under a name, if there is one.
+    pub fn id(&self, name : &str) -> Option<usize> {
+        self.names.iter().find(|(other, _)| other == name).map(|(_, id)| *id)
+    }
+
+    ///Returns the name a texture was added under, unless it was never given one or another texture has taken it over.
+    pub fn name(&self, id : usize) -> Option<&str> {
+        self.names.iter().find(|(_, other)| *other == id).map(|(name, _)| name.as_str())
+    }
+
+    ///Returns the texture with the given id, if it has been added.
+    pub fn get(&self, id : usize) -> Option<&Texture> {
+        self.list.get(id)
+    }
+
+    ///Returns the id along with those of the textures it shows part of, if it is a region, and so on for regions of regions.
+    ///
+    /// Regions should only show textures added before them, so the list stops at one that doesn't, which could lead back round to itself.
+    pub fn referenced(&self, id : usize) -> Vec<usize> {
+        let mut ids = vec![id];
+        let mut current = id;
+        while let Some(Texture::Region(inner, ..)) = self.list.get(current) {
+            if *inner >= current {
+                break;
+            }
+            ids.push(*inner);
+            current = *inner;
+        }
+        ids
+    }
+
+    ///Returns how many textures have been added, so that ids below it are valid.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    ///Returns the color of the texture with the given id at texture coordinates (u, v) and point p. The id must have been added,
+    ///
+    /// as accelerator::AcceleratorKind::build() checks for every object of a scene.
+    pub fn value(&self, id : usize, u : Float, v : Float, p : Point3) -> Color {
+        self.list[id].value(u, v, p, self)
+    }
+
+    ///Returns the color of the texture with the given id as value() does, blurred over a footprint as wide as given in texture coordinates
+    ///
+    /// (see HitRecord::footprint) if it is an image.
+    pub fn filtered(&self, id : usize, u : Float, v : Float, p : Point3, footprint : Float) -> Color {
+        self.list[id].filtered(u, v, p, footprint, self)
+    }
+}
+
+///Determines how texture coordinates outside 0 to 1 are brought back into a region. Variants include
+///
+/// Repeat: tiles the region.
+///
+/// Mirror: tiles the region, flipping every other tile so neighbouring tiles meet seamlessly.
+///
+/// Clamp: stretches the region's edges outwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Wrap {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl Wrap {
+
+    ///Brings a texture coordinate into [0, 1].
+    pub fn apply(&self, x : Float) -> Float {
+        match self {
+            Wrap::Repeat => x - x.floor(),
+            Wrap::Mirror => {
+                let t = x - 2.0 * (x / 2.0).floor();
+                if t > 1.0 {2.0 - t} else {t}
+            },
+            Wrap::Clamp => x.clamp(0.0, 1.0),
+        }
+    }
+}
+
+///The pixels of an image texture: 8 bit sRGB values, three to a pixel, row by row from the top, along with its mipmaps: smaller copies
+///
+/// of it, each half the size of the one before (rounding up) down to a single pixel, for lookups whose footprint covers many pixels
+/// (see sample()). They are stored behind an Arc, so handles (and the textures holding them) can be cloned, registered and shared between scenes
+/// without copying the image. Mipmaps aren't serialized, since they are made again from the pixels.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "RawTextureHandle"))]
+pub struct TextureHandle {
+    pub pixels : Arc<[u8]>,
+    pub width : u32,
+    pub height : u32,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    pub mipmaps : Arc<[Mipmap]>,
+}
+
+///One of the smaller copies of an image texture's pixels (see TextureHandle), laid out as they are.
+#[derive(Debug, Clone)]
+pub struct Mipmap {
+    pub pixels : Vec<u8>,
+    pub width : u32,
+    pub height : u32,
+}
+
+impl TextureHandle {
+
+    ///Creates a handle to the given pixels, which must hold 3 * width * height values, with neither of them 0. Panics if they don't.
+    pub fn new(pixels : impl Into<Arc<[u8]>>, width : u32, height : u32) -> TextureHandle {
+        TextureHandle::try_new(pixels, width, height).unwrap_or_else(|message| panic!("{}", message))
+    }
+
+    ///Creates a handle as new() does, making its mipmaps, or describes why the pixels don't make an image.
+    pub fn try_new(pixels : impl Into<Arc<[u8]>>, width : u32, height : u32) -> Result<TextureHandle, String> {
+        let pixels = pixels.into();
+        check(&pixels, width, height)?;
+        let mut mipmaps : Vec<Mipmap> = vec![];
+        let (mut above, mut above_width, mut above_height) = (&pixels[..], width, height);
+        while above_width > 1 || above_height > 1 {
+            let mipmap = halve(above, above_width, above_height);
+            mipmaps.push(mipmap);
+            let last = mipmaps.last().unwrap();
+            (above, above_width, above_height) = (&last.pixels, last.width, last.height);
+        }
+        Ok(TextureHandle {pixels, width, height, mipmaps : mipmaps.into()})
+    }
+
+    ///Creates a handle as new() does, with mipmaps made before (such as those kept in an asset cache), or describes why they don't fit the image.
+    pub fn try_with_mipmaps(pixels : impl Into<Arc<[u8]>>, width : u32, height : u32, mipmaps : Vec<Mipmap>) -> Result<TextureHandle, String> {
+        let pixels = pixels.into();
+        check(&pixels, width, height)?;
+        let (mut above_width, mut above_height) = (width, height);
+        for mipmap in &mipmaps {
+            if (mipmap.width, mipmap.height) != (above_width.div_ceil(2), above_height.div_ceil(2)) || (above_width, above_height) == (1, 1) {
+                return Err(format!("A {}x{} mipmap can't follow a {}x{} one", mipmap.width, mipmap.height, above_width, above_height));
+            }
+            check(&mipmap.pixels, mipmap.width, mipmap.height)?;
+            (above_width, above_height) = (mipmap.width, mipmap.height);
+        }
+        if (above_width, above_height) != (1, 1) {
+            return Err(format!("Texture mipmaps stop at {}x{}, before reaching a single pixel", above_width, above_height));
+        }
+        Ok(TextureHandle {pixels, width, height, mipmaps : mipmaps.into()})
+    }
+
+    ///Returns the color at texture coordinates (u, v), looked up in the mipmaps whose pixels are about as wide as footprint,
+    ///
+    /// the width the lookup covers in texture coordinates, blending between the two nearest, or in the image itself for a footprint
+    /// no wider than its pixels.
+    pub fn sample(&self, u : Float, v : Float, footprint : Float) -> Color {
+        let level = ((footprint * self.width.max(self.height) as Float).ln() * LOG2_E).clamp(0.0, self.mipmaps.len() as Float);
+        let lower = level as usize;
+        let fraction = level - lower as Float;
+        let color = self.texel(lower, u, v);
+        if fraction > 0.0 {
+            color * (1.0 - fraction) + self.texel(lower + 1, u, v) * fraction
+        } else {
+            color
+        }
+    }
+
+    ///Returns the color of the pixel of the given mipmap (0 for the image itself) at texture coordinates (u, v).
+    fn texel(&self, level : usize, u : Float, v : Float) -> Color {
+        let (pixels, width, height) = match level.checked_sub(1).and_then(|index| self.mipmaps.get(index)) {
+            Some(mipmap) => (&mipmap.pixels[..], mipmap.width, mipmap.height),
+            None => (&self.pixels[..], self.width, self.height),
+        };
+
+        let u_bounded = u.clamp(0.0, 1.0);
+        let v_bounded = if v < 0.0 {1.0} else if v > 1.0 {0.0} else {1.0 - v};
+        let i = ((u_bounded * width as Float) as u32).min(width - 1);
+        let j = ((v_bounded * height as Float) as u32).min(height - 1);
+
+        let index = 3*j*width + 3*i;
+        let channel = |k : u32| srgb_to_linear(pixels[(index + k) as usize] as Float / 255.0);
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+///Checks that pixels hold 3 * width * height values, with neither of them 0.
+fn check(pixels : &[u8], width : u32, height : u32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("Textures can't be {}x{}, they need at least one pixel", width, height));
+    }
+    if pixels.len() != 3 * width as usize * height as usize {
+        return Err(format!("Texture pixels ({} values) don't match its {}x{} size", pixels.len(), width, height));
+    }
+    Ok(())
+}
+
+///Makes the mipmap half the size of the given pixels, averaging each 2 by 2 block of them (the last row or column on its own
+///
+/// where there are an odd number) as linear light, so that bright and dark details blur into the brightness they add up to.
+fn halve(pixels : &[u8], width : u32, height : u32) -> Mipmap {
+    let linear = (0..=255u8).map(|x| srgb_to_linear(x as Float / 255.0)).collect::<Vec<_>>();
+    let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut halved = Vec::with_capacity(3 * half_width as usize * half_height as usize);
+    for j in 0..half_height {
+        let rows = 2*j..(2*j + 2).min(height);
+        for i in 0..half_width {
+            let columns = 2*i..(2*i + 2).min(width);
+            let count = (rows.len() * columns.len()) as Float;
+            for k in 0..3 {
+                let sum = rows.clone().flat_map(|y| columns.clone().map(move |x| 3*(y*width + x) + k)).map(|index| linear[pixels[index as usize] as usize]).sum::<Float>();
+                halved.push((linear_to_srgb(sum / count) * 255.0 + 0.5).clamp(0.0, 255.0) as u8);
+            }
+        }
+    }
+    Mipmap {pixels : halved, width : half_width, height : half_height}
+}
+
+///TextureHandle as it is deserialized, before its pixels are checked against its size.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawTextureHandle {
+    pixels : Arc<[u8]>,
+    width : u32,
+    height : u32,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawTextureHandle> for TextureHandle {
+    type Error = String;
+    fn try_from(raw : RawTextureHandle) -> Result<TextureHandle, String> {
+        TextureHandle::try_new(raw.pixels, raw.width, raw.height)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<RgbImage> for TextureHandle {
+    fn from(image : RgbImage) -> TextureHandle {
+        let (width, height) = image.dimensions();
+        TextureHandle::new(image.into_raw(), width, height)
+    }
+}
+
+///Grid of size[0] by size[1] by size[2] values spread through the box from minimum to maximum, stored x fastest, then y, then z,
+///
+/// and shared between clones as a TextureHandle's pixels are. Values are blended between the centers of the cells around a point,
+/// and points outside the box take the value of the nearest cell.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "RawVoxelGrid"))]
+pub struct VoxelGrid {
+    pub values : Arc<[Float]>,
+    pub size : [usize ; 3],
+    pub minimum : Point3,
+    pub maximum : Point3,
+}
+
+impl VoxelGrid {
+
+    ///Creates a grid of the given values, which must number size[0] * size[1] * size[2], none of them 0. Panics if they don't.
+    pub fn new(values : impl Into<Arc<[Float]>>, size : [usize ; 3], minimum : Point3, maximum : Point3) -> VoxelGrid {
+        VoxelGrid::try_new(values, size, minimum, maximum).unwrap_or_else(|message| panic!("{}", message))
+    }
+
+    ///Creates a grid as new() does, or describes why the values don't fill it.
+    pub fn try_new(values : impl Into<Arc<[Float]>>, size : [usize ; 3], minimum : Point3, maximum : Point3) -> Result<VoxelGrid, String> {
+        let values = values.into();
+        if size.contains(&0) {
+            return Err(String::from("Grids need at least one cell along each axis"));
+        }
+        let cells = size[0].checked_mul(size[1]).and_then(|cells| cells.checked_mul(size[2]));
+        if cells != Some(values.len()) {
+            return Err(format!("Grid values ({}) don't match its {}x{}x{} size", values.len(), size[0], size[1], size[2]));
+        }
+        Ok(VoxelGrid {values, size, minimum, maximum})
+    }
+
+    ///Returns the value at p, interpolated trilinearly.
+    pub fn value(&self, p : Point3) -> Float {
+        //Position in cells, with cell centers at whole numbers, and the cells on either side of it along each axis
+        let mut cells = [(0, 0, 0.0) ; 3];
+        for (axis, cell) in cells.iter_mut().enumerate() {
+            let n = self.size[axis];
+            let extent = self.maximum[axis] - self.minimum[axis];
+            let x = if extent > 0.0 {(p[axis] - self.minimum[axis]) / extent * n as Float - 0.5} else {0.0};
+            let x = x.clamp(0.0, (n - 1) as Float);
+            let low = (x.floor() as usize).min(n - 1);
+            *cell = (low, (low + 1).min(n - 1), x - low as Float);
+        }
+
+        let [(x0, x1, fx), (y0, y1, fy), (z0, z1, fz)] = cells;
+        let at = |x : usize, y : usize, z : usize| self.values[(z * self.size[1] + y) * self.size[0] + x];
+        let lerp = |a : Float, b : Float, t : Float| a + (b - a) * t;
+        let row = |y : usize, z : usize| lerp(at(x0, y, z), at(x1, y, z), fx);
+        lerp(lerp(row(y0, z0), row(y1, z0), fy), lerp(row(y0, z1), row(y1, z1), fy), fz)
+    }
+}
+
+///VoxelGrid as it is deserialized, before its values are checked against its size.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawVoxelGrid {
+    values : Arc<[Float]>,
+    size : [usize ; 3],
+    minimum : Point3,
+    maximum : Point3,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawVoxelGrid> for VoxelGrid {
+    type Error = String;
+    fn try_from(raw : RawVoxelGrid) -> Result<VoxelGrid, String> {
+        VoxelGrid::try_new(raw.values, raw.size, raw.minimum, raw.maximum)
+    }
 }
 
 impl Texture {
-    pub fn value(&self, u : f32, v : f32, p : Point3) -> Color {
+
+    ///Returns the color at texture coordinates (u, v) and point p. Regions look the texture they show up in the scene's textures.
+    pub fn value(&self, u : Float, v : Float, p : Point3, textures : &Textures) -> Color {
+        self.filtered(u, v, p, 0.0, textures)
+    }
+
+    ///Returns the color as value() does, with images looked up in the mipmaps matching a footprint as wide as given in texture coordinates.
+    pub fn filtered(&self, u : Float, v : Float, p : Point3, footprint : Float, textures : &Textures) -> Color {
         match self {
             Texture::Solid(c) => *c,
             Texture::Checker(odd, even) => {
                 let sines = (p.x * 10.0).sin() * (p.y * 10.0).sin() * (p.z * 10.0).sin();
                 if sines < 0.0 {
-                    return *odd;
+                    *odd
                 } else {
-                    return *even;
+                    *even
                 }
             },
             Texture::Noise(per, scale) => Color::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + (*scale * p.z + 10.0*per.turb(p, 7)).sin()),
-            Texture::Image(bytes, w, h) => {
-                let width = *w;
-                let height = *h;
-
-                let u_bounded = if u < 0.0 {0.0} else if u > 1.0 {1.0} else {u};
-                let v_bounded = if v < 0.0 {1.0} else if v > 1.0 {0.0} else {1.0 - v};
-                let mut i = (u_bounded * width as f32) as u32;
-                let mut j = (v_bounded * height as f32) as u32;
-
-                i = i.min(width - 1);
-                j = j.min(height - 1);
-
-                let index = 3*j*width + 3*i;
-                Color::new(bytes[index as usize] as f32 / 255.0, bytes[(index+1) as usize] as f32 / 255.0, bytes[(index+2) as usize] as f32 / 255.0)
+            //Coordinates stay just inside the region, so its edges never pick up the charts around it
+            Texture::Region(texture_id, [u0, v0, u1, v1], [wrap_u, wrap_v]) => {
+                let u = (u0 + wrap_u.apply(u) * (u1 - u0)).max(u0.min(*u1).next_up()).min(u0.max(*u1).next_down());
+                let v = (v0 + wrap_v.apply(v) * (v1 - v0)).max(v0.min(*v1).next_up()).min(v0.max(*v1).next_down());
+                let size = (u1 - u0).abs().max((v1 - v0).abs());
+                textures.filtered(*texture_id, u, v, p, footprint * size)
             },
+            Texture::Image(image) => image.sample(u, v, footprint),
+            Texture::Grid(grid) => {
+                let value = grid.value(p);
+                Color::new(value, value, value)
+            },
+            Texture::Custom(pattern) => pattern.value(u, v, p, textures),
         }
     }
 }
@@ -53,13 +445,24 @@ impl Texture {
 ///Implements the concept of Perlin noise, a type of gradient noise developed
 /// by Kevin Perlin to make procedural generation easier.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Perlin {
+    #[cfg_attr(feature = "serde", serde(with = "long_array"))]
     pub ranvec : [Vec3 ; 256],
+    #[cfg_attr(feature = "serde", serde(with = "long_array"))]
     pub perm_x : [i32 ; 256],
+    #[cfg_attr(feature = "serde", serde(with = "long_array"))]
     pub perm_y : [i32 ; 256],
+    #[cfg_attr(feature = "serde", serde(with = "long_array"))]
     pub perm_z : [i32 ; 256],
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perlin {
 
     pub fn new() -> Perlin {
@@ -73,7 +476,7 @@ impl Perlin {
         p
     }
 
-    fn noise(&self, p : Point3) -> f32 {
+    fn noise(&self, p : Point3) -> Float {
         let u = p.x - p.x.floor();
         let v = p.y - p.y.floor();
         let w = p.z - p.z.floor();
@@ -99,31 +502,31 @@ impl Perlin {
         Perlin::trilinear_interp(&c, u, v, w)
     }
 
-    fn trilinear_interp(c : &[[[Vec3 ; 2] ; 2] ; 2], u : f32, v : f32, w : f32) -> f32 {
+    fn trilinear_interp(c : &[[[Vec3 ; 2] ; 2] ; 2], u : Float, v : Float, w : Float) -> Float {
         //Hermite's cubic to smooth noise
         let uu = u*u*(3.0 - 2.0*u);
         let vv = v*v*(3.0 - 2.0*v);
         let ww = w*w*(3.0 - 2.0*w);
 
         let mut accum = 0.0;
-        for di in 0..=1 {
-            for dj in 0..=1 {
-                for dk in 0..=1 {
-                    let i = di as f32;
-                    let j = dj as f32;
-                    let k = dk as f32;
+        for (di, plane) in c.iter().enumerate() {
+            for (dj, row) in plane.iter().enumerate() {
+                for (dk, gradient) in row.iter().enumerate() {
+                    let i = di as Float;
+                    let j = dj as Float;
+                    let k = dk as Float;
                     let weight_v = Vec3::new(u-i, v-j, w-k);
                     let i_fac = i * uu + (1.0 - i)*(1.0 - uu);
                     let j_fac = j * vv + (1.0 - j)*(1.0 - vv);
                     let k_fac = k * ww + (1.0 - k)*(1.0 - ww);
-                    accum += i_fac * j_fac * k_fac * dot(c[di][dj][dk], weight_v);
+                    accum += i_fac * j_fac * k_fac * dot(*gradient, weight_v);
                 }
             }
         }
         accum
     }
 
-    pub fn turb(&self, p : Point3, depth : i32) -> f32 {
+    pub fn turb(&self, p : Point3, depth : i32) -> Float {
         let mut accum = 0.0;
         let mut temp_p = p;
         let mut weight = 1.0;
@@ -139,12 +542,132 @@ impl Perlin {
         for i in 0..256 {
             arr[i as usize] = i;
         }
-        let mut rng = rand::thread_rng();
         for i in (1..=255).rev() {
-            let target = rng.gen_range(0..(i+1)) as usize;
-            let tmp = arr[i as usize];
-            arr[i as usize] = arr[target];
-            arr[target] = tmp;
+            let target = ((random_float() * (i + 1) as Float) as usize).min(i as usize);
+            arr.swap(i as usize, target);
         }
     }
-}
\ No newline at end of file
+}
+
+///Serializes texture ids as the names their textures were added under (see Textures::add_named()), for #[serde(with = "by_name")],
+///
+/// so that serialized materials and objects don't depend on the order textures happened to be added in. Names are only known
+/// inside with(), given the textures of the scene being serialized or deserialized, so those textures must be built first.
+/// Without the std feature, which keeping the names at hand takes, ids are serialized as numbers instead.
+#[cfg(feature = "serde")]
+pub mod by_name {
+    use serde::{Serializer, Deserializer, Deserialize};
+    #[cfg(feature = "std")]
+    use {alloc::format, alloc::string::String, alloc::vec::Vec, core::cell::RefCell};
+    #[cfg(feature = "std")]
+    use serde::{ser::Error as _, de::Error as _};
+    #[cfg(feature = "std")]
+    use super::Textures;
+
+    #[cfg(feature = "std")]
+    std::thread_local! {
+        ///Names of the textures given to with() on this thread, with their ids.
+        static NAMES : RefCell<Vec<(String, usize)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    ///Serializes and deserializes texture ids as the names of the given textures while f runs, as in
+    ///
+    /// by_name::with(&textures, || serde_json::to_string(&material))
+    #[cfg(feature = "std")]
+    pub fn with<R>(textures : &Textures, f : impl FnOnce() -> R) -> R {
+        let outer = NAMES.with(|names| names.replace(textures.names.clone()));
+        let result = f();
+        NAMES.with(|names| *names.borrow_mut() = outer);
+        result
+    }
+
+    #[cfg(feature = "std")]
+    pub fn serialize<S : Serializer>(id : &usize, serializer : S) -> Result<S::Ok, S::Error> {
+        match NAMES.with(|names| names.borrow().iter().find(|(_, other)| other == id).map(|(name, _)| name.clone())) {
+            Some(name) => serializer.serialize_str(&name),
+            None => Err(S::Error::custom(format!("Texture {} has no name (see Textures::add_named() and by_name::with())", id))),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn deserialize<'de, D : Deserializer<'de>>(deserializer : D) -> Result<usize, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let id = NAMES.with(|names| names.borrow().iter().find(|(other, _)| *other == name).map(|(_, id)| *id));
+        id.ok_or_else(|| D::Error::custom(format!("No texture is named {}", name)))
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn serialize<S : Serializer>(id : &usize, serializer : S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(*id as u64)
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn deserialize<'de, D : Deserializer<'de>>(deserializer : D) -> Result<usize, D::Error> {
+        usize::deserialize(deserializer)
+    }
+}
+
+///Serializes arrays too long for serde's own impls (which stop at 32) as sequences.
+#[cfg(feature = "serde")]
+mod long_array {
+    use alloc::format;
+    use alloc::vec::Vec;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::Error as _;
+
+    pub fn serialize<S : Serializer, T : Serialize, const N : usize>(array : &[T ; N], serializer : S) -> Result<S::Ok, S::Error> {
+        array.as_slice().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D : Deserializer<'de>, T : Deserialize<'de>, const N : usize>(deserializer : D) -> Result<[T ; N], D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        let length = values.len();
+        values.try_into().map_err(|_| D::Error::invalid_length(length, &format!("an array of {} values", N).as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use crate::vec3::{Point3, Color, Float};
+    use super::{Texture, Textures, TextureHandle, Pattern};
+
+    ///Pattern blending two other textures of the scene from left (u = 0) to right (u = 1).
+    #[derive(Debug)]
+    struct Blend(usize, usize);
+
+    impl Pattern for Blend {
+        fn value(&self, u : Float, v : Float, p : Point3, textures : &Textures) -> Color {
+            textures.value(self.0, u, v, p) * (1.0 - u) + textures.value(self.1, u, v, p) * u
+        }
+    }
+
+    ///Samples a pattern defined here through the scene's textures, as materials do, checking it sees the textures it refers to by id.
+    #[test]
+    fn custom_patterns_are_sampled() {
+        let mut textures = Textures::new();
+        let red = textures.add(Texture::Solid(Color::new(1.0, 0.0, 0.0)));
+        let blue = textures.add(Texture::Solid(Color::new(0.0, 0.0, 1.0)));
+        let blend = textures.add(Texture::Custom(Arc::new(Blend(red, blue))));
+
+        let p = Point3::new(0.0, 0.0, 0.0);
+        for (u, expected) in [(0.0, Color::new(1.0, 0.0, 0.0)), (0.25, Color::new(0.75, 0.0, 0.25)), (1.0, Color::new(0.0, 0.0, 1.0))] {
+            let color = textures.value(blend, u, 0.5, p);
+            assert!((color - expected).length() < 1e-6, "{:?} at u = {}, not {:?}", color, u, expected);
+        }
+    }
+
+    ///Blurs a 3x2 black and white checkerboard through its mipmaps, checking they halve it down to a single pixel averaging its light,
+    ///
+    /// and that wide footprints look them up while narrow ones see the image's own pixels.
+    #[test]
+    fn mipmaps_average_the_image() {
+        let image = TextureHandle::new([255, 0, 255, 0, 255, 0].iter().flat_map(|x| [*x ; 3]).collect::<alloc::vec::Vec<u8>>(), 3, 2);
+        assert_eq!(image.mipmaps.iter().map(|mipmap| (mipmap.width, mipmap.height)).collect::<alloc::vec::Vec<_>>(), [(2, 1), (1, 1)]);
+
+        assert!((image.sample(0.1, 0.9, 0.0).x - 1.0).abs() < 1e-6);
+        assert!((image.sample(0.5, 0.9, 0.0).x).abs() < 1e-6);
+        let blurred = image.sample(0.5, 0.5, 10.0).x;
+        assert!((blurred - 0.5).abs() < 0.01, "{} isn't half as bright", blurred);
+    }
+}
@@ -0,0 +1,49 @@
+/*
+Module to store the tone mapping operators, which fit the exposed radiance of each pixel into the range a display can show.
+*/
+
+use crate::vec3::{Color, Float};
+use crate::environment::luminance;
+
+///Determines how exposed radiance is mapped to display values between 0 and 1, before gamma is applied. Variants include
+///
+/// Linear: leaves radiance as it is, so anything brighter than 1 clips to white.
+///
+/// Reinhard: divides each color by one plus its luminance, which keeps its hue and approaches white without ever clipping.
+///
+/// Aces: Krzysztof Narkowicz's fit of the ACES filmic curve, with a slight toe and a strong shoulder. Saturated highlights desaturate towards white.
+///
+/// Filmic: John Hable's curve from Uncharted 2, with a softer shoulder that reaches white at 11.2 times the radiance that appears white with Linear.
+#[derive(Debug, Clone, Copy)]
+pub enum ToneMap {
+    Linear,
+    Reinhard,
+    Aces,
+    Filmic,
+}
+
+impl ToneMap {
+
+    ///Maps a pixel's exposed radiance to display values, which are at most 1 for every operator but Linear.
+    pub fn apply(&self, color : Color) -> Color {
+        match self {
+            ToneMap::Linear => color,
+            ToneMap::Reinhard => {
+                let l = luminance(color);
+                if l > 0.0 {color / (1.0 + l)} else {color}
+            },
+            ToneMap::Aces => map_channels(color, |x| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)),
+            ToneMap::Filmic => map_channels(color, |x| hable(2.0 * x) / hable(11.2)),
+        }
+    }
+}
+
+fn map_channels(color : Color, f : impl Fn(Float) -> Float) -> Color {
+    Color::new(f(color.x.max(0.0)), f(color.y.max(0.0)), f(color.z.max(0.0)))
+}
+
+///John Hable's filmic curve, with its shoulder, linear section and toe strengths, toe numerator and denominator.
+fn hable(x : Float) -> Float {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
@@ -1,28 +1,53 @@
 use crate::hitting::{Hittable, HitRecord};
 use crate::bvh::{AABB, surrounding_box};
-use crate::ray_class::Ray;
+use crate::ray::Ray;
+use crate::textures::Textures;
+use crate::vec3::{random_float, Float};
+use crate::stats::count_traversal;
 use std::cmp::Ordering;
-use rand::Rng;
+use std::iter::from_fn;
+use std::io::{Error, ErrorKind, Result};
 
+///Node of a Bounding Volume Hierarchy. Interior nodes have two children split along an axis, and leaves hold one object.
 #[derive(Debug, Clone)]
 pub struct Node {
-    left : Option<usize>,
-    right : Option<usize>,
-    aabb : Option<AABB>,
-    data : Option<Hittable>,
+    pub left : Option<usize>,
+    pub right : Option<usize>,
+    pub aabb : AABB,
+    pub data : Option<Hittable>,
+    pub axis : usize,
 }
 
 impl Node {
-    fn new(left : Option<usize>, right : Option<usize>, aabb : Option<AABB>, data : Option<Hittable>) -> Node {
+    fn new(left : Option<usize>, right : Option<usize>, aabb : AABB, data : Option<Hittable>, axis : usize) -> Node {
         Node {
             left, 
             right,
             aabb,
             data,
+            axis,
         }
     }
 }
 
+///Maximum depth of the hierarchy that hit() can walk. Trees are split in half at every level, so this is never reached in practice.
+const STACK_SIZE : usize = 64;
+
+///Largest number of rays hit_packet() traces together.
+pub const MAX_PACKET : usize = 16;
+
+///Summary of the shape of a Bounding Volume Hierarchy, for working out why a scene renders slowly.
+///
+/// total_surface_area adds up the boxes of every node; the larger it is compared to the root's, the more boxes an average ray must test.
+#[derive(Debug, Clone, Copy)]
+pub struct TreeStats {
+    pub nodes : usize,
+    pub leaves : usize,
+    pub max_depth : usize,
+    pub average_leaf_size : Float,
+    pub total_surface_area : Float,
+}
+
 ///Represents a Bounding Volume Hierarchy of the objects in the scene. Allows
 /// 
 /// ray collisions to be detected in O(log2 n) time.
@@ -33,30 +58,38 @@ pub struct Tree {
 }
 
 impl Tree {
-    ///Builds a Bounding Volume Hierarchy from a list of Hittavle objects.
-    pub fn build(lst : &mut Vec<Hittable>) -> Tree {
+    ///Builds a Bounding Volume Hierarchy from a list of Hittable objects. An empty list gives an empty hierarchy that nothing hits.
+    /// 
+    /// Fails if any object's bounding box has NaN coordinates, since it could not be placed in the hierarchy.
+    pub fn build(lst : &mut [Hittable]) -> Result<Tree> {
+        if let Some(index) = lst.iter().position(|item| item.bounding_box().has_nan()) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Object {} has an invalid bounding box", index)));
+        }
+
         let mut t = Tree{items : vec![], root : 0};
-        t.root = t.con(lst);
-        t
+        if !lst.is_empty() {
+            t.root = t.con(lst);
+        }
+        Ok(t)
     }
 
-    ///Creates a new node with two children.
-    fn new_node(& mut self, aabb : Option<AABB>, left : Option<usize>, right : Option<usize>) -> usize {
+    ///Creates a new node with two children, split along the given axis.
+    fn new_node(& mut self, aabb : AABB, left : Option<usize>, right : Option<usize>, axis : usize) -> usize {
         let next = self.items.len();
-        self.items.push(Node::new(left, right, aabb, None));
+        self.items.push(Node::new(left, right, aabb, None, axis));
         next
     }
 
     ///Creates a new leaf node containing a Hittable object.
     fn new_leaf(&mut self, item : &Hittable) -> usize {
         let next = self.items.len();
-        self.items.push(Node::new(None, None, Some(item.bounding_box()), Some(item.clone())));
+        self.items.push(Node::new(None, None, item.bounding_box().padded(), Some(item.clone()), 0));
         next
     }
 
-    ///Recursive helper function that constructs a new Bounding Volume Hierarchy from the input slice.
+    ///Recursive helper function that constructs a new Bounding Volume Hierarchy from the input slice, which must not be empty.
     fn con(&mut self, objects : &mut [Hittable]) -> usize {
-        let axis = rand::thread_rng().gen_range(0..3) as usize;
+        let axis = ((random_float() * 3.0) as usize).min(2);
         objects.sort_by(|a : &Hittable, b : &Hittable| cmp(a, b, axis));
 
         let left : usize;
@@ -74,62 +107,251 @@ impl Tree {
             right = self.con(right_l);
         }
 
-        if let Some(r_box) = self.items[left].aabb {
-            if let Some(l_box) = self.items[right].aabb {
-                return self.new_node(Some(surrounding_box(r_box, l_box)), Some(left), Some(right));
+        let aabb = surrounding_box(self.items[left].aabb, self.items[right].aabb);
+        self.new_node(aabb, Some(left), Some(right), axis)
+    }
+
+    ///Returns the bounding box surrounding every object in the hierarchy, or None if it is empty.
+    pub fn bounding_box(&self) -> Option<AABB> {
+        self.items.get(self.root).map(|node| node.aabb)
+    }
+
+    ///Returns statistics about the nodes reachable from the root. The root has a depth of 1.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {nodes : 0, leaves : 0, max_depth : 0, average_leaf_size : 0.0, total_surface_area : 0.0};
+        let mut objects = 0;
+        let mut stack = if self.items.is_empty() {vec![]} else {vec![(self.root, 1)]};
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.items[index];
+            stats.nodes += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            stats.total_surface_area += node.aabb.surface_area();
+            if node.data.is_some() {
+                stats.leaves += 1;
+                objects += 1;
             }
+            stack.extend([node.left, node.right].into_iter().flatten().map(|child| (child, depth + 1)));
+        }
+        if stats.leaves > 0 {
+            stats.average_leaf_size = objects as Float / stats.leaves as Float;
+        }
+        stats
+    }
+
+    ///Checks that the hierarchy is well formed, so that every object can be hit: every node is reachable from the root exactly once,
+    ///
+    /// leaves hold an object and have no children, interior nodes have both children, and every node's box contains its children's.
+    pub fn validate(&self) -> Result<()> {
+        if self.items.is_empty() {
+            return Ok(());
         }
+        if self.root >= self.items.len() {
+            return Err(invalid(format!("Root {} is out of range", self.root)));
+        }
+
+        let mut visited = vec![false ; self.items.len()];
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            if visited[index] {
+                return Err(invalid(format!("Node {} is reachable more than once", index)));
+            }
+            visited[index] = true;
+
+            let node = &self.items[index];
+            match (node.left, node.right, &node.data) {
+                (None, None, Some(_)) => (),
+                (Some(left), Some(right), None) => {
+                    for child in [left, right] {
+                        if child >= self.items.len() {
+                            return Err(invalid(format!("Child {} of node {} is out of range", child, index)));
+                        }
+                        if !node.aabb.contains(self.items[child].aabb) {
+                            return Err(invalid(format!("Node {} is not contained in its parent {}", child, index)));
+                        }
+                        stack.push(child);
+                    }
+                },
+                (_, _, Some(_)) => return Err(invalid(format!("Leaf {} has children", index))),
+                (_, _, None) => return Err(invalid(format!("Interior node {} is missing a child", index))),
+            }
+        }
+
+        match visited.iter().position(|v| !v) {
+            Some(orphan) => Err(invalid(format!("Node {} is not reachable from the root", orphan))),
+            None => Ok(()),
+        }
+    }
+
+    ///Determines if a ray hits any object in the Bounding Volume Hierarchy below the node at index, filling in rec with the closest hit.
+    /// 
+    /// Walks the hierarchy with a stack rather than recursion, visiting the child nearer the ray's origin first, so that
+    /// once something is hit, farther nodes can be skipped.
+    pub fn hit(& self, r : Ray, t_min : Float, t_max : Float, rec : &mut HitRecord, index : usize, textures : &Textures) -> bool {
+        if index >= self.items.len() {
+            return false;
+        }
+
+        let mut stack = [0 ; STACK_SIZE];
+        let mut top = 1;
+        stack[0] = index;
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        let (mut nodes, mut primitives) = (0, 0);
+
+        while top > 0 {
+            top -= 1;
+            let current = stack[top];
+            let node = &self.items[current];
+            nodes += 1;
+            if !node.aabb.hit(r, t_min, closest) {
+                continue;
+            }
+
+            if let Some(d) = &node.data {
+                primitives += 1;
+                if d.hit(r, t_min, closest, rec, textures) {
+                    hit_anything = true;
+                    closest = rec.t;
+                    rec.object = current;
+                }
+                continue;
+            }
 
-        self.new_node(None, None, None)
+            //Children are sorted along the node's axis, so a ray heading down that axis meets the right child first
+            let (near, far) = if r.direction[node.axis].is_sign_negative() {(node.right, node.left)} else {(node.left, node.right)};
+            for child in [far, near].into_iter().flatten() {
+                stack[top] = child;
+                top += 1;
+            }
+        }
+        count_traversal(nodes, primitives);
+        hit_anything
     }
 
-    ///Determines if a ray hits any object in the Bounding Volume Hierarchy.
-    pub fn hit(& self, r : Ray, t_min : f32, t_max : f32, rec : &mut HitRecord, index : usize) -> bool {
-        let node = &self.items[index];
-        if let Some(aabb) = node.aabb {
-            if aabb.hit(r, t_min, t_max) {
-                if let Some(d) = &node.data {
-                    return d.hit(r, t_min, t_max, rec);
+    ///Determines which of a packet of rays hit any object, filling in recs with each ray's closest hit, and returns a bitmask of the rays that hit.
+    ///
+    /// The rays walk the hierarchy together, so each node is fetched once for the whole packet, and subtrees are only entered by the rays
+    /// whose boxes tests pass. Coherent rays, such as the samples through one pixel, mostly take the same path.
+    /// Packets hold at most MAX_PACKET rays, and recs must be at least as long as rays.
+    pub fn hit_packet(&self, rays : &[Ray], t_min : Float, t_max : Float, recs : &mut [HitRecord], textures : &Textures) -> u32 {
+        let n = rays.len().min(MAX_PACKET);
+        if self.items.is_empty() || n == 0 {
+            return 0;
+        }
+
+        let mut closest = [t_max ; MAX_PACKET];
+        let mut hits = 0;
+        let mut stack = [(0, 0) ; STACK_SIZE];
+        stack[0] = (self.root, (1u32 << n) - 1);
+        let mut top = 1;
+        let (mut nodes, mut primitives) = (0, 0);
+
+        while top > 0 {
+            top -= 1;
+            let (index, entering) = stack[top];
+            let node = &self.items[index];
+            nodes += entering.count_ones() as u64;
+            //Coherent rays tend to agree, so once one ray enters the box, the rest after it follow without being tested
+            let active = match rays_in(entering).find(|k| node.aabb.hit(rays[*k], t_min, closest[*k])) {
+                Some(first) => entering & !((1 << first) - 1),
+                None => continue,
+            };
+
+            if let Some(d) = &node.data {
+                primitives += active.count_ones() as u64;
+                for k in rays_in(active) {
+                    if d.hit(rays[k], t_min, closest[k], &mut recs[k], textures) {
+                        hits |= 1 << k;
+                        closest[k] = recs[k].t;
+                        recs[k].object = index;
+                    }
                 }
+                continue;
+            }
 
-                let mut rec_l : HitRecord = rec.clone();
-                let mut rec_r : HitRecord = rec.clone();
-
-                let hit_l = match node.left {
-                    Some(left) => self.hit(r, t_min, t_max, &mut rec_l, left),
-                    None => false,
-                };
-
-                let hit_r = match node.right {
-                    Some(right) => self.hit(r, t_min, if hit_l {rec_l.t} else {t_max}, &mut rec_r, right),
-                    None => false,
-                };
-                
-                if hit_l && !hit_r {
-                    *rec = rec_l.clone();
-                } else if !hit_l && hit_r {
-                    *rec = rec_r.clone();
-                } else {
-                    if rec_l.t < rec_r.t {
-                        *rec = rec_l.clone();
-                    } else {
-                        *rec = rec_r.clone();
+            //The first active ray decides the order for the whole packet
+            let first = rays[active.trailing_zeros() as usize];
+            let (near, far) = if first.direction[node.axis].is_sign_negative() {(node.right, node.left)} else {(node.left, node.right)};
+            for child in [far, near].into_iter().flatten() {
+                stack[top] = (child, active);
+                top += 1;
+            }
+        }
+        count_traversal(nodes, primitives);
+        hits
+    }
+
+    ///Determines which of a packet of shadow rays are blocked by any object before their own t_max, returning a bitmask of them.
+    ///
+    /// The rays walk the hierarchy together as in hit_packet(), but shadow rays leave a point towards different lights, so every ray's box test
+    /// is made, and a ray drops out of the packet as soon as anything blocks it, since the closest hit doesn't matter.
+    /// Packets hold at most MAX_PACKET rays, and t_max must be at least as long as rays.
+    pub fn occluded_packet(&self, rays : &[Ray], t_min : Float, t_max : &[Float], textures : &Textures) -> u32 {
+        let n = rays.len().min(MAX_PACKET);
+        if self.items.is_empty() || n == 0 {
+            return 0;
+        }
+
+        let mut blocked = 0;
+        let mut rec = HitRecord::new();
+        let mut stack = [(0, 0) ; STACK_SIZE];
+        stack[0] = (self.root, (1u32 << n) - 1);
+        let mut top = 1;
+        let (mut nodes, mut primitives) = (0, 0);
+
+        while top > 0 {
+            top -= 1;
+            let (index, entering) = stack[top];
+            let entering = entering & !blocked;
+            let node = &self.items[index];
+            nodes += entering.count_ones() as u64;
+            let active = rays_in(entering).filter(|k| node.aabb.hit(rays[*k], t_min, t_max[*k])).fold(0u32, |mask, k| mask | 1 << k);
+            if active == 0 {
+                continue;
+            }
+
+            if let Some(d) = &node.data {
+                primitives += active.count_ones() as u64;
+                for k in rays_in(active) {
+                    if d.hit(rays[k], t_min, t_max[k], &mut rec, textures) {
+                        blocked |= 1 << k;
                     }
                 }
+                if blocked == (1u32 << n) - 1 {
+                    break;
+                }
+                continue;
+            }
 
-                return hit_l || hit_r;
+            let first = rays[active.trailing_zeros() as usize];
+            let (near, far) = if first.direction[node.axis].is_sign_negative() {(node.right, node.left)} else {(node.left, node.right)};
+            for child in [far, near].into_iter().flatten() {
+                stack[top] = (child, active);
+                top += 1;
             }
         }
-        false
+        count_traversal(nodes, primitives);
+        blocked
     }
 }
 
 ///Custom comparator function for two Hittable objects (based on location).
 pub fn cmp(a : &Hittable, b : &Hittable, index : usize) -> Ordering {
-    if a.bounding_box().minimum[index] < b.bounding_box().minimum[index] {
-        return Ordering::Less;
-    } else if a.bounding_box().minimum[index] > b.bounding_box().minimum[index] {
-        return Ordering::Greater;
-    } 
-    Ordering::Equal
-}
\ No newline at end of file
+    a.bounding_box().minimum[index].total_cmp(&b.bounding_box().minimum[index])
+}
+
+fn invalid(message : String) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid Bounding Volume Hierarchy: {}", message))
+}
+
+///Returns the indices of the rays set in a packet's bitmask.
+fn rays_in(mut mask : u32) -> impl Iterator<Item = usize> {
+    from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+        let k = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        Some(k)
+    })
+}
@@ -0,0 +1,527 @@
+/*
+Module to store the importer for USD scenes written as text (.usda files, or .usd files saved as text), which loads the subset of them
+this tracer can represent into a scene_file::SceneFile:
+
+prims: Mesh (split into flat triangles, with their st texture coordinates), Sphere and Cube, placed by their xformOps and those of their parents;
+materials: UsdPreviewSurface shaders bound with material:binding, which become lights when emissive, glass when not opaque, metals when metallic
+and diffuse surfaces otherwise, with a diffuseColor that may come from a UsdUVTexture (other inputs must be constants);
+cameras: the first Camera prim, with its focal length, apertures, f-stop and focus distance;
+lights: SphereLight, RectLight, DistantLight and DomeLight.
+
+Other prims, such as Xform, Scope, Material and Shader, only group or describe these, and prims without a material use their displayColor.
+Layers are read on their own, so references, payloads and sublayers must be flattened into one first, with usdcat --flatten,
+which also converts binary (usdc) files to text.
+*/
+
+use std::collections::HashMap;
+use std::fs::read;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use crate::vec3::consts::PI;
+use crate::cache::load_image;
+use crate::usda::{Layer, Prim, Value};
+use crate::vec3::{Vec3, Point3, Color, Float};
+use crate::textures::{Texture, Textures};
+use crate::materials::Material;
+use crate::hitting::Hittable;
+use crate::lights::Light;
+use crate::scene_file::{SceneFile, FileSettings};
+use crate::mitsuba::{Matrix, IDENTITY, CONSTANT_RADIUS, rotation, multiply, transform_point, transform_vector, bounds, aligned};
+
+const FLATTEN : &str = "references, payloads, inherits and sublayers aren't followed (flatten the stage with usdcat --flatten first)";
+
+///Loads a USD scene, along with the images it refers to.
+pub fn load(path : &str) -> Result<SceneFile> {
+    let bytes = read(path)?;
+    if bytes.starts_with(b"PXR-USDC") || bytes.starts_with(b"PK") {
+        return Err(invalid("binary (usdc) and packaged (usdz) files can't be read, but usdcat can convert them to text"));
+    }
+    let layer = Layer::parse(&String::from_utf8_lossy(&bytes))?;
+    if metadata(&layer.metadata, "subLayers").is_some_and(|layers| *layers != Value::List(vec![])) {
+        return Err(invalid(FLATTEN));
+    }
+
+    //Scenes with Z up are turned to have Y up, as the camera in main() expects
+    let root = match metadata(&layer.metadata, "upAxis") {
+        Some(Value::Text(axis)) if axis == "Z" => rotation(Vec3::new(1.0, 0.0, 0.0), -PI / 2.0),
+        _ => IDENTITY,
+    };
+
+    let mut importer = Importer {
+        directory : Path::new(path).parent().map_or(PathBuf::new(), Path::to_path_buf),
+        prims : HashMap::new(),
+        materials : HashMap::new(),
+        root,
+        camera : false,
+        scene : SceneFile {settings : FileSettings::default(), textures : Textures::new(), objects : vec![], lights : vec![]},
+    };
+    for prim in &layer.prims {
+        importer.index(prim);
+    }
+    for prim in &layer.prims {
+        importer.prim(prim, &root, None)?;
+    }
+    Ok(importer.scene)
+}
+
+///Imports a layer's prims, keeping track of every prim by path, and of the materials converted so far.
+struct Importer<'a> {
+    directory : PathBuf,
+    prims : HashMap<&'a str, &'a Prim>,
+    materials : HashMap<&'a str, (Material, bool)>,
+    root : Matrix,
+    camera : bool,
+    scene : SceneFile,
+}
+
+impl<'a> Importer<'a> {
+
+    ///Records a prim and its children under their paths, so that relationships and connections can be followed.
+    fn index(&mut self, prim : &'a Prim) {
+        self.prims.insert(&prim.path, prim);
+        for child in &prim.children {
+            self.index(child);
+        }
+    }
+
+    ///Imports a prim and its children, given its parent's transform and the path of the material bound to its nearest ancestor with one.
+    fn prim(&mut self, prim : &'a Prim, parent : &Matrix, binding : Option<&'a str>) -> Result<()> {
+        if prim.specifier == "class" || prim.value("visibility") == Some(&Value::Text(String::from("invisible"))) {
+            return Ok(());
+        }
+        if ["references", "payload", "inherits", "specializes"].iter().any(|key| metadata(&prim.metadata, key).is_some()) {
+            return Err(invalid(FLATTEN));
+        }
+        let transform = self.transform(prim, parent)?;
+        let binding = match prim.value("material:binding") {
+            Some(target) => Some(target_path(target)?),
+            None => binding,
+        };
+
+        match prim.kind.as_str() {
+            "Mesh" => self.mesh(prim, &transform, binding)?,
+            "Sphere" => {
+                let (mat, emissive) = self.material(prim, binding)?;
+                let radius = number(prim, "radius", 1.0)?;
+                let scale = transform_vector(&transform, Vec3::new(1.0, 0.0, 0.0)).length();
+                let center = transform_point(&transform, Point3::new(0.0, 0.0, 0.0));
+                self.scene.objects.push((Hittable::Sphere(mat, center, radius * scale), emissive));
+            },
+            "Cube" => {
+                let (mat, emissive) = self.material(prim, binding)?;
+                let half = number(prim, "size", 2.0)? / 2.0;
+                self.cube(mat, emissive, &transform, half);
+            },
+            "Camera" => self.camera(prim, &transform)?,
+            "SphereLight" | "RectLight" | "DistantLight" | "DomeLight" => self.light(prim, &transform)?,
+            "Cylinder" | "Cone" | "Capsule" | "Plane" | "Points" | "BasisCurves" | "NurbsCurves" | "NurbsPatch" | "PointInstancer"
+            | "DiskLight" | "CylinderLight" | "GeometryLight" | "PortalLight" => {
+                return Err(invalid(&format!("unsupported prim type {} ({})", prim.kind, prim.path)));
+            },
+            _ => {},
+        }
+
+        for child in &prim.children {
+            self.prim(child, &transform, binding)?;
+        }
+        Ok(())
+    }
+
+    ///Combines a prim's xformOps, in the order its xformOpOrder lists them, with its parent's transform.
+    fn transform(&self, prim : &Prim, parent : &Matrix) -> Result<Matrix> {
+        let Some(order) = prim.value("xformOpOrder") else {
+            return Ok(*parent);
+        };
+        let mut matrix = *parent;
+        for operation in list(order)? {
+            let name = text(operation)?;
+            if name == "!resetXformStack!" {
+                matrix = self.root;
+                continue;
+            }
+            let (inverse, name) = match name.strip_prefix("!invert!") {
+                Some(name) => (true, name),
+                None => (false, name),
+            };
+            let value = prim.value(name).ok_or_else(|| invalid(&format!("{} has no {}", prim.path, name)))?;
+
+            //Op names are xformOp:kind, with an optional suffix such as :pivot
+            let step = match name.split(':').nth(1).unwrap_or("") {
+                "translate" => {
+                    let offset = if inverse {-vector(value)?} else {vector(value)?};
+                    [[1.0, 0.0, 0.0, offset.x], [0.0, 1.0, 0.0, offset.y], [0.0, 0.0, 1.0, offset.z], [0.0, 0.0, 0.0, 1.0]]
+                },
+                "scale" => {
+                    let scale = vector(value)?;
+                    let scale = if inverse {Vec3::new(1.0 / scale.x, 1.0 / scale.y, 1.0 / scale.z)} else {scale};
+                    [[scale.x, 0.0, 0.0, 0.0], [0.0, scale.y, 0.0, 0.0], [0.0, 0.0, scale.z, 0.0], [0.0, 0.0, 0.0, 1.0]]
+                },
+                //Matrices are written in rows that multiply points from the left, with the translation in the last row
+                "transform" => match numbers(value)?[..] {
+                    [a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p] if !inverse => [[a, e, i, m], [b, f, j, n], [c, g, k, o], [d, h, l, p]],
+                    [_, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _] => return Err(invalid("inverted transform ops aren't supported")),
+                    _ => return Err(invalid("a transform op needs 16 numbers")),
+                },
+                "orient" => match numbers(value)?[..] {
+                    [w, x, y, z] => orthonormal(quaternion(w, x, y, z), inverse),
+                    _ => return Err(invalid("an orient op needs a quaternion")),
+                },
+                //Rotations such as rotateXYZ turn about each axis in turn, the first one first
+                kind if kind.starts_with("rotate") => {
+                    let axes = &kind["rotate".len()..];
+                    let angles = numbers(value)?;
+                    if axes.is_empty() || axes.len() != angles.len() {
+                        return Err(invalid(&format!("unsupported transform op {}", name)));
+                    }
+                    let mut step = IDENTITY;
+                    for (axis, angle) in axes.chars().zip(angles) {
+                        let axis = match axis {
+                            'X' => Vec3::new(1.0, 0.0, 0.0),
+                            'Y' => Vec3::new(0.0, 1.0, 0.0),
+                            'Z' => Vec3::new(0.0, 0.0, 1.0),
+                            _ => return Err(invalid(&format!("unsupported transform op {}", name))),
+                        };
+                        step = multiply(&rotation(axis, angle.to_radians()), &step);
+                    }
+                    orthonormal(step, inverse)
+                },
+                _ => return Err(invalid(&format!("unsupported transform op {}", name))),
+            };
+            matrix = multiply(&matrix, &step);
+        }
+        Ok(matrix)
+    }
+
+    ///Splits a mesh's faces into triangles, fanning out from each face's first corner.
+    fn mesh(&mut self, prim : &Prim, transform : &Matrix, binding : Option<&'a str>) -> Result<()> {
+        let (mat, emissive) = self.material(prim, binding)?;
+        let points = tuples(prim, "points", 3)?.into_iter().map(|p| transform_point(transform, Point3::new(p[0], p[1], p[2]))).collect::<Vec<_>>();
+        let counts = integers(prim, "faceVertexCounts")?;
+        let indices = integers(prim, "faceVertexIndices")?;
+        if indices.iter().any(|index| *index >= points.len()) || counts.iter().sum::<usize>() > indices.len() {
+            return Err(invalid(&format!("{} has faces with corners that don't exist", prim.path)));
+        }
+        let uvs = texture_coordinates(prim, points.len(), &indices)?;
+        let left_handed = prim.value("orientation") == Some(&Value::Text(String::from("leftHanded")));
+
+        let mut first = 0;
+        for count in counts {
+            for i in 1..count.saturating_sub(1) {
+                let mut corners = [first, first + i, first + i + 1];
+                if left_handed {
+                    corners.swap(1, 2);
+                }
+                let uv = corners.map(|corner| uvs.as_ref().map_or((0.0, 0.0), |uvs| uvs[corner]));
+                self.scene.objects.push((Hittable::Triangle(mat, corners.map(|corner| points[indices[corner]]), uv), emissive));
+            }
+            first += count;
+        }
+        Ok(())
+    }
+
+    ///Adds a cube of the given half size, as a box if its transform leaves it lined up with the axes, or as triangles otherwise.
+    fn cube(&mut self, mat : Material, emissive : bool, transform : &Matrix, half : Float) {
+        if aligned(transform) {
+            let (min, max) = bounds(transform, &[(-half, -half, -half), (half, half, half)]);
+            self.scene.objects.push((Hittable::Box(mat, min, max), emissive));
+            return;
+        }
+        for axis in 0..3 {
+            for side in [-half, half] {
+                let corner = |u : Float, w : Float| {
+                    let mut p = Point3::new(0.0, 0.0, 0.0);
+                    p[axis] = side;
+                    p[(axis + 1) % 3] = u;
+                    p[(axis + 2) % 3] = w;
+                    p
+                };
+                //Corners wind counterclockwise around the positive axis, so faces on the negative side are reversed to face outwards
+                let mut corners = [corner(-half, -half), corner(half, -half), corner(half, half), corner(-half, half)];
+                if side < 0.0 {
+                    corners.reverse();
+                }
+                self.quad(mat, emissive, transform, corners);
+            }
+        }
+    }
+
+    ///Adds a quadrilateral as two triangles, with texture coordinates running from 0 to 1 across it.
+    fn quad(&mut self, mat : Material, emissive : bool, transform : &Matrix, corners : [Point3 ; 4]) {
+        let corners = corners.map(|corner| transform_point(transform, corner));
+        let uvs = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+            self.scene.objects.push((Hittable::Triangle(mat, [corners[a], corners[b], corners[c]], [uvs[a], uvs[b], uvs[c]]), emissive));
+        }
+    }
+
+    ///Returns the material bound to a prim and whether it emits light. Prims without one are diffuse, in their displayColor or grey.
+    fn material(&mut self, prim : &Prim, binding : Option<&'a str>) -> Result<(Material, bool)> {
+        let Some(binding) = binding else {
+            let color = match prim.value("primvars:displayColor").map(numbers).transpose()?.as_deref() {
+                Some([r, g, b, ..]) => Color::new(*r, *g, *b),
+                _ => Color::new(0.5, 0.5, 0.5),
+            };
+            return Ok((Material::Lambertian(self.scene.textures.add(Texture::Solid(color))), false));
+        };
+        if let Some(material) = self.materials.get(binding) {
+            return Ok(*material);
+        }
+        let material = self.surface(binding)?;
+        self.materials.insert(binding, material);
+        Ok(material)
+    }
+
+    ///Converts the UsdPreviewSurface connected to the surface output of a Material prim.
+    fn surface(&mut self, path : &str) -> Result<(Material, bool)> {
+        let material = self.find(path)?;
+        let output = material.value("outputs:surface.connect").ok_or_else(|| invalid(&format!("{} has no surface output", path)))?;
+        let shader = self.find(target_path(output)?)?;
+        if shader.value("info:id") != Some(&Value::Text(String::from("UsdPreviewSurface"))) {
+            return Err(invalid(&format!("{} isn't a UsdPreviewSurface, the only shader supported", shader.path)));
+        }
+        let input = |name : &str, default : Float| shader.value(&format!("inputs:{}", name)).map_or(Ok(default), single);
+        let color = |name : &str, default : Color| shader.value(&format!("inputs:{}", name)).map_or(Ok(default), vector);
+
+        let emission = color("emissiveColor", Color::new(0.0, 0.0, 0.0))?;
+        if emission.x + emission.y + emission.z > 0.0 {
+            return Ok((Material::Light(self.scene.textures.add(Texture::Solid(emission))), true));
+        }
+        if input("opacity", 1.0)? < 1.0 {
+            return Ok((Material::Dielectric(Color::new(1.0, 1.0, 1.0), input("ior", 1.5)?, 0.0, 0), false));
+        }
+        //Metals take a single color, so textured ones are a light grey
+        if input("metallic", 0.0)? >= 0.5 {
+            return Ok((Material::Metal(color("diffuseColor", Color::new(0.8, 0.8, 0.8))?, input("roughness", 0.5)?), false));
+        }
+
+        let texture = match shader.value("inputs:diffuseColor.connect") {
+            Some(target) => {
+                let texture = self.find(target_path(target)?)?;
+                if texture.value("info:id") != Some(&Value::Text(String::from("UsdUVTexture"))) {
+                    return Err(invalid(&format!("{} isn't a UsdUVTexture, the only texture supported", texture.path)));
+                }
+                let file = texture.value("inputs:file").ok_or_else(|| invalid(&format!("{} has no file", texture.path)))?;
+                Texture::Image(load_image(self.directory.join(text(file)?)).map_err(|error| invalid(&error.to_string()))?)
+            },
+            None => Texture::Solid(color("diffuseColor", Color::new(0.18, 0.18, 0.18))?),
+        };
+        Ok((Material::Lambertian(self.scene.textures.add(texture)), false))
+    }
+
+    ///Sets the camera from the first Camera prim, which looks down its -z axis with +y up.
+    fn camera(&mut self, prim : &Prim, transform : &Matrix) -> Result<()> {
+        if self.camera {
+            return Ok(());
+        }
+        self.camera = true;
+        if prim.value("projection") == Some(&Value::Text(String::from("orthographic"))) {
+            return Err(invalid("orthographic cameras aren't supported"));
+        }
+        let settings = &mut self.scene.settings;
+        settings.lookfrom = Some(transform_point(transform, Point3::new(0.0, 0.0, 0.0)));
+        settings.lookat = Some(transform_point(transform, Point3::new(0.0, 0.0, -1.0)));
+        settings.vup = Some(transform_vector(transform, Vec3::new(0.0, 1.0, 0.0)));
+
+        let focal_length = number(prim, "focalLength", 50.0)?;
+        let horizontal = number(prim, "horizontalAperture", 20.955)?;
+        let vertical = number(prim, "verticalAperture", 15.2908)?;
+        settings.aspect_ratio = Some(horizontal / vertical);
+        settings.vfov = Some((2.0 * (vertical / (2.0 * focal_length)).atan()).to_degrees());
+
+        //Focal lengths are in tenths of a scene unit, so the lens is a tenth of the focal length over the f-stop across
+        let f_stop = number(prim, "fStop", 0.0)?;
+        if f_stop > 0.0 {
+            settings.aperture = Some(focal_length / 10.0 / f_stop);
+            settings.focus_distance = Some(number(prim, "focusDistance", 0.0)?);
+            settings.autofocus = Some(false);
+        } else {
+            settings.aperture = Some(0.0);
+        }
+        Ok(())
+    }
+
+    ///Imports a light, whose radiance is its color times its intensity times 2 to the power of its exposure.
+    fn light(&mut self, prim : &Prim, transform : &Matrix) -> Result<()> {
+        //Current versions of UsdLux name the light's parameters inputs:intensity and so on, where older ones use intensity
+        let value = |name : &str| prim.value(&format!("inputs:{}", name)).or_else(|| prim.value(name));
+        let input = |name : &str, default : Float| value(name).map_or(Ok(default), single);
+        let default_intensity = if prim.kind == "DistantLight" {50000.0} else {1.0};
+        let color = value("color").map_or(Ok(Color::new(1.0, 1.0, 1.0)), vector)?;
+        let intensity = input("intensity", default_intensity)? * (2.0 as Float).powf(input("exposure", 0.0)?);
+        let radiance = color * intensity;
+        let origin = transform_point(transform, Point3::new(0.0, 0.0, 0.0));
+
+        match prim.kind.as_str() {
+            "SphereLight" => {
+                let radius = input("radius", 0.5)? * transform_vector(transform, Vec3::new(1.0, 0.0, 0.0)).length();
+                if value("treatAsPoint").is_some_and(truth) {
+                    self.scene.lights.push(Light::Point(origin, radiance * PI * radius * radius, 2.0, None));
+                } else {
+                    let mat = Material::Light(self.scene.textures.add(Texture::Solid(radiance)));
+                    self.scene.objects.push((Hittable::Sphere(mat, origin, radius), true));
+                }
+            },
+            "RectLight" => {
+                let (width, height) = (input("width", 1.0)? / 2.0, input("height", 1.0)? / 2.0);
+                let corners = [Point3::new(-width, -height, 0.0), Point3::new(width, -height, 0.0), Point3::new(width, height, 0.0), Point3::new(-width, height, 0.0)];
+                let mat = Material::Light(self.scene.textures.add(Texture::Solid(radiance)));
+                self.quad(mat, true, transform, corners);
+            },
+            //The irradiance of a distant light is its radiance over the cone of directions it covers
+            "DistantLight" => {
+                let radius = input("angle", 0.53)? / 2.0;
+                let solid_angle = 2.0 * PI * (1.0 - radius.to_radians().cos());
+                let irradiance = if radius > 0.0 {radiance * solid_angle} else {radiance};
+                self.scene.lights.push(Light::Directional(transform_vector(transform, Vec3::new(0.0, 0.0, -1.0)), irradiance, radius));
+            },
+            //Domes without a texture become an emissive sphere surrounding the scene, found by rays that escape it
+            _ => match value("texture:file") {
+                Some(file) => {
+                    self.scene.settings.environment_map = Some(self.directory.join(text(file)?).to_string_lossy().into_owned());
+                    self.scene.settings.environment_intensity = Some(intensity);
+                },
+                None => {
+                    let mat = Material::Light(self.scene.textures.add(Texture::Solid(radiance)));
+                    self.scene.objects.push((Hittable::Sphere(mat, Point3::new(0.0, 0.0, 0.0), CONSTANT_RADIUS), false));
+                },
+            },
+        }
+        Ok(())
+    }
+
+    fn find(&self, path : &str) -> Result<&'a Prim> {
+        self.prims.get(path).copied().ok_or_else(|| invalid(&format!("unknown prim {}", path)))
+    }
+}
+
+///Reads a mesh's texture coordinates (its st primvar, or else its first texCoord2f[] primvar) for every face corner, if it has any.
+fn texture_coordinates(prim : &Prim, points : usize, indices : &[usize]) -> Result<Option<Vec<(Float, Float)>>> {
+    let Some(primvar) = prim.property("primvars:st").or_else(|| prim.properties.iter().find(|property| {
+        property.kind.starts_with("texCoord2") && property.name.starts_with("primvars:") && !property.name.ends_with(":indices")
+    })) else {
+        return Ok(None);
+    };
+    let mut values = tuples(prim, &primvar.name, 2)?.into_iter().map(|uv| (uv[0], uv[1])).collect::<Vec<_>>();
+    if prim.value(&format!("{}:indices", primvar.name)).is_some() {
+        let remapped = integers(prim, &format!("{}:indices", primvar.name))?.into_iter().map(|index| values.get(index).copied()).collect::<Option<Vec<_>>>();
+        values = remapped.ok_or_else(|| invalid(&format!("{} has texture coordinate indices that don't exist", prim.path)))?;
+    }
+
+    //Without an interpolation, it is found from the number of values
+    let interpolation = match metadata(&primvar.metadata, "interpolation") {
+        Some(interpolation) => text(interpolation)?,
+        None if values.len() == points => "vertex",
+        None => "faceVarying",
+    };
+    match interpolation {
+        "faceVarying" if values.len() >= indices.len() => Ok(Some(values)),
+        "vertex" | "varying" if values.len() >= points => Ok(Some(indices.iter().map(|index| values[*index]).collect())),
+        "constant" | "uniform" => Ok(None),
+        _ => Err(invalid(&format!("{} has the wrong number of texture coordinates", prim.path))),
+    }
+}
+
+///Reads an array of points or texture coordinates, each with the given number of values.
+fn tuples(prim : &Prim, name : &str, size : usize) -> Result<Vec<Vec<Float>>> {
+    let value = prim.value(name).ok_or_else(|| invalid(&format!("{} has no {}", prim.path, name)))?;
+    let values = numbers(value)?;
+    if values.len() % size != 0 {
+        return Err(invalid(&format!("{} of {} needs {} numbers each", name, prim.path, size)));
+    }
+    Ok(values.chunks(size).map(<[Float]>::to_vec).collect())
+}
+
+fn integers(prim : &Prim, name : &str) -> Result<Vec<usize>> {
+    let value = prim.value(name).ok_or_else(|| invalid(&format!("{} has no {}", prim.path, name)))?;
+    Ok(numbers(value)?.into_iter().map(|number| number as usize).collect())
+}
+
+fn metadata<'a>(entries : &'a [(String, Value)], key : &str) -> Option<&'a Value> {
+    entries.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+}
+
+///Reads every number in a value, flattening tuples and arrays.
+fn numbers(value : &Value) -> Result<Vec<Float>> {
+    match value {
+        Value::Number(number) => Ok(vec![*number]),
+        Value::List(values) => Ok(values.iter().map(numbers).collect::<Result<Vec<_>>>()?.concat()),
+        _ => Err(invalid(&format!("expected numbers, not {:?}", value))),
+    }
+}
+
+fn number(prim : &Prim, name : &str, default : Float) -> Result<Float> {
+    prim.value(name).map_or(Ok(default), single)
+}
+
+fn single(value : &Value) -> Result<Float> {
+    match numbers(value)?[..] {
+        [x] => Ok(x),
+        _ => Err(invalid("expected a single number")),
+    }
+}
+
+fn vector(value : &Value) -> Result<Vec3> {
+    match numbers(value)?[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(invalid("expected 3 numbers")),
+    }
+}
+
+fn truth(value : &Value) -> bool {
+    match value {
+        Value::Number(number) => *number != 0.0,
+        Value::Text(text) => text == "true",
+        _ => false,
+    }
+}
+
+fn text(value : &Value) -> Result<&str> {
+    match value {
+        Value::Text(text) => Ok(text),
+        _ => Err(invalid(&format!("expected a string, not {:?}", value))),
+    }
+}
+
+fn list(value : &Value) -> Result<&[Value]> {
+    match value {
+        Value::List(values) => Ok(values),
+        _ => Err(invalid(&format!("expected a list, not {:?}", value))),
+    }
+}
+
+///Returns the prim a relationship or connection targets, dropping the property in paths such as </Looks/Wood/Shader.outputs:surface>.
+fn target_path(value : &Value) -> Result<&str> {
+    match value {
+        Value::Path(path) => Ok(path.split('.').next().unwrap_or(path)),
+        Value::List(values) if !values.is_empty() => target_path(&values[0]),
+        _ => Err(invalid(&format!("expected a path, not {:?}", value))),
+    }
+}
+
+///Rotation given by a unit quaternion.
+pub fn quaternion(w : Float, x : Float, y : Float, z : Float) -> Matrix {
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+///Returns a rotation, or its inverse (its transpose).
+fn orthonormal(matrix : Matrix, inverse : bool) -> Matrix {
+    if !inverse {
+        return matrix;
+    }
+    let mut transpose = matrix;
+    for (i, row) in transpose.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = matrix[j][i];
+        }
+    }
+    transpose
+}
+
+fn invalid(message : &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid USD scene: {}", message))
+}
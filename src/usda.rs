@@ -0,0 +1,361 @@
+/*
+Module to store a small parser for USD layers written as text (.usda), used to import USD scenes. It reads prims with their properties
+and metadata, keeping the first sample of animated attributes and skipping dictionaries, which scenes only use for bookkeeping.
+*/
+
+use std::io::{Error, ErrorKind, Result};
+use crate::vec3::Float;
+
+///Value of a property or metadata entry. Strings, tokens and asset paths are all Text, and tuples and arrays are both Lists.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(Float),
+    Text(String),
+    Path(String),
+    List(Vec<Value>),
+}
+
+///Layer of a USD stage: its metadata (such as upAxis) and its root prims.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub metadata : Vec<(String, Value)>,
+    pub prims : Vec<Prim>,
+}
+
+///Prim of a layer. Specifier is def, over or class, kind is the prim's type (such as Mesh or Xform, or empty if it has none),
+///
+/// and path its full path, such as /World/Chair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prim {
+    pub specifier : String,
+    pub kind : String,
+    pub path : String,
+    pub metadata : Vec<(String, Value)>,
+    pub properties : Vec<Property>,
+    pub children : Vec<Prim>,
+}
+
+///Attribute or relationship of a prim with a value, under its full name (such as xformOp:translate, or inputs:diffuseColor.connect for connections).
+///
+/// Kind is its type (such as point3f[]), or rel for relationships.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub kind : String,
+    pub name : String,
+    pub value : Value,
+    pub metadata : Vec<(String, Value)>,
+}
+
+impl Layer {
+
+    ///Parses a layer, which must start with the #usda header.
+    pub fn parse(text : &str) -> Result<Layer> {
+        let mut parser = Parser {bytes : text.as_bytes(), position : 0};
+        if !text.starts_with("#usda") {
+            return Err(parser.invalid("expected the #usda header"));
+        }
+        let metadata = parser.metadata()?;
+        let mut prims = vec![];
+        loop {
+            parser.skip();
+            if parser.position >= parser.bytes.len() {
+                return Ok(Layer {metadata, prims});
+            }
+            prims.push(parser.prim("")?);
+        }
+    }
+}
+
+impl Prim {
+
+    ///Returns the value of a property, if the prim has one with that name.
+    pub fn value(&self, name : &str) -> Option<&Value> {
+        self.property(name).map(|property| &property.value)
+    }
+
+    pub fn property(&self, name : &str) -> Option<&Property> {
+        self.properties.iter().find(|property| property.name == name)
+    }
+}
+
+///Recursive descent parser, reading a layer from position onwards.
+struct Parser<'a> {
+    bytes : &'a [u8],
+    position : usize,
+}
+
+impl Parser<'_> {
+
+    fn prim(&mut self, parent : &str) -> Result<Prim> {
+        let specifier = self.identifier()?;
+        if !matches!(specifier.as_str(), "def" | "over" | "class") {
+            return Err(self.invalid(&format!("expected def, over or class, not {}", specifier)));
+        }
+        self.skip();
+        let kind = if matches!(self.bytes.get(self.position), Some(b'"' | b'\'')) {String::new()} else {self.identifier()?};
+        let path = format!("{}/{}", parent, self.string()?);
+        let metadata = self.metadata()?;
+        self.expect("{")?;
+
+        let mut prim = Prim {specifier, kind, path, metadata, properties : vec![], children : vec![]};
+        loop {
+            if self.eat("}") {
+                return Ok(prim);
+            }
+            let start = self.position;
+            match self.identifier()?.as_str() {
+                "def" | "over" | "class" => {
+                    self.position = start;
+                    let child = self.prim(&prim.path)?;
+                    prim.children.push(child);
+                },
+                "variantSet" => return Err(self.invalid("variant sets aren't supported (flatten the stage with usdcat --flatten first)")),
+                _ => {
+                    self.position = start;
+                    if let Some(property) = self.property()? {
+                        prim.properties.push(property);
+                    }
+                },
+            }
+        }
+    }
+
+    ///Reads a property, returning None for declarations without a value and for statements such as reorder nameChildren = [...].
+    fn property(&mut self) -> Result<Option<Property>> {
+        let mut kind = self.identifier()?;
+        while matches!(kind.as_str(), "custom" | "uniform" | "varying" | "prepend" | "append" | "add" | "delete" | "reorder") {
+            kind = self.identifier()?;
+        }
+        if self.eat("[]") {
+            kind.push_str("[]");
+        }
+        if self.eat("=") {
+            self.value()?;
+            return Ok(None);
+        }
+        let mut name = self.identifier()?;
+        let value = if self.eat("=") {Some(self.value()?)} else {None};
+        let metadata = self.metadata()?;
+        if let Some(base) = name.strip_suffix(".timeSamples") {
+            name = base.to_string();
+        }
+        Ok(value.map(|value| Property {kind, name, value, metadata}))
+    }
+
+    ///Reads a parenthesized list of metadata, if one comes next. Doc strings are kept under the key doc.
+    fn metadata(&mut self) -> Result<Vec<(String, Value)>> {
+        let mut entries = vec![];
+        if !self.eat("(") {
+            return Ok(entries);
+        }
+        loop {
+            if self.eat(")") {
+                return Ok(entries);
+            }
+            if self.eat(";") {
+                continue;
+            }
+            if matches!(self.bytes.get(self.position), Some(b'"' | b'\'')) {
+                entries.push((String::from("doc"), Value::Text(self.string()?)));
+                continue;
+            }
+            let mut key = self.identifier()?;
+            while matches!(key.as_str(), "prepend" | "append" | "add" | "delete" | "reorder") {
+                key = self.identifier()?;
+            }
+            self.expect("=")?;
+            entries.push((key, self.value()?));
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip();
+        match self.bytes.get(self.position) {
+            Some(b'"' | b'\'') => Ok(Value::Text(self.string()?)),
+            Some(b'@') => {
+                let delimiter = if self.bytes[self.position..].starts_with(b"@@@") {"@@@"} else {"@"};
+                let asset = self.delimited(delimiter, delimiter)?;
+                //References give the prim to use from the asset after it, which is skipped
+                if self.bytes.get(self.position) == Some(&b'<') {
+                    self.delimited("<", ">")?;
+                }
+                Ok(Value::Text(asset))
+            },
+            Some(b'<') => Ok(Value::Path(self.delimited("<", ">")?)),
+            Some(open @ (b'(' | b'[')) => {
+                let close = if *open == b'(' {")"} else {"]"};
+                self.position += 1;
+                let mut values = vec![];
+                loop {
+                    if self.eat(close) {
+                        return Ok(Value::List(values));
+                    }
+                    values.push(self.value()?);
+                    if !self.eat(",") {
+                        self.expect(close)?;
+                        return Ok(Value::List(values));
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.position += 1;
+                self.block()
+            },
+            Some(b'-' | b'+' | b'.' | b'0'..=b'9') => Ok(Value::Number(self.number()?)),
+            Some(_) => Ok(Value::Text(self.identifier()?)),
+            None => Err(self.invalid("unexpected end of file")),
+        }
+    }
+
+    ///Reads the rest of a block after its opening brace. Time samples, as in { 0: (0, 0, 0), 24: (0, 5, 0) }, give the value of their first sample,
+    ///
+    /// while dictionaries, as in customData = { string author = "..." }, are skipped and give an empty list.
+    fn block(&mut self) -> Result<Value> {
+        self.skip();
+        if matches!(self.bytes.get(self.position), Some(b'-' | b'.' | b'0'..=b'9')) {
+            let mut first = None;
+            loop {
+                if self.eat("}") {
+                    return first.ok_or_else(|| self.invalid("time samples without any samples"));
+                }
+                self.number()?;
+                self.expect(":")?;
+                let value = self.value()?;
+                first.get_or_insert(value);
+                self.eat(",");
+            }
+        }
+
+        let mut depth = 1;
+        while depth > 0 {
+            match self.bytes.get(self.position) {
+                None => return Err(self.invalid("unterminated dictionary")),
+                Some(b'"' | b'\'') => {
+                    self.string()?;
+                },
+                Some(brace) => {
+                    depth += match brace {
+                        b'{' => 1,
+                        b'}' => -1,
+                        _ => 0,
+                    };
+                    self.position += 1;
+                },
+            }
+        }
+        Ok(Value::List(vec![]))
+    }
+
+    ///Reads a number, including inf, -inf and nan.
+    fn number(&mut self) -> Result<Float> {
+        self.skip();
+        let start = self.position;
+        while self.position < self.bytes.len() && (self.bytes[self.position].is_ascii_alphanumeric() || matches!(self.bytes[self.position], b'+' | b'-' | b'.')) {
+            self.position += 1;
+        }
+        let text = String::from_utf8_lossy(&self.bytes[start..self.position]);
+        text.parse().map_err(|_| self.invalid(&format!("malformed number {}", text)))
+    }
+
+    ///Reads a name, type or token, which can hold namespaces (inputs:diffuseColor) and suffixes (.connect).
+    fn identifier(&mut self) -> Result<String> {
+        self.skip();
+        let start = self.position;
+        if self.bytes.get(self.position).is_some_and(|byte| byte.is_ascii_alphabetic() || *byte == b'_') {
+            while self.position < self.bytes.len() && (self.bytes[self.position].is_ascii_alphanumeric() || matches!(self.bytes[self.position], b'_' | b':' | b'.')) {
+                self.position += 1;
+            }
+        }
+        if start == self.position {
+            return Err(self.invalid("expected a name"));
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+
+    ///Reads a quoted string, which may be triple quoted to span lines, decoding its escapes.
+    fn string(&mut self) -> Result<String> {
+        self.skip();
+        let quote = match self.bytes.get(self.position) {
+            Some(quote @ (b'"' | b'\'')) => *quote,
+            _ => return Err(self.invalid("expected a string")),
+        };
+        let triple = String::from_utf8_lossy(&[quote ; 3]).into_owned();
+        if self.bytes[self.position..].starts_with(triple.as_bytes()) {
+            return self.delimited(&triple, &triple);
+        }
+
+        self.position += 1;
+        let mut text = vec![];
+        loop {
+            match self.bytes.get(self.position) {
+                None | Some(b'\n') => return Err(self.invalid("unterminated string")),
+                Some(b'\\') => {
+                    text.push(match self.bytes.get(self.position + 1) {
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        Some(other) => *other,
+                        None => return Err(self.invalid("unterminated string")),
+                    });
+                    self.position += 2;
+                },
+                Some(byte) if *byte == quote => {
+                    self.position += 1;
+                    return Ok(String::from_utf8_lossy(&text).into_owned());
+                },
+                Some(byte) => {
+                    text.push(*byte);
+                    self.position += 1;
+                },
+            }
+        }
+    }
+
+    ///Reads the text between an opening and closing delimiter, starting at the opening one.
+    fn delimited(&mut self, open : &str, close : &str) -> Result<String> {
+        self.position += open.len();
+        let start = self.position;
+        let length = self.bytes[start..].windows(close.len()).position(|window| window == close.as_bytes())
+            .ok_or_else(|| self.invalid(&format!("expected {}", close)))?;
+        self.position = start + length + close.len();
+        Ok(String::from_utf8_lossy(&self.bytes[start..start + length]).into_owned())
+    }
+
+    fn expect(&mut self, text : &str) -> Result<()> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(self.invalid(&format!("expected '{}'", text)))
+        }
+    }
+
+    ///Skips whitespace and comments, then moves past the given text if it comes next.
+    fn eat(&mut self, text : &str) -> bool {
+        self.skip();
+        let found = self.bytes[self.position..].starts_with(text.as_bytes());
+        if found {
+            self.position += text.len();
+        }
+        found
+    }
+
+    ///Skips whitespace and comments (including the #usda header), which run from # to the end of the line.
+    fn skip(&mut self) {
+        while self.position < self.bytes.len() {
+            if self.bytes[self.position] == b'#' {
+                while self.position < self.bytes.len() && self.bytes[self.position] != b'\n' {
+                    self.position += 1;
+                }
+            } else if self.bytes[self.position].is_ascii_whitespace() {
+                self.position += 1;
+            } else {
+                return;
+            }
+        }
+    }
+
+    ///Returns an error for the current position, giving its line number.
+    fn invalid(&self, message : &str) -> Error {
+        let line = self.bytes[..self.position.min(self.bytes.len())].iter().filter(|b| **b == b'\n').count() + 1;
+        Error::new(ErrorKind::InvalidData, format!("Invalid USD on line {}: {}", line, message))
+    }
+}
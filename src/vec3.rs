@@ -0,0 +1,574 @@
+use core::ops::{Add, Sub, Mul, Div, AddAssign, MulAssign, DivAssign, IndexMut, Index, Neg};
+use core::fmt::{self, Debug, Formatter};
+use core::sync::atomic::{AtomicU64, AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "std")]
+use rand::rngs::StdRng;
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use crate::sampler::{Sampler, RandomSampler};
+#[cfg(not(feature = "std"))]
+use crate::math::Real;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+///Floating point type of all of the tracer's math: f32, or f64 with the f64 feature, for scenes so large (such as the solar system,
+///
+/// 800 units across) that f32 leaves shadow acne and rays slipping past bounding boxes. Images and files keep f32 either way.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+///Mathematical constants (PI and so on) of the Float type.
+#[cfg(not(feature = "f64"))]
+pub use core::f32::consts;
+#[cfg(feature = "f64")]
+pub use core::f64::consts;
+use consts::PI;
+
+///Widens a Float to f64, for sums and math that need the extra precision whichever type Float is.
+#[inline]
+pub const fn wide(x : Float) -> f64 {
+    #[cfg(not(feature = "f64"))]
+    return x as f64;
+    #[cfg(feature = "f64")]
+    return x;
+}
+
+///Used to keep track of 3-dimensional vector data.
+///
+/// With the simd feature (on by default) on x86_64, and without f64, vectors are backed by 16 byte SSE registers, with an unused
+/// fourth lane whose value means nothing (it may become NaN), so that their arithmetic, dot and cross products take a few instructions each.
+/// Results are identical to the scalar version's, which building with --no-default-features --features std gives, for comparison.
+///
+/// The fourth lane is a private field, so vectors can't be written as Vec3 {x, y, z} literals with simd on; use Vec3::new() instead,
+/// which builds the same way in every configuration. Reading and writing x, y and z directly works in all of them.
+#[derive(Clone, Copy)]
+#[cfg_attr(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"), repr(C, align(16)))]
+pub struct Vec3 {
+    pub x : Float,
+    pub y : Float,
+    pub z : Float,
+    #[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+    w : Float,
+}
+
+///Represents a 3D point in vector space.
+pub type Point3 = Vec3;
+
+///Represents an RGB-based color value.
+pub type Color = Vec3;
+
+//These methods make the most sense to use when a vec3 instance is being used as a vector, rather than a point or color.
+impl Vec3 {
+
+    ///Creates a new 3-dimensional vector, point, or color.
+    pub fn new(x : Float, y : Float, z : Float) -> Vec3 {
+        Vec3 {
+            x,
+            y,
+            z,
+            #[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+            w : 0.0,
+        }
+    }
+
+    ///Returns the component at an index (0 for x, 1 for y and 2 for z), or None past the last, where indexing panics.
+    pub fn get(&self, index : usize) -> Option<Float> {
+        match index {
+            0 => Some(self.x),
+            1 => Some(self.y),
+            2 => Some(self.z),
+            _ => None,
+        }
+    }
+
+    ///Returns the square of the length of this vector.
+    pub fn length_squared(&self) -> Float {
+        dot(*self, *self)
+    }
+
+    ///Returns the length of this vector.
+    pub fn length(&self) -> Float {
+        self.length_squared().sqrt()
+    }
+
+    ///Returns the unit vector of this vector.
+    pub fn unit_vector(&self) -> Vec3 {
+        *self / self.length()
+    }
+
+    ///Returns a random vector, point or color, with all 3 parameters being random numbers between 0 and 1 non-inclusive.
+    pub fn random() -> Vec3 {
+        let (x, y, z) = (random_float(), random_float(), random_float());
+        Vec3::new(x, y, z)
+    }
+
+    ///Returns a random vector, point or color, with all 3 parameters being random numbers between a minimum and a maximum non-inclusive.
+    pub fn random_range(minimum : Float, maximum : Float) -> Vec3 {
+        let (x, y, z) = (random_range_float(minimum, maximum), random_range_float(minimum, maximum), random_range_float(minimum, maximum));
+        Vec3::new(x, y, z)
+    }
+
+    ///Returns whether the vector's values are all close to 0. Near zero is needed due to floating point error.
+    pub fn near_zero(&self) -> bool {
+        let s : Float = 0.0001;
+        self.x.abs() < s && self.y.abs() < s && self.z.abs() < s
+    }
+
+    ///Returns a reflected vector based on the input and the calling vector (for use in Dielectric and Metal objects).
+    pub fn reflect(&self, n : Vec3) -> Vec3 {
+        *self -  n * 2.0 * dot(*self, n)
+    }
+
+    ///Returns a refracted vector based on the input and the calling vector (for use in Dielectric objects).
+    pub fn refract(&self, n : Vec3, etai_over_etat : Float) -> Vec3 {
+        let cos = if dot(-(*self), n) < 1.0 {dot(-(*self), n)} else {1.0};
+        let r_perp = (*self + n * cos) * etai_over_etat;
+        let r_parallel = n * -((1.0 - r_perp.length_squared()).abs().sqrt());
+        r_perp + r_parallel
+    }
+
+}
+
+//Written out by hand, so that the unused lane of the SIMD version isn't shown
+impl Debug for Vec3 {
+    fn fmt(&self, f : &mut Formatter) -> fmt::Result {
+        f.debug_struct("Vec3").field("x", &self.x).field("y", &self.y).field("z", &self.z).finish()
+    }
+}
+
+///Vectors are serialized as [x, y, z], leaving out the SIMD lane.
+#[cfg(feature = "serde")]
+impl Serialize for Vec3 {
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+        [self.x, self.y, self.z].serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Vec3 {
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Vec3, D::Error> {
+        let [x, y, z] = <[Float ; 3]>::deserialize(deserializer)?;
+        Ok(Vec3::new(x, y, z))
+    }
+}
+
+///Indexes the components, 0 for x, 1 for y and 2 for z.
+///
+/// # Panics
+/// Past 2. The tracer only indexes with axes it picks itself (such as a node's split axis), which are always 0 to 2,
+/// so a bad index is a bug rather than something to recover from; get() returns None instead, for indices from elsewhere.
+impl Index<usize> for Vec3 {
+    type Output = Float;
+    fn index(&self, index : usize) -> &Float {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Index out of bounds!: {}", index),
+        }
+    }
+} 
+
+///Indexes the components mutably, panicking past 2 as indexing does.
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, index : usize) -> &mut Float {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("Index out of bounds!: {}", index),
+        }
+    }
+} 
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    fn add(self, other : Self) -> Self::Output {
+        lanes::add(self, other)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other : Self) {
+        *self = lanes::add(*self, other);
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    fn sub(self, other : Self) -> Self::Output {
+        lanes::sub(self, other)
+    }
+}
+
+impl Mul for Vec3 {
+    type Output = Vec3;
+    fn mul(self, other : Self) -> Self::Output {
+        lanes::mul(self, other)
+    }
+}
+
+impl Mul<Float> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, other : Float) -> Self::Output {
+        lanes::scale(self, other)
+    }
+}
+
+impl MulAssign<Float> for Vec3 {
+    fn mul_assign(&mut self, other : Float) {
+        *self = lanes::scale(*self, other);
+    }
+}
+
+impl Div for Vec3 {
+    type Output = Vec3;
+    fn div(self, other : Self) -> Self::Output {
+        lanes::div(self, other)
+    }
+}
+
+impl Div<Float> for Vec3 {
+    type Output = Vec3;
+    fn div(self, other : Float) -> Self::Output {
+        lanes::div(self, Vec3::new(other, other, other))
+    }
+}
+
+impl DivAssign<Float> for Vec3 {
+    fn div_assign(&mut self, other : Float) {
+        *self = *self / other;
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        lanes::neg(self)
+    }
+}
+
+///The dot product of two vectors.
+pub fn dot(v1 : Vec3, v2 : Vec3) -> Float {
+    lanes::dot(v1, v2)
+}
+
+///The cross product of two vectors.
+pub fn cross(v1 : Vec3, v2 : Vec3) -> Vec3 {
+    lanes::cross(v1, v2)
+}
+
+#[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+use simd as lanes;
+#[cfg(not(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64")))]
+use scalar as lanes;
+
+///Vector arithmetic one component at a time.
+#[cfg(not(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64")))]
+mod scalar {
+    use super::{Vec3, Float};
+
+    #[inline(always)]
+    pub fn add(a : Vec3, b : Vec3) -> Vec3 {
+        Vec3::new(a.x + b.x, a.y + b.y, a.z + b.z)
+    }
+
+    #[inline(always)]
+    pub fn sub(a : Vec3, b : Vec3) -> Vec3 {
+        Vec3::new(a.x - b.x, a.y - b.y, a.z - b.z)
+    }
+
+    #[inline(always)]
+    pub fn mul(a : Vec3, b : Vec3) -> Vec3 {
+        Vec3::new(a.x * b.x, a.y * b.y, a.z * b.z)
+    }
+
+    #[inline(always)]
+    pub fn div(a : Vec3, b : Vec3) -> Vec3 {
+        Vec3::new(a.x / b.x, a.y / b.y, a.z / b.z)
+    }
+
+    #[inline(always)]
+    pub fn scale(a : Vec3, t : Float) -> Vec3 {
+        Vec3::new(a.x * t, a.y * t, a.z * t)
+    }
+
+    #[inline(always)]
+    pub fn neg(a : Vec3) -> Vec3 {
+        Vec3::new(-a.x, -a.y, -a.z)
+    }
+
+    #[inline(always)]
+    pub fn dot(a : Vec3, b : Vec3) -> Float {
+        (a.x * b.x) + (a.y * b.y) + (a.z * b.z)
+    }
+
+    #[inline(always)]
+    pub fn cross(a : Vec3, b : Vec3) -> Vec3 {
+        Vec3::new((a.y * b.z) - (a.z * b.y), (a.z * b.x) - (a.x * b.z), (a.x * b.y) - (a.y * b.x))
+    }
+}
+
+///Vector arithmetic on all components at once with SSE. Its intrinsics are unsafe to call only because the processor might lack SSE,
+///
+/// but every x86_64 processor has it. Sums are added up in the same order as the scalar version's, and negation flips the sign bit as it does,
+/// so that both give exactly the same results.
+#[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+mod simd {
+    use core::arch::x86_64::*;
+    use super::Vec3;
+
+    #[inline(always)]
+    fn load(v : Vec3) -> __m128 {
+        //Vec3 has the size and alignment of an SSE register, with every lane initialized
+        unsafe {core::mem::transmute::<Vec3, __m128>(v)}
+    }
+
+    #[inline(always)]
+    fn store(m : __m128) -> Vec3 {
+        unsafe {core::mem::transmute::<__m128, Vec3>(m)}
+    }
+
+    #[inline(always)]
+    pub fn add(a : Vec3, b : Vec3) -> Vec3 {
+        unsafe {store(_mm_add_ps(load(a), load(b)))}
+    }
+
+    #[inline(always)]
+    pub fn sub(a : Vec3, b : Vec3) -> Vec3 {
+        unsafe {store(_mm_sub_ps(load(a), load(b)))}
+    }
+
+    #[inline(always)]
+    pub fn mul(a : Vec3, b : Vec3) -> Vec3 {
+        unsafe {store(_mm_mul_ps(load(a), load(b)))}
+    }
+
+    #[inline(always)]
+    pub fn div(a : Vec3, b : Vec3) -> Vec3 {
+        unsafe {store(_mm_div_ps(load(a), load(b)))}
+    }
+
+    #[inline(always)]
+    pub fn scale(a : Vec3, t : f32) -> Vec3 {
+        unsafe {store(_mm_mul_ps(load(a), _mm_set1_ps(t)))}
+    }
+
+    #[inline(always)]
+    pub fn neg(a : Vec3) -> Vec3 {
+        unsafe {store(_mm_xor_ps(load(a), _mm_set1_ps(-0.0)))}
+    }
+
+    #[inline(always)]
+    pub fn dot(a : Vec3, b : Vec3) -> f32 {
+        unsafe {
+            let m = _mm_mul_ps(load(a), load(b));
+            let y = _mm_shuffle_ps::<0b01_01_01_01>(m, m);
+            let z = _mm_movehl_ps(m, m);
+            _mm_cvtss_f32(_mm_add_ss(_mm_add_ss(m, y), z))
+        }
+    }
+
+    #[inline(always)]
+    pub fn cross(a : Vec3, b : Vec3) -> Vec3 {
+        unsafe {
+            let (a, b) = (load(a), load(b));
+            let a_yzx = _mm_shuffle_ps::<0b11_00_10_01>(a, a);
+            let b_yzx = _mm_shuffle_ps::<0b11_00_10_01>(b, b);
+            let a_zxy = _mm_shuffle_ps::<0b11_01_00_10>(a, a);
+            let b_zxy = _mm_shuffle_ps::<0b11_01_00_10>(b, b);
+            store(_mm_sub_ps(_mm_mul_ps(a_yzx, b_zxy), _mm_mul_ps(a_zxy, b_yzx)))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static SAMPLER : RefCell<Option<Box<dyn Sampler>>> = RefCell::new(None);
+    static GENERATOR : RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+///Seed set with set_seed(), which makes rendering reproducible once SEEDED is true.
+static SEED : AtomicU64 = AtomicU64::new(0);
+static SEEDED : AtomicBool = AtomicBool::new(false);
+
+///Random numbers without std, which has no threads to give generators of their own: a SplitMix64 stream shared by every caller,
+///
+/// counting up by the golden ratio from a state that seed_stream() resets.
+#[cfg(not(feature = "std"))]
+static STATE : AtomicU64 = AtomicU64::new(0x853c49e6748fea9b);
+
+///Makes every random decision from here on reproducible: runs with the same seed produce identical images.
+/// 
+/// Also reseeds the current thread, so that work done before rendering (such as building the scene) is reproducible too.
+/// The seed is process-wide: every render afterwards uses it, on every thread and for every scene.
+pub fn set_seed(seed : u64) {
+    SEED.store(seed, Ordering::Relaxed);
+    SEEDED.store(true, Ordering::Relaxed);
+    seed_stream(&[]);
+}
+
+///If a seed has been set, reseeds the current thread's random numbers from it and the given ids (such as a pixel's coordinates
+/// 
+/// and a sample's index). Called at the start of every independent piece of parallel work, so that its random numbers
+/// don't depend on which thread it happened to run on, or in what order.
+pub fn seed_stream(ids : &[u64]) {
+    if !SEEDED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut state = SEED.load(Ordering::Relaxed);
+    for id in ids {
+        state = split_mix(state ^ split_mix(*id));
+    }
+    #[cfg(feature = "std")]
+    GENERATOR.with(|generator| *generator.borrow_mut() = StdRng::seed_from_u64(state));
+    #[cfg(not(feature = "std"))]
+    STATE.store(state, Ordering::Relaxed);
+}
+
+///SplitMix64 hash, for combining seeds and ids.
+fn split_mix(x : u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+///Returns a random number between 0 and 1 non-inclusive from the current thread's generator, ignoring any sampler.
+/// 
+/// Used by samplers themselves, for numbers they don't choose any other way.
+#[cfg(feature = "std")]
+pub fn uniform_float() -> Float {
+    GENERATOR.with(|generator| generator.borrow_mut().gen::<Float>())
+}
+
+///Returns a random number between 0 and 1 non-inclusive from the shared stream, made from as many bits as Float holds exactly.
+#[cfg(not(feature = "std"))]
+pub fn uniform_float() -> Float {
+    let bits = split_mix(STATE.fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed));
+    (bits >> (64 - Float::MANTISSA_DIGITS)) as Float / (1u64 << Float::MANTISSA_DIGITS) as Float
+}
+
+///Returns a random number between 0 and 1 non-inclusive, drawn from the current thread's sampler (if any).
+/// 
+/// Every random decision made while tracing a path draws from this or random_2d().
+pub fn random_float() -> Float {
+    #[cfg(feature = "std")]
+    return SAMPLER.with(|sampler| match sampler.borrow_mut().as_mut() {
+        Some(sampler) => sampler.get_1d(),
+        None => uniform_float(),
+    });
+    #[cfg(not(feature = "std"))]
+    uniform_float()
+}
+
+///Returns a pair of random numbers between 0 and 1 non-inclusive, drawn together from the current thread's sampler (if any)
+/// 
+/// so that samplers can spread them evenly over the square. Used for decisions that pick a point on a 2D domain, such as a lens or a sphere.
+pub fn random_2d() -> (Float, Float) {
+    #[cfg(feature = "std")]
+    return SAMPLER.with(|sampler| match sampler.borrow_mut().as_mut() {
+        Some(sampler) => sampler.get_2d(),
+        None => (uniform_float(), uniform_float()),
+    });
+    #[cfg(not(feature = "std"))]
+    (uniform_float(), uniform_float())
+}
+
+///Returns a random number between a minimum and a maximum non-inclusive, drawn from random_float().
+pub fn random_range_float(minimum : Float, maximum : Float) -> Float {
+    minimum + (maximum - minimum) * random_float()
+}
+
+///Replaces (or with None, removes) the sampler that random_float() and random_2d() draw from on the current thread.
+#[cfg(feature = "std")]
+pub fn set_sampler(sampler : Option<Box<dyn Sampler>>) {
+    SAMPLER.with(|s| *s.borrow_mut() = sampler);
+}
+
+///Removes and returns the current thread's sampler, if there is one.
+#[cfg(feature = "std")]
+pub fn take_sampler() -> Option<Box<dyn Sampler>> {
+    SAMPLER.with(|s| s.borrow_mut().take())
+}
+
+///Returns a copy of the current thread's random number generator, so that the numbers drawn from here on can be replayed with restore_generator().
+#[cfg(feature = "std")]
+pub fn save_generator() -> StdRng {
+    GENERATOR.with(|generator| generator.borrow().clone())
+}
+
+///Replaces the current thread's random number generator, as saved by save_generator() or made by fork_generator().
+#[cfg(feature = "std")]
+pub fn restore_generator(state : StdRng) {
+    GENERATOR.with(|generator| *generator.borrow_mut() = state);
+}
+
+///Returns a new random number generator seeded from the current thread's, whose numbers are independent of those the current one goes on to draw.
+#[cfg(feature = "std")]
+pub fn fork_generator() -> StdRng {
+    GENERATOR.with(|generator| StdRng::seed_from_u64(generator.borrow_mut().gen()))
+}
+
+///Runs f on the current thread's sampler, or on a RandomSampler if there isn't one, returning its result.
+/// 
+/// f must draw its random numbers from the sampler it is given, since random_float() can't be used until it returns.
+#[cfg(feature = "std")]
+pub fn sample_with<T>(f : impl FnOnce(&mut dyn Sampler) -> T) -> T {
+    SAMPLER.with(|sampler| match sampler.borrow_mut().as_mut() {
+        Some(sampler) => f(sampler.as_mut()),
+        None => f(&mut RandomSampler),
+    })
+}
+
+///Runs f on the current thread's sampler, if there is one. Used to move it on to the next pixel or sample.
+#[cfg(feature = "std")]
+pub fn with_sampler(f : impl FnOnce(&mut dyn Sampler)) {
+    SAMPLER.with(|sampler| {
+        if let Some(sampler) = sampler.borrow_mut().as_mut() {
+            f(sampler.as_mut());
+        }
+    });
+}
+
+///Generates a random vector within a unit sphere (for use in ray scattering).
+pub fn random_in_unit_sphere() -> Vec3 {
+    let (r1, r2) = random_2d();
+    Vec3::new((2.0 * PI * r1).cos() * 2.0 * (r2 * (1.0 - r2)).sqrt(), (2.0 * PI * r1).sin() * 2.0 * (r2 * (1.0 - r2)).sqrt(), 1.0 - (2.0 * r2))
+}
+
+///Generates a random Vec3 in the camera's unit disk (for use in defocus blur).
+pub fn random_in_unit_disk() -> Vec3 {
+    let (r1, r2) = random_2d();
+    point_in_unit_disk(r1, r2)
+}
+
+///Maps two numbers between 0 and 1 to a point in the unit disk, spreading them evenly over its area.
+pub fn point_in_unit_disk(r1 : Float, r2 : Float) -> Vec3 {
+    let r = r1.sqrt();
+    let theta = 2.0 * PI * r2;
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+///Builds two unit vectors that, together with the unit vector n, form an orthonormal basis.
+pub fn orthonormal_basis(n : Vec3) -> (Vec3, Vec3) {
+    let a = if n.x.abs() > 0.9 {Vec3::new(0.0, 1.0, 0.0)} else {Vec3::new(1.0, 0.0, 0.0)};
+    let v = cross(n, a).unit_vector();
+    let u = cross(n, v);
+    (u, v)
+}
+
+///Generates a random unit vector within a cone of directions around the unit vector axis, whose half-angle has the given cosine.
+pub fn random_in_cone(axis : Vec3, cos_max : Float) -> Vec3 {
+    let (r1, r2) = random_2d();
+    let cos_theta = 1.0 - r1 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * r2;
+    let (u, v) = orthonormal_basis(axis);
+    u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + axis * cos_theta
+}
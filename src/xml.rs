@@ -0,0 +1,156 @@
+/*
+Module to store a small XML parser, used to import Mitsuba scenes. It reads elements and their attributes,
+skipping the declaration, comments, processing instructions and text, which scene files don't use.
+*/
+
+use std::io::{Error, ErrorKind, Result};
+
+///Element of an XML document, with its attributes and child elements in the order they were written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub name : String,
+    pub attributes : Vec<(String, String)>,
+    pub children : Vec<Element>,
+}
+
+impl Element {
+
+    ///Parses an XML document, returning its root element.
+    pub fn parse(text : &str) -> Result<Element> {
+        let mut parser = Parser {bytes : text.as_bytes(), position : 0};
+        parser.skip_misc()?;
+        let root = parser.element()?;
+        parser.skip_misc()?;
+        if parser.position < parser.bytes.len() {
+            return Err(parser.invalid("unexpected text after the root element"));
+        }
+        Ok(root)
+    }
+
+    ///Returns the value of an attribute, if the element has it.
+    pub fn attribute(&self, name : &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+///Recursive descent parser, reading a document from position onwards.
+struct Parser<'a> {
+    bytes : &'a [u8],
+    position : usize,
+}
+
+impl Parser<'_> {
+
+    fn element(&mut self) -> Result<Element> {
+        if !self.eat("<") {
+            return Err(self.invalid("expected an element"));
+        }
+        let name = self.name()?;
+        let mut attributes = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.eat("/>") {
+                return Ok(Element {name, attributes, children : vec![]});
+            }
+            if self.eat(">") {
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.eat("=") {
+                return Err(self.invalid("expected '='"));
+            }
+            self.skip_whitespace();
+            attributes.push((key, self.value()?));
+        }
+
+        let mut children = vec![];
+        loop {
+            //Text between elements is skipped
+            while self.position < self.bytes.len() && self.bytes[self.position] != b'<' {
+                self.position += 1;
+            }
+            if self.eat("</") {
+                let closing = self.name()?;
+                self.skip_whitespace();
+                if closing != name || !self.eat(">") {
+                    return Err(self.invalid(&format!("expected </{}>", name)));
+                }
+                return Ok(Element {name, attributes, children});
+            }
+            if self.position >= self.bytes.len() {
+                return Err(self.invalid(&format!("unterminated element {}", name)));
+            }
+            if !self.skip_special()? {
+                children.push(self.element()?);
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let start = self.position;
+        while self.position < self.bytes.len() && (self.bytes[self.position].is_ascii_alphanumeric() || matches!(self.bytes[self.position], b'_' | b'-' | b'.' | b':')) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(self.invalid("expected a name"));
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+
+    ///Reads a quoted attribute value, decoding its entities.
+    fn value(&mut self) -> Result<String> {
+        let quote = match self.bytes.get(self.position) {
+            Some(quote @ (b'"' | b'\'')) => *quote,
+            _ => return Err(self.invalid("expected a quoted value")),
+        };
+        let start = self.position + 1;
+        let length = self.bytes[start..].iter().position(|byte| *byte == quote).ok_or_else(|| self.invalid("unterminated value"))?;
+        self.position = start + length + 1;
+        let raw = String::from_utf8_lossy(&self.bytes[start..start + length]);
+        Ok(raw.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&"))
+    }
+
+    ///Skips whitespace, comments, processing instructions (such as the XML declaration) and doctypes outside the root element.
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            if !self.skip_special()? {
+                return Ok(());
+            }
+        }
+    }
+
+    ///Skips a comment, processing instruction, doctype or CDATA section if one comes next, returning whether one did.
+    fn skip_special(&mut self) -> Result<bool> {
+        for (start, end) in [("<!--", "-->"), ("<?", "?>"), ("<![CDATA[", "]]>"), ("<!", ">")] {
+            if self.eat(start) {
+                let length = self.bytes[self.position..].windows(end.len()).position(|window| window == end.as_bytes())
+                    .ok_or_else(|| self.invalid(&format!("expected {}", end)))?;
+                self.position += length + end.len();
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn eat(&mut self, text : &str) -> bool {
+        let found = self.bytes[self.position..].starts_with(text.as_bytes());
+        if found {
+            self.position += text.len();
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    ///Returns an error for the current position, giving its line number.
+    fn invalid(&self, message : &str) -> Error {
+        let line = self.bytes[..self.position.min(self.bytes.len())].iter().filter(|b| **b == b'\n').count() + 1;
+        Error::new(ErrorKind::InvalidData, format!("Invalid XML on line {}: {}", line, message))
+    }
+}
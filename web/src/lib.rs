@@ -0,0 +1,109 @@
+/*
+Module to store the WebAssembly interface of the tracer, which the browser demo in index.html renders with. WebAssembly has no threads to render on,
+so the demo runs a web worker for every core instead, each building the scene and rendering one band of its rows (see CropWindow) pass by pass,
+and copies every pass into a pixel buffer shared by all of them, which the page draws into a canvas as it fills in.
+Scenes are the built-in examples or JSON scene files given as text; the files they refer to, such as images and environment maps, can't be read
+in a browser. Elsewhere, the library takes its clocks from web_time, since std's panic in browsers, and rayon falls back to the calling thread.
+*/
+
+use std::path::Path;
+use wasm_bindgen::prelude::*;
+use js_sys::{Function, Uint8ClampedArray};
+use rust_tracer::vec3::{Vec3, Point3, set_seed, Float};
+use rust_tracer::ray::{SpawnOffset, DEFAULT_EPSILON, set_self_intersection};
+use rust_tracer::camera::{Camera, StandardCamera};
+use rust_tracer::atmosphere::Atmosphere;
+use rust_tracer::accelerator::AcceleratorKind;
+use rust_tracer::integrator::{Integrator, Clamping};
+use rust_tracer::crop::CropWindow;
+use rust_tracer::scene::Scene;
+use rust_tracer::scene_file::SceneFile;
+use rust_tracer::render::{Renderer, RenderSettings, SaveSettings, get_color};
+use rust_tracer::examples;
+
+///Band of rows of a scene's image, rendered by one worker: top is the first row (counted from the top) and rows how many it has.
+#[wasm_bindgen]
+pub struct WebRender {
+    renderer : Renderer,
+    world : Scene,
+    cameras : Vec<Box<dyn Camera>>,
+    top : u32,
+    rows : u32,
+}
+
+#[wasm_bindgen]
+impl WebRender {
+
+    ///Builds a scene (the name of an example scene, or the text of a JSON scene file) to render width pixels across, samples_per_pixel samples
+    ///
+    /// samples_per_pass at a time. The image is split into bands of rows from the top, as evenly as they go, and only number band is rendered.
+    /// Settings the scene leaves out take main()'s values, except for a path depth of 50.
+    #[wasm_bindgen(constructor)]
+    pub fn new(scene : &str, width : u32, samples_per_pixel : i32, samples_per_pass : i32, band : u32, bands : u32) -> Result<WebRender, JsError> {
+        let description = if examples::NAMES.contains(&scene) {examples::example(scene)?} else {SceneFile::parse(scene, Path::new(""))?};
+        let file = &description.settings;
+        if let Some(seed) = file.seed {
+            set_seed(seed);
+        }
+        set_self_intersection(file.epsilon.unwrap_or(DEFAULT_EPSILON), file.spawn_offset.unwrap_or(SpawnOffset::Distance));
+
+        //The sky is baked at a quarter of main()'s resolution, since every worker bakes its own
+        let environment_intensity = file.environment_intensity.unwrap_or(1.0);
+        let environment = file.atmosphere.map(|atmosphere| atmosphere.bake(512, 256, environment_intensity));
+        let mut world = description.scene(environment, AcceleratorKind::Bvh)?;
+        world.atmosphere = file.atmosphere.map(|atmosphere| Atmosphere {sun_intensity : atmosphere.sun_intensity * environment_intensity, ..atmosphere});
+        world.fog = file.fog;
+
+        let aspect_ratio = file.aspect_ratio.unwrap_or(1.0);
+        let height = ((width as Float / aspect_ratio) as u32).max(1);
+        let lookat = file.lookat.unwrap_or(Point3::new(278.0, 278.0, 0.0));
+        let camera = StandardCamera::new(file.lookfrom.unwrap_or(Point3::new(278.0, 278.0, -800.0)), lookat, file.vup.unwrap_or(Vec3::new(0.0, 1.0, 0.0)),
+            file.vfov.unwrap_or(40.0), aspect_ratio, file.aperture.unwrap_or(0.0), file.focus_distance.unwrap_or(20.0));
+        let camera = if file.autofocus.unwrap_or(true) {camera.autofocus(&world, lookat)} else {camera};
+
+        let bands = bands.max(1);
+        let (top, bottom) = (height * band.min(bands) / bands, height * (band + 1).min(bands) / bands);
+        let mut settings = RenderSettings::new(width, height, samples_per_pixel, Integrator::PathTracer(file.max_depth.unwrap_or(50), Clamping::None));
+        settings.samples_per_pass = samples_per_pass.max(1);
+        settings.crop = Some(CropWindow::Pixels(0, top, width, bottom));
+        Ok(WebRender {renderer : Renderer::new(settings), world, cameras : vec![Box::new(camera)], top, rows : bottom - top})
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.renderer.image_size().0
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.renderer.image_size().1
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn top(&self) -> u32 {
+        self.top
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    ///Renders the band, calling on_pass(pixels, pass, passes) after every pass with its rows so far as 8 bit sRGB RGBA values, top row first,
+    ///
+    /// ready to be copied into a canvas's ImageData. Rendering stops early if on_pass returns false.
+    pub fn render(&mut self, on_pass : &Function) {
+        let (width, top, rows) = (self.width(), self.top, self.rows);
+        let save = SaveSettings::default();
+        self.renderer.render_with(&mut self.world, &self.cameras, None, None, "", |image, pass, passes| {
+            let pixels = image.pixels();
+            let mut rgba = Vec::with_capacity(4 * (width * rows) as usize);
+            for (pixel, alpha) in &pixels[(top * width) as usize..((top + rows) * width) as usize] {
+                let (r, g, b) = if *alpha > 0.0 {get_color(*pixel / *alpha, save.exposure, save.tone_map, save.transform)} else {(0, 0, 0)};
+                rgba.extend_from_slice(&[r, g, b, 255]);
+            }
+            let pixels = Uint8ClampedArray::from(&rgba[..]);
+            on_pass.call3(&JsValue::NULL, &pixels, &pass.into(), &passes.into()).is_ok_and(|keep| keep.as_bool() != Some(false))
+        });
+    }
+}
@@ -0,0 +1,32 @@
+// Renders one band of the image's rows for index.html, pass by pass, either straight into the pixel buffer shared by every worker,
+// or (when the page can't share memory) by sending each pass's pixels back to the page.
+import init, {WebRender} from './pkg/rust_tracer_web.js';
+
+const ready = init();
+let band;
+
+onmessage = async ({data}) => {
+    await ready;
+    try {
+        if (data.type === 'build') {
+            band = new WebRender(data.scene, data.width, data.samplesPerPixel, data.samplesPerPass, data.band, data.bands);
+            postMessage({type: 'size', width: band.width, height: band.height});
+        } else if (data.type === 'start') {
+            // The band can't be read while it renders, so where its rows go is worked out first
+            const offset = band.top * band.width * 4;
+            const shared = data.buffer && new Uint8ClampedArray(data.buffer, offset, band.rows * band.width * 4);
+            band.render((pixels, pass, passes) => {
+                if (shared) {
+                    shared.set(pixels);
+                    postMessage({type: 'pass', pass, passes});
+                } else {
+                    postMessage({type: 'pass', pass, passes, offset, pixels}, [pixels.buffer]);
+                }
+                return true;
+            });
+            postMessage({type: 'done'});
+        }
+    } catch (error) {
+        postMessage({type: 'error', message: String(error)});
+    }
+};